@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use gnew::error::Result;
+use gnew::repo::object::Commit;
+use gnew::repo::revwalk::RevWalkOptions;
+use gnew::storage::transport;
+use gnew::testutil;
+
+const COMMITS: usize = 50;
+const FILES: usize = 1000;
+
+fn status(c: &mut Criterion) {
+    let t = testutil::generate_repo(COMMITS, FILES).unwrap();
+    let tree = t.repo.head_tree().unwrap();
+    c.bench_function("status", |b| b.iter(|| t.repo.status(&tree).unwrap()));
+}
+
+fn diff(c: &mut Criterion) {
+    let t = testutil::generate_repo(COMMITS, FILES).unwrap();
+    let log = t.repo.log(COMMITS as u32, RevWalkOptions::default()).unwrap();
+    let log: Vec<Commit> = log.collect::<Result<_>>().unwrap();
+    let new = log.first().unwrap().tree().unwrap();
+    let old = log.last().unwrap().tree().unwrap();
+    c.bench_function("diff", |b| b.iter(|| old.diff(&new).unwrap()));
+}
+
+fn log(c: &mut Criterion) {
+    let t = testutil::generate_repo(COMMITS, FILES).unwrap();
+    c.bench_function("log", |b| {
+        b.iter(|| {
+            let log = t.repo.log(COMMITS as u32, RevWalkOptions::default()).unwrap();
+            log.collect::<Result<Vec<_>>>().unwrap()
+        })
+    });
+}
+
+fn read_commit(c: &mut Criterion) {
+    let t = testutil::generate_repo(1, 1).unwrap();
+    let hash = t.repo.head_hash().unwrap();
+    c.bench_function("read_commit", |b| {
+        b.iter(|| transport::read_commit(hash).unwrap())
+    });
+}
+
+criterion_group!(benches, status, diff, log, read_commit);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#![no_main]
+
+use gnew::storage::serialize::{deserialize_blob, serialize_blob};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut blob) = deserialize_blob(data) {
+        // whatever is accepted serializes back to an object with its hash
+        let hash = blob.hash();
+        serialize_blob(&mut blob);
+        assert_eq!(blob.hash(), hash);
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use gnew::storage::serialize::{deserialize_commit, serialize_commit};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut commit) = deserialize_commit(data) {
+        // whatever is accepted serializes back to an object with its hash
+        let hash = commit.hash();
+        serialize_commit(&mut commit);
+        assert_eq!(commit.hash(), hash);
+    }
+});
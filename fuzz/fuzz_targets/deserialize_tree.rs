@@ -0,0 +1,13 @@
+#![no_main]
+
+use gnew::storage::serialize::{deserialize_tree, serialize_tree};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(mut tree) = deserialize_tree(data) {
+        // whatever is accepted serializes back to an object with its hash
+        let hash = tree.hash();
+        serialize_tree(&mut tree);
+        assert_eq!(tree.hash(), hash);
+    }
+});
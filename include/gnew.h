@@ -0,0 +1,53 @@
+/*
+ * C interface to gnew repositories.
+ *
+ * Build the library with `cargo build --release --features ffi` and link
+ * with -lgnew. Strings returned by these functions are owned by the caller
+ * and must be freed with gnew_string_free. Functions that fail return NULL
+ * and set the message returned by gnew_last_error.
+ *
+ * Calls do not change the current directory. A repository, and the
+ * iterators made from it, must only be used by one thread at a time; other
+ * repositories can be used from other threads meanwhile.
+ */
+
+#ifndef GNEW_H
+#define GNEW_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Repository gnew_repository;
+typedef struct GnewLog gnew_log;
+
+/* Message of the last error of the calling thread, or NULL. */
+const char *gnew_last_error(void);
+
+/* Frees a string returned by this library. */
+void gnew_string_free(char *s);
+
+/* Opens the repository containing the directory path. */
+gnew_repository *gnew_repository_open(const char *path);
+void gnew_repository_free(gnew_repository *repo);
+
+/* Status of the working tree, in the format of `gnew status --porcelain`. */
+char *gnew_status(gnew_repository *repo);
+
+/* Commits the tracked files and returns the hash of the new commit. */
+char *gnew_commit(gnew_repository *repo, const char *message);
+
+/*
+ * Iterates over the history of HEAD, newest first. gnew_log_next returns
+ * the hash of the next commit, or NULL at the end of the history or on
+ * error, in which case gnew_last_error is not NULL.
+ */
+gnew_log *gnew_log_new(gnew_repository *repo);
+char *gnew_log_next(gnew_log *log);
+void gnew_log_free(gnew_log *log);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,205 @@
+use self::Error::*;
+use crate::repo::object::Hash;
+use crate::storage::serialize::DeserializeError;
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::result;
+
+/// The result of fallible operations throughout the crate.
+pub type Result<T> = result::Result<T, Error>;
+
+/// Errors of all layers: storage errors, such as corrupted objects or IO
+/// errors, and errors of repository operations.
+#[derive(Debug)]
+pub enum Error {
+    /// Changes saved by `--autostash` that were not applied again yet.
+    AutostashPending(Hash),
+    BranchConflict(String, String),
+    BranchExists,
+    /// A branch that does not exist, with similar branch and tag names.
+    BranchNotFound(String, Vec<String>),
+    CheckoutFailed,
+    /// A checkout failed and could not be rolled back. The files it replaced
+    /// are kept in the directory.
+    CheckoutInterrupted(PathBuf),
+    /// A commit of a history walk that could not be read, and why.
+    CommitUnreadable(Hash, Box<Error>),
+    ConfigCorrupted,
+    DirtyWorktree,
+    EmptyBranch(String),
+    FileNotFound,
+    FilesMissing(Vec<PathBuf>),
+    HashCollision,
+    HookFailed(String),
+    HookNotExecutable(String),
+    InNestedRepository(PathBuf),
+    InvalidAuthor(String),
+    InvalidCommitMessage(String),
+    InvalidDate(String),
+    InvalidPattern(String),
+    InvalidRefName(String),
+    InvalidRefspec(String),
+    InvalidRebaseTodo(String),
+    IoError(io::Error),
+    LocalModifications(Vec<PathBuf>),
+    MergeFailed(Vec<PathBuf>),
+    /// There is no previous HEAD to return to with `-` or `@{-1}`.
+    NoPreviousHead,
+    NoRebaseInProgress,
+    NoteNotFound(Hash),
+    NoRepository,
+    NotRecursive,
+    ObjectCorrupted,
+    ObjectMalformed(Hash, DeserializeError),
+    ObjectMissing,
+    ObjectNotFound,
+    ObjectsNotCopied(Vec<(PathBuf, Error)>),
+    ObjectsTampered(Vec<PathBuf>),
+    PathNotFound(String, PathBuf),
+    PushFailed(Vec<String>),
+    RebaseCorrupted,
+    RebaseInProgress,
+    /// A rebase stopped at a commit whose changes conflict in the files.
+    RebaseStopped(Hash, Vec<PathBuf>),
+    ReferenceNotFound,
+    RemoteNotFound(String),
+    RevisionNotFound,
+    RepositoryExists,
+    SnapshotCorrupted,
+    TracklistCorrupted,
+    TreeCycle(Hash),
+    TreeTooDeep(PathBuf),
+    UnrelatedHistories,
+    UnsafePath(String),
+    /// Untracked files that a checkout would overwrite.
+    UntrackedOverwritten(Vec<PathBuf>),
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CommitUnreadable(_, err) => Some(err.as_ref()),
+            IoError(err) => Some(err),
+            ObjectMalformed(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutostashPending(commit) => write!(
+                f,
+                "changes saved by an earlier autostash in {} are not applied yet",
+                commit
+            ),
+            BranchConflict(name, existing) => {
+                write!(f, "cannot create branch '{}': branch '{}' exists", name, existing)
+            }
+            BranchExists => write!(f, "branch already exists"),
+            BranchNotFound(name, _) => write!(f, "reference not found: '{}'", name),
+            CheckoutFailed => write!(f, "commit or remove changes first"),
+            CheckoutInterrupted(dir) => write!(
+                f,
+                "a checkout was interrupted, the files it replaced are kept in '{}'",
+                dir.display()
+            ),
+            CommitUnreadable(hash, err) => write!(f, "cannot read commit {}: {}", hash, err),
+            ConfigCorrupted => write!(f, "corrupted config file"),
+            DirtyWorktree => write!(f, "dirty work tree"),
+            EmptyBranch(b) => write!(f, "branch '{}' has no commits yet", b),
+            FileNotFound => write!(f, "file not found"),
+            FilesMissing(paths) => {
+                let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "tracked files are missing: {}", paths.join(", "))
+            }
+            HashCollision => write!(f, "object differs from stored object with the same hash"),
+            HookFailed(name) => write!(f, "{} hook failed", name),
+            HookNotExecutable(name) => write!(f, "{} hook is not executable", name),
+            InNestedRepository(path) => {
+                write!(f, "'{}' is inside a nested repository", path.display())
+            }
+            InvalidAuthor(author) => write!(f, "invalid author: '{}'", author),
+            InvalidCommitMessage(reason) => write!(f, "invalid commit message: {}", reason),
+            InvalidDate(date) => write!(f, "invalid date '{}'", date),
+            InvalidPattern(reason) => write!(f, "invalid pattern: {}", reason),
+            InvalidRefName(name) => write!(f, "invalid reference name: '{}'", name),
+            InvalidRefspec(refspec) => write!(f, "invalid refspec: '{}'", refspec),
+            InvalidRebaseTodo(reason) => write!(f, "invalid rebase todo list: {}", reason),
+            IoError(error) => write!(f, "IO error: {}", error),
+            LocalModifications(paths) => {
+                let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "files have local modifications: {}", paths.join(", "))
+            }
+            MergeFailed(_) => write!(f, "merge failed"),
+            NoPreviousHead => write!(f, "no previous branch or commit was checked out"),
+            NoRebaseInProgress => write!(f, "no rebase in progress"),
+            NoteNotFound(hash) => write!(f, "no note found for object {}", hash),
+            NoRepository => write!(f, "no repository at file path"),
+            NotRecursive => write!(f, "not removing a directory without -r"),
+            ObjectCorrupted => write!(f, "corrupted object"),
+            ObjectMalformed(hash, err) => write!(f, "malformed object {}: {}", hash, err),
+            ObjectMissing => write!(f, "missing object"),
+            ObjectNotFound => write!(f, "object not found"),
+            ObjectsNotCopied(failed) => {
+                let failed: Vec<_> =
+                    failed.iter().map(|(o, err)| format!("{} ({})", o.display(), err)).collect();
+                write!(f, "could not copy objects: {}", failed.join(", "))
+            }
+            ObjectsTampered(objects) => {
+                let objects: Vec<_> = objects.iter().map(|o| o.display().to_string()).collect();
+                write!(f, "received objects do not match their hashes: {}", objects.join(", "))
+            }
+            PathNotFound(rev, path) => {
+                write!(f, "file not found: '{}' in '{}'", path.display(), rev)
+            }
+            PushFailed(branches) => write!(
+                f,
+                "local and remote repositories differ, pull first: {}",
+                branches.join(", ")
+            ),
+            RebaseCorrupted => write!(f, "corrupted rebase state"),
+            RebaseInProgress => write!(
+                f,
+                "a rebase is in progress, run 'rebase --continue' or 'rebase --abort'"
+            ),
+            RebaseStopped(commit, _) => write!(
+                f,
+                "could not apply {}: fix the conflicts and run 'rebase --continue'",
+                commit
+            ),
+            ReferenceNotFound => write!(f, "reference not found"),
+            RemoteNotFound(name) => write!(f, "no such remote: '{}'", name),
+            RevisionNotFound => write!(f, "revision not found"),
+            RepositoryExists => write!(
+                f,
+                "local repository by the same name already exists, delete it first"
+            ),
+            SnapshotCorrupted => write!(f, "corrupted snapshot file"),
+            TracklistCorrupted => write!(f, "corrupted tracklist"),
+            TreeCycle(hash) => write!(f, "tree {} contains itself", hash),
+            TreeTooDeep(path) => write!(f, "tree is nested too deeply at '{}'", path.display()),
+            UnrelatedHistories => write!(f, "the histories are unrelated"),
+            UnsafePath(name) => write!(f, "refusing unsafe path in tree: '{}'", name),
+            UntrackedOverwritten(paths) => {
+                let paths: Vec<_> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "untracked files would be overwritten: {}", paths.join(", "))
+            }
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        IoError(err)
+    }
+}
+
+impl From<walkdir::Error> for Error {
+    fn from(err: walkdir::Error) -> Error {
+        IoError(err.into())
+    }
+}
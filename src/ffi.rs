@@ -0,0 +1,299 @@
+//! C interface to repositories, built with the `ffi` feature. The
+//! declarations are in `include/gnew.h`.
+//!
+//! Strings returned by these functions are owned by the caller and freed
+//! with `gnew_string_free`. Functions that fail return NULL and leave a
+//! message for `gnew_last_error`; a panic is reported the same way instead
+//! of unwinding into the caller. A repository, and the iterators made from
+//! it, must only be used by one thread at a time; different repositories can
+//! be used from different threads at once.
+
+use crate::repo::repository::{CommitOptions, Repository};
+use crate::repo::revwalk::{RevWalk, RevWalkOptions};
+use crate::error::Result;
+use crate::storage::transport::{self, Store};
+use crate::wd::ui;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An iterator over the history of HEAD.
+pub struct GnewLog {
+    walk: RevWalk,
+    store: Store,
+}
+
+/// Runs the body of an exported function, recording a panic as an error
+/// and returning `failed` instead of unwinding into C.
+fn guard<T>(failed: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        set_error("internal error".to_owned());
+        failed
+    })
+}
+
+/// Returns the result as a pointer, or NULL after recording the error.
+fn into_ptr<T>(result: Result<T>, f: impl FnOnce(T) -> *mut c_char) -> *mut c_char {
+    match result {
+        Ok(value) => f(value),
+        Err(err) => {
+            set_error(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+fn set_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', " ")).unwrap().into_raw()
+}
+
+/// Borrows a pointer argument, recording an error if it is NULL.
+unsafe fn read_ptr<'a, T>(p: *mut T) -> Option<&'a mut T> {
+    if p.is_null() {
+        set_error("null argument".to_owned());
+    }
+    p.as_mut()
+}
+
+/// Reads a string argument, recording an error if it is not UTF-8.
+unsafe fn read_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        set_error("null argument".to_owned());
+        return None;
+    }
+    match CStr::from_ptr(s).to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_error("argument is not UTF-8".to_owned());
+            None
+        }
+    }
+}
+
+/// Returns the message of the last error of the calling thread, or NULL.
+/// The message is valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn gnew_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Frees a string returned by one of these functions.
+///
+/// # Safety
+///
+/// `s` must be NULL or a string returned by this library, not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        guard((), || drop(CString::from_raw(s)));
+    }
+}
+
+/// Opens the repository containing the directory `path`.
+///
+/// # Safety
+///
+/// `path` must be NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_repository_open(path: *const c_char) -> *mut Repository {
+    guard(ptr::null_mut(), || {
+        let path = match read_str(path) {
+            Some(path) => path,
+            None => return ptr::null_mut(),
+        };
+        match Repository::open_at(path) {
+            Ok(repo) => Box::into_raw(Box::new(repo)),
+            Err(err) => {
+                set_error(err.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees a repository.
+///
+/// # Safety
+///
+/// `repo` must be NULL or a repository returned by `gnew_repository_open`,
+/// not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_repository_free(repo: *mut Repository) {
+    if !repo.is_null() {
+        guard((), || drop(Box::from_raw(repo)));
+    }
+}
+
+/// Returns the status of the working tree in the format of
+/// `gnew status --porcelain`.
+///
+/// # Safety
+///
+/// `repo` must be NULL or a repository returned by `gnew_repository_open`.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_status(repo: *mut Repository) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let repo = match read_ptr(repo) {
+            Some(repo) => repo,
+            None => return ptr::null_mut(),
+        };
+        let _store = repo.enter();
+        let status = repo.head_tree().and_then(|tree| repo.status(&tree));
+        into_ptr(status, |s| into_c_string(ui::format_porcelain_status(&s)))
+    })
+}
+
+/// Commits the tracked files with a message and returns the commit hash.
+///
+/// # Safety
+///
+/// `repo` must be NULL or a repository returned by `gnew_repository_open`,
+/// and `message` NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_commit(repo: *mut Repository, message: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let (repo, message) = match (read_ptr(repo), read_str(message)) {
+            (Some(repo), Some(message)) => (repo, message.to_owned()),
+            _ => return ptr::null_mut(),
+        };
+        let _store = repo.enter();
+        let commit = repo.commit(message, &CommitOptions::default());
+        into_ptr(commit, |c| into_c_string(c.hash().to_string()))
+    })
+}
+
+/// Starts iterating over the history of HEAD, newest first.
+///
+/// # Safety
+///
+/// `repo` must be NULL or a repository returned by `gnew_repository_open`.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_log_new(repo: *mut Repository) -> *mut GnewLog {
+    guard(ptr::null_mut(), || {
+        let repo = match read_ptr(repo) {
+            Some(repo) => repo,
+            None => return ptr::null_mut(),
+        };
+        let _store = repo.enter();
+        let tips: Vec<_> = repo.head_hash().into_iter().collect();
+        match RevWalk::new(&tips, RevWalkOptions::default()) {
+            Ok(walk) => Box::into_raw(Box::new(GnewLog {
+                walk,
+                store: repo.store(),
+            })),
+            Err(err) => {
+                set_error(err.to_string());
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Returns the hash of the next commit, or NULL at the end of the history
+/// or on error, in which case `gnew_last_error` is set.
+///
+/// # Safety
+///
+/// `log` must be NULL or an iterator returned by `gnew_log_new`.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_log_next(log: *mut GnewLog) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let log = match read_ptr(log) {
+            Some(log) => log,
+            None => return ptr::null_mut(),
+        };
+        let _store = transport::enter_store(&log.store);
+        match log.walk.next() {
+            Some(commit) => into_ptr(commit, |c| into_c_string(c.hash().to_string())),
+            None => {
+                LAST_ERROR.with(|e| *e.borrow_mut() = None);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Frees a history iterator.
+///
+/// # Safety
+///
+/// `log` must be NULL or an iterator returned by `gnew_log_new`, not freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn gnew_log_free(log: *mut GnewLog) {
+    if !log.is_null() {
+        guard((), || drop(Box::from_raw(log)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::repository::AddMode;
+    use crate::testutil::TestRepo;
+
+    fn take_string(s: *mut c_char) -> Option<String> {
+        if s.is_null() {
+            return None;
+        }
+        let owned = unsafe { CStr::from_ptr(s) }.to_str().unwrap().to_owned();
+        unsafe { gnew_string_free(s) };
+        Some(owned)
+    }
+
+    fn last_error() -> String {
+        let e = gnew_last_error();
+        assert!(!e.is_null());
+        unsafe { CStr::from_ptr(e) }.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn c_api_test() {
+        let t = TestRepo::new();
+        t.write("a.txt", "a");
+        let path = CString::new(t.path().join("sub").to_str().unwrap()).unwrap();
+        std::fs::create_dir(t.path().join("sub")).unwrap();
+
+        unsafe {
+            let repo = gnew_repository_open(path.as_ptr());
+            assert!(!repo.is_null());
+            assert_eq!(take_string(gnew_status(repo)).unwrap(), "? a.txt\n");
+            (*repo).add(&[t.path().join("a.txt")], AddMode::Paths).unwrap();
+            let msg = CString::new("first").unwrap();
+            let hash = take_string(gnew_commit(repo, msg.as_ptr())).unwrap();
+
+            let log = gnew_log_new(repo);
+            assert_eq!(take_string(gnew_log_next(log)), Some(hash));
+            assert_eq!(take_string(gnew_log_next(log)), None);
+            assert!(gnew_last_error().is_null());
+            gnew_log_free(log);
+            gnew_repository_free(repo);
+        }
+    }
+
+    #[test]
+    fn c_api_rejects_null_arguments() {
+        unsafe {
+            assert!(gnew_repository_open(ptr::null()).is_null());
+            assert_eq!(last_error(), "null argument");
+            assert!(gnew_status(ptr::null_mut()).is_null());
+            assert!(gnew_commit(ptr::null_mut(), ptr::null()).is_null());
+            assert!(gnew_log_new(ptr::null_mut()).is_null());
+            assert!(gnew_log_next(ptr::null_mut()).is_null());
+            assert_eq!(last_error(), "null argument");
+            gnew_repository_free(ptr::null_mut());
+            gnew_log_free(ptr::null_mut());
+        }
+    }
+}
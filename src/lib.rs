@@ -1,3 +1,8 @@
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod error;
 pub mod repo;
 pub mod storage;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
 pub mod wd;
@@ -1,2 +1,27 @@
+pub mod attributes;
+pub mod autostash;
+pub mod bitmap;
+pub mod cache;
+pub mod config;
+pub mod hooks;
+pub mod ignore;
+pub mod journal;
+pub mod lost_found;
+pub mod mailmap;
+pub mod maintenance;
+pub mod message;
+pub mod notes;
 pub mod object;
+pub mod owners;
+pub mod rebase;
+pub mod refs;
+pub mod refspec;
+pub mod rerere;
 pub mod repository;
+pub mod revwalk;
+pub mod rewrite;
+pub mod search;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod tracklist;
@@ -0,0 +1,274 @@
+use crate::repo::ignore::{self, IgnorePattern};
+use crate::error::Result;
+use std::path::Path;
+
+/// Name of the files assigning attributes to paths.
+pub const ATTRIBUTES_FILE: &str = ".gnewattributes";
+
+/// Number of bytes looked at to guess if data is binary.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+/// The value of an attribute for a path.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    /// Set with `name`.
+    Set,
+    /// Unset with `-name`.
+    Unset,
+    /// Set to a value with `name=value`.
+    Value(String),
+}
+
+/// How files are merged, from the `merge` attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeDriver {
+    /// Merge lines, with conflict markers for conflicting changes.
+    Text,
+    /// Keep our version and report a conflict if theirs differs (`-merge`).
+    Binary,
+    /// Always keep our version (`merge=ours`).
+    Ours,
+    /// Always take their version (`merge=theirs`).
+    Theirs,
+}
+
+/// Line endings of text files in the working tree, from the `eol` attribute.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+}
+
+/// A line of an attributes file: a pattern and the attributes it assigns.
+/// `None` values are unspecified with `!name`.
+#[derive(Clone, Debug, PartialEq)]
+struct AttrRule {
+    pattern: IgnorePattern,
+    attrs: Vec<(String, Option<AttrValue>)>,
+}
+
+/// The attributes assigned to paths by `.gnew/info/attributes` and the
+/// `.gnewattributes` files of a working tree. Each line holds a pattern, as
+/// in ignore files, followed by attributes: `name`, `-name`, `name=value` or
+/// `!name`. Later lines and files in deeper directories take precedence.
+///
+/// Recognized attributes:
+///
+/// - `text`: normalize line endings to LF when storing files, `-text` never
+/// - `eol=lf|crlf`: line endings of the file in the working tree
+/// - `diff`: always diff as text, `-diff` as binary; detected if unspecified
+/// - `merge=ours|theirs`, `-merge` for binary merges
+/// - `binary`: same as `-text -diff -merge`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Attributes {
+    rules: Vec<AttrRule>,
+}
+
+impl Attributes {
+    pub fn new() -> Attributes {
+        Attributes::default()
+    }
+
+    /// Reads the attributes files of a working tree. `skip` is called on
+    /// each directory and returns true for directories that are not
+    /// searched, such as nested repositories.
+    pub fn load<F>(worktree: &Path, skip: F) -> Result<Attributes>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut attributes = Attributes::new();
+        let info = ignore::info_file(worktree, "attributes");
+        if let Some(content) = ignore::read_optional(&worktree.join(&info))? {
+            attributes.parse(&content, Path::new(""), &info);
+        }
+        for dir in ignore::worktree_dirs(worktree, skip)? {
+            let source = dir.join(ATTRIBUTES_FILE);
+            if let Some(content) = ignore::read_optional(&worktree.join(&source))? {
+                attributes.parse(&content, &dir, &source);
+            }
+        }
+        Ok(attributes)
+    }
+
+    /// Adds the rules of an attributes file in the directory base.
+    /// Lines with negated patterns, which are not allowed, are skipped.
+    pub fn parse(&mut self, content: &str, base: &Path, source: &Path) {
+        for (i, line) in content.lines().enumerate() {
+            let mut fields = line.split_whitespace();
+            let pattern = match fields.next() {
+                Some(p) if !p.starts_with('#') => IgnorePattern::parse(p, base, source, i + 1),
+                _ => None,
+            };
+            let pattern = match pattern {
+                Some(p) if !p.is_negated() => p,
+                _ => continue,
+            };
+            let attrs = fields.flat_map(parse_attr).collect();
+            self.rules.push(AttrRule { pattern, attrs });
+        }
+    }
+
+    /// Returns the value of an attribute for a path relative to the working
+    /// tree, or None if it is unspecified.
+    pub fn get(&self, path: &Path, name: &str) -> Option<&AttrValue> {
+        self.rules
+            .iter()
+            .rev()
+            .filter(|r| r.pattern.matches(path, false))
+            .flat_map(|r| r.attrs.iter().rev())
+            .find(|(n, _)| n == name)
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    /// Checks if the line endings of a file are normalized to LF when it
+    /// is stored.
+    pub fn is_text(&self, path: &Path) -> bool {
+        match self.get(path, "text") {
+            Some(AttrValue::Unset) => false,
+            Some(_) => true,
+            None => self.eol(path).is_some(),
+        }
+    }
+
+    /// Returns the line endings of a file in the working tree, if set.
+    pub fn eol(&self, path: &Path) -> Option<Eol> {
+        if self.get(path, "text") == Some(&AttrValue::Unset) {
+            return None;
+        }
+        match self.get(path, "eol")? {
+            AttrValue::Value(v) if v == "crlf" => Some(Eol::Crlf),
+            AttrValue::Value(v) if v == "lf" => Some(Eol::Lf),
+            _ => None,
+        }
+    }
+
+    /// Checks if a change to a file is shown as binary by diff, using the
+    /// `diff` attribute or else the contents.
+    pub fn is_binary_diff(&self, path: &Path, old: &[u8], new: &[u8]) -> bool {
+        match self.get(path, "diff") {
+            Some(AttrValue::Unset) => true,
+            Some(_) => false,
+            None => is_binary_data(old) || is_binary_data(new),
+        }
+    }
+
+    pub fn merge_driver(&self, path: &Path) -> MergeDriver {
+        match self.get(path, "merge") {
+            Some(AttrValue::Unset) => MergeDriver::Binary,
+            Some(AttrValue::Value(v)) if v == "ours" => MergeDriver::Ours,
+            Some(AttrValue::Value(v)) if v == "theirs" => MergeDriver::Theirs,
+            Some(AttrValue::Value(v)) if v == "binary" => MergeDriver::Binary,
+            _ => MergeDriver::Text,
+        }
+    }
+
+    /// Converts the contents of a working tree file for storage.
+    pub fn clean(&self, path: &Path, data: Vec<u8>) -> Vec<u8> {
+        if self.is_text(path) && data.contains(&b'\r') {
+            to_lf(&data)
+        } else {
+            data
+        }
+    }
+
+    /// Returns whether `smudge` converts the contents of a path.
+    pub fn smudges(&self, path: &Path) -> bool {
+        self.eol(path) == Some(Eol::Crlf)
+    }
+
+    /// Converts stored contents for the working tree. Returns None if they
+    /// are written as is.
+    pub fn smudge(&self, path: &Path, data: &[u8]) -> Option<Vec<u8>> {
+        match self.eol(path) {
+            Some(Eol::Crlf) => Some(to_crlf(data)),
+            _ => None,
+        }
+    }
+}
+
+/// Parses an attribute, expanding the `binary` macro.
+fn parse_attr(s: &str) -> Vec<(String, Option<AttrValue>)> {
+    let attr = if let Some(name) = s.strip_prefix('-') {
+        (name.to_owned(), Some(AttrValue::Unset))
+    } else if let Some(name) = s.strip_prefix('!') {
+        (name.to_owned(), None)
+    } else if let Some((name, value)) = s.split_once('=') {
+        (name.to_owned(), Some(AttrValue::Value(value.to_owned())))
+    } else {
+        (s.to_owned(), Some(AttrValue::Set))
+    };
+    if attr == ("binary".to_owned(), Some(AttrValue::Set)) {
+        let unset = |n: &str| (n.to_owned(), Some(AttrValue::Unset));
+        return vec![attr, unset("text"), unset("diff"), unset("merge")];
+    }
+    vec![attr]
+}
+
+/// Guesses if data is binary, i.e. has a NUL byte near the start.
+pub fn is_binary_data(data: &[u8]) -> bool {
+    data.iter().take(BINARY_CHECK_LENGTH).any(|&b| b == 0)
+}
+
+/// Converts CRLF line endings to LF.
+fn to_lf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if !(b == b'\r' && data.get(i + 1) == Some(&b'\n')) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Converts LF line endings to CRLF.
+fn to_crlf(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &b) in data.iter().enumerate() {
+        if b == b'\n' && (i == 0 || data[i - 1] != b'\r') {
+            out.push(b'\r');
+        }
+        out.push(b);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attributes(content: &str) -> Attributes {
+        let mut attributes = Attributes::new();
+        attributes.parse(content, Path::new(""), Path::new(ATTRIBUTES_FILE));
+        attributes
+    }
+
+    #[test]
+    fn lookup() {
+        let a = attributes("*.txt text eol=crlf\n*.png binary\n# comment\nkeep.txt !eol\n");
+        let txt = Path::new("doc/a.txt");
+        let png = Path::new("a.png");
+
+        assert_eq!(a.get(txt, "text"), Some(&AttrValue::Set));
+        assert_eq!(a.eol(txt), Some(Eol::Crlf));
+        assert_eq!(a.eol(Path::new("keep.txt")), None);
+        assert!(a.is_text(Path::new("keep.txt")));
+        assert!(!a.is_text(png));
+        assert!(a.is_binary_diff(png, b"", b""));
+        assert_eq!(a.merge_driver(png), MergeDriver::Binary);
+        assert_eq!(a.merge_driver(txt), MergeDriver::Text);
+        assert_eq!(a.get(Path::new("a.rs"), "text"), None);
+    }
+
+    #[test]
+    fn conversions() {
+        let a = attributes("*.txt eol=crlf\n*.bin merge=ours\n");
+        let txt = Path::new("a.txt");
+
+        assert_eq!(a.clean(txt, b"a\r\nb\n".to_vec()), b"a\nb\n");
+        assert_eq!(a.smudge(txt, b"a\nb\r\n").unwrap(), b"a\r\nb\r\n");
+        assert_eq!(a.smudge(Path::new("a.bin"), b"a\n"), None);
+        assert!(a.smudges(txt) && !a.smudges(Path::new("a.bin")));
+        assert_eq!(a.merge_driver(Path::new("a.bin")), MergeDriver::Ours);
+        assert!(a.is_binary_diff(Path::new("a.rs"), b"\0", b""));
+    }
+}
@@ -0,0 +1,65 @@
+use crate::error::{Error, Result};
+use crate::repo::object::{Commit, CommitInfo};
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use chrono::Utc;
+use std::path::PathBuf;
+
+impl Repository {
+    /// Saves the changes to tracked files in a commit on top of HEAD and
+    /// checks out HEAD, so that a pull or rebase can run on a clean working
+    /// tree. Untracked files are left as they are. Returns false if there
+    /// were no changes to save.
+    pub fn autostash(&mut self) -> Result<bool> {
+        if let Some(stash) = transport::read_autostash(self.worktree())? {
+            return Err(Error::AutostashPending(stash));
+        }
+        let head = transport::read_commit(self.head_hash()?)?;
+        let tree = head.tree()?;
+        match self.is_clean(&tree) {
+            Err(Error::DirtyWorktree) => (),
+            r => return r.map(|_| false),
+        }
+        if transport::read_merge_head(self.worktree())?.is_some() {
+            return Err(Error::DirtyWorktree);
+        }
+
+        let mut stash = Commit::new(CommitInfo {
+            tree: self.write_tree()?.hash(),
+            parents: vec![head.hash()],
+            author: self.identity()?,
+            time: Utc::now(),
+            msg: "autostash".to_owned(),
+        });
+        transport::write_commit(&mut stash)?;
+        let plan = self.plan_checkout(&tree, false)?;
+        self.apply_checkout(plan)?;
+        transport::write_autostash(self.worktree(), Some(stash.hash()))?;
+        Ok(true)
+    }
+
+    /// Applies the changes saved by `autostash` to the working tree with a
+    /// three-way merge against HEAD. Returns the files with conflicts, or
+    /// None if there are no saved changes, or if a merge or rebase has to be
+    /// completed or the working tree has changes first.
+    pub fn apply_autostash(&mut self) -> Result<Option<Vec<PathBuf>>> {
+        let stash = match transport::read_autostash(self.worktree())? {
+            Some(hash) => transport::read_commit(hash)?,
+            None => return Ok(None),
+        };
+        if self.rebase_state()?.is_some()
+            || transport::read_merge_head(self.worktree())?.is_some()
+        {
+            return Ok(None);
+        }
+        let head = transport::read_commit(self.head_hash()?)?;
+        match self.is_clean(&head.tree()?) {
+            Err(Error::DirtyWorktree) => return Ok(None),
+            r => r?,
+        }
+        let base = stash.parent().transpose()?;
+        let conflicts = self.merge_into_worktree(&head, base.as_ref(), &stash)?;
+        transport::write_autostash(self.worktree(), None)?;
+        Ok(Some(conflicts))
+    }
+}
@@ -0,0 +1,142 @@
+use crate::repo::object::{Hash, TreeEntryKind};
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use crate::error::Result;
+use std::collections::{HashMap, HashSet};
+
+/// Reachability bitmaps: for selected commits, the set of objects reachable
+/// from them, stored as a bitmap over a shared ordering of objects.
+#[derive(Debug, Default, PartialEq)]
+pub struct BitmapIndex {
+    objects: Vec<Hash>,
+    positions: HashMap<Hash, usize>,
+    bitmaps: HashMap<Hash, Vec<u8>>,
+}
+
+impl BitmapIndex {
+    /// Creates an index without bitmaps over the given objects.
+    pub fn new(objects: Vec<Hash>) -> BitmapIndex {
+        let positions = objects.iter().enumerate().map(|(i, &h)| (h, i)).collect();
+        BitmapIndex {
+            objects,
+            positions,
+            bitmaps: HashMap::new(),
+        }
+    }
+
+    pub fn objects(&self) -> &[Hash] {
+        &self.objects
+    }
+
+    pub fn bitmaps(&self) -> impl Iterator<Item = (&Hash, &[u8])> {
+        self.bitmaps.iter().map(|(h, b)| (h, b.as_slice()))
+    }
+
+    /// Adds the bitmap of a commit given the objects reachable from it.
+    /// Objects that are not in the index are ignored.
+    pub fn insert(&mut self, commit: Hash, reachable: &HashSet<Hash>) {
+        let mut bits = vec![0u8; self.bitmap_len()];
+        for i in reachable.iter().filter_map(|h| self.positions.get(h)) {
+            bits[i / 8] |= 1 << (i % 8);
+        }
+        self.bitmaps.insert(commit, bits);
+    }
+
+    /// Adds a bitmap read from storage.
+    pub fn insert_bitmap(&mut self, commit: Hash, bits: Vec<u8>) {
+        self.bitmaps.insert(commit, bits);
+    }
+
+    /// Length in bytes of each bitmap.
+    pub fn bitmap_len(&self) -> usize {
+        self.objects.len().div_ceil(8)
+    }
+
+    /// Returns the objects reachable from a commit, if it has a bitmap.
+    pub fn reachable(&self, commit: Hash) -> Option<impl Iterator<Item = Hash> + '_> {
+        let bits = self.bitmaps.get(&commit)?;
+        Some(
+            self.objects
+                .iter()
+                .enumerate()
+                .filter(move |(i, _)| bits[i / 8] & (1 << (i % 8)) != 0)
+                .map(|(_, &h)| h),
+        )
+    }
+}
+
+impl Repository {
+    /// Writes reachability bitmaps for the branch heads.
+    /// Returns the number of bitmaps written.
+    pub fn write_bitmaps(&self) -> Result<usize> {
+        let empty = BitmapIndex::default();
+        let mut reachable = HashMap::new();
+        let mut all = HashSet::new();
+
+        for &tip in self.branches().values() {
+            let objects = self.reachable_objects(&[tip], &empty)?;
+            all.extend(objects.iter().copied());
+            reachable.insert(tip, objects);
+        }
+        let mut objects: Vec<_> = all.into_iter().collect();
+        objects.sort_unstable_by_key(|h| h.to_string());
+
+        let mut index = BitmapIndex::new(objects);
+        for (tip, objects) in &reachable {
+            index.insert(*tip, objects);
+        }
+        transport::write_bitmaps(&index)?;
+        Ok(reachable.len())
+    }
+
+    /// Returns the objects reachable from the tips that are not reachable
+    /// from the haves, using the stored reachability bitmaps where possible.
+    pub fn objects_between(&self, tips: &[Hash], haves: &[Hash]) -> Result<HashSet<Hash>> {
+        let index = transport::read_bitmaps();
+        let have = self.reachable_objects(haves, &index)?;
+        let mut objects = self.reachable_objects(tips, &index)?;
+        objects.retain(|h| !have.contains(h));
+        Ok(objects)
+    }
+
+    /// Returns the objects reachable from the given commits. The history of
+    /// commits with a bitmap is not walked.
+    pub(crate) fn reachable_objects(
+        &self,
+        tips: &[Hash],
+        index: &BitmapIndex,
+    ) -> Result<HashSet<Hash>> {
+        let mut seen = HashSet::new();
+        let mut commits = tips.to_vec();
+
+        while let Some(hash) = commits.pop() {
+            if seen.contains(&hash) {
+                continue;
+            }
+            if let Some(objects) = index.reachable(hash) {
+                seen.extend(objects);
+                continue;
+            }
+            seen.insert(hash);
+            let commit = transport::read_commit(hash)?;
+            reachable_tree(commit.tree_hash(), &mut seen)?;
+            commits.extend(commit.parent_hashes());
+        }
+        Ok(seen)
+    }
+}
+
+fn reachable_tree(hash: Hash, seen: &mut HashSet<Hash>) -> Result<()> {
+    if !seen.insert(hash) {
+        return Ok(());
+    }
+    for e in transport::read_tree(hash)?.entries() {
+        match e.kind() {
+            TreeEntryKind::Tree => reachable_tree(e.hash(), seen)?,
+            TreeEntryKind::Blob => {
+                seen.insert(e.hash());
+            }
+        }
+    }
+    Ok(())
+}
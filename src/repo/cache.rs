@@ -0,0 +1,84 @@
+use crate::repo::object::Hash;
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Files modified this recently are not cached, since a later change
+/// within the timestamp granularity would go unnoticed.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// Blob hashes of working tree files, keyed by path and validated against
+/// the size and modification time of the file.
+#[derive(Debug, Default, PartialEq)]
+pub struct StatCache {
+    entries: HashMap<String, CacheEntry>,
+    dirty: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub hash: Hash,
+    /// Modification time in nanoseconds since the Unix epoch.
+    pub mtime: u128,
+    pub size: u64,
+}
+
+impl StatCache {
+    pub fn new() -> StatCache {
+        StatCache::default()
+    }
+
+    /// Returns the cached hash of a file if it is unchanged.
+    pub fn get(&self, path: &str, md: &Metadata) -> Option<Hash> {
+        let entry = self.entries.get(path)?;
+        if (entry.mtime, entry.size) == (mtime(md)?, md.len()) {
+            Some(entry.hash)
+        } else {
+            None
+        }
+    }
+
+    /// Records the hash of a file with the given metadata.
+    pub fn insert(&mut self, path: String, md: &Metadata, hash: Hash) {
+        let racy = !md
+            .modified()
+            .is_ok_and(|t| t + RACY_WINDOW <= SystemTime::now());
+
+        match mtime(md) {
+            Some(mtime) if !racy => {
+                let entry = CacheEntry {
+                    hash,
+                    mtime,
+                    size: md.len(),
+                };
+                if self.entries.insert(path, entry) != Some(entry) {
+                    self.dirty = true;
+                }
+            }
+            _ => {
+                if self.entries.remove(&path).is_some() {
+                    self.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// Adds an entry read from storage.
+    pub fn insert_entry(&mut self, path: String, entry: CacheEntry) {
+        self.entries.insert(path, entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &CacheEntry)> {
+        self.entries.iter()
+    }
+
+    /// Checks if the cache changed since it was read.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+}
+
+fn mtime(md: &Metadata) -> Option<u128> {
+    let t = md.modified().ok()?;
+    Some(t.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
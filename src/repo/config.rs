@@ -0,0 +1,36 @@
+use std::collections::BTreeMap;
+
+/// Repository settings, stored as dotted keys with string values
+/// (e.g. `remote.origin.url`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    entries: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(String::as_str)
+    }
+
+    /// Returns a boolean setting, which is false unless set to `true`.
+    pub fn get_bool(&self, key: &str) -> bool {
+        self.get(key) == Some("true")
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.entries.insert(key.to_owned(), value.to_owned());
+    }
+
+    pub fn unset(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// Returns the settings in key order.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
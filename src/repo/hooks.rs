@@ -0,0 +1,50 @@
+use crate::error::{Error::*, Result};
+use std::ffi::OsStr;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Runs the hook `.gnew/hooks/<name>` with the given arguments and the
+/// environment variables `envs` from the root of the working tree, with
+/// `input` on its standard input if given. Succeeds if there is no such
+/// hook. Fails with `HookFailed` if the hook exits unsuccessfully, and with
+/// `HookNotExecutable` if it cannot be run, so that the checks of a hook
+/// are not skipped by mistake.
+pub fn run_hook<S: AsRef<OsStr>>(
+    storage_dir: &Path,
+    worktree: &Path,
+    name: &str,
+    args: &[S],
+    input: Option<&str>,
+    envs: &[(&str, &Path)],
+) -> Result<()> {
+    let path = storage_dir.join("hooks").join(name);
+    if !path.is_file() {
+        return Ok(());
+    }
+    let mut child = match Command::new(&path)
+        .args(args)
+        .envs(envs.iter().copied())
+        .current_dir(worktree)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::inherit() })
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => {
+            return Err(HookNotExecutable(name.to_owned()))
+        }
+        Err(err) => return Err(err.into()),
+    };
+    if let Some(input) = input {
+        /* the hook may exit without reading its input */
+        let written = child.stdin.take().unwrap().write_all(input.as_bytes());
+        match written {
+            Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+            _ => (),
+        }
+    }
+    if !child.wait()?.success() {
+        return Err(HookFailed(name.to_owned()));
+    }
+    Ok(())
+}
@@ -0,0 +1,329 @@
+use crate::error::Result;
+use crate::storage::transport;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Name of the files listing patterns of untracked files to ignore.
+pub const IGNORE_FILE: &str = ".gnewignore";
+
+/// A pattern from an ignore file, in the syntax of `.gitignore`:
+///
+/// - `*` matches anything but `/`, `?` any character but `/`, `[a-z]` and
+///   `[!a-z]` a character in or not in a class, and `**` any number of
+///   directories
+/// - patterns containing a `/` other than a trailing one are matched against
+///   the path relative to the directory of the ignore file; other patterns
+///   are matched against the name of the file at any depth
+/// - a trailing `/` only matches directories
+/// - a leading `!` re-includes files ignored by an earlier pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgnorePattern {
+    /// The ignore file, relative to the working tree.
+    pub source: PathBuf,
+    /// The line number in the ignore file, starting at 1.
+    pub line: usize,
+    /// The pattern as written.
+    pub text: String,
+    base: PathBuf,
+    glob: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// The patterns of the ignore files of a working tree: `.gnew/info/exclude`
+/// and the `.gnewignore` files, with patterns in deeper directories and
+/// later lines taking precedence.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct IgnoreMatcher {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl IgnorePattern {
+    /// Parses a line of an ignore file in the directory base.
+    /// Returns None for blank lines and comments.
+    pub fn parse(line: &str, base: &Path, source: &Path, number: usize) -> Option<IgnorePattern> {
+        let text = line.trim_end();
+        if text.is_empty() || text.starts_with('#') {
+            return None;
+        }
+        let (negated, glob) = match text.strip_prefix('!') {
+            Some(glob) => (true, glob),
+            None => (false, text.strip_prefix('\\').unwrap_or(text)),
+        };
+        let (dir_only, glob) = match glob.strip_suffix('/') {
+            Some(glob) => (true, glob),
+            None => (false, glob),
+        };
+        let anchored = glob.contains('/');
+        Some(IgnorePattern {
+            source: source.to_owned(),
+            line: number,
+            text: text.to_owned(),
+            base: base.to_owned(),
+            glob: glob.trim_start_matches('/').to_owned(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Checks if the pattern re-includes files instead of ignoring them.
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Checks if a path relative to the working tree matches the pattern.
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let rel = match path.strip_prefix(&self.base) {
+            Ok(rel) => rel,
+            Err(_) => return false,
+        };
+        let text = if self.anchored {
+            rel.to_str()
+        } else {
+            rel.file_name().and_then(|n| n.to_str())
+        };
+        text.is_some_and(|t| glob_match(self.glob.as_bytes(), t.as_bytes()))
+    }
+}
+
+impl IgnoreMatcher {
+    pub fn new() -> IgnoreMatcher {
+        IgnoreMatcher::default()
+    }
+
+    /// Reads the ignore files of a working tree. `skip` is called on each
+    /// directory and returns true for directories that are not searched,
+    /// such as nested repositories.
+    pub fn load<F>(worktree: &Path, skip: F) -> Result<IgnoreMatcher>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut matcher = IgnoreMatcher::new();
+        matcher.read_file(worktree, &info_file(worktree, "exclude"), Path::new(""))?;
+
+        for dir in worktree_dirs(worktree, skip)? {
+            matcher.read_file(worktree, &dir.join(IGNORE_FILE), &dir)?;
+        }
+        Ok(matcher)
+    }
+
+    fn read_file(&mut self, worktree: &Path, source: &Path, base: &Path) -> Result<()> {
+        let content = match read_optional(&worktree.join(source))? {
+            Some(content) => content,
+            None => return Ok(()),
+        };
+        let patterns = content
+            .lines()
+            .enumerate()
+            .filter_map(|(i, l)| IgnorePattern::parse(l, base, source, i + 1));
+        self.patterns.extend(patterns);
+        let exclude = info_file(worktree, "exclude");
+        self.patterns.sort_by_key(|p| {
+            (
+                p.source != exclude,
+                p.base.components().count(),
+            )
+        });
+        Ok(())
+    }
+
+    pub fn add(&mut self, pattern: IgnorePattern) {
+        self.patterns.push(pattern)
+    }
+
+    /// Returns the pattern deciding whether a path relative to the working
+    /// tree is ignored, if any. A negated pattern means the path is not
+    /// ignored. A path inside an ignored directory is ignored by the pattern
+    /// of the directory.
+    pub fn explain(&self, path: &Path, is_dir: bool) -> Option<&IgnorePattern> {
+        let mut prefix = PathBuf::new();
+        let mut components = path.components().peekable();
+
+        while let Some(c) = components.next() {
+            prefix.push(c);
+            let last = components.peek().is_none();
+            let pattern = self.last_match(&prefix, !last || is_dir);
+            match pattern {
+                _ if last => return pattern,
+                Some(p) if !p.negated => return pattern,
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// Checks if a path relative to the working tree is ignored.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.explain(path, is_dir).is_some_and(|p| !p.negated)
+    }
+
+    fn last_match(&self, path: &Path, is_dir: bool) -> Option<&IgnorePattern> {
+        self.patterns.iter().rev().find(|p| p.matches(path, is_dir))
+    }
+}
+
+/// Returns the directories of a working tree relative to it, starting with
+/// the root, in depth-first name order. `skip` is called on each directory
+/// and returns true for directories that are left out with their contents.
+pub fn worktree_dirs<F>(worktree: &Path, skip: F) -> Result<Vec<PathBuf>>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut result = vec![];
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        let mut subdirs = vec![];
+        for entry in fs::read_dir(worktree.join(&dir))? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() && !skip(&entry.path()) {
+                subdirs.push(dir.join(entry.file_name()));
+            }
+        }
+        // visit subdirectories in name order
+        subdirs.sort_unstable_by(|a, b| b.cmp(a));
+        dirs.extend(subdirs);
+        result.push(dir);
+    }
+    Ok(result)
+}
+
+/// Returns the path of a file in the `info` directory of the storage
+/// directory, e.g. `.gnew/info/exclude`, relative to the working tree if the
+/// storage directory is inside it.
+pub fn info_file(worktree: &Path, name: &str) -> PathBuf {
+    let path = transport::storage_dir(worktree).join("info").join(name);
+    match path.strip_prefix(worktree) {
+        Ok(relative) => relative.to_owned(),
+        Err(_) => path,
+    }
+}
+
+/// Reads a text file, which may not exist.
+pub fn read_optional(path: &Path) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Matches a text against a glob pattern.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, text)
+                || (0..text.len()).any(|i| text[i] == b'/' && glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => match text {
+            [c, text @ ..] if *c != b'/' => glob_match(rest, text),
+            _ => false,
+        },
+        [b'[', class @ ..] => match (class_match(class, text.first()), text) {
+            (Some((true, rest)), [_, text @ ..]) => glob_match(rest, text),
+            (Some(_), _) => false,
+            (None, [b'[', text @ ..]) => glob_match(class, text),
+            (None, _) => false,
+        },
+        [b'\\', c, rest @ ..] | [c, rest @ ..] => match text {
+            [t, text @ ..] if t == c => glob_match(rest, text),
+            _ => false,
+        },
+    }
+}
+
+/// Matches a character against a class like `a-z]` (after the `[`).
+/// Returns whether it matched and the rest of the pattern, or None if the
+/// class is not terminated.
+fn class_match<'a>(class: &'a [u8], c: Option<&u8>) -> Option<(bool, &'a [u8])> {
+    let (negated, class) = match class {
+        [b'!' | b'^', class @ ..] => (true, class),
+        _ => (false, class),
+    };
+    // a `]` right after the `[` is part of the class
+    let end = class.iter().skip(1).position(|&b| b == b']')? + 1;
+    let (set, rest) = (&class[..end], &class[end + 1..]);
+    let c = match c {
+        Some(&c) if c != b'/' => c,
+        _ => return Some((false, rest)),
+    };
+    let mut matched = false;
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == b'-' {
+            matched |= (set[i]..=set[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= set[i] == c;
+            i += 1;
+        }
+    }
+    Some((matched != negated, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        let m = |p: &str, t: &str| glob_match(p.as_bytes(), t.as_bytes());
+        assert!(m("*.o", "main.o"));
+        assert!(!m("*.o", "dir/main.o"));
+        assert!(m("a?c", "abc"));
+        assert!(m("[a-c]x", "bx"));
+        assert!(!m("[!a-c]x", "bx"));
+        assert!(m("**/build", "a/b/build"));
+        assert!(m("**/build", "build"));
+        assert!(m("doc/**", "doc/a/b.txt"));
+        assert!(m("a/**/b", "a/x/y/b"));
+        assert!(m("a/**/b", "a/b"));
+        assert!(m("\\*", "*"));
+        assert!(!m("\\*", "x"));
+    }
+
+    fn matcher(lines: &[(&str, &str)]) -> IgnoreMatcher {
+        let mut matcher = IgnoreMatcher::new();
+        for (i, (base, line)) in lines.iter().enumerate() {
+            let source = Path::new(base).join(IGNORE_FILE);
+            matcher.add(IgnorePattern::parse(line, Path::new(base), &source, i + 1).unwrap());
+        }
+        matcher
+    }
+
+    #[test]
+    fn explain() {
+        let m = matcher(&[
+            ("", "*.log"),
+            ("", "!keep.log"),
+            ("", "/build/"),
+            ("sub", "local/*.txt"),
+        ]);
+        let ignored = |p: &str| m.is_ignored(Path::new(p), false);
+
+        assert!(ignored("a.log"));
+        assert!(ignored("dir/a.log"));
+        assert!(!ignored("keep.log"));
+        assert_eq!(m.explain(Path::new("keep.log"), false).unwrap().line, 2);
+        assert!(ignored("build/out/a.o"));
+        assert_eq!(
+            m.explain(Path::new("build/a.o"), false).unwrap().text,
+            "/build/"
+        );
+        assert!(!ignored("src/build/a.o"));
+        assert!(!ignored("build"));
+        assert!(ignored("sub/local/a.txt"));
+        assert!(!ignored("local/a.txt"));
+        assert!(m.explain(Path::new("main.rs"), false).is_none());
+    }
+}
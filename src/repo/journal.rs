@@ -0,0 +1,133 @@
+use crate::error::{Error, Result};
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Name of the directory of the storage directory holding the working tree
+/// files that a checkout in progress replaced or removed.
+pub const JOURNAL_DIR: &str = "checkout-journal";
+
+/// The working tree files changed by a checkout, so that they can be
+/// restored if it fails halfway. Files that are replaced or removed are
+/// moved into the journal directory, and only deleted once the checkout
+/// completes.
+#[derive(Debug)]
+pub struct CheckoutJournal {
+    dir: PathBuf,
+    worktree: PathBuf,
+    /// Files moved into the journal, relative to the working tree.
+    saved: Vec<PathBuf>,
+    /// Files written by the checkout, relative to the working tree.
+    written: Vec<PathBuf>,
+}
+
+impl CheckoutJournal {
+    /// Starts the journal of a checkout. Fails if an interrupted checkout
+    /// left its journal, whose files were not restored.
+    pub fn begin(storage_dir: &Path, worktree: &Path) -> Result<CheckoutJournal> {
+        let dir = storage_dir.join(JOURNAL_DIR);
+        if dir.exists() {
+            return Err(Error::CheckoutInterrupted(dir));
+        }
+        fs::create_dir_all(&dir)?;
+        Ok(CheckoutJournal {
+            dir,
+            worktree: worktree.to_owned(),
+            saved: vec![],
+            written: vec![],
+        })
+    }
+
+    /// Moves a working tree file into the journal, if there is one, and
+    /// removes the directories it leaves empty.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        let file = self.worktree.join(path);
+        // a missing file may also be under a path that is a file
+        if !fs::symlink_metadata(&file).is_ok_and(|md| !md.is_dir()) {
+            return Ok(());
+        }
+        let dest = self.dir.join(path);
+        fs::create_dir_all(dest.parent().unwrap())?;
+        move_file(&file, &dest)?;
+        self.saved.push(path.to_owned());
+        remove_empty_parents(&self.worktree, &file);
+        Ok(())
+    }
+
+    /// Records that the checkout writes a working tree file.
+    pub fn write(&mut self, path: &Path) {
+        self.written.push(path.to_owned());
+    }
+
+    /// Ends a checkout that completed, deleting the saved files.
+    pub fn commit(self) -> Result<()> {
+        Ok(fs::remove_dir_all(&self.dir)?)
+    }
+
+    /// Undoes a checkout that failed: removes the files it wrote and moves
+    /// the saved ones back. The journal is kept if this fails too.
+    pub fn rollback(self) -> Result<()> {
+        for path in &self.written {
+            let file = self.worktree.join(path);
+            match fs::remove_file(&file) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => remove_empty_parents(&self.worktree, &file),
+            }
+        }
+        for path in &self.saved {
+            let dest = self.worktree.join(path);
+            fs::create_dir_all(dest.parent().unwrap())?;
+            move_file(&self.dir.join(path), &dest)?;
+        }
+        Ok(fs::remove_dir_all(&self.dir)?)
+    }
+}
+
+/// Moves a file, copying it and removing the original if it cannot be
+/// renamed because the storage directory is on another file system.
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Removes the directories above a working tree file that are empty.
+pub(crate) fn remove_empty_parents(worktree: &Path, file: &Path) {
+    for dir in file.ancestors().skip(1) {
+        if dir == worktree || fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+    use std::os::unix::fs::MetadataExt;
+    use std::process;
+
+    #[test]
+    fn journal_on_another_file_system() {
+        let t = TestRepo::new();
+        let other = Path::new("/dev/shm");
+        let dev = |p: &Path| fs::metadata(p).map(|m| m.dev()).ok();
+        if dev(other).is_none() || dev(other) == dev(t.path()) {
+            return;
+        }
+        let storage = other.join(format!("gnew-journal-{}", process::id()));
+        t.write("dir/a.txt", "a");
+
+        let mut journal = CheckoutJournal::begin(&storage, t.path()).unwrap();
+        journal.save(Path::new("dir/a.txt")).unwrap();
+        assert!(!t.path().join("dir").exists());
+        let rolled_back = journal.rollback();
+        fs::remove_dir_all(&storage).unwrap();
+        rolled_back.unwrap();
+        assert_eq!(fs::read_to_string(t.path().join("dir/a.txt")).unwrap(), "a");
+    }
+}
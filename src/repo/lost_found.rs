@@ -0,0 +1,72 @@
+use crate::error::Result;
+use crate::repo::object::{Commit, Hash};
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use std::collections::HashSet;
+
+impl Repository {
+    /// Returns the dangling commits, newest first: the stored commits that
+    /// `prune` would not keep, such as those left behind by a reset or a
+    /// deleted branch, and that are not the parent of another of them.
+    pub fn dangling_commits(&self) -> Result<Vec<Commit>> {
+        let reachable = self.reachable()?;
+        let mut unreachable = vec![];
+        for name in transport::get_objects(self.storage_dir())? {
+            let hash: Hash = match name.to_str().and_then(|n| n.parse().ok()) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            if reachable.contains(&hash)
+                || transport::read_object_kind(self.storage_dir(), &name).ok().as_deref()
+                    != Some("commit")
+            {
+                continue;
+            }
+            unreachable.push(transport::read_commit(hash)?);
+        }
+
+        let parents: HashSet<Hash> = unreachable
+            .iter()
+            .flat_map(|c| c.parent_hashes().iter().copied())
+            .collect();
+        let mut dangling: Vec<_> = unreachable
+            .into_iter()
+            .filter(|c| !parents.contains(&c.hash()))
+            .collect();
+        dangling.sort_by_key(|c| std::cmp::Reverse(c.time()));
+        Ok(dangling)
+    }
+
+    /// Creates a branch at a commit, e.g. a dangling one, without checking
+    /// it out.
+    pub fn restore_commit(&mut self, commit: Hash, name: &str) -> Result<()> {
+        transport::read_commit(commit)?;
+        self.check_new_branch(name)?;
+        self.set_branch(name, commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repo::repository::Reference;
+    use crate::testutil::TestRepo;
+
+    #[test]
+    fn dangling_commits_test() {
+        let mut t = TestRepo::new();
+        let first = t.commit_file("a.txt", "a");
+        t.repo.create_branch("topic").unwrap();
+        t.commit_file("b.txt", "b");
+        let tip = t.commit_file("c.txt", "c");
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        assert!(t.repo.dangling_commits().unwrap().is_empty());
+
+        t.repo.set_branch("topic", first.hash()).unwrap();
+        let dangling = t.repo.dangling_commits().unwrap();
+        assert_eq!(dangling.iter().map(|c| c.hash()).collect::<Vec<_>>(), [tip.hash()]);
+
+        t.repo.restore_commit(tip.hash(), "restored").unwrap();
+        assert!(t.repo.dangling_commits().unwrap().is_empty());
+        assert!(t.repo.restore_commit(tip.hash(), "restored").is_err());
+    }
+}
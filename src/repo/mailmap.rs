@@ -0,0 +1,137 @@
+use crate::repo::ignore;
+use crate::error::Result;
+use std::path::Path;
+
+/// Name of the file mapping the authors recorded in commits to their
+/// canonical identity.
+pub const MAILMAP_FILE: &str = ".mailmap";
+
+/// Canonical author identities, in the syntax of `.mailmap`. Each line maps
+/// an author by email, or by name and email, with the canonical name, email
+/// or both first:
+///
+/// ```text
+/// Proper Name <commit@email>
+/// <proper@email> <commit@email>
+/// Proper Name <proper@email> <commit@email>
+/// Proper Name <proper@email> Commit Name <commit@email>
+/// ```
+///
+/// Names and emails are matched regardless of case. Lines starting with `#`
+/// are comments.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct MailmapEntry {
+    name: Option<String>,
+    email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+impl Mailmap {
+    pub fn parse(content: &str) -> Mailmap {
+        Mailmap {
+            entries: content.lines().filter_map(parse_line).collect(),
+        }
+    }
+
+    /// Reads the `.mailmap` file at the root of a working tree, if any.
+    pub fn load(worktree: &Path) -> Result<Mailmap> {
+        let content = ignore::read_optional(&worktree.join(MAILMAP_FILE))?;
+        Ok(Mailmap::parse(content.as_deref().unwrap_or("")))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the canonical identity of an author given as `Name <email>`.
+    /// Authors without an email or without an entry are returned as is.
+    pub fn map(&self, author: &str) -> String {
+        let (name, email) = match split_author(author) {
+            Some(identity) => identity,
+            None => return author.to_owned(),
+        };
+        let matches = |e: &&MailmapEntry| e.commit_email.eq_ignore_ascii_case(email);
+        // entries with a name are more specific, then later lines win
+        let entries = || self.entries.iter().rev().filter(matches);
+        let entry = entries()
+            .find(|e| e.commit_name.as_ref().is_some_and(|n| n.eq_ignore_ascii_case(name)))
+            .or_else(|| entries().find(|e| e.commit_name.is_none()));
+
+        match entry {
+            Some(e) => format!(
+                "{} <{}>",
+                e.name.as_deref().unwrap_or(name),
+                e.email.as_deref().unwrap_or(email)
+            ),
+            None => author.to_owned(),
+        }
+    }
+}
+
+/// Splits an author into its name and email.
+fn split_author(author: &str) -> Option<(&str, &str)> {
+    let (name, rest) = author.split_once('<')?;
+    let email = rest.strip_suffix('>')?;
+    Some((name.trim(), email))
+}
+
+fn parse_line(line: &str) -> Option<MailmapEntry> {
+    if line.trim_start().starts_with('#') {
+        return None;
+    }
+    let non_empty = |s: &str| Some(s.trim().to_owned()).filter(|s| !s.is_empty());
+    let (name, rest) = line.split_once('<')?;
+    let (email, rest) = rest.split_once('>')?;
+
+    Some(match rest.split_once('<') {
+        Some((commit_name, rest)) => MailmapEntry {
+            name: non_empty(name),
+            email: non_empty(email),
+            commit_name: non_empty(commit_name),
+            commit_email: rest.split_once('>')?.0.trim().to_owned(),
+        },
+        None => MailmapEntry {
+            name: non_empty(name),
+            email: None,
+            commit_name: None,
+            commit_email: email.trim().to_owned(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_authors() {
+        let mailmap = Mailmap::parse(
+            "# comment\n\
+             Proper Name <old@example.com>\n\
+             <new@example.com> <typo@example.com>\n\
+             Jane Doe <jane@example.com> <JANE@laptop>\n\
+             Work Name <work@example.com> Home Name <shared@example.com>\n",
+        );
+
+        assert_eq!(mailmap.map("nick <old@example.com>"), "Proper Name <old@example.com>");
+        assert_eq!(mailmap.map("A <Typo@Example.com>"), "A <new@example.com>");
+        assert_eq!(mailmap.map("jd <jane@laptop>"), "Jane Doe <jane@example.com>");
+        assert_eq!(
+            mailmap.map("home name <shared@example.com>"),
+            "Work Name <work@example.com>"
+        );
+        assert_eq!(mailmap.map("Other <shared@example.com>"), "Other <shared@example.com>");
+        assert_eq!(mailmap.map("G7"), "G7");
+    }
+
+    #[test]
+    fn ignore_invalid_lines() {
+        assert!(Mailmap::parse("no email\n<unterminated\n").is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::repo::bitmap::BitmapIndex;
+use crate::repo::object::Hash;
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use crate::error::{Error, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+/// Number of loose objects above which `maintenance run --auto` runs the
+/// tasks, unless configured in `maintenance.auto`.
+const AUTO_LOOSE_OBJECTS: usize = 6700;
+
+/// Unreachable objects and abandoned push quarantines younger than this are
+/// kept, since a concurrent command may be about to reference them.
+const PRUNE_EXPIRY: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+
+/// What `Repository::maintain` did.
+#[derive(Debug, Default, PartialEq)]
+pub struct MaintenanceReport {
+    /// Number of unreachable objects removed.
+    pub pruned_objects: usize,
+    /// Number of abandoned push quarantines removed.
+    pub pruned_quarantines: usize,
+    /// Number of references moved into the packed references file.
+    pub packed_refs: usize,
+    /// Number of reachability bitmaps written.
+    pub bitmaps: usize,
+}
+
+impl Repository {
+    /// Returns whether the object store has grown enough for maintenance to
+    /// be worth running: more loose objects than `maintenance.auto`, where
+    /// `0` disables automatic maintenance.
+    pub fn needs_maintenance(&self) -> Result<bool> {
+        let config = transport::read_config(self.worktree())?;
+        let limit = match config.get("maintenance.auto") {
+            Some(n) => n.parse().or(Err(Error::ConfigCorrupted))?,
+            None => AUTO_LOOSE_OBJECTS,
+        };
+        Ok(limit > 0 && transport::get_objects(self.storage_dir())?.len() > limit)
+    }
+
+    /// Removes unreachable objects, packs the references, rewrites the
+    /// reachability bitmaps and refreshes the stat cache.
+    pub fn maintain(&self) -> Result<MaintenanceReport> {
+        let (pruned_objects, pruned_quarantines) = self.prune(SystemTime::now() - PRUNE_EXPIRY)?;
+        let report = MaintenanceReport {
+            pruned_objects,
+            pruned_quarantines,
+            packed_refs: self.pack_refs()?,
+            bitmaps: self.write_bitmaps()?,
+        };
+        self.status(&self.head_tree()?)?;
+        Ok(report)
+    }
+
+    /// Removes the loose objects that are not reachable from a reference,
+    /// HEAD, the merge or rebase in progress or the autostash, and the push
+    /// quarantines, that were last modified before `expiry`. Returns how
+    /// many of each were removed.
+    pub fn prune(&self, expiry: SystemTime) -> Result<(usize, usize)> {
+        let reachable = self.reachable()?;
+        let objects_dir = self.storage_dir().join("objects");
+        let mut objects = 0;
+        for name in transport::get_objects(self.storage_dir())? {
+            let path = objects_dir.join(&name);
+            let hash = name.to_str().and_then(|n| n.parse().ok());
+            if hash.is_some_and(|h| reachable.contains(&h)) || !is_older(&path, expiry)? {
+                continue;
+            }
+            fs::remove_file(path)?;
+            objects += 1;
+        }
+
+        let mut quarantines = 0;
+        for entry in fs::read_dir(self.storage_dir())? {
+            let path = entry?.path();
+            let is_quarantine = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with("incoming-"));
+            if is_quarantine && is_older(&path, expiry)? {
+                transport::remove_quarantine(&path)?;
+                quarantines += 1;
+            }
+        }
+        Ok((objects, quarantines))
+    }
+
+    /// Returns the objects reachable from a reference, HEAD, the merge or
+    /// rebase in progress or the autostash, which `prune` keeps.
+    pub(crate) fn reachable(&self) -> Result<HashSet<Hash>> {
+        let mut tips: Vec<Hash> = self.references().iter().map(|(_, h)| h).collect();
+        tips.extend(self.head_hash().ok());
+        tips.extend(transport::read_merge_head(self.worktree())?);
+        tips.extend(self.rebase_state()?.map(|s| s.orig_head));
+        tips.extend(transport::read_autostash(self.worktree())?);
+        let mut reachable = self.reachable_objects(&tips, &BitmapIndex::default())?;
+        for stages in self.conflicts().values() {
+            reachable.extend([stages.base, stages.ours, stages.theirs].iter().flatten());
+        }
+        reachable.extend(self.rerere_preimages()?);
+        Ok(reachable)
+    }
+}
+
+fn is_older(path: &std::path::Path, expiry: SystemTime) -> Result<bool> {
+    Ok(fs::metadata(path)?.modified()? < expiry)
+}
@@ -0,0 +1,157 @@
+use crate::repo::config::Config;
+use crate::error::{Error::*, Result};
+
+/// Rules for commit messages, from the `commit.maxSubjectLength` and
+/// `commit.requireBody` settings.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MessagePolicy {
+    /// Maximum number of characters in the first line.
+    pub max_subject_length: Option<usize>,
+    /// Whether a body must follow the subject.
+    pub require_body: bool,
+}
+
+impl MessagePolicy {
+    pub fn from_config(config: &Config) -> Result<MessagePolicy> {
+        let max_subject_length = match config.get("commit.maxSubjectLength") {
+            Some(len) => Some(len.parse().or(Err(ConfigCorrupted))?),
+            None => None,
+        };
+        Ok(MessagePolicy {
+            max_subject_length,
+            require_body: config.get_bool("commit.requireBody"),
+        })
+    }
+
+    /// Checks a cleaned up message against the rules.
+    pub fn check(&self, msg: &str) -> Result<()> {
+        let mut lines = msg.lines();
+        let subject = lines.next().unwrap_or("");
+        if subject.is_empty() {
+            return Err(InvalidCommitMessage("empty subject".to_owned()));
+        }
+        if let Some(max) = self.max_subject_length {
+            let len = subject.chars().count();
+            if len > max {
+                return Err(InvalidCommitMessage(format!(
+                    "subject is {} characters long, the limit is {}",
+                    len, max
+                )));
+            }
+        }
+        if self.require_body && lines.all(|l| l.trim().is_empty()) {
+            return Err(InvalidCommitMessage("missing body".to_owned()));
+        }
+        Ok(())
+    }
+}
+
+/// Removes trailing whitespace and leading and trailing blank lines from a
+/// message, and comment lines starting with `#` if `strip_comments` is set.
+pub fn clean_message(msg: &str, strip_comments: bool) -> String {
+    let lines: Vec<_> = msg
+        .lines()
+        .filter(|l| !(strip_comments && l.starts_with('#')))
+        .map(str::trim_end)
+        .collect();
+    lines.join("\n").trim_matches('\n').to_owned()
+}
+
+/// Parses the trailers of a message: the `Key: value` lines of its last
+/// paragraph, if every line of the paragraph is one and it is not the
+/// subject.
+pub fn parse_trailers(msg: &str) -> Vec<(String, String)> {
+    let msg = msg.trim_end();
+    let paragraph = match msg.rsplit_once("\n\n") {
+        Some((_, paragraph)) => paragraph,
+        None => return vec![],
+    };
+    paragraph
+        .lines()
+        .map(parse_trailer)
+        .collect::<Option<_>>()
+        .unwrap_or_default()
+}
+
+fn parse_trailer(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once(": ")?;
+    let valid_key = !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !valid_key {
+        return None;
+    }
+    Some((key.to_owned(), value.trim().to_owned()))
+}
+
+/// Appends a trailer to a message, after its existing trailers if it has
+/// any. Does nothing if the message ends with the same trailer.
+pub fn append_trailer(msg: &str, key: &str, value: &str) -> String {
+    let msg = msg.trim_end();
+    let trailer = format!("{}: {}", key, value);
+    let trailers = parse_trailers(msg);
+
+    if trailers.last() == Some(&(key.to_owned(), value.to_owned())) {
+        msg.to_owned()
+    } else if trailers.is_empty() {
+        format!("{}\n\n{}", msg, trailer)
+    } else {
+        format!("{}\n{}", msg, trailer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clean() {
+        assert_eq!(
+            clean_message("\n# comment\nsubject  \n\nbody\n\n", true),
+            "subject\n\nbody"
+        );
+        assert_eq!(clean_message("# only a comment\n", true), "");
+        assert_eq!(clean_message("#1 fix\n", false), "#1 fix");
+    }
+
+    #[test]
+    fn policy() {
+        let policy = MessagePolicy {
+            max_subject_length: Some(10),
+            require_body: true,
+        };
+        assert!(policy.check("subject\n\nbody").is_ok());
+        assert!(policy.check("").is_err());
+        assert!(policy.check("a long subject\n\nbody").is_err());
+        assert!(policy.check("subject\n\n").is_err());
+        assert!(MessagePolicy::default().check("subject").is_ok());
+    }
+
+    #[test]
+    fn trailers() {
+        let msg = "subject\n\nbody\n\nSigned-off-by: G7 <g7@example.com>\nFixes: #12\n";
+        assert_eq!(
+            parse_trailers(msg),
+            vec![
+                ("Signed-off-by".to_owned(), "G7 <g7@example.com>".to_owned()),
+                ("Fixes".to_owned(), "#12".to_owned()),
+            ]
+        );
+        assert!(parse_trailers("Fixes: #12").is_empty());
+        assert!(parse_trailers("subject\n\nnot a trailer\nFixes: #12").is_empty());
+    }
+
+    #[test]
+    fn append() {
+        assert_eq!(
+            append_trailer("subject", "Fixes", "#1"),
+            "subject\n\nFixes: #1"
+        );
+        assert_eq!(
+            append_trailer("subject\n\nFixes: #1\n", "Fixes", "#2"),
+            "subject\n\nFixes: #1\nFixes: #2"
+        );
+        assert_eq!(
+            append_trailer("subject\n\nFixes: #1", "Fixes", "#1"),
+            "subject\n\nFixes: #1"
+        );
+    }
+}
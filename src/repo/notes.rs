@@ -0,0 +1,148 @@
+use crate::error::Result;
+use crate::repo::object::{Commit, CommitInfo, Hash, Tree};
+use crate::repo::refs;
+use crate::repo::repository::{RefUpdate, Repository};
+use crate::storage::transport;
+use chrono::{SecondsFormat, Utc};
+use std::path::Path;
+
+/// Config key naming the notes reference that pushes are recorded in.
+pub const PUSH_NOTES_KEY: &str = "push.notes";
+
+/// Returns the full name of a notes reference, e.g. `refs/notes/ci` for
+/// `ci`, and checks that it is a valid name.
+pub fn notes_ref(name: &str) -> Result<String> {
+    let name = name.strip_prefix(refs::NOTES).unwrap_or(name);
+    refs::check_new_name(name)?;
+    Ok(format!("{}{}", refs::NOTES, name))
+}
+
+/// Returns the note recording that a commit was pushed to a remote branch:
+/// where, what the branch was before and after, as read back from the
+/// remote once it was updated, and when.
+pub fn push_note(remote: &Path, update: &RefUpdate, acknowledged: Option<Hash>) -> String {
+    let hash = |h: Option<Hash>| h.map_or_else(|| "0".repeat(40), |h| h.to_string());
+    format!(
+        "Pushed-To: {}\nRef: {}\nOld: {}\nNew: {}\nRemote-State: {}\nDate: {}\n",
+        remote.display(),
+        refs::branch_ref(&update.name),
+        hash(update.old),
+        update.new,
+        hash(acknowledged),
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
+impl Repository {
+    /// Returns the tree of a notes reference, which has a blob named after
+    /// each annotated object, or None if it has no notes yet.
+    fn notes_tree(&self, notes: &str) -> Result<Option<Tree>> {
+        match self.references().get(notes) {
+            Some(hash) => Ok(Some(transport::read_commit(hash)?.tree()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the note on an object in a notes reference, if any.
+    pub fn note(&self, notes: &str, object: Hash) -> Result<Option<String>> {
+        let tree = match self.notes_tree(notes)? {
+            Some(tree) => tree,
+            None => return Ok(None),
+        };
+        match tree.entries().iter().find(|e| e.name() == object.to_string()) {
+            Some(entry) => {
+                let blob = transport::read_blob(entry.hash())?;
+                Ok(Some(String::from_utf8_lossy(blob.content()).into_owned()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Sets the note on an object, or appends to it, and records the notes
+    /// in a new commit of the notes reference.
+    pub fn add_note(&mut self, notes: &str, object: Hash, text: &str, append: bool) -> Result<()> {
+        let parent = self.references().get(notes);
+        let mut text = text.to_owned();
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        if append {
+            if let Some(old) = self.note(notes, object)? {
+                text = format!("{}\n{}", old, text);
+            }
+        }
+
+        let blob = transport::write_blob_data(text.into_bytes())?;
+        let name = object.to_string();
+        let mut tree = Tree::new();
+        if let Some(old) = self.notes_tree(notes)? {
+            for entry in old.entries().iter().filter(|e| e.name() != name) {
+                tree.add_blob(entry.hash(), entry.name().to_owned());
+            }
+        }
+        tree.add_blob(blob.hash(), name);
+        transport::write_tree(&mut tree)?;
+
+        let mut commit = Commit::new(CommitInfo {
+            tree: tree.hash(),
+            parents: parent.into_iter().collect(),
+            author: self.identity()?,
+            time: Utc::now(),
+            msg: format!("Notes added to {}", object),
+        });
+        transport::write_commit(&mut commit)?;
+        self.set_reference(notes, commit.hash())
+    }
+
+    /// Returns the notes reference named by `push.notes` that pushes are
+    /// recorded in, if set.
+    pub(crate) fn push_notes(&self) -> Result<Option<String>> {
+        let config = transport::read_config(self.worktree())?;
+        config.get(PUSH_NOTES_KEY).map(notes_ref).transpose()
+    }
+
+    /// Records the branches updated by a push as notes on the pushed
+    /// commits, in the notes reference `notes`.
+    pub(crate) fn record_push(
+        &mut self,
+        notes: &str,
+        remote: &Repository,
+        updates: &[RefUpdate],
+    ) -> Result<()> {
+        for update in updates.iter().filter(|u| u.old != Some(u.new)) {
+            let acknowledged = remote.branch(&update.name).ok();
+            let note = push_note(remote.worktree(), update, acknowledged);
+            self.add_note(notes, update.new, &note, true)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    #[test]
+    fn notes_test() {
+        let mut t = TestRepo::new();
+        let first = t.commit_file("a.txt", "first").hash();
+        let second = t.commit_file("a.txt", "second").hash();
+        let notes = notes_ref("ci").unwrap();
+        assert_eq!(notes, "refs/notes/ci");
+        assert!(notes_ref("a..b").is_err());
+        assert_eq!(t.repo.note(&notes, first).unwrap(), None);
+
+        t.repo.add_note(&notes, first, "build passed", false).unwrap();
+        t.repo.add_note(&notes, second, "build failed", false).unwrap();
+        t.repo.add_note(&notes, second, "retried", true).unwrap();
+        assert_eq!(t.repo.note(&notes, first).unwrap().unwrap(), "build passed\n");
+        assert_eq!(t.repo.note(&notes, second).unwrap().unwrap(), "build failed\n\nretried\n");
+
+        t.repo.add_note(&notes, first, "replaced", false).unwrap();
+        assert_eq!(t.repo.note(&notes, first).unwrap().unwrap(), "replaced\n");
+        let head = transport::read_commit(t.repo.references().get(&notes).unwrap()).unwrap();
+        assert_eq!(head.tree().unwrap().entries().len(), 2);
+        assert_eq!(head.parent().unwrap().unwrap().tree().unwrap().entries().len(), 2);
+    }
+}
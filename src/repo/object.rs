@@ -1,9 +1,9 @@
-use crate::storage::serialize::serialize_blob;
+use crate::repo::message;
 use crate::storage::transport;
-use crate::wd::ui::{Error::*, Result};
-use chrono::{DateTime, Utc};
+use crate::error::{Error, Error::*, Result};
+use chrono::{DateTime, TimeZone, Utc};
 use sha1::{self, Sha1};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs;
@@ -12,7 +12,7 @@ use std::result;
 use std::str;
 use std::vec;
 
-const MAX_TREE_DEPTH: usize = 1024;
+pub(crate) const MAX_TREE_DEPTH: usize = 1024;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct Hash(sha1::Digest);
@@ -21,7 +21,7 @@ pub struct Hash(sha1::Digest);
 pub struct Commit {
     hash: Hash,
     tree: Hash,
-    parent: Option<Hash>,
+    parents: Vec<Hash>,
     author: String,
     time: DateTime<Utc>,
     msg: String,
@@ -31,13 +31,15 @@ pub struct Commit {
 #[derive(Debug, PartialEq)]
 pub struct CommitInfo {
     pub tree: Hash,
-    pub parent: Option<Hash>,
+    /// The parent commits, the first one being the previous commit of the
+    /// branch and the others merged commits.
+    pub parents: Vec<Hash>,
     pub author: String,
     pub time: DateTime<Utc>,
     pub msg: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Tree {
     hash: Hash,
     entries: Vec<TreeEntry>,
@@ -48,6 +50,8 @@ pub struct TreeEntry {
     kind: TreeEntryKind,
     hash: Hash,
     name: String,
+    /// Whether a blob is checked out as an executable file.
+    executable: bool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -61,6 +65,7 @@ pub enum TreeEntryKind {
 pub struct File {
     pub path: PathBuf,
     pub hash: Hash,
+    pub executable: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -68,14 +73,20 @@ pub enum Change {
     Add(ChangeEntry),
     Remove(ChangeEntry),
     Modify(ChangeEntry, ChangeEntry),
+    /// A file moved to another path with the same contents.
+    Rename(ChangeEntry, ChangeEntry),
+    /// A path that changed from a file to a directory or the reverse.
+    TypeChange(ChangeEntry, ChangeEntry),
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ChangeEntry {
     /// A stored file object.
     File(File),
-    /// A working tree path.
-    Path(PathBuf),
+    /// A working tree path, and the file on disk it names.
+    Path(PathBuf, PathBuf),
+    /// A directory, whose files are separate changes.
+    Dir(PathBuf),
 }
 
 /// The hashed contents of a file.
@@ -90,11 +101,24 @@ impl Hash {
         Hash(Sha1::new().digest())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "hash", level = "trace", skip_all))]
     pub fn update(&mut self, data: &[u8]) {
         self.0 = Sha1::from(data).digest()
     }
 }
 
+impl From<sha1::Digest> for Hash {
+    fn from(digest: sha1::Digest) -> Hash {
+        Hash(digest)
+    }
+}
+
+impl Default for Hash {
+    fn default() -> Hash {
+        Hash::new()
+    }
+}
+
 impl fmt::Display for Hash {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.0)
@@ -105,6 +129,11 @@ impl str::FromStr for Hash {
     type Err = sha1::DigestParseError;
 
     fn from_str(s: &str) -> result::Result<Hash, sha1::DigestParseError> {
+        // sha1 slices the string by bytes and accepts signs, so only
+        // pass it hex digits, or an empty string to get its error
+        if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return "".parse().map(Hash);
+        }
         Ok(Hash(s.parse()?))
     }
 }
@@ -114,7 +143,7 @@ impl Commit {
         Commit {
             hash: Hash::new(),
             tree: info.tree,
-            parent: info.parent,
+            parents: info.parents,
             author: info.author,
             time: info.time,
             msg: info.msg,
@@ -138,12 +167,22 @@ impl Commit {
         transport::read_tree(self.tree)
     }
 
+    /// Returns the first parent.
     pub fn parent_hash(&self) -> Option<Hash> {
-        self.parent
+        self.parents.first().copied()
+    }
+
+    pub fn parent_hashes(&self) -> &[Hash] {
+        &self.parents
     }
 
+    pub fn is_merge(&self) -> bool {
+        self.parents.len() > 1
+    }
+
+    /// Reads the first parent.
     pub fn parent(&self) -> Option<Result<Commit>> {
-        self.parent.map(transport::read_commit)
+        self.parent_hash().map(transport::read_commit)
     }
 
     pub fn author(&self) -> &str {
@@ -158,32 +197,36 @@ impl Commit {
         &self.msg
     }
 
-    pub fn into_iter(self) -> CommitIter {
-        CommitIter { commit: Some(self) }
+    /// Returns the `Key: value` trailers at the end of the message.
+    pub fn trailers(&self) -> Vec<(String, String)> {
+        message::parse_trailers(&self.msg)
     }
 
-    pub fn into_common_ancestor(self, other: Commit) -> Result<Commit> {
-        let mut ita = self.into_iter();
-        let mut itb = other.into_iter();
-        let mut amap = HashMap::new();
-        let mut bmap = HashMap::new();
-
-        loop {
-            match ita.next().transpose()? {
-                Some(c) if bmap.contains_key(&c.hash) => return Ok(c),
-                Some(c) => {
-                    amap.insert(c.hash, c);
+    /// Returns the most recent ancestor of both commits, or None if their
+    /// histories are unrelated. Both histories are walked breadth first
+    /// through all parents, one commit at a time.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn into_common_ancestor(self, other: Commit) -> Result<Option<Commit>> {
+        let mut queues = [VecDeque::from([self]), VecDeque::from([other])];
+        let mut seen = [HashSet::new(), HashSet::new()];
+
+        while !queues[0].is_empty() || !queues[1].is_empty() {
+            for side in 0..2 {
+                let commit = match queues[side].pop_front() {
+                    Some(c) => c,
+                    None => continue,
+                };
+                if seen[1 - side].contains(&commit.hash) {
+                    return Ok(Some(commit));
                 }
-                None => (),
-            }
-            match itb.next().transpose()? {
-                Some(c) if amap.contains_key(&c.hash) => return Ok(c),
-                Some(c) => {
-                    bmap.insert(c.hash, c);
+                if seen[side].insert(commit.hash) {
+                    for &parent in &commit.parents {
+                        queues[side].push_back(transport::read_commit(parent)?);
+                    }
                 }
-                None => (),
             }
         }
+        Ok(None)
     }
 }
 
@@ -191,19 +234,28 @@ impl fmt::Display for Commit {
     /// Formats a commit object in a format suitable for serialization.
     ///
     /// tree <tree hash>
-    /// [parent <parent hash>]
+    /// [parent <parent hash>]...
     /// author <author name>
     /// time <timestamp>
     ///
     /// <commit message>
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "tree {}\n", self.tree)?;
+        writeln!(f, "tree {}", self.tree)?;
 
-        if let Some(parent) = self.parent {
-            write!(f, "parent {}\n", parent)?;
+        for parent in &self.parents {
+            writeln!(f, "parent {}", parent)?;
         }
-        write!(f, "author {}\n", self.author)?;
-        write!(f, "time {}\n\n{}\n", self.time.timestamp_millis(), self.msg)
+        writeln!(f, "author {}", self.author)?;
+        writeln!(f, "time {}\n\n{}", self.time.timestamp_millis(), self.msg)
+    }
+}
+
+impl IntoIterator for Commit {
+    type Item = Result<Commit>;
+    type IntoIter = CommitIter;
+
+    fn into_iter(self) -> CommitIter {
+        CommitIter { commit: Some(self) }
     }
 }
 
@@ -222,7 +274,7 @@ impl Iterator for CommitIter {
             Some(Err(err)) => return Some(Err(err)),
             None => self.commit = None,
         }
-        return Some(Ok(out_commit));
+        Some(Ok(out_commit))
     }
 }
 
@@ -257,10 +309,16 @@ impl Tree {
 
     /// Add a blob entry with the given hash and filename.
     pub fn add_blob(&mut self, hash: Hash, name: String) {
+        self.add_file(hash, name, false)
+    }
+
+    /// Add a blob entry with the given hash, filename and mode.
+    pub fn add_file(&mut self, hash: Hash, name: String, executable: bool) {
         self.entries.push(TreeEntry {
             kind: TreeEntryKind::Blob,
             hash,
             name,
+            executable,
         })
     }
 
@@ -270,9 +328,41 @@ impl Tree {
             kind: TreeEntryKind::Tree,
             hash,
             name,
+            executable: false,
         })
     }
 
+    /// Checks if two trees have the same entries, regardless of their order.
+    pub fn same_entries(&self, other: &Tree) -> bool {
+        fn sorted(t: &Tree) -> Vec<&TreeEntry> {
+            let mut entries: Vec<_> = t.entries.iter().collect();
+            entries.sort_unstable_by_key(|e| e.name());
+            entries
+        }
+        sorted(self) == sorted(other)
+    }
+
+    /// Returns the subtree with the given name, if there is a readable one.
+    pub fn subtree(&self, name: &str) -> Option<Tree> {
+        self.dir(OsStr::new(name)).ok()
+    }
+
+    /// Checks if the tree has a directory at a path.
+    pub fn has_dir<P: AsRef<Path>>(&self, path: P) -> bool {
+        let parts: Vec<_> = path.as_ref().iter().collect();
+        self.find_entry(&parts).is_ok_and(|e| e.kind() == TreeEntryKind::Tree)
+    }
+
+    /// Returns the directory at a path in the tree, or the tree itself for
+    /// an empty path.
+    pub fn tree_at<P: AsRef<Path>>(&self, path: P) -> Result<Tree> {
+        let mut tree = self.clone();
+        for name in path.as_ref().iter() {
+            tree = tree.dir(name)?;
+        }
+        Ok(tree)
+    }
+
     /// Returns a file given its path in the tree.
     pub fn file<P: AsRef<Path>>(&self, path: P) -> Result<File> {
         let path = path.as_ref();
@@ -280,7 +370,11 @@ impl Tree {
 
         self.find_entry(&parts).and_then(|e| match e.kind() {
             TreeEntryKind::Tree => Err(FileNotFound),
-            TreeEntryKind::Blob => Ok(File::new(path.into(), e.hash())),
+            TreeEntryKind::Blob => Ok(File {
+                path: path.into(),
+                hash: e.hash(),
+                executable: e.is_executable(),
+            }),
         })
     }
 
@@ -313,40 +407,134 @@ impl Tree {
     pub fn files(&self) -> FileIter {
         FileIter {
             stack: vec![self.entries.clone().into_iter()],
+            trees: vec![self.hash],
             path: PathBuf::new(),
         }
     }
 
     /// Returns the changes between this tree and the provided one.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn diff(&self, to: &Tree) -> Result<Vec<Change>> {
-        // This could be much faster if we pruned directories with equal hashes.
+        self.diff_paths(to, &[])
+    }
+
+    /// Returns the changes between this tree and the provided one to files
+    /// at or below the given paths, or to all files if there are none.
+    /// Directories with the same hash in both trees, and those outside of
+    /// the paths, are not read.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn diff_paths(&self, to: &Tree, paths: &[PathBuf]) -> Result<Vec<Change>> {
         let mut changes = vec![];
-        let mut to_files = HashMap::new();
-
-        for f in to.files() {
-            let f = f?;
-            to_files.insert(f.path.clone(), f);
-        }
-        for from in self.files() {
-            let from = from?;
-            let change = match to_files.remove(&from.path) {
-                Some(to) if from.hash != to.hash => Change::new_modify(from, to),
-                Some(_) => continue,
-                None => Change::new_remove(from),
+        let mut trees = [vec![self.hash], vec![to.hash]];
+        diff_dirs([self, to], Path::new(""), paths, &mut trees, &mut changes)?;
+        // added files come last, as in a walk of the old tree then the new one
+        changes.sort_by_key(|c| matches!(c, Change::Add(_)));
+        Ok(detect_type_changes(changes))
+    }
+}
+
+/// Adds the changes between two versions of the directory at `dir` to
+/// `changes`, reading only the subdirectories whose hashes differ. `trees`
+/// holds the hashes of the directories above on each side, to detect cycles.
+fn diff_dirs(
+    dirs: [&Tree; 2],
+    dir: &Path,
+    paths: &[PathBuf],
+    trees: &mut [Vec<Hash>; 2],
+    changes: &mut Vec<Change>,
+) -> Result<()> {
+    // the entries of both sides by name, in the order of the old side
+    let mut entries: Vec<(&str, [Option<&TreeEntry>; 2])> = vec![];
+    let mut index = HashMap::new();
+    for (side, tree) in dirs.iter().enumerate() {
+        for entry in &tree.entries {
+            check_entry_name(entry.name())?;
+            let i = *index.entry(entry.name()).or_insert_with(|| {
+                entries.push((entry.name(), [None, None]));
+                entries.len() - 1
+            });
+            entries[i].1[side] = Some(entry);
+        }
+    }
+
+    let empty = Tree::new();
+    for (name, [old, new]) in entries {
+        let path = dir.join(name);
+        let is_file = |e: &&TreeEntry| e.kind() == TreeEntryKind::Blob;
+        let file = |e: &TreeEntry| File {
+            path: path.clone(),
+            hash: e.hash(),
+            executable: e.is_executable(),
+        };
+        if paths.is_empty() || paths.iter().any(|p| path.starts_with(p)) {
+            match (old.filter(is_file), new.filter(is_file)) {
+                (Some(a), Some(b)) if a.hash() == b.hash() => {
+                    if a.is_executable() != b.is_executable() {
+                        changes.push(Change::new_modify(file(a), file(b)))
+                    }
+                }
+                (Some(a), Some(b)) => changes.push(Change::new_modify(file(a), file(b))),
+                (Some(a), None) => changes.push(Change::new_remove(file(a))),
+                (None, Some(b)) => changes.push(Change::new_add(file(b))),
+                (None, None) => (),
+            }
+        }
+
+        let subdirs = [old, new].map(|e| e.filter(|e| e.kind() == TreeEntryKind::Tree));
+        let selected = paths.is_empty()
+            || paths.iter().any(|p| path.starts_with(p) || p.starts_with(&path));
+        if subdirs == [None, None] || subdirs[0] == subdirs[1] || !selected {
+            continue;
+        }
+        let mut read = [None, None];
+        for (side, entry) in subdirs.iter().enumerate() {
+            let hash = match entry {
+                Some(entry) => entry.hash(),
+                None => continue,
+            };
+            if trees[side].contains(&hash) {
+                return Err(TreeCycle(hash));
+            }
+            if trees[side].len() >= MAX_TREE_DEPTH {
+                return Err(TreeTooDeep(path));
+            }
+            read[side] = match transport::read_tree(hash) {
+                Err(ObjectNotFound) => return Err(ObjectMissing),
+                r => Some(r?),
             };
-            changes.push(change)
         }
-        for to in to_files.into_values() {
-            changes.push(Change::new_add(to))
+        for (side, entry) in subdirs.iter().enumerate() {
+            if let Some(entry) = entry {
+                trees[side].push(entry.hash());
+            }
+        }
+        let [a, b] = &read;
+        diff_dirs(
+            [a.as_ref().unwrap_or(&empty), b.as_ref().unwrap_or(&empty)],
+            &path,
+            paths,
+            trees,
+            changes,
+        )?;
+        for (side, entry) in subdirs.iter().enumerate() {
+            if entry.is_some() {
+                trees[side].pop();
+            }
         }
-        Ok(changes)
+    }
+    Ok(())
+}
+
+impl Default for Tree {
+    fn default() -> Tree {
+        Tree::new()
     }
 }
 
 impl fmt::Display for Tree {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for entry in &self.entries {
-            write!(f, "{} {}\t{}\n", entry.kind(), entry.hash(), entry.name())?
+            writeln!(f, "{} {}\t{}", entry.type_name(), entry.hash(), entry.name())?
         }
         Ok(())
     }
@@ -364,6 +552,20 @@ impl TreeEntry {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    /// Returns the type stored in tree objects: `blob`, `exec` for
+    /// executable blobs, or `tree`.
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            TreeEntryKind::Blob if self.executable => "exec",
+            TreeEntryKind::Blob => "blob",
+            TreeEntryKind::Tree => "tree",
+        }
+    }
 }
 
 impl fmt::Display for TreeEntryKind {
@@ -377,7 +579,11 @@ impl fmt::Display for TreeEntryKind {
 
 impl File {
     pub fn new(path: PathBuf, hash: Hash) -> File {
-        File { path, hash }
+        File {
+            path,
+            hash,
+            executable: false,
+        }
     }
 
     pub fn contents(&self) -> Result<Vec<u8>> {
@@ -389,36 +595,57 @@ impl File {
 #[derive(Debug)]
 pub struct FileIter {
     stack: Vec<vec::IntoIter<TreeEntry>>,
+    /// Hashes of the trees on the stack, to detect cycles.
+    trees: Vec<Hash>,
     path: PathBuf,
 }
 
+impl FileIter {
+    /// Ends the iteration with an error.
+    fn fail(&mut self, err: Error) -> Option<Result<File>> {
+        self.stack.clear();
+        Some(Err(err))
+    }
+}
+
 impl Iterator for FileIter {
     type Item = Result<File>;
 
     fn next(&mut self) -> Option<Result<File>> {
         loop {
-            // infinite loop?
-            assert!(self.stack.len() <= MAX_TREE_DEPTH);
-
             let entry = match self.stack.last_mut()?.next() {
                 None => {
                     // end of current tree
                     self.stack.pop();
+                    self.trees.pop();
                     self.path.pop();
                     continue;
                 }
                 Some(entry) => entry,
             };
+            if let Err(err) = check_entry_name(entry.name()) {
+                return Some(Err(err));
+            }
             match entry.kind() {
                 TreeEntryKind::Blob => {
-                    let path = self.path.join(entry.name());
-                    return Some(Ok(File::new(path, entry.hash())));
+                    return Some(Ok(File {
+                        path: self.path.join(entry.name()),
+                        hash: entry.hash(),
+                        executable: entry.is_executable(),
+                    }));
+                }
+                TreeEntryKind::Tree if self.trees.contains(&entry.hash()) => {
+                    return self.fail(TreeCycle(entry.hash()));
+                }
+                TreeEntryKind::Tree if self.stack.len() >= MAX_TREE_DEPTH => {
+                    return self.fail(TreeTooDeep(self.path.join(entry.name())));
                 }
                 TreeEntryKind::Tree => match transport::read_tree(entry.hash()) {
                     Err(ObjectNotFound) => return Some(Err(ObjectMissing)),
                     Err(err) => return Some(Err(err)),
                     Ok(tree) => {
-                        self.stack.push(tree.into_entries().into_iter());
+                        self.stack.push(tree.into_entries());
+                        self.trees.push(entry.hash());
                         self.path.push(entry.name());
                         continue;
                     }
@@ -428,6 +655,20 @@ impl Iterator for FileIter {
     }
 }
 
+/// Checks that a tree entry name is a single path component that stays
+/// inside the working tree: not empty, `.`, `..` or `.gnew`, and without
+/// separators. Trees with other names could write anywhere on checkout.
+pub fn check_entry_name(name: &str) -> Result<()> {
+    let unsafe_name = matches!(name, "" | "." | "..")
+        || name.eq_ignore_ascii_case(".gnew")
+        || name.contains(['/', '\\', '\0'])
+        || Path::new(name).is_absolute();
+    if unsafe_name {
+        return Err(UnsafePath(name.to_owned()));
+    }
+    Ok(())
+}
+
 impl Change {
     pub fn new_add<T: Into<ChangeEntry>>(new: T) -> Change {
         Change::Add(new.into())
@@ -445,40 +686,234 @@ impl Change {
         Change::Modify(old.into(), new.into())
     }
 
-    /// Returns the path of the changed file.
+    /// Returns the path of the changed file, or the new path of a renamed one.
     pub fn path(&self) -> &Path {
         match self {
             Change::Add(e) => e,
             Change::Remove(e) => e,
             Change::Modify(e, _) => e,
+            Change::Rename(_, e) => e,
+            Change::TypeChange(e, _) => e,
         }
         .path()
     }
 
+    /// Returns the letter identifying the kind of change: A, D, M, R or T.
+    pub fn status(&self) -> char {
+        match self {
+            Change::Add(_) => 'A',
+            Change::Remove(_) => 'D',
+            Change::Modify(..) => 'M',
+            Change::Rename(..) => 'R',
+            Change::TypeChange(..) => 'T',
+        }
+    }
+
+    /// Returns the old and new executable bits of a modified or renamed file
+    /// whose mode changed.
+    pub fn mode_change(&self) -> Option<(bool, bool)> {
+        match self {
+            Change::Modify(old, new) | Change::Rename(old, new) => {
+                Some((old.is_executable()?, new.is_executable()?)).filter(|(a, b)| a != b)
+            }
+            _ => None,
+        }
+    }
+
     /// Returns the old and new contents.
     pub fn contents(&self) -> Result<(Vec<u8>, Vec<u8>)> {
         Ok(match self {
             Change::Add(new) => (vec![], new.contents()?),
             Change::Remove(old) => (old.contents()?, vec![]),
             Change::Modify(old, new) => (old.contents()?, new.contents()?),
+            Change::Rename(old, new) => (old.contents()?, new.contents()?),
+            Change::TypeChange(old, new) => (old.contents()?, new.contents()?),
+        })
+    }
+}
+
+/// Replaces the removal of a file at a path where files were added under a
+/// directory, or the reverse, with a type change of the path. The changes of
+/// the files in the directory are kept.
+pub fn detect_type_changes(changes: Vec<Change>) -> Vec<Change> {
+    // A path is never both added and removed, so any match is below it.
+    let is_dir = |path: &Path, paths: &[PathBuf]| paths.iter().any(|p| p.starts_with(path));
+    let (mut added, mut removed) = (vec![], vec![]);
+
+    for change in &changes {
+        match change {
+            Change::Add(new) => added.push(new.path().to_owned()),
+            Change::Remove(old) => removed.push(old.path().to_owned()),
+            _ => (),
+        }
+    }
+
+    changes
+        .into_iter()
+        .map(|change| match change {
+            Change::Remove(old) if is_dir(old.path(), &added) => {
+                let dir = ChangeEntry::Dir(old.path().to_owned());
+                Change::TypeChange(old, dir)
+            }
+            Change::Add(new) if is_dir(new.path(), &removed) => {
+                Change::TypeChange(ChangeEntry::Dir(new.path().to_owned()), new)
+            }
+            change => change,
+        })
+        .collect()
+}
+
+/// Replaces each removed file whose contents were added at another path
+/// with a rename.
+pub fn detect_renames(changes: Vec<Change>) -> Result<Vec<Change>> {
+    let mut removed = HashMap::new();
+    let mut rest = vec![];
+
+    for change in changes {
+        match change {
+            Change::Remove(old) => {
+                removed
+                    .entry(old.hash()?)
+                    .or_insert_with(Vec::new)
+                    .push(old);
+            }
+            change => rest.push(change),
+        }
+    }
+    let mut result = vec![];
+    for change in rest {
+        let change = match change {
+            Change::Add(new) => match removed.get_mut(&new.hash()?).and_then(Vec::pop) {
+                Some(old) => Change::Rename(old, new),
+                None => Change::Add(new),
+            },
+            change => change,
+        };
+        result.push(change);
+    }
+    result.extend(removed.into_values().flatten().map(Change::Remove));
+    Ok(result)
+}
+
+/// Returns the directories moved to another path, as `(old, new)` pairs,
+/// given changes with renames detected and whether a directory exists after
+/// them. A directory is moved if it no longer exists and most of the files
+/// renamed out of it went to the same directory, keeping their names.
+/// Moves implied by the move of a parent directory are left out.
+pub fn detect_dir_renames<F>(changes: &[Change], exists: F) -> Vec<(PathBuf, PathBuf)>
+where
+    F: Fn(&Path) -> bool,
+{
+    let mut votes: BTreeMap<&Path, HashMap<&Path, usize>> = BTreeMap::new();
+    for change in changes {
+        let (old, new) = match change {
+            Change::Rename(old, new) if old.path().file_name() == new.path().file_name() => {
+                (old.path().parent().unwrap(), new.path().parent().unwrap())
+            }
+            _ => continue,
+        };
+        if old != new && !old.as_os_str().is_empty() {
+            *votes.entry(old).or_default().entry(new).or_default() += 1;
+        }
+    }
+
+    let mut renames = vec![];
+    for (old, targets) in votes {
+        if exists(old) {
+            continue;
+        }
+        let mut targets: Vec<_> = targets.into_iter().collect();
+        targets.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        // a tie between two directories is ambiguous
+        if targets.get(1).is_none_or(|t| t.1 < targets[0].1) {
+            renames.push((old.to_owned(), targets[0].0.to_owned()));
+        }
+    }
+    let implied = |(old, new): &(PathBuf, PathBuf)| {
+        renames.iter().any(|(o, n)| {
+            o != old && old.strip_prefix(o).is_ok_and(|rest| *new == n.join(rest))
         })
+    };
+    renames.iter().filter(|r| !implied(r)).cloned().collect()
+}
+
+/// Returns the path of a file after the moves of directories, if one of
+/// them moved it.
+pub fn renamed_path(path: &Path, renames: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    renames
+        .iter()
+        .find_map(|(old, new)| Some(new.join(path.strip_prefix(old).ok()?)))
+}
+
+/// Replaces each file removed from a moved directory and added at its path
+/// in the new directory with a rename, which may have changed contents.
+pub fn apply_dir_renames(changes: Vec<Change>, renames: &[(PathBuf, PathBuf)]) -> Vec<Change> {
+    let mut removed = HashMap::new();
+    let mut rest = vec![];
+    for change in changes {
+        match change {
+            Change::Remove(old) => match renamed_path(old.path(), renames) {
+                Some(path) => {
+                    removed.insert(path, old);
+                }
+                None => rest.push(Change::Remove(old)),
+            },
+            change => rest.push(change),
+        }
     }
+    let mut result: Vec<_> = rest
+        .into_iter()
+        .map(|change| match change {
+            Change::Add(new) => match removed.remove(new.path()) {
+                Some(old) => Change::Rename(old, new),
+                None => Change::Add(new),
+            },
+            change => change,
+        })
+        .collect();
+    result.extend(removed.into_values().map(Change::Remove));
+    result
 }
 
 impl ChangeEntry {
     pub fn path(&self) -> &Path {
         match self {
             ChangeEntry::File(f) => &f.path,
-            ChangeEntry::Path(p) => p,
+            ChangeEntry::Path(p, _) | ChangeEntry::Dir(p) => p,
         }
     }
 
+    /// Returns the hash of the contents, reading working tree paths.
+    pub fn hash(&self) -> Result<Hash> {
+        match self {
+            ChangeEntry::File(f) => Ok(f.hash),
+            ChangeEntry::Path(_, file) => hash_file(file),
+            ChangeEntry::Dir(_) => Err(FileNotFound),
+        }
+    }
+
+    /// Returns the contents, which are empty for directories.
     pub fn contents(&self) -> Result<Vec<u8>> {
         Ok(match self {
             ChangeEntry::File(f) => f.contents()?,
-            ChangeEntry::Path(p) => fs::read(p)?,
+            ChangeEntry::Path(_, file) => fs::read(file)?,
+            ChangeEntry::Dir(_) => vec![],
         })
     }
+
+    /// Returns whether the file is executable, reading working tree paths.
+    /// None if the file system of a working tree path has no permissions.
+    pub fn is_executable(&self) -> Option<bool> {
+        match self {
+            ChangeEntry::File(f) => Some(f.executable),
+            ChangeEntry::Path(_, file) => executable_bit(file),
+            ChangeEntry::Dir(_) => Some(false),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        matches!(self, ChangeEntry::Dir(_))
+    }
 }
 
 impl From<File> for ChangeEntry {
@@ -487,12 +922,6 @@ impl From<File> for ChangeEntry {
     }
 }
 
-impl From<PathBuf> for ChangeEntry {
-    fn from(p: PathBuf) -> ChangeEntry {
-        ChangeEntry::Path(p)
-    }
-}
-
 impl Blob {
     pub fn new(content: Vec<u8>) -> Blob {
         Blob {
@@ -523,7 +952,173 @@ impl From<Blob> for Vec<u8> {
 
 /// Computes the hash for a blob object with the contents of a file.
 pub fn hash_file<P: AsRef<Path>>(path: P) -> Result<Hash> {
-    let mut blob = Blob::new(fs::read(path)?);
-    serialize_blob(&mut blob);
-    Ok(blob.hash())
+    Ok(hash_blob(&fs::read(path)?))
+}
+
+/// Checks if a working tree file has an execute permission bit set.
+/// Returns None where the file system has no such permission.
+pub fn executable_bit<P: AsRef<Path>>(path: P) -> Option<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        None
+    }
+}
+
+/// Sets the execute permission bits of a file the way `chmod +x` or `-x`
+/// would, keeping the read and write bits.
+pub fn set_executable<P: AsRef<Path>>(path: P, executable: bool) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = fs::metadata(&path)?.permissions();
+        let mode = perms.mode();
+        // give execute permission to whoever can read the file
+        let mode = match executable {
+            true => mode | (mode & 0o444) >> 2,
+            false => mode & !0o111,
+        };
+        if mode != perms.mode() {
+            perms.set_mode(mode);
+            fs::set_permissions(path, perms)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, executable);
+    Ok(())
+}
+
+/// Computes the hash of a blob with the given contents, without building
+/// the serialized object.
+#[cfg_attr(feature = "tracing", tracing::instrument(name = "hash", level = "trace", skip_all))]
+pub fn hash_blob(data: &[u8]) -> Hash {
+    let mut sha = Sha1::new();
+    sha.update(b"blob\0");
+    sha.update(data);
+    Hash(sha.digest())
+}
+
+/// Parses a time given as a Unix timestamp, in ISO 8601 as output by
+/// `--date=iso` (e.g. `2020-09-13 12:26:40 +0000`), RFC 3339 or RFC 2822.
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>> {
+    if let Ok(secs) = s.parse::<i64>() {
+        if let Some(time) = Utc.timestamp_opt(secs, 0).single() {
+            return Ok(time);
+        }
+    }
+    DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S %z")
+        .or_else(|_| DateTime::parse_from_rfc3339(s))
+        .or_else(|_| DateTime::parse_from_rfc2822(s))
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| InvalidDate(s.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_times() {
+        let time = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        assert_eq!(parse_time("1600000000").ok(), Some(time));
+        assert_eq!(parse_time("2020-09-13 12:26:40 +0000").ok(), Some(time));
+        assert_eq!(parse_time("2020-09-13T14:26:40+02:00").ok(), Some(time));
+        assert_eq!(parse_time("Sun, 13 Sep 2020 12:26:40 +0000").ok(), Some(time));
+        assert!(parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn unsafe_entry_names() {
+        for name in ["", ".", "..", ".gnew", ".GNEW", "../evil", "/etc", "a\\b"] {
+            assert!(check_entry_name(name).is_err(), "{:?}", name);
+        }
+        for name in ["file", ".gnewignore", "..file"] {
+            assert!(check_entry_name(name).is_ok(), "{:?}", name);
+        }
+
+        let mut tree = Tree::new();
+        tree.add_blob(Hash::new(), "../evil".to_owned());
+        assert!(matches!(tree.files().next(), Some(Err(UnsafePath(_)))));
+    }
+
+    #[test]
+    fn tree_cycles_and_depth() {
+        let mut tree = Tree::new();
+        tree.add_tree(Hash::new(), "self".to_owned());
+        let mut files = tree.files();
+        assert!(matches!(files.next(), Some(Err(TreeCycle(_)))));
+        assert!(files.next().is_none());
+
+        let mut inner = Tree::new();
+        let mut hash = Hash::new();
+        hash.update(b"inner");
+        inner.add_tree(hash, "dir".to_owned());
+        let mut files = inner.files();
+        files.stack = (0..MAX_TREE_DEPTH)
+            .map(|_| inner.entries.clone().into_iter())
+            .collect();
+        assert!(matches!(files.next(), Some(Err(TreeTooDeep(_)))));
+        assert!(files.next().is_none());
+    }
+
+    #[test]
+    fn type_changes() {
+        let path = |p: &str| ChangeEntry::Path(PathBuf::from(p), PathBuf::from(p));
+        let changes = vec![
+            Change::new_remove(path("a")),
+            Change::new_add(path("a/b")),
+            Change::new_remove(path("c/d")),
+            Change::new_add(path("c")),
+            Change::new_remove(path("e")),
+            Change::new_add(path("ef")),
+        ];
+        let statuses: Vec<_> = detect_type_changes(changes)
+            .iter()
+            .map(|c| (c.status(), c.path().to_owned()))
+            .collect();
+
+        assert_eq!(
+            statuses,
+            [
+                ('T', PathBuf::from("a")),
+                ('A', PathBuf::from("a/b")),
+                ('D', PathBuf::from("c/d")),
+                ('T', PathBuf::from("c")),
+                ('D', PathBuf::from("e")),
+                ('A', PathBuf::from("ef")),
+            ]
+        );
+    }
+
+    #[test]
+    fn dir_renames() {
+        let path = |p: &str| ChangeEntry::Path(PathBuf::from(p), PathBuf::from(p));
+        let rename = |a, b| Change::Rename(path(a), path(b));
+        let changes = vec![
+            rename("old/a", "new/a"),
+            rename("old/b", "new/b"),
+            rename("old/sub/c", "new/sub/c"),
+            rename("kept/d", "new/d"),
+            rename("tie/e", "x/e"),
+            rename("tie/f", "y/f"),
+            Change::new_remove(path("old/g")),
+            Change::new_add(path("new/g")),
+            Change::new_remove(path("old/h")),
+        ];
+        let renames = detect_dir_renames(&changes, |dir| dir == Path::new("kept"));
+        assert_eq!(renames, [(PathBuf::from("old"), PathBuf::from("new"))]);
+        assert_eq!(renamed_path(Path::new("old/sub/i"), &renames), Some("new/sub/i".into()));
+        assert_eq!(renamed_path(Path::new("older/i"), &renames), None);
+
+        let statuses: Vec<_> = apply_dir_renames(changes, &renames)
+            .iter()
+            .map(|c| (c.status(), c.path().to_owned()))
+            .collect();
+        assert_eq!(&statuses[6..], [('R', PathBuf::from("new/g")), ('D', PathBuf::from("old/h"))]);
+    }
 }
@@ -0,0 +1,312 @@
+use crate::error::Result;
+use crate::repo::ignore::{self, IgnorePattern};
+use crate::repo::object::{Commit, Hash, Tree};
+use crate::repo::repository::Repository;
+use crate::repo::revwalk::{RevWalk, RevWalkOptions};
+use crate::storage::transport;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Name of the files assigning owners to paths.
+pub const OWNERS_FILE: &str = "GNEWOWNERS";
+
+/// A line of an owners file: a pattern and the owners of the paths it
+/// matches.
+#[derive(Clone, Debug, PartialEq)]
+struct OwnerRule {
+    pattern: IgnorePattern,
+    owners: Vec<String>,
+}
+
+/// The owners assigned to paths by the `GNEWOWNERS` files of a tree. Each
+/// line holds a pattern, as in ignore files, followed by owners: names,
+/// emails or identities of authors, e.g. `ana`, `ana@example.com` or
+/// `Ana <ana@example.com>` quoted as a whole with `"`. A pattern matching a
+/// directory matches the files below it. The last matching line, in the
+/// deepest file, gives the owners; a line without owners leaves the paths
+/// it matches unowned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Owners {
+    rules: Vec<OwnerRule>,
+}
+
+/// A change pushed to a path by an author that does not own it.
+#[derive(Debug, PartialEq)]
+pub struct OwnerViolation {
+    pub commit: Hash,
+    pub author: String,
+    pub path: PathBuf,
+}
+
+impl Owners {
+    pub fn new() -> Owners {
+        Owners::default()
+    }
+
+    /// Reads the owners files of a working tree. `skip` is called on each
+    /// directory and returns true for directories that are not searched,
+    /// such as nested repositories.
+    pub fn load<F>(worktree: &Path, skip: F) -> Result<Owners>
+    where
+        F: Fn(&Path) -> bool,
+    {
+        let mut owners = Owners::new();
+        for dir in ignore::worktree_dirs(worktree, skip)? {
+            let source = dir.join(OWNERS_FILE);
+            if let Some(content) = ignore::read_optional(&worktree.join(&source))? {
+                owners.parse(&content, &dir, &source);
+            }
+        }
+        Ok(owners)
+    }
+
+    /// Reads the owners files stored in a tree.
+    pub fn from_tree(tree: &Tree) -> Result<Owners> {
+        let mut files = vec![];
+        for file in tree.files() {
+            let file = file?;
+            if file.path.file_name().is_some_and(|n| n == OWNERS_FILE) {
+                files.push(file);
+            }
+        }
+        // directories before the directories below them
+        files.sort_by(|a, b| a.path.parent().cmp(&b.path.parent()));
+
+        let mut owners = Owners::new();
+        for file in files {
+            let content = String::from_utf8_lossy(&file.contents()?).into_owned();
+            let base = file.path.parent().unwrap_or(Path::new(""));
+            owners.parse(&content, base, &file.path);
+        }
+        Ok(owners)
+    }
+
+    /// Adds the rules of an owners file in the directory base. Lines with
+    /// negated patterns, which are not allowed, are skipped.
+    pub fn parse(&mut self, content: &str, base: &Path, source: &Path) {
+        for (i, line) in content.lines().enumerate() {
+            let (pattern, rest) = match line.trim().split_once(char::is_whitespace) {
+                Some((pattern, rest)) => (pattern, rest),
+                None => (line.trim(), ""),
+            };
+            let pattern = match IgnorePattern::parse(pattern, base, source, i + 1) {
+                Some(p) if !p.is_negated() => p,
+                _ => continue,
+            };
+            let owners = parse_owners(rest);
+            self.rules.push(OwnerRule { pattern, owners });
+        }
+    }
+
+    /// Returns the owners of a path relative to the working tree, which are
+    /// none for unowned paths.
+    pub fn get(&self, path: &Path) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|r| matches_path(&r.pattern, path))
+            .map_or(&[], |r| &r.owners)
+    }
+
+    /// Returns whether an author, as recorded in commits, may change a path:
+    /// if the path is unowned or the author is one of its owners.
+    pub fn is_owner(&self, path: &Path, author: &str) -> bool {
+        let owners = self.get(path);
+        owners.is_empty() || owners.iter().any(|o| is_identity(o, author))
+    }
+}
+
+/// Splits the owners of a line, keeping quoted identities whole, up to a
+/// comment.
+fn parse_owners(s: &str) -> Vec<String> {
+    let mut owners = vec![];
+    let mut rest = s.trim_start();
+    while !rest.is_empty() && !rest.starts_with('#') {
+        let (owner, after) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        owners.push(owner.to_owned());
+        rest = after.trim_start();
+    }
+    owners
+}
+
+/// Checks whether a pattern matches a path or one of the directories it
+/// is in.
+fn matches_path(pattern: &IgnorePattern, path: &Path) -> bool {
+    let mut dirs = path.ancestors().filter(|p| !p.as_os_str().is_empty());
+    dirs.next().is_some_and(|p| pattern.matches(p, false)) || dirs.any(|d| pattern.matches(d, true))
+}
+
+/// Checks whether an owner names an author `Name <email>` or `Name`: by its
+/// whole identity, its name or its email.
+fn is_identity(owner: &str, author: &str) -> bool {
+    let (name, email) = match author.split_once(" <") {
+        Some((name, email)) => (name, email.strip_suffix('>')),
+        None => (author, None),
+    };
+    owner == author || owner == name || Some(owner) == email
+}
+
+impl Repository {
+    /// Returns the owners of the working tree paths.
+    pub fn owners(&self) -> Result<Owners> {
+        Owners::load(self.worktree(), |p| self.is_repo_dir(p))
+    }
+
+    /// Returns the changes of the commits from `new` that `old` does not
+    /// contain, to paths that their authors do not own, for a branch moved
+    /// from `old` to `new` by a push. Owners are read from `old`, so that a
+    /// push cannot give itself ownership, or for a new branch from HEAD, the
+    /// default branch of the receiving repository. A merge commit is checked
+    /// for the paths that differ from all of its parents.
+    pub fn check_owners(&self, old: Option<Hash>, new: Hash) -> Result<Vec<OwnerViolation>> {
+        let rules_from = match old {
+            Some(old) => Some(old),
+            None => self.head_hash().ok(),
+        };
+        let owners = match rules_from {
+            Some(hash) => Owners::from_tree(&transport::read_commit(hash)?.tree()?)?,
+            None => Owners::new(),
+        };
+        let known: HashSet<Hash> = match old {
+            Some(old) => RevWalk::new(&[old], RevWalkOptions::default())?
+                .map(|c| c.map(|c| c.hash()))
+                .collect::<Result<_>>()?,
+            None => HashSet::new(),
+        };
+
+        let mut violations = vec![];
+        for commit in RevWalk::new(&[new], RevWalkOptions::default())? {
+            let commit = commit?;
+            if known.contains(&commit.hash()) {
+                continue;
+            }
+            for path in changed_paths(&commit)? {
+                if !owners.is_owner(&path, commit.author()) {
+                    violations.push(OwnerViolation {
+                        commit: commit.hash(),
+                        author: commit.author().to_owned(),
+                        path,
+                    });
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+/// Returns the paths a commit changed from its parent. For a merge, these
+/// are the paths that differ from every parent, which no parent brought in.
+fn changed_paths(commit: &Commit) -> Result<Vec<PathBuf>> {
+    let tree = commit.tree()?;
+    let mut parents = commit.parent_hashes().iter();
+    let first = match parents.next() {
+        Some(&hash) => transport::read_commit(hash)?.tree()?,
+        None => Tree::new(),
+    };
+    let mut paths: Vec<_> = first.diff(&tree)?.iter().map(|c| c.path().to_owned()).collect();
+    for &parent in parents {
+        let changed: HashSet<_> = transport::read_commit(parent)?
+            .tree()?
+            .diff(&tree)?
+            .iter()
+            .map(|c| c.path().to_owned())
+            .collect();
+        paths.retain(|p| changed.contains(p));
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::repository::{MergeOptions, Reference};
+    use crate::testutil::TestRepo;
+
+    fn owners(content: &str) -> Owners {
+        let mut owners = Owners::new();
+        owners.parse(content, Path::new(""), Path::new(OWNERS_FILE));
+        owners
+    }
+
+    #[test]
+    fn lookup() {
+        let o = owners("* ana\n# comment\ndocs/ bo \"Cy <cy@example.com>\"\n*.lock\n");
+        assert_eq!(o.get(Path::new("src/main.rs")), ["ana"]);
+        assert_eq!(o.get(Path::new("docs/guide/a.md")), ["bo", "Cy <cy@example.com>"]);
+        assert!(o.get(Path::new("Cargo.lock")).is_empty());
+
+        let docs = Path::new("docs/a.md");
+        assert!(o.is_owner(docs, "bo"));
+        assert!(o.is_owner(docs, "Cy <cy@example.com>"));
+        assert!(!o.is_owner(docs, "Cy Other <cy@example.com>"));
+        assert!(!o.is_owner(docs, "ana"));
+        assert!(o.is_owner(Path::new("Cargo.lock"), "anyone"));
+
+        let mut nested = owners("* ana\n");
+        nested.parse("*.rs bo\n", Path::new("lib"), Path::new("lib/GNEWOWNERS"));
+        assert_eq!(nested.get(Path::new("lib/a.rs")), ["bo"]);
+        assert_eq!(nested.get(Path::new("a.rs")), ["ana"]);
+    }
+
+    #[test]
+    fn check_owners_test() {
+        let mut t = TestRepo::new();
+        t.commit_file(OWNERS_FILE, "docs/ someone\n");
+        let old = t.commit_file("docs/a.md", "a").hash();
+        t.commit_file("src/a.rs", "a");
+        let new = t.commit_file("docs/a.md", "changed").hash();
+
+        let violations = t.repo.check_owners(Some(old), new).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].commit, new);
+        assert_eq!(violations[0].path, Path::new("docs/a.md"));
+        assert!(t.repo.check_owners(Some(new), new).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_owners_of_merges() {
+        let mut t = TestRepo::new();
+        t.commit_file(OWNERS_FILE, "docs/ someone\n");
+        let old = t.commit_file("a.txt", "a").hash();
+        t.repo.create_branch("topic").unwrap();
+        t.repo.checkout(Reference::Branch("topic".to_owned()), false).unwrap();
+        let topic = t.commit_file("b.txt", "b").hash();
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        t.commit_file("c.txt", "c");
+
+        /* a merge that also changes a file of neither side */
+        let options = MergeOptions {
+            no_commit: true,
+            ..MergeOptions::default()
+        };
+        t.repo.merge(topic, &options).unwrap();
+        t.write("docs/evil.md", "evil");
+        t.add(&["docs/evil.md"]);
+        let merge = t.commit("merge").hash();
+
+        let violations = t.repo.check_owners(Some(old), merge).unwrap();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].commit, merge);
+        assert_eq!(violations[0].path, Path::new("docs/evil.md"));
+    }
+
+    #[test]
+    fn new_branches_are_checked_with_the_rules_of_head() {
+        let mut t = TestRepo::new();
+        t.commit_file(OWNERS_FILE, "docs/ someone\n");
+        let base = t.commit_file("a.txt", "a").hash();
+        t.repo.create_branch("topic").unwrap();
+        t.repo.checkout(Reference::Branch("topic".to_owned()), false).unwrap();
+        t.commit_file(OWNERS_FILE, "* G7\n");
+        let new = t.commit_file("docs/a.md", "a").hash();
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+
+        let violations = t.repo.check_owners(None, new).unwrap();
+        let paths: Vec<_> = violations.iter().map(|v| v.path.to_str().unwrap()).collect();
+        assert_eq!(paths, ["docs/a.md"]);
+        assert!(t.repo.check_owners(Some(base), base).unwrap().is_empty());
+    }
+}
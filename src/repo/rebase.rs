@@ -0,0 +1,435 @@
+use crate::error::{Error, Result};
+use crate::repo::message;
+use crate::repo::object::{Commit, CommitInfo, Hash};
+use crate::repo::repository::{CheckoutAction, Reference, Repository};
+use crate::repo::revwalk::{RevWalk, RevWalkOptions};
+use crate::storage::transport;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::result;
+use std::str;
+
+/// What to do with a commit replayed by a rebase.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RebaseAction {
+    Pick,
+    /// Pick the commit and edit its message.
+    Reword,
+    /// Meld the commit into the previous one and edit the joined messages.
+    Squash,
+    /// Meld the commit into the previous one, keeping its message.
+    Fixup,
+}
+
+/// A line of the todo list of a rebase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RebaseStep {
+    pub action: RebaseAction,
+    pub commit: Hash,
+}
+
+/// A rebase in progress, kept in the storage directory between the steps
+/// that stop at conflicts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RebaseState {
+    /// What HEAD pointed to before the rebase, which is moved to the result.
+    pub head: Reference,
+    /// The commit HEAD pointed to before the rebase.
+    pub orig_head: Hash,
+    /// The commit the steps are replayed on.
+    pub onto: Hash,
+    /// The step that stopped at conflicts, if any.
+    pub current: Option<RebaseStep>,
+    /// The steps left to do.
+    pub todo: Vec<RebaseStep>,
+}
+
+/// How a rebase ended.
+#[derive(Debug, PartialEq)]
+pub struct RebaseReport {
+    /// The branch or commit that HEAD points to after the rebase.
+    pub head: Reference,
+    /// Number of commits on top of the commit replayed on.
+    pub commits: usize,
+}
+
+impl RebaseAction {
+    fn name(&self) -> &'static str {
+        match self {
+            RebaseAction::Pick => "pick",
+            RebaseAction::Reword => "reword",
+            RebaseAction::Squash => "squash",
+            RebaseAction::Fixup => "fixup",
+        }
+    }
+}
+
+impl str::FromStr for RebaseAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> result::Result<RebaseAction, ()> {
+        Ok(match s {
+            "pick" | "p" => RebaseAction::Pick,
+            "reword" | "r" => RebaseAction::Reword,
+            "squash" | "s" => RebaseAction::Squash,
+            "fixup" | "f" => RebaseAction::Fixup,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl fmt::Display for RebaseStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.action.name(), self.commit)
+    }
+}
+
+impl fmt::Display for RebaseState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "head {}", self.head)?;
+        writeln!(f, "orig-head {}", self.orig_head)?;
+        writeln!(f, "onto {}", self.onto)?;
+        if let Some(step) = &self.current {
+            writeln!(f, "current {}", step)?;
+        }
+        for step in &self.todo {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+impl str::FromStr for RebaseState {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<RebaseState> {
+        let corrupted = || Error::RebaseCorrupted;
+        let mut lines = s.lines();
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|l| l.strip_prefix(name))
+                .and_then(|l| l.strip_prefix(' '))
+                .ok_or_else(corrupted)
+        };
+        let head = field("head")?.parse().or(Err(corrupted()))?;
+        let orig_head = field("orig-head")?.parse().or(Err(corrupted()))?;
+        let onto = field("onto")?.parse().or(Err(corrupted()))?;
+
+        let mut current = None;
+        let mut todo = vec![];
+        for line in lines {
+            match line.strip_prefix("current ") {
+                Some(step) => current = Some(parse_step(step).ok_or_else(corrupted)?),
+                None => todo.push(parse_step(line).ok_or_else(corrupted)?),
+            }
+        }
+        Ok(RebaseState {
+            head,
+            orig_head,
+            onto,
+            current,
+            todo,
+        })
+    }
+}
+
+/// Parses `<action> <hash>`, ignoring what follows the hash.
+fn parse_step(line: &str) -> Option<RebaseStep> {
+    let mut words = line.split_whitespace();
+    Some(RebaseStep {
+        action: words.next()?.parse().ok()?,
+        commit: words.next()?.parse().ok()?,
+    })
+}
+
+/// Formats the todo list edited by `rebase -i`, with a line
+/// `<action> <hash> <summary>` per commit.
+pub fn format_todo(commits: &[Commit]) -> String {
+    let mut todo = String::new();
+    for c in commits {
+        let summary = c.msg().lines().next().unwrap_or("");
+        todo += &format!("pick {} {}\n", c.hash(), summary);
+    }
+    todo += "\n\
+        # Commands:\n\
+        # p, pick <commit> = use commit\n\
+        # r, reword <commit> = use commit, but edit the commit message\n\
+        # s, squash <commit> = use commit, but meld into previous commit\n\
+        # f, fixup <commit> = like squash, but keep only the previous message\n\
+        # d, drop <commit> = remove commit\n\
+        #\n\
+        # Lines can be reordered, and removing a line drops its commit.\n";
+    todo
+}
+
+/// Parses an edited todo list. Empty lines, comments and dropped commits
+/// are left out.
+pub fn parse_todo(todo: &str) -> Result<Vec<RebaseStep>> {
+    let mut steps = vec![];
+    for line in todo.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if matches!(line.split_whitespace().next(), Some("drop" | "d")) {
+            continue;
+        }
+        let step = parse_step(line);
+        steps.push(step.ok_or_else(|| Error::InvalidRebaseTodo(format!("'{}'", line)))?);
+    }
+    match steps.first() {
+        Some(RebaseStep {
+            action: RebaseAction::Squash | RebaseAction::Fixup,
+            ..
+        }) => Err(Error::InvalidRebaseTodo(
+            "cannot squash without a previous commit".to_owned(),
+        )),
+        _ => Ok(steps),
+    }
+}
+
+impl Repository {
+    /// Returns the commits of HEAD that are not in `upstream`, oldest first,
+    /// following the first parents.
+    pub fn rebase_commits(&self, upstream: Hash) -> Result<Vec<Commit>> {
+        let mut upstream_commits = HashSet::new();
+        for c in RevWalk::new(&[upstream], RevWalkOptions::default())? {
+            upstream_commits.insert(c?.hash());
+        }
+        let mut commits = vec![];
+        let mut next = Some(self.head_hash()?);
+        while let Some(hash) = next.filter(|h| !upstream_commits.contains(h)) {
+            let commit = transport::read_commit(hash)?;
+            next = commit.parent_hash();
+            commits.push(commit);
+        }
+        commits.reverse();
+        Ok(commits)
+    }
+
+    /// Replays the steps on top of `onto`, then moves HEAD to the result.
+    /// The working tree must be clean. If a step has conflicts, the rebase
+    /// stops with `RebaseStopped` until `rebase_continue` or `rebase_abort`.
+    pub fn rebase(&mut self, onto: Hash, todo: Vec<RebaseStep>) -> Result<RebaseReport> {
+        self.batch_tracklist(|r| r.start_rebase(onto, todo))
+    }
+
+    fn start_rebase(&mut self, onto: Hash, todo: Vec<RebaseStep>) -> Result<RebaseReport> {
+        if self.rebase_state()?.is_some() {
+            return Err(Error::RebaseInProgress);
+        }
+        let orig_head = self.head_hash()?;
+        self.is_clean(&self.head_tree()?)?;
+        if transport::read_merge_head(self.worktree())?.is_some() {
+            return Err(Error::DirtyWorktree);
+        }
+
+        let state = RebaseState {
+            head: self.head().clone(),
+            orig_head,
+            onto,
+            current: None,
+            todo,
+        };
+        self.move_head(onto)?;
+        transport::write_rebase_state(self.worktree(), Some(&state))?;
+        self.run_rebase(state)
+    }
+
+    /// Returns the steps of an interactive rebase, from the todo list of the
+    /// commits as edited in the editor.
+    pub fn edit_rebase_todo(&self, commits: &[Commit]) -> Result<Vec<RebaseStep>> {
+        parse_todo(&self.edit_file("rebase-todo", &format_todo(commits))?)
+    }
+
+    /// Commits the resolved step that stopped at conflicts and replays the
+    /// rest of the steps.
+    pub fn rebase_continue(&mut self) -> Result<RebaseReport> {
+        self.batch_tracklist(|r| r.continue_rebase())
+    }
+
+    fn continue_rebase(&mut self) -> Result<RebaseReport> {
+        let mut state = self.rebase_state()?.ok_or(Error::NoRebaseInProgress)?;
+        if let Some(step) = &state.current {
+            let unresolved = self.unresolved_conflicts()?;
+            if !unresolved.is_empty() {
+                return Err(Error::RebaseStopped(step.commit, unresolved));
+            }
+        }
+        if let Some(step) = state.current.take() {
+            self.rerere_record()?;
+            self.clear_merge_state()?;
+            self.commit_step(&step)?;
+            transport::write_rebase_state(self.worktree(), Some(&state))?;
+        }
+        self.run_rebase(state)
+    }
+
+    /// Stops the rebase in progress and restores HEAD and the working tree
+    /// as they were before it. Untracked files are left alone, unless they
+    /// are in the way of a file of the restored tree.
+    pub fn rebase_abort(&mut self) -> Result<()> {
+        let state = self.rebase_state()?.ok_or(Error::NoRebaseInProgress)?;
+        let tree = transport::read_commit(state.orig_head)?.tree()?;
+        let mut plan = self.plan_checkout(&tree, true)?;
+        plan.actions.retain(|a| match a {
+            CheckoutAction::Remove(path) => self.is_tracked(path),
+            CheckoutAction::Write(..) => true,
+        });
+        self.apply_checkout(plan)?;
+        self.clear_merge_state()?;
+        self.set_head(state.head)?;
+        transport::write_rebase_state(self.worktree(), None)
+    }
+
+    /// Returns the rebase in progress, if any.
+    pub fn rebase_state(&self) -> Result<Option<RebaseState>> {
+        transport::read_rebase_state(self.worktree())
+    }
+
+    /// Replays the steps left, saving the state after each of them.
+    fn run_rebase(&mut self, mut state: RebaseState) -> Result<RebaseReport> {
+        while !state.todo.is_empty() {
+            let step = state.todo.remove(0);
+            let conflicts = self.apply_step(&step)?;
+            if !conflicts.is_empty() {
+                state.current = Some(step.clone());
+                transport::write_rebase_state(self.worktree(), Some(&state))?;
+                return Err(Error::RebaseStopped(step.commit, conflicts));
+            }
+            self.commit_step(&step)?;
+            transport::write_rebase_state(self.worktree(), Some(&state))?;
+        }
+
+        let new_head = self.head_hash()?;
+        let mut commits = 0;
+        let mut next = Some(new_head);
+        while let Some(hash) = next.filter(|&h| h != state.onto) {
+            next = transport::read_commit(hash)?.parent_hash();
+            commits += 1;
+        }
+        if let Reference::Branch(b) = &state.head {
+            self.set_branch(b, new_head)?;
+        }
+        self.set_head(state.head.clone())?;
+        transport::write_rebase_state(self.worktree(), None)?;
+        Ok(RebaseReport {
+            head: state.head,
+            commits,
+        })
+    }
+
+    /// Applies the changes of a step's commit to the working tree. A pick of
+    /// a child of HEAD moves HEAD to the commit instead. Returns the files
+    /// with conflicts.
+    fn apply_step(&mut self, step: &RebaseStep) -> Result<Vec<PathBuf>> {
+        let commit = transport::read_commit(step.commit)?;
+        let head = transport::read_commit(self.head_hash()?)?;
+        if step.action == RebaseAction::Pick && commit.parent_hash() == Some(head.hash()) {
+            self.move_head(commit.hash())?;
+            return Ok(vec![]);
+        }
+        let base = commit.parent().transpose()?;
+        self.merge_into_worktree(&head, base.as_ref(), &commit)
+    }
+
+    /// Records the working tree as the result of a step, unless HEAD is
+    /// already the step's commit or the working tree has no changes to
+    /// record.
+    fn commit_step(&mut self, step: &RebaseStep) -> Result<()> {
+        let head = transport::read_commit(self.head_hash()?)?;
+        if head.hash() == step.commit {
+            return Ok(());
+        }
+        let commit = transport::read_commit(step.commit)?;
+        let tree = self.write_tree()?.hash();
+        let squash = matches!(step.action, RebaseAction::Squash | RebaseAction::Fixup);
+        if tree == head.tree_hash() && !squash {
+            return Ok(());
+        }
+
+        // a squash or fixup replaces HEAD, keeping its author and time
+        let (parents, original) = if squash {
+            (head.parent_hashes().to_vec(), &head)
+        } else {
+            (vec![head.hash()], &commit)
+        };
+        let edit = |msg: &str| -> Result<String> {
+            let msg = self.edit_file("COMMIT_EDITMSG", msg)?;
+            Ok(message::clean_message(&msg, true))
+        };
+        let msg = match step.action {
+            RebaseAction::Pick => commit.msg().to_owned(),
+            RebaseAction::Reword => edit(commit.msg())?,
+            RebaseAction::Squash => edit(&format!("{}\n\n{}", head.msg(), commit.msg()))?,
+            RebaseAction::Fixup => head.msg().to_owned(),
+        };
+        let mut new = Commit::new(CommitInfo {
+            tree,
+            parents,
+            author: original.author().to_owned(),
+            time: original.time(),
+            msg,
+        });
+        transport::write_commit(&mut new)?;
+        self.set_head(Reference::Hash(new.hash()))
+    }
+
+    /// Detaches HEAD at a commit and checks out its tree.
+    fn move_head(&mut self, commit: Hash) -> Result<()> {
+        let tree = transport::read_commit(commit)?.tree()?;
+        let plan = self.plan_checkout(&tree, false)?;
+        self.apply_checkout(plan)?;
+        self.set_head(Reference::Hash(commit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_todo_list() {
+        let hash = |c: char| c.to_string().repeat(40).parse::<Hash>().unwrap();
+        let todo = format!(
+            "pick {} first\n\n# comment\nd {} dropped\nf {}\nreword {} last\n",
+            hash('1'),
+            hash('2'),
+            hash('3'),
+            hash('4')
+        );
+        let steps = parse_todo(&todo).unwrap();
+        let actions: Vec<_> = steps.iter().map(|s| (s.action, s.commit)).collect();
+        assert_eq!(
+            actions,
+            [
+                (RebaseAction::Pick, hash('1')),
+                (RebaseAction::Fixup, hash('3')),
+                (RebaseAction::Reword, hash('4'))
+            ]
+        );
+
+        assert!(matches!(parse_todo("edit 1234"), Err(Error::InvalidRebaseTodo(_))));
+        let squash_first = format!("squash {}\n", hash('1'));
+        assert!(matches!(parse_todo(&squash_first), Err(Error::InvalidRebaseTodo(_))));
+    }
+
+    #[test]
+    fn rebase_state_round_trip() {
+        let hash = "a".repeat(40).parse().unwrap();
+        let step = RebaseStep {
+            action: RebaseAction::Squash,
+            commit: hash,
+        };
+        let state = RebaseState {
+            head: Reference::Branch("topic".to_owned()),
+            orig_head: hash,
+            onto: hash,
+            current: Some(step.clone()),
+            todo: vec![step],
+        };
+        assert_eq!(state.to_string().parse::<RebaseState>().unwrap(), state);
+        assert!(matches!("head x\n".parse::<RebaseState>(), Err(Error::RebaseCorrupted)));
+    }
+}
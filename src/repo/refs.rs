@@ -0,0 +1,217 @@
+use crate::error::{Error::*, Result};
+use crate::repo::object::Hash;
+use std::collections::BTreeMap;
+
+/// Namespace of the branch heads.
+pub const HEADS: &str = "refs/heads/";
+/// Namespace of the tags.
+pub const TAGS: &str = "refs/tags/";
+/// Namespace of the notes attached to commits, as `<notes>`.
+pub const NOTES: &str = "refs/notes/";
+/// Namespace of the branches of remote repositories, as `<remote>/<branch>`.
+pub const REMOTES: &str = "refs/remotes/";
+
+/// Returns the full reference name of a branch.
+pub fn branch_ref(name: &str) -> String {
+    format!("{}{}", HEADS, name)
+}
+
+/// Returns the name of a reference without its namespace, e.g. `main` for
+/// `refs/heads/main` and `origin/main` for `refs/remotes/origin/main`.
+pub fn short_name(name: &str) -> &str {
+    [HEADS, TAGS, REMOTES]
+        .iter()
+        .find_map(|ns| name.strip_prefix(ns))
+        .unwrap_or(name)
+}
+
+/// Checks if a branch name is valid: made of ASCII letters, digits and
+/// `-_./`, not starting with `-` and without empty or dot-only components.
+pub fn is_valid_branch_name(name: &str) -> bool {
+    !name.starts_with('-')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"-_./".contains(&b))
+        && name.split('/').all(|c| !c.is_empty() && !c.starts_with('.'))
+}
+
+/// Normalizes the name of a new branch or tag as typed, removing leading,
+/// trailing and repeated `/`, e.g. `feature//login/` to `feature/login`.
+pub fn normalize_name(name: &str) -> String {
+    name.split('/').filter(|c| !c.is_empty()).collect::<Vec<_>>().join("/")
+}
+
+/// Checks the name of a new branch or tag: it must be valid, have no `..`
+/// and no component ending with `.lock`, and not read as another revision,
+/// i.e. `HEAD` or a full hash.
+pub fn check_new_name(name: &str) -> Result<()> {
+    let is_hash = name.len() == 40 && name.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_valid_branch_name(name)
+        || name.contains("..")
+        || name.split('/').any(|c| c.ends_with(".lock"))
+        || name == "HEAD"
+        || is_hash
+    {
+        return Err(InvalidRefName(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Returns the names close to a misspelled name, closest first: those
+/// within an edit distance of a third of its length, and at least 1.
+pub fn similar_names<'a, I>(name: &str, names: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let max = (name.chars().count() / 3).max(1);
+    let mut similar: Vec<_> = names
+        .into_iter()
+        .map(|n| (edit_distance(name, n), n))
+        .filter(|&(d, _)| d <= max)
+        .collect();
+    similar.sort();
+    similar.into_iter().map(|(_, n)| n.to_owned()).collect()
+}
+
+/// Returns the edit distance between two strings, counting insertions,
+/// deletions, substitutions and swaps of adjacent characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// Named pointers to commits, keyed by their full name
+/// (e.g. `refs/heads/main`).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Refs {
+    refs: BTreeMap<String, Hash>,
+}
+
+impl Refs {
+    pub fn new() -> Refs {
+        Refs::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<Hash> {
+        self.refs.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, hash: Hash) {
+        self.refs.insert(name.to_owned(), hash);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Hash> {
+        self.refs.remove(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.refs.contains_key(name)
+    }
+
+    /// Returns a reference that cannot exist alongside a new one because
+    /// the file of one would be a directory of the other, e.g.
+    /// `refs/heads/feature` for `refs/heads/feature/login`.
+    pub fn conflicting(&self, name: &str) -> Option<&str> {
+        let nested = |a: &str, b: &str| a.strip_prefix(b).is_some_and(|r| r.starts_with('/'));
+        self.refs
+            .keys()
+            .map(String::as_str)
+            .find(|&n| nested(n, name) || nested(name, n))
+    }
+
+    /// Returns the references in name order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, Hash)> {
+        self.refs.iter().map(|(n, &h)| (n.as_str(), h))
+    }
+
+    /// Returns the references in a namespace such as `refs/heads/`,
+    /// with the namespace stripped from their names.
+    pub fn namespace<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, Hash)> {
+        self.iter()
+            .filter_map(move |(n, h)| Some((n.strip_prefix(prefix)?, h)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.refs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+}
+
+impl Extend<(String, Hash)> for Refs {
+    fn extend<T: IntoIterator<Item = (String, Hash)>>(&mut self, iter: T) {
+        self.refs.extend(iter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn branch_names() {
+        for name in ["main", "feature/x", "v1.0", "fix-42_b"] {
+            assert!(is_valid_branch_name(name), "{}", name);
+        }
+        for name in ["", "-b", "a b", "a..b/../c", "a//b", "a/", ".hidden", "caf\u{e9}", "a:b"] {
+            assert!(!is_valid_branch_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn new_names() {
+        assert_eq!(normalize_name("/feature//login/"), "feature/login");
+        assert!(check_new_name("feature/login").is_ok());
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        for name in ["HEAD", hash, "main.lock", "a.lock/b", "a..b", "a/../b", "a\tb", "x\u{7}"] {
+            assert!(check_new_name(name).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn suggest_similar_names() {
+        let names = ["main", "maintenance", "feature", "features", "fix"];
+        assert_eq!(similar_names("mian", names), ["main"]);
+        assert_eq!(similar_names("featur", names), ["feature", "features"]);
+        assert!(similar_names("unrelated", names).is_empty());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn nested_names_conflict() {
+        let mut refs = Refs::new();
+        refs.set("refs/heads/feature/login", Hash::new());
+        refs.set("refs/heads/fix", Hash::new());
+        assert_eq!(refs.conflicting("refs/heads/feature"), Some("refs/heads/feature/login"));
+        assert_eq!(refs.conflicting("refs/heads/fix/typo"), Some("refs/heads/fix"));
+        assert_eq!(refs.conflicting("refs/heads/feature/logout"), None);
+        assert_eq!(refs.conflicting("refs/heads/feat"), None);
+    }
+
+    #[test]
+    fn short_names() {
+        assert_eq!(short_name("refs/heads/feature/x"), "feature/x");
+        assert_eq!(short_name("refs/tags/v1"), "v1");
+        assert_eq!(short_name("refs/remotes/origin/main"), "origin/main");
+        assert_eq!(short_name("refs/notes/ci"), "refs/notes/ci");
+    }
+}
@@ -0,0 +1,85 @@
+use crate::error::{Error, Error::*, Result};
+use std::str::FromStr;
+
+/// A mapping from branch names of one repository to branch names of
+/// another, written `<src>:<dst>`, or `<name>` for the same name on both
+/// sides. A side may end with `*`, which matches the rest of a name and is
+/// replaced by it on the other side, e.g. `release/*` or `main:upstream`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Refspec {
+    src: String,
+    dst: String,
+}
+
+impl Refspec {
+    /// Returns the name a branch is mapped to, or None if it does not match.
+    pub fn map(&self, name: &str) -> Option<String> {
+        match self.src.strip_suffix('*') {
+            Some(prefix) => {
+                let rest = name.strip_prefix(prefix)?;
+                let dst = self.dst.strip_suffix('*').unwrap();
+                Some(format!("{}{}", dst, rest))
+            }
+            None if name == self.src => Some(self.dst.clone()),
+            None => None,
+        }
+    }
+}
+
+impl FromStr for Refspec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Refspec> {
+        let (src, dst) = s.split_once(':').unwrap_or((s, s));
+        let valid_side = |side: &str| {
+            let name = side.strip_suffix('*').unwrap_or(side);
+            !side.is_empty() && !name.contains('*')
+        };
+        if !valid_side(src) || !valid_side(dst) || src.ends_with('*') != dst.ends_with('*') {
+            return Err(InvalidRefspec(s.to_owned()));
+        }
+        Ok(Refspec {
+            src: src.to_owned(),
+            dst: dst.to_owned(),
+        })
+    }
+}
+
+/// Parses a whitespace-separated list of refspecs, as stored in the config.
+pub fn parse_refspecs(s: &str) -> Result<Vec<Refspec>> {
+    s.split_whitespace().map(str::parse).collect()
+}
+
+/// Returns the name a branch is mapped to by the first matching refspec.
+pub fn map_branch(refspecs: &[Refspec], name: &str) -> Option<String> {
+    refspecs.iter().find_map(|r| r.map(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert!("main".parse::<Refspec>().is_ok());
+        assert!("release/*:stable/*".parse::<Refspec>().is_ok());
+        assert!("release/*:stable".parse::<Refspec>().is_err());
+        assert!("a*b".parse::<Refspec>().is_err());
+        assert!("a**".parse::<Refspec>().is_err());
+        assert!(":main".parse::<Refspec>().is_err());
+        assert!(parse_refspecs("main  release/*\n").unwrap().len() == 2);
+    }
+
+    #[test]
+    fn map() {
+        let refspecs = parse_refspecs("main release/*:stable/* dev:upstream").unwrap();
+        assert_eq!(map_branch(&refspecs, "main"), Some("main".to_owned()));
+        assert_eq!(
+            map_branch(&refspecs, "release/1.0"),
+            Some("stable/1.0".to_owned())
+        );
+        assert_eq!(map_branch(&refspecs, "dev"), Some("upstream".to_owned()));
+        assert_eq!(map_branch(&refspecs, "feature"), None);
+        assert_eq!(map_branch(&refspecs, "mainline"), None);
+    }
+}
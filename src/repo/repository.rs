@@ -1,23 +1,60 @@
-use crate::repo::object::{self, Blob, Change, Commit, CommitInfo, File, Hash, Tree};
-use crate::storage::transport;
-use crate::wd::ui::{Error::*, Result};
-use chrono::Utc;
-use fs_extra::{copy_items, dir};
-use std::collections::{HashMap, HashSet};
+use crate::repo::attributes::{Attributes, MergeDriver, ATTRIBUTES_FILE};
+use crate::repo::cache::StatCache;
+use crate::repo::hooks;
+use crate::repo::ignore::IgnoreMatcher;
+use crate::repo::journal::{self, CheckoutJournal};
+use crate::repo::message::{self, MessagePolicy};
+use crate::repo::object::{
+    self, Change, ChangeEntry, Commit, CommitInfo, File, Hash, Tree, TreeEntryKind,
+};
+use crate::repo::refs::{self, Refs};
+use crate::repo::refspec::{self, Refspec};
+use crate::repo::revwalk::{RevWalk, RevWalkOptions};
+use crate::repo::rewrite::{FilterOptions, FilterReport, HistoryRewrite};
+use crate::repo::tracklist;
+use crate::storage::transport::{self, Store, StoreGuard};
+use crate::error::{Error, Error::*, Result};
+use chrono::{DateTime, Utc};
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::fmt;
 use std::fs;
 use std::io::ErrorKind;
+use std::iter;
 use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::result;
+use std::sync::Mutex;
+use std::str;
+use std::thread;
 use walkdir::{self, DirEntry, WalkDir};
 
+/// Minimum number of files for a checkout to write them in parallel.
+const PARALLEL_CHECKOUT_MIN: usize = 64;
+
 #[derive(Debug)]
 pub struct Repository {
     head: Reference,
-    branches: HashMap<String, Hash>,
-    tracklist: Vec<String>,
+    refs: Refs,
+    tracklist: Tracklist,
+    /// Whether the tracklist changed during a batch of updates, which writes
+    /// it once at the end. None outside of a batch.
+    tracklist_batch: Option<bool>,
+    /// The files with conflicts that the last merge resolved with recorded
+    /// resolutions.
+    rerere_resolved: Vec<PathBuf>,
+    cache: RefCell<StatCache>,
+    /// The attributes files of the working tree, read on first use.
+    attributes: OnceCell<Attributes>,
     worktree: PathBuf,
     storage_dir: PathBuf,
+    /// The directory the repository was opened from, relative to the
+    /// working tree.
+    prefix: PathBuf,
+    /// The objects of a push being received, which a hook opening the
+    /// repository is given in GNEW_QUARANTINE_PATH.
+    quarantine: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -26,22 +63,47 @@ pub enum Reference {
     Branch(String),
 }
 
+/// The canonical form of a reference, as stored in HEAD: a commit hash, or
+/// `ref: refs/heads/<branch>`.
 impl fmt::Display for Reference {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Reference::Hash(h) => write!(f, "{}", h),
-            Reference::Branch(b) => write!(f, "branch '{}'", b),
+            Reference::Branch(b) => write!(f, "ref: {}", refs::branch_ref(b)),
+        }
+    }
+}
+
+impl str::FromStr for Reference {
+    type Err = Error;
+
+    /// Parses the canonical form of a reference. `ref: <branch>` without
+    /// the namespace is accepted too, as written by older versions. Branch
+    /// names are not checked, so that branches created before names were
+    /// restricted can still be read; new names are checked when created.
+    fn from_str(s: &str) -> Result<Reference> {
+        match s.strip_prefix("ref: ") {
+            Some(name) => match name.strip_prefix(refs::HEADS).unwrap_or(name) {
+                "" => Err(InvalidRefName(name.to_owned())),
+                branch => Ok(Reference::Branch(branch.to_owned())),
+            },
+            None => s.parse().map(Reference::Hash).or(Err(InvalidRefName(s.to_owned()))),
         }
     }
 }
 
 pub type Status = HashMap<PathBuf, FileStatus>;
 
+/// The sorted set of tracked paths, relative to the working tree.
+pub type Tracklist = BTreeSet<String>;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FileStatus {
     Untracked,
     Unmodified,
     Modified,
+    /// Only the executable bit of the file changed.
+    ModeChanged,
     Added,
     /// File was removed from tracking list.
     Deleted,
@@ -55,46 +117,340 @@ impl FileStatus {
             FileStatus::Untracked => '?',
             FileStatus::Unmodified => ' ',
             FileStatus::Modified => 'M',
+            FileStatus::ModeChanged => 'T',
             FileStatus::Added => 'A',
             FileStatus::Deleted => 'R',
             FileStatus::Missing => '!',
         }
     }
+
+    /// Returns the code used by `status --porcelain`. Unlike `code`, these
+    /// are stable across versions: `?` untracked, `M` modified, `T` mode
+    /// changed, `A` added, `D` deleted and `!` missing.
+    pub fn porcelain_code(&self) -> char {
+        match self {
+            FileStatus::Deleted => 'D',
+            _ => self.code(),
+        }
+    }
+}
+
+/// Options for cloning a repository.
+#[derive(Clone, Debug, Default)]
+pub struct CloneOptions {
+    /// A local repository whose objects are shared instead of copied.
+    pub reference: Option<PathBuf>,
+    /// Hard link objects instead of copying them.
+    pub local: bool,
+    /// Leave out blobs, which are fetched from the source repository when
+    /// they are first needed.
+    pub without_blobs: bool,
+    /// The branch to check out instead of the source's HEAD.
+    pub branch: Option<String>,
+    /// Only copy the history and reference of the checked out branch.
+    pub single_branch: bool,
+}
+
+/// Which files `Repository::add` adds to the tracking list.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddMode {
+    /// The given files and directories.
+    Paths,
+    /// Only update tracked files, untracking those that were deleted.
+    Update,
+    /// Track every file in the working tree and untrack deleted files.
+    All,
+}
+
+/// Options for removing files.
+#[derive(Clone, Debug, Default)]
+pub struct RmOptions {
+    /// Only remove the files from the tracking list, keeping them on disk.
+    pub cached: bool,
+    /// Allow removing directories.
+    pub recursive: bool,
+    /// Remove files even if they differ from HEAD.
+    pub force: bool,
+}
+
+/// Options for creating a commit.
+#[derive(Clone, Debug, Default)]
+pub struct CommitOptions {
+    /// The author, instead of the configured identity.
+    pub author: Option<String>,
+    /// The commit time, instead of the current time.
+    pub time: Option<DateTime<Utc>>,
+    /// Commit even if tracked files are missing from the working tree,
+    /// which leaves them out of the commit.
+    pub force: bool,
+}
+
+/// A working tree file operation of a checkout.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CheckoutAction {
+    /// Write the blob with the given hash to the path, as an executable
+    /// file if set.
+    Write(PathBuf, Hash, bool),
+    /// Remove the file at the path.
+    Remove(PathBuf),
+}
+
+/// The changes needed to update the working tree to another tree.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckoutPlan {
+    pub actions: Vec<CheckoutAction>,
+    /// The tracklist after the checkout.
+    pub tracklist: Tracklist,
+}
+
+/// What a checkout changes in the working tree.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheckoutSummary {
+    /// Files written where the working tree has none.
+    pub created: Vec<PathBuf>,
+    /// Files overwritten with other contents or mode.
+    pub updated: Vec<PathBuf>,
+    pub deleted: Vec<PathBuf>,
+}
+
+impl CheckoutSummary {
+    pub fn is_empty(&self) -> bool {
+        self.created.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// The files a commit records as changed, relative to its parent.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommitSummary {
+    pub added: Vec<PathBuf>,
+    pub modified: Vec<PathBuf>,
+    /// Files removed from the tracking list or missing from the working tree.
+    pub deleted: Vec<PathBuf>,
+}
+
+impl CommitSummary {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.deleted.is_empty()
+    }
+}
+
+/// Options for merging a commit.
+#[derive(Clone, Debug, Default)]
+pub struct MergeOptions {
+    /// Merge commits without a common ancestor, as if they had one with no
+    /// files.
+    pub allow_unrelated_histories: bool,
+    /// Leave a clean three-way merge uncommitted.
+    pub no_commit: bool,
+    /// The message of the merge commit, instead of `Merge commit <hash>`.
+    pub message: Option<String>,
+}
+
+/// A version of a file in a conflicted merge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// The version in the common ancestor.
+    Base,
+    Ours,
+    Theirs,
+}
+
+impl str::FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<Stage, String> {
+        match s {
+            "base" => Ok(Stage::Base),
+            "ours" => Ok(Stage::Ours),
+            "theirs" => Ok(Stage::Theirs),
+            _ => Err(format!("unknown stage '{}'", s)),
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Base => write!(f, "base"),
+            Stage::Ours => write!(f, "ours"),
+            Stage::Theirs => write!(f, "theirs"),
+        }
+    }
+}
+
+/// The blob hashes of the versions of a conflicted file, None for versions
+/// where the file does not exist.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConflictStages {
+    pub base: Option<Hash>,
+    pub ours: Option<Hash>,
+    pub theirs: Option<Hash>,
+}
+
+impl ConflictStages {
+    pub fn get(&self, stage: Stage) -> Option<Hash> {
+        match stage {
+            Stage::Base => self.base,
+            Stage::Ours => self.ours,
+            Stage::Theirs => self.theirs,
+        }
+    }
+}
+
+/// The conflicted files of a merge in progress, keyed by path.
+pub type Conflicts = BTreeMap<String, ConflictStages>;
+
+/// The update of a branch by a push or a pull.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefUpdate {
+    pub name: String,
+    /// The previous head of the branch, None if the update creates it.
+    pub old: Option<Hash>,
+    pub new: Hash,
+}
+
+/// What `Repository::pull` did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PullReport {
+    /// How the current branch was updated, or for all branches whether any
+    /// of them was.
+    pub strategy: MergeStrategy,
+    /// Number of objects copied from the other repository.
+    pub objects: usize,
+    /// The local branches that moved.
+    pub updates: Vec<RefUpdate>,
+}
+
+/// What `Repository::push` did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PushReport {
+    /// Number of objects copied to the other repository.
+    pub objects: usize,
+    /// The remote branches that were pushed, including those up to date.
+    pub updates: Vec<RefUpdate>,
+    /// The notes reference the push was recorded in, set by `push.notes`.
+    pub notes: Option<String>,
+    /// Problems that did not stop the push, like a note that could not be
+    /// recorded.
+    pub warnings: Vec<String>,
+}
+
+/// How the current branch compares to its remote-tracking branch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    /// The remote-tracking branch, e.g. `origin/main`.
+    pub tracking: String,
+    /// Number of commits of the branch missing on the remote.
+    pub ahead: usize,
+    /// Number of commits of the remote missing in the branch.
+    pub behind: usize,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MergeStrategy {
+    /// The commit is already an ancestor of HEAD.
+    AlreadyUpToDate,
     FastForward,
     ThreeWay,
 }
 
 impl Repository {
     /// Creates an empty repository in the current directory.
-    pub fn init() -> Result<Repository> {
-        let worktree = fs::canonicalize(".")?;
-        let storage_dir = worktree.join(".gnew");
+    /// The initial branch defaults to the `init.defaultBranch` setting of the
+    /// user configuration, or `main`.
+    pub fn init(initial_branch: Option<&str>) -> Result<Repository> {
+        Repository::init_at(".", initial_branch)
+    }
+
+    /// Creates an empty repository in a directory, like `init`.
+    pub fn init_at<P: AsRef<Path>>(dir: P, initial_branch: Option<&str>) -> Result<Repository> {
+        let worktree = fs::canonicalize(dir)?;
+        let storage_dir = match env::var_os(transport::DIR_ENV) {
+            Some(dir) => worktree.join(dir),
+            None => worktree.join(".gnew"),
+        };
+        transport::set_store(&Store {
+            worktree: worktree.clone(),
+            storage_dir: storage_dir.clone(),
+            quarantine: None,
+        });
+        let branch = match initial_branch {
+            Some(b) => b.to_owned(),
+            None => Repository::default_branch()?,
+        };
+        refs::check_new_name(&branch)?;
         transport::write_empty_repo()?;
+        let head = Reference::Branch(branch);
+        transport::write_head(&worktree, &head)?;
 
         Ok(Repository {
-            head: Reference::Branch("main".to_owned()),
-            branches: HashMap::new(),
-            tracklist: Vec::<String>::new(),
+            head,
+            refs: Refs::new(),
+            tracklist: Tracklist::new(),
+            tracklist_batch: None,
+            rerere_resolved: vec![],
+            cache: RefCell::new(StatCache::new()),
+            attributes: OnceCell::new(),
             worktree,
             storage_dir,
+            prefix: PathBuf::new(),
+            quarantine: None,
         })
     }
 
-    /// Opens a repository in the current directory.
+    /// Returns the initial branch of new repositories.
+    fn default_branch() -> Result<String> {
+        let config = transport::read_user_config()?;
+        Ok(config
+            .get("init.defaultBranch")
+            .unwrap_or("main")
+            .to_owned())
+    }
+
+    /// Opens the repository containing the current directory and makes it
+    /// the one the current thread reads and writes objects of.
+    /// GNEW_WORK_TREE and GNEW_DIR give the working tree and the storage
+    /// directory instead; with only GNEW_DIR, the working tree is the
+    /// current directory.
     pub fn open() -> Result<Repository> {
-        let worktree = fs::canonicalize(".")?;
+        Repository::open_at(".")
+    }
+
+    /// Opens the repository containing a directory, like `open`. Relative
+    /// paths in GNEW_WORK_TREE and GNEW_DIR are relative to the directory.
+    pub fn open_at<P: AsRef<Path>>(dir: P) -> Result<Repository> {
+        let cwd = fs::canonicalize(dir)?;
+        let dir = env::var_os(transport::DIR_ENV).map(|dir| cwd.join(dir));
+        let worktree = match env::var_os(transport::WORK_TREE_ENV) {
+            Some(worktree) => fs::canonicalize(cwd.join(worktree))?,
+            None if dir.is_some() => cwd.clone(),
+            None => transport::find_repo(&cwd)?,
+        };
+        let storage_dir = match dir {
+            Some(dir) => fs::canonicalize(&dir).unwrap_or(dir),
+            None => worktree.join(".gnew"),
+        };
+        let quarantine = env::var_os(transport::QUARANTINE_ENV).map(PathBuf::from);
+        transport::set_store(&Store {
+            worktree: worktree.clone(),
+            storage_dir: storage_dir.clone(),
+            quarantine: quarantine.clone(),
+        });
         let storage_dir = transport::check_repo_exists(&worktree)?;
+        let prefix = cwd.strip_prefix(&worktree).unwrap_or(Path::new("")).to_owned();
 
         Ok(Repository {
             head: transport::read_head(&worktree)?,
-            branches: transport::read_branches(&worktree)?,
+            refs: transport::read_refs(&worktree)?,
             tracklist: transport::read_tracklist(&worktree)?,
+            tracklist_batch: None,
+            rerere_resolved: vec![],
+            cache: RefCell::new(transport::read_stat_cache(&worktree)),
+            attributes: OnceCell::new(),
             worktree,
             storage_dir,
+            prefix,
+            quarantine,
         })
     }
 
@@ -104,10 +460,16 @@ impl Repository {
 
         Ok(Repository {
             head: transport::read_head(&worktree)?,
-            branches: transport::read_branches(&worktree)?,
+            refs: transport::read_refs(&worktree)?,
             tracklist: transport::read_tracklist(&worktree)?,
+            tracklist_batch: None,
+            rerere_resolved: vec![],
+            cache: RefCell::new(transport::read_stat_cache(&worktree)),
+            attributes: OnceCell::new(),
             worktree,
             storage_dir,
+            prefix: PathBuf::new(),
+            quarantine: None,
         })
     }
 
@@ -115,12 +477,96 @@ impl Repository {
         &self.storage_dir
     }
 
+    /// Returns the store that objects of the repository are read from and
+    /// written to.
+    pub fn store(&self) -> Store {
+        Store {
+            worktree: self.worktree.clone(),
+            storage_dir: self.storage_dir.clone(),
+            quarantine: self.quarantine.clone(),
+        }
+    }
+
+    /// Makes the repository the one the current thread reads and writes
+    /// objects of, until the guard is dropped. Opening a repository makes it
+    /// the current one; this switches back to it from another.
+    pub fn enter(&self) -> StoreGuard {
+        transport::enter_store(&self.store())
+    }
+
+    pub fn worktree(&self) -> &Path {
+        &self.worktree
+    }
+
+    pub fn tracklist(&self) -> &Tracklist {
+        &self.tracklist
+    }
+
+    pub(crate) fn set_tracklist(&mut self, tracklist: Tracklist) -> Result<()> {
+        self.tracklist = tracklist;
+        self.save_tracklist()
+    }
+
+    /// Writes the tracklist, or only marks it as changed during a batch of
+    /// updates.
+    fn save_tracklist(&mut self) -> Result<()> {
+        match &mut self.tracklist_batch {
+            Some(changed) => *changed = true,
+            None => transport::write_tracklist(&self.worktree, &self.tracklist)?,
+        }
+        Ok(())
+    }
+
+    /// Runs an operation that updates the tracklist several times, and
+    /// writes it once when the operation returns, even if it failed, so
+    /// that it matches the files written so far. Batches may be nested.
+    pub(crate) fn batch_tracklist<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if self.tracklist_batch.is_some() {
+            return f(self);
+        }
+        self.tracklist_batch = Some(false);
+        let result = f(self);
+        if self.tracklist_batch.take() == Some(true) {
+            self.save_tracklist()?;
+        }
+        result
+    }
+
+    /// Returns the directory the repository was opened from, relative to
+    /// the working tree.
+    pub fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Resolves a path given relative to the directory the repository was
+    /// opened from.
+    pub fn resolve_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.worktree.join(&self.prefix).join(path)
+    }
+
     pub fn head(&self) -> &Reference {
         &self.head
     }
 
+    /// Returns the commit of HEAD, or an `EmptyBranch` error if HEAD is a
+    /// branch without commits yet.
     pub fn head_hash(&self) -> Result<Hash> {
-        self.resolve_reference(&self.head)
+        match &self.head {
+            Reference::Branch(b) => self.branch(b).or(Err(EmptyBranch(b.clone()))),
+            r => self.resolve_reference(r),
+        }
+    }
+
+    /// Returns the tree of HEAD, which is empty on a branch without commits.
+    pub fn head_tree(&self) -> Result<Tree> {
+        match self.head_hash() {
+            Ok(hash) => transport::read_commit(hash)?.tree(),
+            Err(EmptyBranch(_)) => Ok(Tree::new()),
+            Err(err) => Err(err),
+        }
     }
 
     fn resolve_reference(&self, r: &Reference) -> Result<Hash> {
@@ -130,66 +576,268 @@ impl Repository {
         }
     }
 
-    fn set_head(&mut self, head: Reference) -> Result<()> {
+    pub(crate) fn set_head(&mut self, head: Reference) -> Result<()> {
         transport::write_head(&self.worktree, &head)?;
-        Ok(self.head = head)
+        self.head = head;
+        Ok(())
+    }
+
+    /// Points HEAD to another reference, recording the current one as the
+    /// previous HEAD.
+    fn switch_head(&mut self, head: Reference) -> Result<()> {
+        if head != self.head {
+            transport::write_prev_head(&self.worktree, &self.head)?;
+        }
+        self.set_head(head)
+    }
+
+    /// Returns the reference checked out before the current one, if any.
+    pub fn previous_head(&self) -> Result<Option<Reference>> {
+        transport::read_prev_head(&self.worktree)
     }
 
     pub fn branch(&self, name: &str) -> Result<Hash> {
-        self.branches.get(name).copied().ok_or(ReferenceNotFound)
+        self.refs
+            .get(&refs::branch_ref(name))
+            .ok_or(ReferenceNotFound)
+    }
+
+    /// Returns the branch names and their heads.
+    pub fn branches(&self) -> HashMap<String, Hash> {
+        self.refs
+            .namespace(refs::HEADS)
+            .map(|(name, hash)| (name.to_owned(), hash))
+            .collect()
+    }
+
+    pub(crate) fn set_branch(&mut self, name: &str, hash: Hash) -> Result<()> {
+        self.set_reference(&refs::branch_ref(name), hash)
+    }
+
+    /// Returns all references by their full name, e.g. `refs/heads/main`.
+    pub fn references(&self) -> &Refs {
+        &self.refs
     }
 
-    pub fn branches(&self) -> &HashMap<String, Hash> {
-        &self.branches
+    /// Returns the names of the references pointing to each commit, as shown
+    /// in the log: `HEAD -> <branch>` for the current branch, `HEAD` if
+    /// detached, `<branch>`, `tag: <tag>` and `<remote>/<branch>`.
+    pub fn decorations(&self) -> HashMap<Hash, Vec<String>> {
+        let mut decorations: HashMap<Hash, Vec<String>> = HashMap::new();
+
+        if let Reference::Hash(hash) = self.head {
+            decorations.entry(hash).or_default().push("HEAD".to_owned());
+        }
+        for (name, hash) in self.refs.iter() {
+            let decoration = if let Some(branch) = name.strip_prefix(refs::HEADS) {
+                match &self.head {
+                    Reference::Branch(b) if b == branch => format!("HEAD -> {}", branch),
+                    _ => branch.to_owned(),
+                }
+            } else if let Some(tag) = name.strip_prefix(refs::TAGS) {
+                format!("tag: {}", tag)
+            } else if let Some(remote) = name.strip_prefix(refs::REMOTES) {
+                remote.to_owned()
+            } else {
+                name.to_owned()
+            };
+            decorations.entry(hash).or_default().push(decoration);
+        }
+        /* HEAD first, then local branches, remote branches and tags */
+        for names in decorations.values_mut() {
+            names.sort_by_key(|n| {
+                let rank = if n.starts_with("HEAD") {
+                    0
+                } else if n.starts_with("tag: ") {
+                    3
+                } else if n.contains('/') {
+                    2
+                } else {
+                    1
+                };
+                (rank, n.clone())
+            });
+        }
+        decorations
     }
 
-    pub fn branches_mut(&mut self) -> &mut HashMap<String, Hash> {
-        &mut self.branches
+    pub(crate) fn set_reference(&mut self, name: &str, hash: Hash) -> Result<()> {
+        transport::write_ref(&self.worktree, name, hash)?;
+        self.refs.set(name, hash);
+        Ok(())
     }
 
-    fn set_branch(&mut self, name: &str, hash: Hash) -> Result<()> {
-        transport::write_branch(&self.worktree, name, hash)?;
-        self.branches.insert(name.to_owned(), hash);
+    fn remove_reference(&mut self, name: &str) -> Result<()> {
+        transport::remove_loose_ref(&self.worktree, name)?;
+        let mut packed = transport::read_packed_refs(&self.worktree)?;
+        if packed.remove(name).is_some() {
+            transport::write_packed_refs(&self.worktree, &packed)?;
+        }
+        self.refs.remove(name);
         Ok(())
     }
 
+    /// Deletes the branches fully merged into a commit and returns them with
+    /// their heads. The current branch, the target branch and the branches
+    /// listed in the `branch.protected` setting are kept.
+    pub fn delete_merged_branches(&mut self, target: &str) -> Result<Vec<(String, Hash)>> {
+        let target_hash = self.rev_parse(target)?;
+        let config = transport::read_config(&self.worktree)?;
+        let mut protected: HashSet<&str> = config
+            .get("branch.protected")
+            .unwrap_or("")
+            .split_whitespace()
+            .collect();
+        protected.insert(target);
+        if let Reference::Branch(current) = &self.head {
+            protected.insert(current);
+        }
+
+        let mut merged = vec![];
+        for (branch, hash) in self.refs.namespace(refs::HEADS) {
+            if !protected.contains(branch) && self.is_ancestor(hash, target_hash)? {
+                merged.push((branch.to_owned(), hash));
+            }
+        }
+        for (branch, _) in &merged {
+            self.remove_reference(&refs::branch_ref(branch))?;
+        }
+        Ok(merged)
+    }
+
+    /// Moves all loose references into the packed references file.
+    /// Returns the number of packed references.
+    pub fn pack_refs(&self) -> Result<usize> {
+        transport::write_packed_refs(&self.worktree, &self.refs)?;
+        for (name, _) in self.refs.iter() {
+            transport::remove_loose_ref(&self.worktree, name)?;
+        }
+        Ok(self.refs.len())
+    }
+
     /// Updates HEAD to point to a new branch.
     pub fn create_branch(&mut self, name: &str) -> Result<()> {
-        if self.branches.contains_key(name) {
-            return Err(BranchExists);
-        }
+        self.check_new_branch(name)?;
         if let Ok(hash) = self.head_hash() {
             self.set_branch(name, hash)?;
         }
-        self.set_head(Reference::Branch(name.to_owned()))
+        self.switch_head(Reference::Branch(name.to_owned()))
+    }
+
+    /// Checks that a branch can be created with the given name: that it is
+    /// valid and that no branch has it or is nested in it or around it.
+    pub(crate) fn check_new_branch(&self, name: &str) -> Result<()> {
+        refs::check_new_name(name)?;
+        let branch_ref = refs::branch_ref(name);
+        if self.refs.contains(&branch_ref) {
+            return Err(BranchExists);
+        }
+        if let Some(existing) = self.refs.conflicting(&branch_ref) {
+            let existing = refs::short_name(existing).to_owned();
+            return Err(BranchConflict(name.to_owned(), existing));
+        }
+        Ok(())
+    }
+
+    /// Returns the best common ancestor of two commits, or None if their
+    /// histories are unrelated.
+    pub fn merge_base(&self, a: Hash, b: Hash) -> Result<Option<Hash>> {
+        let a = transport::read_commit(a)?;
+        let b = transport::read_commit(b)?;
+        Ok(a.into_common_ancestor(b)?.map(|c| c.hash()))
+    }
+
+    /// Returns whether commit `a` is reachable from commit `b`. A commit is
+    /// its own ancestor.
+    pub fn is_ancestor(&self, a: Hash, b: Hash) -> Result<bool> {
+        for commit in RevWalk::new(&[b], RevWalkOptions::default())? {
+            if commit?.hash() == a {
+                return Ok(true);
+            }
+        }
+        Ok(false)
     }
 
     /// Returns the commit specified by a revision string.
-    /// Supported formats: HEAD, <branch>, <hash>.
+    /// Supported formats: HEAD, <branch>, <hash>, <remote>/<branch> and
+    /// `@{-1}` (the previous HEAD), each optionally followed by `~<n>` (the
+    /// n-th ancestor) or `^` (the parent), e.g. `HEAD~2`.
     pub fn rev_parse(&self, r: &str) -> Result<Hash> {
-        if r == "HEAD" {
-            self.head_hash()
+        let (base, mut suffix) = r.split_at(r.find(['~', '^']).unwrap_or(r.len()));
+        let mut hash = if base == "HEAD" {
+            self.head_hash().map_err(|err| match err {
+                EmptyBranch(_) => err,
+                _ => RevisionNotFound,
+            })?
+        } else if base == "@{-1}" {
+            let prev = self.previous_head()?.ok_or(NoPreviousHead)?;
+            self.resolve_reference(&prev).or(Err(RevisionNotFound))?
         } else {
-            r.parse().or_else(|_| self.branch(r))
+            base.parse().or_else(|_| self.branch(base)).or_else(|_| {
+                self.refs
+                    .get(&format!("{}{}", refs::REMOTES, base))
+                    .ok_or(RevisionNotFound)
+            })
+            .or(Err(RevisionNotFound))?
+        };
+
+        while let Some(op) = suffix.chars().next() {
+            suffix = &suffix[1..];
+            let digits = suffix
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(suffix.len());
+            let n = match (&suffix[..digits], op) {
+                ("", _) => 1,
+                (n, '~') => n.parse().or(Err(RevisionNotFound))?,
+                ("1", '^') => 1,
+                _ => return Err(RevisionNotFound),
+            };
+            suffix = &suffix[digits..];
+
+            for _ in 0..n {
+                hash = transport::read_commit(hash)?
+                    .parent_hash()
+                    .ok_or(RevisionNotFound)?;
+            }
         }
-        .or(Err(RevisionNotFound))
+        Ok(hash)
     }
 
     /// Checks if a file is tracked.
     /// The path can be absolute or relative to the working tree.
     pub fn is_tracked(&self, path: &Path) -> bool {
         let path = path.strip_prefix(&self.worktree).unwrap_or(path);
-        self.tracklist.contains(&path.to_str().unwrap().to_owned())
+        self.tracklist.contains(path.to_str().unwrap())
     }
 
-    /// Returns the working tree status.
+    /// Reads the ignore files of the working tree.
+    pub fn ignore_matcher(&self) -> Result<IgnoreMatcher> {
+        IgnoreMatcher::load(&self.worktree, |p| self.is_repo_dir(p))
+    }
+
+    /// Returns the attributes of the working tree paths.
+    pub fn attributes(&self) -> Result<&Attributes> {
+        if let Some(attributes) = self.attributes.get() {
+            return Ok(attributes);
+        }
+        let attributes = Attributes::load(&self.worktree, |p| self.is_repo_dir(p))?;
+        Ok(self.attributes.get_or_init(|| attributes))
+    }
+
+    /// Returns the working tree status. Ignored untracked files are left out.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn status(&self, tree: &Tree) -> Result<Status> {
         let mut status = HashMap::new();
         let mut head_files = HashMap::new();
+        let ignore = self.ignore_matcher()?;
 
         for f in tree.files() {
-            let File { path, hash } = f?;
-            head_files.insert(path, hash);
+            let File {
+                path,
+                hash,
+                executable,
+            } = f?;
+            head_files.insert(path, (hash, executable));
         }
         for f in self.walk_worktree(Path::new(".")) {
             let f = f?;
@@ -198,12 +846,15 @@ impl Repository {
 
             let fstatus = match (head_files.remove(rpath), self.is_tracked(path)) {
                 (None, true) => FileStatus::Added,
+                (None, false) if ignore.is_ignored(rpath, false) => continue,
                 (None, false) => FileStatus::Untracked,
-                (Some(hash), true) => {
-                    if object::hash_file(path)? == hash {
-                        FileStatus::Unmodified
-                    } else {
+                (Some((hash, executable)), true) => {
+                    if self.hash_worktree_file(path)? != hash {
                         FileStatus::Modified
+                    } else if object::executable_bit(path).is_some_and(|e| e != executable) {
+                        FileStatus::ModeChanged
+                    } else {
+                        FileStatus::Unmodified
                     }
                 }
                 (Some(_), false) => FileStatus::Deleted,
@@ -218,57 +869,267 @@ impl Repository {
             };
             status.insert(path, fstatus);
         }
+        self.save_cache()?;
         Ok(status)
     }
 
     /// Writes a tree object from the working directory.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn write_tree(&self) -> Result<Tree> {
-        let mut tree = self.write_tree_rec(&self.worktree)?;
-        transport::write_tree(&mut tree)?;
+        let parent = self
+            .head_hash()
+            .and_then(transport::read_commit)
+            .and_then(|c| c.tree())
+            .ok();
+        let tree = self.write_tree_rec(&self.worktree, parent.as_ref())?;
+        self.save_cache()?;
         Ok(tree)
     }
 
-    fn write_tree_rec(&self, dir: &Path) -> Result<Tree> {
+    /// Writes the tree of a directory and its subtrees. Blob hashes are taken
+    /// from the stat cache for unchanged files, and the parent's tree is reused
+    /// if the directory has the same entries.
+    fn write_tree_rec(&self, dir: &Path, parent: Option<&Tree>) -> Result<Tree> {
         let mut tree = Tree::new();
 
         for entry in fs::read_dir(dir)? {
             let entry = entry?;
             let path = entry.path();
-            if path.starts_with(&self.storage_dir) {
+            if self.is_repo_dir(&path) {
                 continue;
             }
             let fname = entry.file_name().to_str().unwrap().to_owned();
 
             if entry.file_type()?.is_dir() {
-                let mut subtree = self.write_tree_rec(&path)?;
+                let parent = parent.and_then(|t| t.subtree(&fname));
+                let subtree = self.write_tree_rec(&path, parent.as_ref())?;
                 if !subtree.is_empty() {
-                    transport::write_tree(&mut subtree)?;
                     tree.add_tree(subtree.hash(), fname)
                 }
             } else if self.is_tracked(&path) {
-                tree.add_blob(transport::write_blob(path)?.hash(), fname)
+                // without permission bits, keep the mode of the parent's file
+                let executable = object::executable_bit(&path).unwrap_or_else(|| {
+                    parent.and_then(|t| t.file(&fname).ok()).is_some_and(|f| f.executable)
+                });
+                tree.add_file(self.write_worktree_blob(&path)?, fname, executable)
             }
         }
-        Ok(tree)
+        match parent {
+            Some(parent) if parent.same_entries(&tree) => Ok(parent.clone()),
+            _ => {
+                transport::write_tree(&mut tree)?;
+                Ok(tree)
+            }
+        }
+    }
+
+    /// Writes a blob object for a working tree file unless the stat cache
+    /// shows that it is unchanged and already stored, or a blob with the
+    /// same contents is.
+    fn write_worktree_blob(&self, path: &Path) -> Result<Hash> {
+        let key = self.cache_key(path);
+        let md = fs::metadata(self.worktree.join(path))?;
+
+        match self.cache.borrow().get(&key, &md) {
+            Some(hash) if transport::object_exists(hash) => return Ok(hash),
+            _ => (),
+        }
+        let hash = transport::write_blob_if_absent(self.read_worktree_file(path)?)?;
+        self.cache.borrow_mut().insert(key, &md, hash);
+        Ok(hash)
+    }
+
+    /// Computes the blob hash of a working tree file, using the stat cache
+    /// when the file is unchanged since it was last hashed.
+    fn hash_worktree_file(&self, path: &Path) -> Result<Hash> {
+        let key = self.cache_key(path);
+        let md = fs::metadata(self.worktree.join(path))?;
+
+        if let Some(hash) = self.cache.borrow().get(&key, &md) {
+            return Ok(hash);
+        }
+        let hash = object::hash_blob(&self.read_worktree_file(path)?);
+        self.cache.borrow_mut().insert(key, &md, hash);
+        Ok(hash)
+    }
+
+    /// Reads a working tree file as it is stored, with the line endings
+    /// of text files normalized.
+    fn read_worktree_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let data = fs::read(self.worktree.join(path))?;
+        let path = path.strip_prefix(&self.worktree).unwrap_or(path);
+        Ok(self.attributes()?.clean(path, data))
+    }
+
+    fn cache_key(&self, path: &Path) -> String {
+        let path = path.strip_prefix(&self.worktree).unwrap_or(path);
+        path.to_str().unwrap().to_owned()
+    }
+
+    fn save_cache(&self) -> Result<()> {
+        let cache = self.cache.borrow();
+        if cache.is_dirty() {
+            transport::write_stat_cache(&self.worktree, &cache)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the changes that a commit of the working tree would record.
+    pub fn commit_summary(&self) -> Result<CommitSummary> {
+        let mut status: Vec<_> = self.status(&self.head_tree()?)?.into_iter().collect();
+        status.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut summary = CommitSummary::default();
+        for (path, fstatus) in status {
+            match fstatus {
+                FileStatus::Added => summary.added.push(path),
+                FileStatus::Modified | FileStatus::ModeChanged => summary.modified.push(path),
+                FileStatus::Deleted | FileStatus::Missing => summary.deleted.push(path),
+                FileStatus::Untracked | FileStatus::Unmodified => (),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Returns the tracked files that are missing from the working tree.
+    fn missing_files(&self) -> Vec<PathBuf> {
+        self.tracklist
+            .iter()
+            .filter(|p| fs::symlink_metadata(self.worktree.join(p)).is_err())
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    /// Records the tracked files in a new commit on HEAD. The message is
+    /// passed to the `commit-msg` hook, which may edit it, and is checked
+    /// against the message policy before any objects are written.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn commit(&mut self, msg: String, options: &CommitOptions) -> Result<Commit> {
+        if let Some(author) = &options.author {
+            if author.trim().is_empty() || author.contains('\n') {
+                return Err(InvalidAuthor(author.clone()));
+            }
+        }
+        let missing = self.missing_files();
+        if !missing.is_empty() && !options.force {
+            return Err(FilesMissing(missing));
+        }
+        let msg = self.prepare_message(msg)?;
+        self.create_commit(msg, options)
     }
 
-    pub fn commit(&mut self, msg: String) -> Result<Commit> {
+    fn create_commit(&mut self, msg: String, options: &CommitOptions) -> Result<Commit> {
         let tree = self.write_tree()?;
-        let user = env::var("USER").unwrap_or_else(|_| "noname".to_owned());
+        let author = match &options.author {
+            Some(author) => author.clone(),
+            None => self.identity()?,
+        };
+
+        let time = match (options.time, env::var("GNEW_AUTHOR_DATE")) {
+            (Some(time), _) => time,
+            (None, Ok(date)) => object::parse_time(&date)?,
+            (None, Err(_)) => Utc::now(),
+        };
+
+        let merged = transport::read_merge_head(&self.worktree)?;
 
         let mut commit = Commit::new(CommitInfo {
             tree: tree.hash(),
-            parent: self.head_hash().ok(),
-            author: user,
-            time: Utc::now(),
+            parents: self.head_hash().ok().into_iter().chain(merged).collect(),
+            author,
+            time,
             msg,
         });
 
         transport::write_commit(&mut commit)?;
         self.update_head(commit.hash())?;
+        self.rerere_record()?;
+        self.clear_merge_state()?;
         Ok(commit)
     }
 
+    /// Returns the identity recorded in commits: `user.name` and `user.email`
+    /// from the repository or user configuration, as `Name <email>`.
+    /// `GNEW_AUTHOR_NAME` and `GNEW_AUTHOR_EMAIL` take precedence over them.
+    /// Falls back to the login name if `user.name` is not set.
+    pub fn identity(&self) -> Result<String> {
+        let config = transport::read_config(&self.worktree)?;
+        let user_config = transport::read_user_config()?;
+        let get = |key, var| {
+            env::var(var)
+                .ok()
+                .or_else(|| config.get(key).or_else(|| user_config.get(key)).map(str::to_owned))
+        };
+
+        let name = match get("user.name", "GNEW_AUTHOR_NAME") {
+            Some(name) => name,
+            None => env::var("USER").unwrap_or_else(|_| "noname".to_owned()),
+        };
+        Ok(match get("user.email", "GNEW_AUTHOR_EMAIL") {
+            Some(email) => format!("{} <{}>", name, email),
+            None => name,
+        })
+    }
+
+    /// Appends a `Signed-off-by` trailer with the identity to a message.
+    pub fn sign_off(&self, msg: &str) -> Result<String> {
+        Ok(message::append_trailer(
+            msg,
+            "Signed-off-by",
+            &self.identity()?,
+        ))
+    }
+
+    /// Runs the `commit-msg` hook on a message and checks the result against
+    /// the message policy. Returns the message with whitespace cleaned up.
+    fn prepare_message(&self, msg: String) -> Result<String> {
+        let path = self.storage_dir.join("COMMIT_EDITMSG");
+        fs::write(&path, msg)?;
+        hooks::run_hook(&self.storage_dir, &self.worktree, "commit-msg", &[&path], None, &[])?;
+
+        let msg = message::clean_message(&fs::read_to_string(&path)?, false);
+        let config = transport::read_config(&self.worktree)?;
+        MessagePolicy::from_config(&config)?.check(&msg)?;
+        Ok(msg)
+    }
+
+    /// Returns a commit message written in an editor, starting from the
+    /// `commit.template` file if it is set. The editor is taken from
+    /// `GNEW_EDITOR` or `EDITOR`; without one the template is used as is.
+    /// Comment lines starting with `#` are removed.
+    pub fn edit_message(&self) -> Result<String> {
+        let config = transport::read_config(&self.worktree)?;
+        let template = match config.get("commit.template") {
+            Some(path) => fs::read_to_string(self.worktree.join(path))?,
+            None => String::new(),
+        };
+        let msg = self.edit_file("COMMIT_EDITMSG", &template)?;
+        Ok(message::clean_message(&msg, true))
+    }
+
+    /// Writes a file of the storage directory and returns its contents after
+    /// editing it in the editor of `GNEW_EDITOR` or `EDITOR`, if one is set.
+    pub(crate) fn edit_file(&self, name: &str, content: &str) -> Result<String> {
+        let path = self.storage_dir.join(name);
+        fs::write(&path, content)?;
+
+        if let Some(editor) = env::var_os("GNEW_EDITOR").or_else(|| env::var_os("EDITOR")) {
+            let mut script = editor;
+            script.push(" \"$@\"");
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(script)
+                .arg("editor")
+                .arg(&path)
+                .current_dir(&self.worktree)
+                .status()?;
+            if !status.success() {
+                return Err(InvalidCommitMessage("editor failed".to_owned()));
+            }
+        }
+        Ok(fs::read_to_string(&path)?)
+    }
+
     fn update_head(&mut self, commit: Hash) -> Result<()> {
         match &self.head.clone() {
             Reference::Hash(_) => self.set_head(Reference::Hash(commit)),
@@ -276,8 +1137,41 @@ impl Repository {
         }
     }
 
-    pub fn checkout(&mut self, new_head: Reference, force: bool) -> Result<()> {
-        let hash = self.resolve_reference(&new_head)?;
+    pub fn checkout(&mut self, new_head: Reference, force: bool) -> Result<CheckoutSummary> {
+        self.checkout_with_progress(new_head, force, &|_, _| ())
+    }
+
+    /// Checks out a commit like `checkout`, calling `progress` with the
+    /// number of files updated so far and their total as the working tree is
+    /// updated. Returns what changed in the working tree.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "checkout", skip_all))]
+    pub fn checkout_with_progress(
+        &mut self,
+        new_head: Reference,
+        force: bool,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Result<CheckoutSummary> {
+        let summary = self.checkout_files(&new_head, force, progress)?;
+        self.switch_head(new_head)?;
+        Ok(summary)
+    }
+
+    /// Checks out a commit like `checkout`, without recording the previous
+    /// HEAD, for operations that move HEAD as part of something else.
+    fn reset_to(&mut self, new_head: Reference, force: bool) -> Result<()> {
+        self.checkout_files(&new_head, force, &|_, _| ())?;
+        self.set_head(new_head)
+    }
+
+    /// Updates the working tree to the commit of a reference, leaving HEAD
+    /// alone.
+    fn checkout_files(
+        &mut self,
+        new_head: &Reference,
+        force: bool,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Result<CheckoutSummary> {
+        let hash = self.resolve_reference(new_head)?;
 
         /* first, we need to make sure that we are safe to switch to another commit,
          * which means there all the files in the dir are either Unmodified or Missing
@@ -285,130 +1179,338 @@ impl Repository {
 
         /* if checkout is forced, skip the safe switch check */
         if !force {
-            self.check_safe_switch()?;
+            self.check_safe_switch(true)?;
         }
 
         /* next, we can do the actual checkout */
 
         /* read commit by hash, get tree */
         let tree = transport::read_commit(hash)?.tree()?;
-        let status = self.status(&tree)?;
-        let mut tree_files = HashMap::new();
+        let plan = self.plan_checkout(&tree, force)?;
+        let summary = self.checkout_summary(&plan);
+        self.apply_checkout_with_progress(plan, progress)?;
+        self.clear_merge_state()?;
+        Ok(summary)
+    }
 
-        for f in tree.files() {
-            let File { path, hash } = f?;
-            tree_files.insert(path, hash);
-        }
+    /// Computes the file operations that update the working tree to a tree.
+    /// Untracked files are kept, unless the checkout is forced, and it fails
+    /// if the tree has different contents at the path of one of them.
+    pub fn plan_checkout(&self, tree: &Tree, force: bool) -> Result<CheckoutPlan> {
+        let status = self.status(tree)?;
+        let mut plan = CheckoutPlan::default();
+        let mut overwritten = vec![];
 
-        for f in status {
-            match f.1 {
-                /* file was added, remove */
-                FileStatus::Added => {
-                    fs::remove_file(self.worktree.join(f.0))?;
-                }
-                /* file was deleted or went missing, copy over */
-                FileStatus::Deleted | FileStatus::Missing => {
-                    self.copy_objects_to_files(&tree_files, f.0)?;
-                }
-                /* file was modified, remove and copy over */
-                FileStatus::Modified => {
-                    fs::remove_file(self.worktree.join(&f.0))?;
-                    self.copy_objects_to_files(&tree_files, f.0)?;
-                }
+        for f in tree.files() {
+            let File {
+                path,
+                hash,
+                executable,
+            } = f?;
+            match status.get(&path) {
                 /* file is the same, do nothing */
-                FileStatus::Unmodified => continue,
-                /* if checkout was forced, delete the untracked file */
-                FileStatus::Untracked => {
-                    if force {
-                        fs::remove_file(self.worktree.join(f.0))?
-                    } else {
-                        return Err(CheckoutFailed);
+                Some(FileStatus::Unmodified) => (),
+                /* an untracked file is in the way */
+                Some(FileStatus::Deleted) if !force && self.is_untracked_file(&path) => {
+                    if self.hash_worktree_file(&self.worktree.join(&path))? != hash {
+                        overwritten.push(path.clone());
                     }
+                    plan.actions.push(CheckoutAction::Write(path.clone(), hash, executable))
                 }
-            };
+                /* file was modified, deleted or went missing, copy over */
+                _ => plan.actions.push(CheckoutAction::Write(path.clone(), hash, executable)),
+            }
+            plan.tracklist.insert(path.to_str().unwrap().to_owned());
         }
-
-        /* update tracklist on disc */
-        let mut new_tracklist = Vec::new();
-        for file in tree_files {
-            new_tracklist.push(file.0.to_str().unwrap().to_owned())
+        if !overwritten.is_empty() {
+            overwritten.sort();
+            return Err(UntrackedOverwritten(overwritten));
         }
-        transport::write_tracklist(&self.worktree, &new_tracklist)?;
-
-        /* update HEAD */
-        self.set_head(new_head)
-    }
 
-    fn copy_objects_to_files(&self, files: &HashMap<PathBuf, Hash>, f: PathBuf) -> Result<()> {
-        for file in files {
-            if *(file.0) == f {
-                fs::create_dir_all(self.worktree.join(file.0.parent().unwrap()))?;
-                let blob: Blob = transport::read_blob(*file.1).map(|blob| blob.into())?;
-                fs::write(self.worktree.join(file.0), blob.content())?;
-                break;
+        for (path, fstatus) in status {
+            match fstatus {
+                /* file was added, remove */
+                FileStatus::Added => plan.actions.push(CheckoutAction::Remove(path)),
+                /* if checkout was forced, delete the untracked file */
+                FileStatus::Untracked if force => plan.actions.push(CheckoutAction::Remove(path)),
+                _ => (),
             }
         }
-        Ok(())
+        self.check_type_changes(&plan)?;
+        Ok(plan)
     }
 
-    pub fn log(&self, amount: u32) -> Result<Vec<Commit>> {
-        let head_hash = match self.head_hash() {
-            Ok(hash) => hash,
-            Err(_) => return Ok(Vec::new()),
-        };
+    /// Returns what applying a checkout plan would change in the working
+    /// tree.
+    pub fn checkout_summary(&self, plan: &CheckoutPlan) -> CheckoutSummary {
+        let mut summary = CheckoutSummary::default();
+        for action in &plan.actions {
+            match action {
+                CheckoutAction::Write(path, ..) => {
+                    match fs::symlink_metadata(self.worktree.join(path)) {
+                        Ok(_) => summary.updated.push(path.clone()),
+                        Err(_) => summary.created.push(path.clone()),
+                    }
+                }
+                CheckoutAction::Remove(path) => summary.deleted.push(path.clone()),
+            }
+        }
+        summary
+    }
 
-        let mut count = 0;
-        let mut commit_iter = transport::read_commit(head_hash)?.into_iter();
-        let mut commit_vec: Vec<Commit> = Vec::new();
+    /// Checks if there is an untracked file at a path of the working tree.
+    fn is_untracked_file(&self, path: &Path) -> bool {
+        !self.is_tracked(path) && self.worktree.join(path).is_file()
+    }
 
-        while let Some(commit) = commit_iter.next() {
-            if amount != 0 && count == amount {
-                break;
+    /// Checks that the files of a plan can be written where the working tree
+    /// has a directory, or under a path that is a file, once the files the
+    /// plan removes are gone. This way a checkout fails before it changes
+    /// anything.
+    fn check_type_changes(&self, plan: &CheckoutPlan) -> Result<()> {
+        let removed: HashSet<_> = plan
+            .actions
+            .iter()
+            .filter_map(|a| match a {
+                CheckoutAction::Remove(path) => Some(self.worktree.join(path)),
+                _ => None,
+            })
+            .collect();
+
+        for action in &plan.actions {
+            let path = match action {
+                CheckoutAction::Write(path, ..) => self.worktree.join(path),
+                _ => continue,
+            };
+            let blocked_by_file = path
+                .ancestors()
+                .skip(1)
+                .take_while(|p| *p != self.worktree)
+                .any(|p| p.is_file() && !removed.contains(p));
+            let blocked_by_dir = path.is_dir()
+                && WalkDir::new(&path)
+                    .into_iter()
+                    .filter_map(|e| e.ok())
+                    .any(|e| !e.file_type().is_dir() && !removed.contains(e.path()));
+            if blocked_by_file || blocked_by_dir {
+                return Err(CheckoutFailed);
             }
+        }
+        Ok(())
+    }
+
+    /// Removes a working tree file and the directories it leaves empty.
+    fn remove_worktree_file(&self, path: &Path) -> Result<()> {
+        let path = self.worktree.join(path);
+        fs::remove_file(&path)?;
+        journal::remove_empty_parents(&self.worktree, &path);
+        Ok(())
+    }
+
+    /// Applies a checkout plan to the working tree and the tracklist. The
+    /// files that are replaced or removed are kept in a journal until all
+    /// files are written, so a checkout that fails leaves the working tree
+    /// as it was.
+    pub fn apply_checkout(&mut self, plan: CheckoutPlan) -> Result<()> {
+        self.apply_checkout_with_progress(plan, &|_, _| ())
+    }
 
-            commit_vec.push(commit?);
-            count += 1;
+    /// Applies a checkout plan like `apply_checkout`, calling `progress`
+    /// with the number of files updated so far and their total.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "apply_checkout", skip_all))]
+    pub fn apply_checkout_with_progress(
+        &mut self,
+        plan: CheckoutPlan,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Result<()> {
+        let mut journal = CheckoutJournal::begin(&self.storage_dir, &self.worktree)?;
+        if let Err(err) = self.write_checkout(plan.actions, &mut journal, progress) {
+            self.attributes = OnceCell::new();
+            let dir = self.storage_dir.join(journal::JOURNAL_DIR);
+            journal.rollback().or(Err(CheckoutInterrupted(dir)))?;
+            return Err(err);
         }
+        journal.commit()?;
 
-        Ok(commit_vec)
+        /* update tracklist on disc */
+        self.tracklist = plan.tracklist;
+        self.save_tracklist()
     }
 
-    pub fn add<P: AsRef<Path>>(&mut self, files: &Vec<P>) -> Result<()> {
+    /// Performs the file operations of a checkout, recording them in the
+    /// journal, and reports each file done to `progress`.
+    fn write_checkout(
+        &mut self,
+        actions: Vec<CheckoutAction>,
+        journal: &mut CheckoutJournal,
+        progress: &(dyn Fn(usize, usize) + Sync),
+    ) -> Result<()> {
+        let mut writes = vec![];
+        let mut dirs = BTreeSet::new();
+        let total = actions.len();
+        /* the workers report while holding the count, so that `progress`
+         * sees the counts in order */
+        let done = Mutex::new(0);
+        let report = || {
+            let mut done = done.lock().unwrap();
+            *done += 1;
+            progress(*done, total);
+        };
+
+        for action in actions {
+            match action {
+                CheckoutAction::Remove(path) => {
+                    journal.save(&path)?;
+                    report();
+                }
+                CheckoutAction::Write(path, hash, executable) => {
+                    journal.save(&path)?;
+                    journal.write(&path);
+                    let path = self.worktree.join(path);
+                    dirs.insert(path.parent().unwrap().to_owned());
+                    writes.push((path, hash, executable));
+                }
+            }
+        }
+        for dir in dirs {
+            fs::create_dir_all(dir)?;
+        }
+
+        /* write attributes files first, and the other files with the line
+         * endings of the attributes that are checked out */
+        let is_attributes = |w: &&(PathBuf, Hash, bool)| {
+            w.0.file_name().is_some_and(|n| n == ATTRIBUTES_FILE)
+        };
+        let (first, writes): (Vec<_>, Vec<_>) = writes.iter().partition(is_attributes);
+        let no_attributes = Attributes::new();
+        for write in first {
+            checkout_file(write, &self.worktree, &no_attributes)?;
+            report();
+        }
+        self.attributes = OnceCell::new();
+        let attributes = self.attributes()?;
+        let checkout_file = |write: &&(PathBuf, Hash, bool)| -> Result<()> {
+            checkout_file(write, &self.worktree, attributes)?;
+            report();
+            Ok(())
+        };
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        if writes.len() < PARALLEL_CHECKOUT_MIN || workers == 1 {
+            writes.iter().try_for_each(checkout_file)?;
+        } else {
+            let chunk_size = writes.len().div_ceil(workers);
+            let store = self.store();
+            thread::scope(|s| {
+                writes
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        let store = &store;
+                        s.spawn(move || {
+                            let _store = transport::enter_store(store);
+                            chunk.iter().try_for_each(checkout_file)
+                        })
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .try_for_each(|h| h.join().unwrap())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Returns up to `amount` commits of the history of HEAD selected by the
+    /// options, newest first, or all of them if `amount` is 0. A commit that
+    /// cannot be read is yielded as an error after the commits before it.
+    pub fn log(&self, amount: u32, options: RevWalkOptions) -> Result<iter::Take<RevWalk>> {
+        let tips: Vec<_> = self.head_hash().into_iter().collect();
+        let walk = RevWalk::new(&tips, options)?;
+
+        match amount {
+            0 => Ok(walk.take(usize::MAX)),
+            n => Ok(walk.take(n as usize)),
+        }
+    }
+
+    /// Adds files to the tracking list. In the `Update` and `All` modes, the
+    /// files are limited to the given paths, or the whole working tree if
+    /// there are none.
+    pub fn add<P: AsRef<Path>>(&mut self, files: &[P], mode: AddMode) -> Result<()> {
+        self.tracklist = tracklist::canonical_tracklist(&self.tracklist).0;
+        match mode {
+            AddMode::Paths => self.add_paths(files)?,
+            AddMode::Update | AddMode::All => {
+                let scope = files
+                    .iter()
+                    .map(|f| self.worktree_path(f.as_ref()))
+                    .collect::<Result<Vec<_>>>()?;
+                let in_scope = |path: &str| {
+                    scope.is_empty()
+                        || scope.iter().any(|s| {
+                            s.is_empty() || path == s || path.starts_with(&format!("{}/", s))
+                        })
+                };
+
+                /* untrack files that were deleted */
+                let worktree = self.worktree.clone();
+                self.tracklist
+                    .retain(|t| !in_scope(t) || worktree.join(t).exists());
+
+                if mode == AddMode::All {
+                    let ignore = self.ignore_matcher()?;
+                    let mut paths = vec![];
+                    for entry in self.walk_worktree(Path::new(".")) {
+                        let entry = entry?;
+                        let p = entry.path().strip_prefix(&self.worktree).unwrap();
+                        if ignore.is_ignored(p, false) && !self.is_tracked(p) {
+                            continue;
+                        }
+                        let p = tracklist::path_entry(p);
+                        if in_scope(&p) {
+                            paths.push(p);
+                        }
+                    }
+                    self.tracklist.extend(paths);
+                }
+            }
+        }
+        self.save_tracklist()
+    }
+
+    fn add_paths<P: AsRef<Path>>(&mut self, files: &[P]) -> Result<()> {
         transport::check_existence(files)?;
 
         for file in files {
             let file = fs::canonicalize(file)?;
             let f: &Path = file.strip_prefix(&self.worktree).unwrap();
-            let md = fs::metadata(f).unwrap();
+            if self.in_nested_repo(&file) {
+                return Err(InNestedRepository(f.to_owned()));
+            }
+            let md = fs::metadata(&file)?;
 
             if md.is_file() {
-                let p = f.to_str().unwrap().to_string();
-                if !self.tracklist.contains(&p) {
-                    self.tracklist.push(p);
-                }
+                self.tracklist.insert(tracklist::path_entry(f));
             } else if md.is_dir() {
+                let ignore = self.ignore_matcher()?;
                 let mut paths: Vec<String> = Vec::new();
 
                 for entry in self.walk_worktree(f.as_ref()) {
                     let entry = entry?;
                     let p = entry.path().strip_prefix(&self.worktree).unwrap();
-                    paths.push(p.to_str().unwrap().to_string());
-                }
-
-                for p in paths {
-                    if !self.tracklist.contains(&p) {
-                        self.tracklist.push(p);
+                    if ignore.is_ignored(p, false) && !self.is_tracked(p) {
+                        continue;
                     }
+                    paths.push(tracklist::path_entry(p));
                 }
+
+                self.tracklist.extend(paths);
             }
         }
-
-        transport::write_tracklist(&self.worktree, &self.tracklist)?;
-
         Ok(())
     }
 
     pub fn remove<P: AsRef<Path>>(&mut self, files: &Vec<P>) -> Result<()> {
+        self.tracklist = tracklist::canonical_tracklist(&self.tracklist).0;
+
         /* create dud files for files that don't exist on disk */
         let duds: Vec<&P> = files.iter().filter(|x| !x.as_ref().exists()).collect();
         for d in &duds {
@@ -419,22 +1521,21 @@ impl Repository {
         for f in files {
             let f = fs::canonicalize(f)?;
             let p = f.strip_prefix(&self.worktree).unwrap();
-            let md = fs::metadata(p).unwrap();
-            let mut prefix = p.to_str().unwrap().to_string();
+            let md = fs::metadata(&f)?;
+            let mut prefix = tracklist::path_entry(p);
 
             if md.is_file() {
                 /* remove file from tracklist */
-                self.tracklist.retain(|x| !(*x == prefix));
+                self.tracklist.retain(|x| *x != prefix);
             } else if md.is_dir() {
-                let mut tmp = [0u8; 4];
-                prefix = prefix + '/'.encode_utf8(&mut tmp);
+                prefix.push('/');
 
                 /* remove all files in a directory */
                 self.tracklist.retain(|x| !(*x).starts_with(&prefix));
             }
         }
 
-        transport::write_tracklist(&self.worktree, &self.tracklist)?;
+        self.save_tracklist()?;
 
         /* remove duds */
         for d in duds {
@@ -444,33 +1545,228 @@ impl Repository {
         Ok(())
     }
 
-    pub fn clone<P: AsRef<Path> + Copy>(src: P) -> Result<()> {
-        let options = dir::CopyOptions::new();
-        let paths = vec![src];
+    /// Removes tracked files from the tracking list and, unless `cached` is
+    /// set, from the working tree. Files that differ from HEAD are only
+    /// removed from disk with `force`.
+    /// Returns the removed paths.
+    pub fn rm<P: AsRef<Path>>(&mut self, files: &[P], options: &RmOptions) -> Result<Vec<String>> {
+        let mut removed = BTreeSet::new();
+
+        for file in files {
+            let path = self.worktree_path(file.as_ref())?;
+            let prefix = format!("{}/", path);
+            let matched: Vec<_> = self
+                .tracklist
+                .iter()
+                .filter(|t| **t == path || t.starts_with(&prefix) || path.is_empty())
+                .cloned()
+                .collect();
+
+            if matched.is_empty() {
+                return Err(FileNotFound);
+            }
+            if !options.recursive && !matched.contains(&path) {
+                return Err(NotRecursive);
+            }
+            removed.extend(matched);
+        }
+
+        if !options.cached && !options.force {
+            let modified = self.modified_files(&removed)?;
+            if !modified.is_empty() {
+                return Err(LocalModifications(modified));
+            }
+        }
+
+        for path in &removed {
+            self.tracklist.remove(path);
+        }
+        self.save_tracklist()?;
+
+        if !options.cached {
+            for path in &removed {
+                let path = self.worktree.join(path);
+                match fs::remove_file(&path) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                    _ => (),
+                }
+                /* remove directories that became empty */
+                for dir in path.ancestors().skip(1) {
+                    if dir == self.worktree || fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(removed.into_iter().collect())
+    }
+
+    /// Returns the path of a file relative to the working tree. The file
+    /// does not need to exist.
+    fn worktree_path(&self, path: &Path) -> Result<String> {
+        let path = match fs::canonicalize(path) {
+            Ok(path) => path,
+            Err(_) => {
+                let parent = path.parent().filter(|p| !p.as_os_str().is_empty());
+                let parent = fs::canonicalize(parent.unwrap_or_else(|| Path::new(".")))?;
+                parent.join(path.file_name().ok_or(FileNotFound)?)
+            }
+        };
+        let path = path.strip_prefix(&self.worktree).or(Err(FileNotFound))?;
+        Ok(path.to_str().unwrap().to_owned())
+    }
+
+    /// Returns the files on disk that are not in HEAD or differ from it.
+    fn modified_files(&self, paths: &BTreeSet<String>) -> Result<Vec<PathBuf>> {
+        let mut head_files = HashMap::new();
+        if let Ok(hash) = self.head_hash() {
+            for f in transport::read_commit(hash)?.tree()?.files() {
+                let File { path, hash, .. } = f?;
+                head_files.insert(path, hash);
+            }
+        }
 
-        match copy_items(&paths, ".", &options) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(RepositoryExists),
+        let mut modified = vec![];
+        for path in paths.iter().map(PathBuf::from) {
+            let file = self.worktree.join(&path);
+            if !file.exists() {
+                continue;
+            }
+            match head_files.get(&path) {
+                Some(&hash) if self.hash_worktree_file(&file)? == hash => (),
+                _ => modified.push(path),
+            }
         }
+        self.save_cache()?;
+        Ok(modified)
+    }
+
+    /// Copies a repository into a new directory of the same name in `dir`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn clone<P: AsRef<Path>, Q: AsRef<Path>>(
+        src: P,
+        dir: Q,
+        options: &CloneOptions,
+    ) -> Result<()> {
+        let src = fs::canonicalize(src)?;
+        let src_storage = transport::check_repo_exists(&src)?;
+        let dest = fs::canonicalize(dir)?.join(src.file_name().ok_or(NoRepository)?);
+        if dest.exists() {
+            return Err(RepositoryExists);
+        }
+        // check out the default branch of the source, as named by its HEAD
+        let source = Repository::open_remote(&src)?;
+        let head = match &options.branch {
+            Some(branch) => {
+                source.branch(branch)?;
+                Reference::Branch(branch.clone())
+            }
+            None => source.head.clone(),
+        };
+        transport::copy_repo_without_objects(&src, &dest)?;
+        let dest_storage = dest.join(".gnew");
+        transport::write_head(&dest, &source.head)?;
+
+        let history = match options.single_branch {
+            true => {
+                let tips: Vec<_> = source.resolve_reference(&head).into_iter().collect();
+                /* objects are read from the repository being walked */
+                let _source = source.enter();
+                Some(source.objects_between(&tips, &[])?)
+            }
+            false => None,
+        };
+        let mut objects = match &history {
+            Some(history) => history.iter().map(|h| PathBuf::from(h.to_string())).collect(),
+            None => transport::get_objects(&src_storage)?,
+        };
+        if let Some(reference) = &options.reference {
+            let reference = transport::check_repo_exists(fs::canonicalize(reference)?)?;
+            let shared = transport::get_all_objects(&reference)?;
+            objects.retain(|o| !shared.contains(o));
+
+            let mut alternates = transport::read_alternates(&dest_storage)?;
+            alternates.push(reference.join("objects"));
+            transport::write_alternates(&dest_storage, &alternates)?;
+        }
+        if options.without_blobs {
+            let mut kept = vec![];
+            for o in objects {
+                if transport::read_object_kind(&src_storage, &o)? != "blob" {
+                    kept.push(o);
+                }
+            }
+            objects = kept;
+        }
+        if options.local {
+            transport::link_objects(&src_storage, &dest_storage, &objects)?;
+        } else {
+            transport::copy_objects(&src_storage, &dest_storage, &objects)?;
+        }
+
+        let mut config = transport::read_config(&dest)?;
+        config.set("remote.origin.url", src.to_str().unwrap());
+        if options.without_blobs {
+            config.set("remote.origin.promisor", "true");
+        } else {
+            config.unset("remote.origin.promisor");
+        }
+        transport::write_config(&dest, &config)?;
+
+        let mut cloned = Repository::open_remote(&dest)?;
+        if let Some(history) = history {
+            /* keep the branch, and the other references whose history was copied */
+            let kept = match &head {
+                Reference::Branch(branch) => refs::branch_ref(branch),
+                Reference::Hash(_) => String::new(),
+            };
+            let dropped: Vec<_> = cloned
+                .refs
+                .iter()
+                .filter(|&(name, hash)| {
+                    name != kept && (name.starts_with(refs::HEADS) || !history.contains(&hash))
+                })
+                .map(|(name, _)| name.to_owned())
+                .collect();
+            for name in dropped {
+                cloned.remove_reference(&name)?;
+            }
+            if let Reference::Branch(branch) = &head {
+                config.set("remote.origin.fetch", branch);
+                transport::write_config(&dest, &config)?;
+            }
+        }
+        if head != source.head {
+            let _cloned = cloned.enter();
+            cloned.reset_to(head, true)?;
+        }
+        Ok(())
     }
 
     /// Returns the changes between a tree and the working tree.
+    /// Files whose size and modification time match the stat cache are not read.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub fn diff_worktree(&self, from: &Tree) -> Result<Vec<Change>> {
         let mut changes = vec![];
         let mut from_files = HashMap::new();
+        let mode_changed =
+            |f: &File, path: &Path| object::executable_bit(path).is_some_and(|e| e != f.executable);
 
         for f in from.files() {
             let f = f?;
             from_files.insert(f.path.to_str().unwrap().to_owned(), f);
         }
         for to in &self.tracklist {
-            let to_path = PathBuf::from(to);
+            let to_file = self.worktree.join(to);
+            let to_path = ChangeEntry::Path(PathBuf::from(to), to_file.clone());
 
             let change = match from_files.remove(to) {
-                Some(from) => match object::hash_file(&to_path) {
+                Some(from) => match self.hash_worktree_file(&to_file) {
                     Err(IoError(err)) if err.kind() == ErrorKind::NotFound => continue,
                     Err(err) => return Err(err),
-                    Ok(to_hash) if from.hash != to_hash => Change::new_modify(from, to_path),
+                    Ok(to_hash) if from.hash != to_hash || mode_changed(&from, &to_file) => {
+                        Change::new_modify(from, to_path)
+                    }
                     Ok(_) => continue,
                 },
                 None => Change::new_add(to_path),
@@ -480,22 +1776,32 @@ impl Repository {
         for from in from_files.into_values() {
             changes.push(Change::new_remove(from))
         }
-        Ok(changes)
+        self.save_cache()?;
+        Ok(object::detect_type_changes(changes))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn merge(&mut self, commit: Hash, options: &MergeOptions) -> Result<MergeStrategy> {
+        self.batch_tracklist(|r| r.merge_commit(commit, options))
     }
 
-    pub fn merge(&mut self, commit: Hash) -> Result<MergeStrategy> {
+    fn merge_commit(&mut self, commit: Hash, options: &MergeOptions) -> Result<MergeStrategy> {
         let ours = transport::read_commit(self.head_hash()?)?;
         let theirs = transport::read_commit(commit)?;
-        let base = ours.clone().into_common_ancestor(theirs.clone())?;
+        let base = match ours.clone().into_common_ancestor(theirs.clone())? {
+            None if !options.allow_unrelated_histories => return Err(UnrelatedHistories),
+            base => base,
+        };
+        let base_hash = base.as_ref().map(Commit::hash);
 
-        if base.hash() == theirs.hash() {
-            return Err(NothingToMerge);
+        if base_hash == Some(theirs.hash()) {
+            return Ok(MergeStrategy::AlreadyUpToDate);
         }
         self.is_clean(&ours.tree()?)?;
 
-        if base.hash() == ours.hash() {
+        if base_hash == Some(ours.hash()) {
             let old_head = self.head.clone();
-            self.checkout(Reference::Hash(theirs.hash()), false)?;
+            self.reset_to(Reference::Hash(theirs.hash()), false)?;
 
             if let Reference::Branch(b) = &old_head {
                 self.set_branch(b, theirs.hash())?;
@@ -504,6 +1810,33 @@ impl Repository {
             return Ok(MergeStrategy::FastForward);
         }
 
+        let conflicts = self.merge_into_worktree(&ours, base.as_ref(), &theirs)?;
+        transport::write_merge_head(&self.worktree, Some(commit))?;
+
+        if !conflicts.is_empty() {
+            return Err(MergeFailed(conflicts));
+        }
+        if !options.no_commit {
+            let msg = match &options.message {
+                Some(msg) => msg.clone(),
+                None => format!("Merge commit {}", commit),
+            };
+            self.create_commit(msg, &CommitOptions::default())?;
+        }
+        Ok(MergeStrategy::ThreeWay)
+    }
+
+    /// Applies the changes from `base` to `theirs` to the working tree, which
+    /// has the files of `ours`, and updates the tracklist. Files changed on
+    /// both sides are merged. Returns the conflicted files, whose versions are
+    /// recorded as the conflicts of the merge in progress, including those
+    /// resolved with recorded resolutions.
+    pub(crate) fn merge_into_worktree(
+        &mut self,
+        ours: &Commit,
+        base: Option<&Commit>,
+        theirs: &Commit,
+    ) -> Result<Vec<PathBuf>> {
         let filemap = |c: &Commit| -> Result<_> {
             let mut m = HashMap::new();
             for f in c.tree()?.files() {
@@ -512,31 +1845,78 @@ impl Repository {
             }
             Ok(m)
         };
-        let ourfiles = filemap(&ours)?;
-        let theirfiles = filemap(&theirs)?;
-        let basefiles = filemap(&base)?;
+        let mut ourfiles = filemap(ours)?;
+        let mut theirfiles = filemap(theirs)?;
+        let mut basefiles = match base {
+            Some(base) => filemap(base)?,
+            None => HashMap::new(),
+        };
+
+        // Files under a directory that one side moved are moved along with
+        // it on the other side, unless both sides moved the directory.
+        if let Some(base) = base {
+            let base_tree = base.tree()?;
+            let dir_renames = |c: &Commit| -> Result<Vec<(PathBuf, PathBuf)>> {
+                let tree = c.tree()?;
+                let changes = object::detect_renames(base_tree.diff(&tree)?)?;
+                Ok(object::detect_dir_renames(&changes, |d| tree.has_dir(d)))
+            };
+            let (our_renames, their_renames) = (dir_renames(ours)?, dir_renames(theirs)?);
+            let only = |renames: &[(PathBuf, PathBuf)], other: &[(PathBuf, PathBuf)]| {
+                let moved_by_other = |old: &PathBuf| other.iter().any(|(o, _)| o == old);
+                let only = renames.iter().filter(|(old, _)| !moved_by_other(old));
+                only.cloned().collect::<Vec<_>>()
+            };
+            let ours_only = only(&our_renames, &their_renames);
+            let theirs_only = only(&their_renames, &our_renames);
+
+            for (old, new) in relocate(&mut ourfiles, &theirs_only) {
+                self.remove_worktree_file(&old)?;
+                self.tracklist.remove(old.to_str().unwrap());
+                self.tracklist.insert(new.to_str().unwrap().to_owned());
+            }
+            relocate(&mut basefiles, &[theirs_only, ours_only.clone()].concat());
+            relocate(&mut theirfiles, &ours_only);
+        }
         let all: HashSet<_> = ourfiles.keys().chain(theirfiles.keys()).collect();
+        let attributes = self.attributes()?.clone();
         let mut conflicts = vec![];
 
+        // Paths theirs removed come first, so that directories can replace
+        // removed files. A file that is to be written where ours has a
+        // directory, or below a file, is a conflict.
+        let mut all: Vec<_> = all.into_iter().collect();
+        all.sort_by_key(|p| (theirfiles.contains_key(*p), *p));
+        let worktree = self.worktree.clone();
+        let blocked = |path: &Path| {
+            worktree.join(path).is_dir()
+                || path.ancestors().skip(1).any(|p| worktree.join(p).is_file())
+        };
+
         for path in all {
             let ours = ourfiles.get(path);
             let base = basefiles.get(path);
             let theirs = theirfiles.get(path);
 
             match (ours, base, theirs) {
+                // Ours removed it, theirs didn't change it
+                (None, Some(base), Some(theirs)) if base.hash == theirs.hash => (),
+                // Theirs has a file where ours has a directory, or the reverse
+                (_, _, Some(_)) if blocked(path) => {
+                    conflicts.push(path.to_owned());
+                }
                 // Theirs added it
                 (None, None, Some(theirs)) => {
-                    fs::create_dir_all(path.parent().unwrap())?;
-                    fs::write(path, theirs.contents()?)?;
-                    self.tracklist.push(path.to_str().unwrap().to_owned())
+                    let file = self.worktree.join(path);
+                    fs::create_dir_all(file.parent().unwrap())?;
+                    fs::write(file, theirs.contents()?)?;
+                    self.tracklist.insert(path.to_str().unwrap().to_owned());
                 }
                 // Ours didn't change it, theirs removed it
                 (Some(ours), Some(base), None) if ours.hash == base.hash => {
-                    fs::remove_file(path)?;
+                    self.remove_worktree_file(path)?;
                     self.tracklist.retain(|p| p != path.to_str().unwrap());
                 }
-                // Ours removed it, theirs didn't change it
-                (None, Some(base), Some(theirs)) if base.hash == theirs.hash => (),
                 // Merge needed
                 _ => {
                     let contents = |f: Option<&File>| f.map_or_else(|| Ok(vec![]), File::contents);
@@ -544,24 +1924,63 @@ impl Repository {
                     let ours = contents(ours)?;
                     let theirs = contents(theirs)?;
 
-                    let b = diffy::merge_bytes(&base, &ours, &theirs).unwrap_or_else(|b| {
-                        conflicts.push(path.to_owned());
-                        b
-                    });
-                    fs::write(path, &b)?;
+                    let b = match attributes.merge_driver(path) {
+                        MergeDriver::Text => diffy::merge_bytes(&base, &ours, &theirs)
+                            .unwrap_or_else(|b| {
+                                conflicts.push(path.to_owned());
+                                b
+                            }),
+                        MergeDriver::Ours => ours,
+                        MergeDriver::Theirs => theirs,
+                        MergeDriver::Binary => {
+                            if ours != theirs {
+                                conflicts.push(path.to_owned());
+                            }
+                            ours
+                        }
+                    };
+                    let file = self.worktree.join(path);
+                    fs::create_dir_all(file.parent().unwrap())?;
+                    fs::write(file, &b)?;
                 }
             }
         }
-        transport::write_tracklist(&self.worktree, &self.tracklist)?;
+        let stages = conflicts
+            .iter()
+            .map(|path| {
+                let stages = ConflictStages {
+                    base: basefiles.get(path).map(|f| f.hash),
+                    ours: ourfiles.get(path).map(|f| f.hash),
+                    theirs: theirfiles.get(path).map(|f| f.hash),
+                };
+                (path.to_str().unwrap().to_owned(), stages)
+            })
+            .collect();
+        self.save_tracklist()?;
+        transport::write_conflicts(&self.worktree, &stages)?;
+        self.rerere_resolved = self.rerere(&conflicts)?;
+        Ok(conflicts)
+    }
 
-        if conflicts.is_empty() {
-            Ok(MergeStrategy::ThreeWay)
-        } else {
-            Err(MergeFailed(conflicts))
-        }
+    /// Returns the versions of the conflicted files of the merge in progress.
+    pub fn conflicts(&self) -> Conflicts {
+        transport::read_conflicts(&self.worktree)
     }
 
-    fn is_clean(&self, tree: &Tree) -> Result<()> {
+    /// Returns the conflicted files of the last merge that recorded
+    /// resolutions resolved, with `rerere.enabled` set.
+    pub fn rerere_resolved(&self) -> &[PathBuf] {
+        &self.rerere_resolved
+    }
+
+    /// Forgets the merge in progress, if any.
+    pub(crate) fn clear_merge_state(&self) -> Result<()> {
+        transport::write_merge_head(&self.worktree, None)?;
+        transport::write_merge_rr(&self.worktree, &[])?;
+        transport::write_conflicts(&self.worktree, &Conflicts::new())
+    }
+
+    pub(crate) fn is_clean(&self, tree: &Tree) -> Result<()> {
         for fstatus in self.status(tree)?.values() {
             match fstatus {
                 FileStatus::Unmodified | FileStatus::Untracked => (),
@@ -571,59 +1990,277 @@ impl Repository {
         Ok(())
     }
 
+    /// Walks the files of the working tree under a path, leaving out
+    /// repository directories and nested working trees.
     fn walk_worktree(&self, path: &Path) -> impl Iterator<Item = walkdir::Result<DirEntry>> + '_ {
-        WalkDir::new(self.worktree.join(path))
+        let root = self.worktree.join(path);
+        let nested = self.in_nested_repo(&root);
+
+        WalkDir::new(root)
             .into_iter()
-            .filter_entry(|e| !e.path().starts_with(&self.storage_dir))
+            .filter_entry(move |e| !nested && !self.is_repo_dir(e.path()))
             .filter(|e| match e {
                 Ok(e) => !e.file_type().is_dir(),
                 _ => true,
             })
     }
 
-    pub fn pull<P: AsRef<Path>>(&mut self, path: P, all: bool) -> Result<()> {
-        self.check_safe_switch()?;
+    /// Checks if a path is a `.gnew` directory, the storage directory of
+    /// this repository or the working tree of a nested repository, other than
+    /// this repository's working tree.
+    pub(crate) fn is_repo_dir(&self, path: &Path) -> bool {
+        path != self.worktree
+            && (path.file_name() == Some(".gnew".as_ref())
+                || path.join(".gnew").exists()
+                || self.worktree.join(path) == self.storage_dir)
+    }
 
-        let remote = Repository::open_remote(path)?;
+    /// Checks if a path is inside a repository directory or nested working
+    /// tree of the working tree.
+    fn in_nested_repo(&self, path: &Path) -> bool {
+        path.ancestors()
+            .take_while(|a| a.starts_with(&self.worktree))
+            .any(|a| self.is_repo_dir(a))
+    }
+
+    /// Returns the refspecs set in `remote.<name>.fetch` or
+    /// `remote.<name>.push` for the remote whose `remote.<name>.url` is the
+    /// given repository, or None if there are none.
+    fn remote_refspecs(&self, remote: &Path, direction: &str) -> Result<Option<Vec<Refspec>>> {
+        let config = transport::read_config(&self.worktree)?;
+        for (key, url) in config.entries() {
+            let name = match key.strip_prefix("remote.").and_then(|k| k.strip_suffix(".url")) {
+                Some(name) => name,
+                None => continue,
+            };
+            if fs::canonicalize(self.worktree.join(url)).ok().as_deref() != Some(remote) {
+                continue;
+            }
+            if let Some(refspecs) = config.get(&format!("remote.{}.{}", name, direction)) {
+                return refspec::parse_refspecs(refspecs).map(Some);
+            }
+        }
+        Ok(None)
+    }
 
-        let remote_objects = transport::get_objects(&remote.storage_dir)?;
-        let local_objects = transport::get_objects(&self.storage_dir)?;
+    /// Maps branches with the refspecs, if any, leaving out the branches
+    /// they do not match.
+    fn map_branches(
+        branches: HashMap<String, Hash>,
+        refspecs: Option<Vec<Refspec>>,
+    ) -> Vec<(String, Hash)> {
+        branches
+            .into_iter()
+            .filter_map(|(name, hash)| match &refspecs {
+                Some(refspecs) => Some((refspec::map_branch(refspecs, &name)?, hash)),
+                None => Some((name, hash)),
+            })
+            .collect()
+    }
+
+    /// Copies the objects of another repository that are missing here.
+    /// Returns all the objects of the other repository and the number of
+    /// copied ones.
+    fn copy_remote_objects(&self, remote: &Repository) -> Result<(Vec<PathBuf>, usize)> {
+        let remote_objects = transport::get_all_objects(&remote.storage_dir)?;
+        let local_objects = transport::get_all_objects(&self.storage_dir)?;
 
         /* remove any objects that already exist */
         let mut to_copy = remote_objects.clone();
         to_copy.retain(|x| !local_objects.contains(x));
-        /* copy objects from remote to local */
+        /* copy objects from remote to local, refusing any that do not match their names */
         transport::copy_objects(&remote.storage_dir, &self.storage_dir, &to_copy)?;
+        Ok((remote_objects, to_copy.len()))
+    }
+
+    /// Returns the path configured in `remote.<name>.url`, resolved from the
+    /// working tree.
+    pub fn remote_path(&self, name: &str) -> Result<PathBuf> {
+        let config = transport::read_config(&self.worktree)?;
+        match config.get(&format!("remote.{}.url", name)) {
+            Some(url) => Ok(self.worktree.join(url)),
+            None => Err(RemoteNotFound(name.to_owned())),
+        }
+    }
+
+    /// Opens the remote repository configured in `remote.<name>.url`.
+    fn open_named_remote(&self, name: &str) -> Result<Repository> {
+        Repository::open_remote(self.remote_path(name)?)
+    }
+
+    /// Returns the branches of a remote repository selected by its `fetch`
+    /// refspecs, under the names they map to.
+    fn remote_branches(&self, remote: &Repository) -> Result<Vec<(String, Hash)>> {
+        let refspecs = self.remote_refspecs(&remote.worktree, "fetch")?;
+        Ok(Repository::map_branches(remote.branches(), refspecs))
+    }
+
+    /// Fetches the objects and branches of a configured remote into its
+    /// remote-tracking branches, `refs/remotes/<remote>/<branch>`. With
+    /// `prune`, also removes the remote-tracking branches whose branch no
+    /// longer exists. Returns the pruned branches.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn fetch(&mut self, name: &str, prune: bool) -> Result<Vec<String>> {
+        let remote = self.open_named_remote(name)?;
+        self.copy_remote_objects(&remote)?;
+
+        let branches = self.remote_branches(&remote)?;
+        for (branch, hash) in &branches {
+            self.set_reference(&format!("{}{}/{}", refs::REMOTES, name, branch), *hash)?;
+        }
+        match prune {
+            true => self.prune_tracking_branches(name, &branches),
+            false => Ok(vec![]),
+        }
+    }
 
+    /// Removes the remote-tracking branches of a configured remote whose
+    /// branch no longer exists, without fetching. Returns them.
+    pub fn prune_remote(&mut self, name: &str) -> Result<Vec<String>> {
+        let remote = self.open_named_remote(name)?;
+        let branches = self.remote_branches(&remote)?;
+        self.prune_tracking_branches(name, &branches)
+    }
+
+    /// Fetches a configured remote, without merging, and compares the
+    /// current branch with its remote-tracking branch. Returns None if HEAD
+    /// is detached or the remote has no branch of that name.
+    pub fn compare_with_remote(&mut self, name: &str) -> Result<Option<Divergence>> {
+        let branch = match &self.head {
+            Reference::Branch(branch) => branch.clone(),
+            Reference::Hash(_) => return Ok(None),
+        };
+        self.fetch(name, false)?;
+        let tracking = format!("{}/{}", name, branch);
+        let theirs = match self.refs.get(&format!("{}{}", refs::REMOTES, tracking)) {
+            Some(hash) => hash,
+            None => return Ok(None),
+        };
+        let ours = self.head_hash().ok();
+        Ok(Some(Divergence {
+            tracking,
+            ahead: count_missing(ours, Some(theirs))?,
+            behind: count_missing(Some(theirs), ours)?,
+        }))
+    }
+
+    fn prune_tracking_branches(
+        &mut self,
+        name: &str,
+        branches: &[(String, Hash)],
+    ) -> Result<Vec<String>> {
+        let prefix = format!("{}{}/", refs::REMOTES, name);
+        let stale: Vec<_> = self
+            .refs
+            .namespace(&prefix)
+            .map(|(branch, _)| branch.to_owned())
+            .filter(|branch| !branches.iter().any(|(b, _)| b == branch))
+            .collect();
+        for branch in &stale {
+            self.remove_reference(&format!("{}{}", prefix, branch))?;
+        }
+        Ok(stale)
+    }
+
+    /// Rewrites the history of all references and HEAD, moving them to the
+    /// rewritten commits, and untracks the removed paths. The working tree
+    /// must be clean, and the files are kept on disk.
+    pub fn filter(&mut self, options: &FilterOptions) -> Result<FilterReport> {
+        if transport::read_merge_head(&self.worktree)?.is_some() {
+            return Err(DirtyWorktree);
+        }
+        self.is_clean(&self.head_tree()?)?;
+
+        let mut tips: Vec<Hash> = self.refs.iter().map(|(_, h)| h).collect();
+        tips.extend(self.head_hash().ok());
+        let mut rewrite = HistoryRewrite::new(options);
+        rewrite.rewrite(&tips)?;
+
+        let mut report = FilterReport {
+            commits: rewrite.changed(),
+            ..FilterReport::default()
+        };
+        let moved: Vec<_> = self
+            .refs
+            .iter()
+            .filter_map(|(name, hash)| Some((name.to_owned(), rewrite.get(hash)?)))
+            .filter(|(name, hash)| self.refs.get(name) != Some(*hash))
+            .collect();
+        for (name, hash) in moved {
+            self.set_reference(&name, hash)?;
+            report.refs.push(name);
+        }
+        if let Reference::Hash(hash) = self.head {
+            if let Some(new) = rewrite.get(hash) {
+                self.set_head(Reference::Hash(new))?;
+            }
+        }
+
+        let is_removed = |path: &str| {
+            let path = Path::new(path);
+            let mut dirs = path.ancestors().skip(1).filter(|d| !d.as_os_str().is_empty());
+            rewrite.is_removed(path, false) || dirs.any(|d| rewrite.is_removed(d, true))
+        };
+        report.untracked = self.tracklist.iter().filter(|t| is_removed(t)).cloned().collect();
+        for path in &report.untracked {
+            self.tracklist.remove(path);
+        }
+        self.save_tracklist()?;
+
+        if transport::read_bitmaps().bitmaps().next().is_some() {
+            self.write_bitmaps()?;
+        }
+        Ok(report)
+    }
+
+    /// Pulls the current branch, or all branches, from another repository.
+    /// With `all`, only the branches matching the `fetch` refspecs of the
+    /// remote are pulled, under the names they map to.
+    /// Returns how the branches were updated.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn pull<P: AsRef<Path>>(&mut self, path: P, all: bool) -> Result<PullReport> {
+        let path = path.as_ref();
+        self.batch_tracklist(|r| r.pull_from(path, all))
+    }
+
+    fn pull_from(&mut self, path: &Path, all: bool) -> Result<PullReport> {
+        if !all {
+            self.head_hash()?;
+        }
+        self.check_safe_switch(false)?;
+
+        let remote = Repository::open_remote(path)?;
+        let (remote_objects, objects) = self.copy_remote_objects(&remote)?;
+
+        let mut strategy = MergeStrategy::AlreadyUpToDate;
+        let mut updates = vec![];
         if all {
-            /* copy over all the branches */
-            for remote_branch in remote.branches() {
-                match self.branches.get_mut(remote_branch.0) {
-                    /* a local branch with the same name exists */
-                    Some(local_hash) => {
-                        if remote_objects.contains(&PathBuf::from(local_hash.to_string())) {
-                            /* if the last commit of the branch is stored in remote repo,
-                             * can skip "fast-forward" merge by just moving the branch hash
-                             */
-                            *local_hash = *remote_branch.1;
-                        } else {
-                            /* have to merge */
-                            return Err(MergeFailed(vec![PathBuf::from(
-                                remote_branch.1.to_string(),
-                            )]));
-                        }
+            /* copy over all the branches selected by the refspecs */
+            let remote_branches = self.remote_branches(&remote)?;
+            for (name, remote_hash) in &remote_branches {
+                /* a local branch with the same name exists */
+                if let Ok(local_hash) = self.branch(name) {
+                    if !remote_objects.contains(&PathBuf::from(local_hash.to_string())) {
+                        /* have to merge */
+                        return Err(MergeFailed(vec![PathBuf::from(remote_hash.to_string())]));
                     }
-                    /* no local branch by the name, create new */
-                    None => {
-                        self.branches
-                            .insert((*remote_branch.0).to_string(), *remote_branch.1);
-                    }
-                };
+                }
             }
 
-            /* update local branches on disk */
-            for b in &self.branches {
-                transport::write_branch(&self.worktree, &b.0, *b.1)?;
+            /* if the last commit of the branch is stored in remote repo,
+             * can skip "fast-forward" merge by just moving the branch hash
+             */
+            for (name, remote_hash) in remote_branches {
+                let old = self.branch(&name).ok();
+                if old != Some(remote_hash) {
+                    strategy = MergeStrategy::FastForward;
+                    self.set_branch(&name, remote_hash)?;
+                    updates.push(RefUpdate {
+                        name,
+                        old,
+                        new: remote_hash,
+                    });
+                }
             }
         } else {
             /* current branch name
@@ -637,66 +2274,71 @@ impl Repository {
             /* hash of head of remote branch by the local name
              * return ReferenceNotFound if remote repo has no local branch
              */
-            let remote_hash = match remote.branches().get(&curr_branch) {
-                Some(h) => h,
-                None => return Err(ReferenceNotFound),
-            };
+            let remote_hash = &remote.branch(&curr_branch)?;
 
             let local_hash = self.head_hash()?;
 
-            if remote_objects.contains(&PathBuf::from(local_hash.to_string())) {
+            if local_hash == *remote_hash {
+                /* already up to date */
+            } else if remote_objects.contains(&PathBuf::from(local_hash.to_string())) {
                 /* if the last commit of the branch is stored in remote repo,
                  * can skip "fast-forward" merge by just moving the branch hash
                  */
                 self.set_branch(&curr_branch, *remote_hash)?;
+                strategy = MergeStrategy::FastForward;
             } else {
                 /* have to merge */
-                self.merge(*remote_hash)?;
-                self.commit(format!("Merge {} with {}", local_hash, remote_hash))?;
+                let options = MergeOptions {
+                    message: Some(format!("Merge {} with {}", local_hash, remote_hash)),
+                    ..Default::default()
+                };
+                strategy = self.merge(*remote_hash, &options)?;
+            }
+            if strategy != MergeStrategy::AlreadyUpToDate {
+                updates.push(RefUpdate {
+                    name: curr_branch,
+                    old: Some(local_hash),
+                    new: self.head_hash()?,
+                });
             }
         }
 
         /* switch to latest version of branch head */
-        self.checkout(self.head.clone(), true)?;
+        self.reset_to(self.head.clone(), true)?;
 
-        Ok(())
+        Ok(PullReport {
+            strategy,
+            objects,
+            updates,
+        })
     }
 
-    pub fn push<P: AsRef<Path>>(&self, path: P, all: bool) -> Result<()> {
-        self.check_safe_switch()?;
+    /// Pushes the current branch, or all branches, to another repository.
+    /// With `all`, only the branches matching the `push` refspecs of the
+    /// remote are pushed, under the names they map to. If `push.notes` is
+    /// set, the pushed commits get a note recording the push; failing to
+    /// record it is reported as a warning, since the push is done by then.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub fn push<P: AsRef<Path>>(&mut self, path: P, all: bool) -> Result<PushReport> {
+        if !all {
+            self.head_hash()?;
+        }
+        self.check_safe_switch(false)?;
+        let notes = self.push_notes()?;
 
         let mut remote = Repository::open_remote(path)?;
 
-        let remote_objects = transport::get_objects(&remote.storage_dir)?;
-        let mut local_objects = transport::get_objects(&self.storage_dir)?;
-
-        if all {
-            for local_branch in &self.branches {
-                match remote.branches_mut().get_mut(local_branch.0) {
-                    Some(remote_hash) => {
-                        if local_objects.contains(&PathBuf::from(remote_hash.to_string())) {
-                            /* head of remote branch is stored in local repo, which
-                             * means its safe to "fast-forward" merge
-                             */
-                            *remote_hash = *local_branch.1;
-                        } else {
-                            /* have to pull to local before updating remote */
-                            return Err(PushFailed);
-                        }
-                    }
-                    None => {
-                        /* no remote branch by the name, create new */
-                        remote
-                            .branches_mut()
-                            .insert((*local_branch.0).to_string(), *local_branch.1);
-                    }
-                };
-            }
-
-            /* update remote branches on disk */
-            for b in remote.branches() {
-                transport::write_branch(&remote.worktree, &b.0, *b.1)?;
-            }
+        /* remote heads stored in the local repo, with all objects reachable from them */
+        let haves: Vec<_> = remote
+            .branches()
+            .values()
+            .copied()
+            .filter(|&h| transport::object_exists(h))
+            .collect();
+
+        let mut branches = if all {
+            let refspecs = self.remote_refspecs(&remote.worktree, "push")?;
+            Repository::map_branches(self.branches(), refspecs)
         } else {
             /* current branch name
              * return ReferenceNotFound if HEAD detached
@@ -705,43 +2347,214 @@ impl Repository {
                 Reference::Branch(name) => name.clone(),
                 Reference::Hash(_) => return Err(ReferenceNotFound),
             };
-            let local_hash = self.head_hash()?;
+            vec![(curr_branch, self.head_hash()?)]
+        };
+        branches.sort_by(|a, b| a.0.cmp(&b.0));
 
-            match remote.branches().get(&curr_branch) {
-                Some(remote_hash) => {
-                    if local_objects.contains(&PathBuf::from(remote_hash.to_string())) {
-                        remote.set_branch(&curr_branch, local_hash)?;
-                    } else {
-                        return Err(PushFailed);
-                    }
-                }
-                None => {
-                    remote.set_branch(&curr_branch, local_hash)?;
+        /* check every update before changing anything: the head of a remote
+         * branch must be stored in the local repo and be an ancestor of the
+         * new head, which means it is safe to "fast-forward" it, otherwise
+         * have to pull first
+         */
+        let mut updates = vec![];
+        let mut rejected = vec![];
+        for (name, new) in branches {
+            let old = remote.branch(&name).ok();
+            match old {
+                Some(old) if !haves.contains(&old) || !self.is_ancestor(old, new)? => {
+                    rejected.push(name)
                 }
+                _ => updates.push(RefUpdate { name, old, new }),
             }
         }
+        if !rejected.is_empty() {
+            return Err(PushFailed(rejected));
+        }
+        let tips: Vec<_> = updates.iter().map(|u| u.new).collect();
 
-        local_objects.retain(|x| !remote_objects.contains(x));
         /* copy objects from local to remote */
-        transport::copy_objects(&self.storage_dir, &remote.storage_dir, &local_objects)?;
+        let remote_objects = remote.storage_dir.join("objects");
+        let objects: Vec<_> = self
+            .objects_between(&tips, &haves)?
+            .into_iter()
+            .map(|h| PathBuf::from(h.to_string()))
+            .filter(|o| !remote_objects.join(o).exists())
+            .collect();
+        remote.receive_objects(&self.storage_dir, &objects, &updates)?;
+
+        /* the objects are in place, now update the branches together */
+        remote.update_branches(&updates)?;
+
+        /* refresh the remote's working tree if its current branch moved */
+        if let Reference::Branch(b) = remote.head().clone() {
+            if updates.iter().any(|u| u.name == b) {
+                remote.reset_to(Reference::Branch(b), true)?;
+            }
+        }
 
-        /* switch to latest version of branch head */
-        remote.checkout(self.head().clone(), true)?;
+        let mut warnings = vec![];
+        let notes = notes.filter(|notes| match self.record_push(notes, &remote, &updates) {
+            Ok(()) => true,
+            Err(err) => {
+                warnings.push(format!("could not record the push in {}: {}", notes, err));
+                false
+            }
+        });
+        Ok(PushReport {
+            objects: objects.len(),
+            updates,
+            notes,
+            warnings,
+        })
+    }
 
+    /// Receives the objects of a push from another storage directory: copies
+    /// them into a quarantine, checks their hashes and runs the receive
+    /// hooks, and only then moves them into the object store. Nothing is
+    /// kept if the push is refused.
+    fn receive_objects(
+        &self,
+        from: &Path,
+        objects: &[PathBuf],
+        updates: &[RefUpdate],
+    ) -> Result<()> {
+        let quarantine = transport::create_quarantine(&self.storage_dir)?;
+        let accepted = transport::copy_objects(from, &quarantine, objects)
+            .and_then(|_| self.run_receive_hooks(updates, &quarantine.join("objects")));
+        match accepted {
+            Ok(()) => transport::migrate_quarantine(&quarantine, &self.storage_dir),
+            Err(err) => {
+                transport::remove_quarantine(&quarantine)?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Runs the hooks of a repository receiving a push: `pre-receive` once,
+    /// with a line `<old> <new> <ref>` per update on its input, then `update`
+    /// with the arguments `<ref> <old> <new>` for each update. A created
+    /// branch has an old hash of zeros. Any failing hook rejects the push.
+    /// The hooks find the objects of the push in `quarantine`.
+    fn run_receive_hooks(&self, updates: &[RefUpdate], quarantine: &Path) -> Result<()> {
+        let lines: Vec<_> = updates
+            .iter()
+            .map(|u| {
+                let old = u.old.map_or_else(|| "0".repeat(40), |h| h.to_string());
+                [old, u.new.to_string(), refs::branch_ref(&u.name)]
+            })
+            .collect();
+        let input: String = lines.iter().map(|l| format!("{}\n", l.join(" "))).collect();
+        let envs = [(transport::QUARANTINE_ENV, quarantine)];
+        let (storage_dir, worktree) = (&self.storage_dir, &self.worktree);
+        let no_args: &[&str] = &[];
+        hooks::run_hook(storage_dir, worktree, "pre-receive", no_args, Some(&input), &envs)?;
+        for [old, new, name] in &lines {
+            hooks::run_hook(storage_dir, worktree, "update", &[name, old, new], Some(""), &envs)?;
+        }
         Ok(())
     }
 
-    fn check_safe_switch(&self) -> Result<()> {
-        /* read commit by hash, get tree */
-        let curr_tree = transport::read_commit(self.head_hash().unwrap())?.tree()?;
-        let curr_status = self.status(&curr_tree)?;
+    /// Applies branch updates together: if one of them fails, the branches
+    /// already updated are restored.
+    fn update_branches(&mut self, updates: &[RefUpdate]) -> Result<()> {
+        for (i, update) in updates.iter().enumerate() {
+            if let Err(err) = self.set_branch(&update.name, update.new) {
+                for update in &updates[..=i] {
+                    /* best effort, the first error is the one to report */
+                    let _ = match update.old {
+                        Some(old) => self.set_branch(&update.name, old),
+                        None => self.remove_reference(&refs::branch_ref(&update.name)),
+                    };
+                }
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Restores objects reachable from HEAD and the branches that are missing
+    /// or corrupted in the local store by copying them from another repository.
+    /// Returns the hashes of the restored objects.
+    pub fn repair<P: AsRef<Path>>(&self, path: P) -> Result<Vec<Hash>> {
+        let remote = Repository::open_remote(path)?;
+        let mut repaired = vec![];
+        let mut seen = HashSet::new();
+        let tips: Vec<_> = self
+            .head_hash()
+            .into_iter()
+            .chain(self.branches().into_values())
+            .collect();
+
+        let mut commits = tips;
+        while let Some(hash) = commits.pop() {
+            if !seen.insert(hash) {
+                continue;
+            }
+            let commit =
+                self.restore_object(&remote, hash, transport::read_commit, &mut repaired)?;
+            self.restore_tree(&remote, commit.tree_hash(), &mut seen, &mut repaired)?;
+            commits.extend(commit.parent_hashes());
+        }
+        Ok(repaired)
+    }
+
+    fn restore_tree(
+        &self,
+        remote: &Repository,
+        hash: Hash,
+        seen: &mut HashSet<Hash>,
+        repaired: &mut Vec<Hash>,
+    ) -> Result<()> {
+        if !seen.insert(hash) {
+            return Ok(());
+        }
+        let tree = self.restore_object(remote, hash, transport::read_tree, repaired)?;
+
+        for e in tree.entries() {
+            match e.kind() {
+                TreeEntryKind::Tree => self.restore_tree(remote, e.hash(), seen, repaired)?,
+                TreeEntryKind::Blob if seen.insert(e.hash()) => {
+                    self.restore_object(remote, e.hash(), transport::read_blob, repaired)?;
+                }
+                TreeEntryKind::Blob => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads an object, copying it from the remote first if it is missing or corrupted.
+    fn restore_object<T>(
+        &self,
+        remote: &Repository,
+        hash: Hash,
+        read: fn(Hash) -> Result<T>,
+        repaired: &mut Vec<Hash>,
+    ) -> Result<T> {
+        match read(hash) {
+            Err(ObjectNotFound) | Err(ObjectCorrupted) => {
+                let objects = vec![PathBuf::from(hash.to_string())];
+                transport::copy_objects(&remote.storage_dir, &self.storage_dir, &objects)
+                    .or(Err(ObjectMissing))?;
+                repaired.push(hash);
+                read(hash)
+            }
+            r => r,
+        }
+    }
+
+    /// Checks that the working tree has no changes to tracked files, nor
+    /// untracked files unless `keep_untracked` is set.
+    fn check_safe_switch(&self, keep_untracked: bool) -> Result<()> {
+        let curr_status = self.status(&self.head_tree()?)?;
 
         for f in curr_status {
             match f.1 {
+                FileStatus::Untracked if keep_untracked => continue,
                 FileStatus::Untracked
                 | FileStatus::Added
                 | FileStatus::Deleted
-                | FileStatus::Modified => return Err(CheckoutFailed),
+                | FileStatus::Modified
+                | FileStatus::ModeChanged => return Err(CheckoutFailed),
                 FileStatus::Unmodified | FileStatus::Missing => continue,
             };
         }
@@ -750,28 +2563,435 @@ impl Repository {
     }
 }
 
+/// Moves the files of a map under moved directories to their new paths.
+/// Returns the old and new paths of the moved files.
+fn relocate(
+    files: &mut HashMap<PathBuf, File>,
+    renames: &[(PathBuf, PathBuf)],
+) -> Vec<(PathBuf, PathBuf)> {
+    let moved: Vec<_> = files
+        .keys()
+        .filter_map(|p| Some((p.clone(), object::renamed_path(p, renames)?)))
+        .collect();
+    for (old, new) in &moved {
+        let file = files.remove(old).unwrap();
+        files.insert(new.clone(), file);
+    }
+    moved
+}
+
+/// Counts the commits reachable from `from` but not from `exclude`.
+fn count_missing(from: Option<Hash>, exclude: Option<Hash>) -> Result<usize> {
+    let mut excluded = HashSet::new();
+    for commit in RevWalk::new(exclude.as_slice(), RevWalkOptions::default())? {
+        excluded.insert(commit?.hash());
+    }
+    let mut count = 0;
+    for commit in RevWalk::new(from.as_slice(), RevWalkOptions::default())? {
+        count += usize::from(!excluded.contains(&commit?.hash()));
+    }
+    Ok(count)
+}
+
+/// Writes a file of a checkout, converting its contents for the working tree
+/// as its attributes say.
+fn checkout_file(
+    (path, hash, executable): &(PathBuf, Hash, bool),
+    worktree: &Path,
+    attributes: &Attributes,
+) -> Result<()> {
+    let rel = path.strip_prefix(worktree).or(Err(FileNotFound))?;
+    if attributes.smudges(rel) {
+        let blob = transport::read_blob(*hash)?;
+        let data = attributes.smudge(rel, blob.content());
+        fs::write(path, data.as_deref().unwrap_or(blob.content()))?;
+    } else {
+        transport::checkout_blob(*hash, path)?;
+    }
+    object::set_executable(path, *executable)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testutil::TestRepo;
 
     #[test]
     fn init_repo_test() {
-        let _a1 = Repository::init();
+        let t = TestRepo::new();
+        assert_eq!(t.repo.head(), &Reference::Branch("main".to_owned()));
+        assert!(t.path().join(".gnew/objects").is_dir());
+        assert!(matches!(Repository::init_at(t.path(), None), Err(IoError(_))));
+    }
+
+    #[test]
+    fn reference_round_trip() {
+        for r in [Reference::Branch("feature/x".to_owned()), Reference::Hash(Hash::new())] {
+            assert_eq!(r.to_string().parse::<Reference>().unwrap(), r);
+        }
+        let main = Reference::Branch("main".to_owned());
+        assert_eq!("ref: refs/heads/main".parse::<Reference>().unwrap(), main);
+        assert_eq!("ref: main".parse::<Reference>().unwrap(), main);
+        let old = Reference::Branch("caf\u{e9}".to_owned());
+        assert_eq!("ref: refs/heads/caf\u{e9}".parse::<Reference>().unwrap(), old);
+        assert!(matches!("ref: refs/heads/".parse::<Reference>(), Err(InvalidRefName(_))));
+        assert!(matches!("main".parse::<Reference>(), Err(InvalidRefName(_))));
     }
 
     #[test]
-    #[should_panic]
     fn add_test() {
-        let mut path = env::current_dir().unwrap_or(PathBuf::new());
-        path.push("object.rs");
-        let mut r = Repository::init().unwrap();
-        r.add(&vec![path]).unwrap();
+        let mut t = TestRepo::new();
+        assert!(t.repo.add(&["missing.txt"], AddMode::Paths).is_err());
+
+        t.write("dir/a.txt", "a");
+        t.add(&["dir"]);
+        t.assert_status(&[("dir/a.txt", FileStatus::Added)]);
+    }
+
+    #[test]
+    fn batch_tracklist_test() {
+        let mut t = TestRepo::new();
+        t.write("a.txt", "a");
+        t.write("b.txt", "b");
+        let on_disk = || transport::read_tracklist(".").unwrap();
+        let path = |p| t.path().join(p);
+        let (a, b, missing) = (path("a.txt"), path("b.txt"), path("missing.txt"));
+        let result: Result<()> = t.repo.batch_tracklist(|r| {
+            r.add(&[a], AddMode::Paths)?;
+            r.batch_tracklist(|r| r.add(&[b], AddMode::Paths))?;
+            assert!(on_disk().is_empty());
+            r.add(&[missing], AddMode::Paths)
+        });
+        assert!(result.is_err());
+        assert_eq!(on_disk(), Tracklist::from(["a.txt".to_owned(), "b.txt".to_owned()]));
     }
 
     #[test]
-    #[should_panic]
     fn commit_test() {
-        let mut r = Repository::init().unwrap();
-        r.commit("test commit".to_string()).unwrap();
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "first");
+        t.write("a.txt", "changed");
+        t.assert_status(&[("a.txt", FileStatus::Modified)]);
+
+        t.commit("second");
+        t.assert_status(&[]);
+        t.assert_log(&["second", "first"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mode_change_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("run.sh", "echo");
+        object::set_executable(t.path().join("run.sh"), true).unwrap();
+        t.assert_status(&[("run.sh", FileStatus::ModeChanged)]);
+
+        let commit = t.commit("make executable");
+        assert!(commit.tree().unwrap().file("run.sh").unwrap().executable);
+        t.assert_status(&[]);
+    }
+
+    #[test]
+    fn checkout_rollback_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.write("old/b.txt", "b");
+        t.add(&["old"]);
+        t.commit("b");
+        t.repo.create_branch("other").unwrap();
+        t.write("a.txt", "changed");
+        t.write("new/c.txt", "c");
+        fs::remove_dir_all(t.path().join("old")).unwrap();
+        t.repo.remove(&vec![t.path().join("old/b.txt")]).unwrap();
+        t.add(&["a.txt", "new"]);
+        t.commit("other");
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+
+        let missing = object::hash_blob(b"c");
+        fs::remove_file(t.path().join(".gnew/objects").join(missing.to_string())).unwrap();
+        let other = Reference::Branch("other".to_owned());
+        assert!(t.repo.checkout(other, false).is_err());
+
+        assert_eq!(fs::read_to_string(t.path().join("a.txt")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(t.path().join("old/b.txt")).unwrap(), "b");
+        assert!(!t.path().join("new").exists());
+        assert!(!t.path().join(".gnew").join(journal::JOURNAL_DIR).exists());
+        assert_eq!(t.repo.head, Reference::Branch("main".to_owned()));
+        t.assert_status(&[]);
+    }
+
+    #[test]
+    fn nested_branches_test() {
+        let mut t = TestRepo::new();
+        let commit = t.commit_file("a.txt", "a").hash();
+        t.repo.create_branch("feature/login").unwrap();
+        assert!(t.path().join(".gnew/refs/heads/feature/login").is_file());
+        let reopened = Repository::open_at(t.path()).unwrap();
+        assert_eq!(reopened.branch("feature/login").unwrap(), commit);
+        assert!(matches!(t.repo.create_branch("feature"), Err(BranchConflict(..))));
+        assert!(matches!(t.repo.create_branch("feature/login/x"), Err(BranchConflict(..))));
+
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        t.repo.delete_merged_branches("main").unwrap();
+        assert!(!t.path().join(".gnew/refs/heads/feature").exists());
+        t.repo.create_branch("feature").unwrap();
+        assert_eq!(t.repo.branch("feature").unwrap(), commit);
+    }
+
+    #[test]
+    fn checkout_untracked_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.repo.create_branch("other").unwrap();
+        t.commit_file("b.txt", "b");
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        let other = || Reference::Branch("other".to_owned());
+
+        t.write("notes.txt", "notes");
+        t.write("b.txt", "mine");
+        match t.repo.checkout(other(), false) {
+            Err(UntrackedOverwritten(paths)) => assert_eq!(paths, [PathBuf::from("b.txt")]),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        t.write("b.txt", "b");
+        t.repo.checkout(other(), false).unwrap();
+        assert!(t.path().join("notes.txt").exists());
+        t.assert_status(&[("notes.txt", FileStatus::Untracked)]);
+    }
+
+    #[test]
+    fn checkout_summary_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.commit_file("b.txt", "b");
+        t.repo.create_branch("other").unwrap();
+        t.commit_file("a.txt", "changed");
+        t.commit_file("c.txt", "c");
+        let main = Reference::Branch("main".to_owned());
+
+        let calls = Mutex::new(vec![]);
+        let progress = |done, total| calls.lock().unwrap().push((done, total));
+        let summary = t.repo.checkout_with_progress(main, false, &progress).unwrap();
+        assert_eq!(summary.updated, [PathBuf::from("a.txt")]);
+        assert_eq!(summary.deleted, [PathBuf::from("c.txt")]);
+        assert!(summary.created.is_empty());
+        assert_eq!(calls.into_inner().unwrap(), [(1, 2), (2, 2)]);
+
+        let summary = t.repo.checkout(Reference::Branch("other".to_owned()), false).unwrap();
+        assert_eq!(summary.created, [PathBuf::from("c.txt")]);
+        assert_eq!(summary.updated, [PathBuf::from("a.txt")]);
+        assert!(summary.deleted.is_empty());
+    }
+
+    #[test]
+    fn parallel_checkout_reports_progress_in_order() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.repo.create_branch("other").unwrap();
+        let paths: Vec<_> = (0..PARALLEL_CHECKOUT_MIN * 2)
+            .map(|i| format!("dir/{}.txt", i))
+            .collect();
+        for path in &paths {
+            t.write(path, path);
+        }
+        t.add(&paths);
+        t.commit("many files");
+        t.repo.checkout(Reference::Branch("other".to_owned()), false).unwrap();
+
+        let calls = Mutex::new(vec![]);
+        let progress = |done, _| calls.lock().unwrap().push(done);
+        let main = Reference::Branch("main".to_owned());
+        t.repo.checkout_with_progress(main, false, &progress).unwrap();
+        let expected: Vec<_> = (1..=paths.len()).collect();
+        assert_eq!(calls.into_inner().unwrap(), expected);
+    }
+
+    #[test]
+    fn diff_paths_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("dir/a.txt", "a");
+        let first = t.commit_file("dir/sub/b.txt", "b").tree().unwrap();
+        t.commit_file("dir/a.txt", "changed");
+        t.commit_file("dir/sub/b.txt", "changed");
+        let second = t.commit_file("c.txt", "c").tree().unwrap();
+
+        let paths = |changes: Vec<Change>| -> Vec<_> {
+            changes.iter().map(|c| (c.status(), c.path().to_owned())).collect()
+        };
+        let all = paths(first.diff(&second).unwrap());
+        assert_eq!(all.len(), 3);
+        let changes = first.diff_paths(&second, &[PathBuf::from("dir/sub")]).unwrap();
+        assert_eq!(paths(changes), [('M', PathBuf::from("dir/sub/b.txt"))]);
+        let changes = first.diff_paths(&second, &[PathBuf::from("c.txt")]).unwrap();
+        assert_eq!(paths(changes), [('A', PathBuf::from("c.txt"))]);
+        assert!(first.diff_paths(&second, &[PathBuf::from("dir/a")]).unwrap().is_empty());
+        assert!(second.diff_paths(&second, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rebase_test() {
+        use crate::repo::rebase::{RebaseAction, RebaseStep};
+
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.repo.create_branch("topic").unwrap();
+        let one = t.commit_file("b.txt", "b");
+        let two = t.commit_file("a.txt", "topic");
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        let upstream = t.commit_file("a.txt", "main");
+        t.repo.checkout(Reference::Branch("topic".to_owned()), false).unwrap();
+
+        let commits = t.repo.rebase_commits(upstream.hash()).unwrap();
+        let hashes: Vec<_> = commits.iter().map(Commit::hash).collect();
+        assert_eq!(hashes, [one.hash(), two.hash()]);
+        let pick = |c: &Commit| RebaseStep {
+            action: RebaseAction::Pick,
+            commit: c.hash(),
+        };
+        match t.repo.rebase(upstream.hash(), commits.iter().map(pick).collect()) {
+            Err(RebaseStopped(commit, paths)) => {
+                assert_eq!(commit, two.hash());
+                assert_eq!(paths, [PathBuf::from("a.txt")]);
+            }
+            r => panic!("unexpected result: {:?}", r),
+        }
+        assert!(matches!(t.repo.rebase_continue(), Err(RebaseStopped(..))));
+
+        t.write("a.txt", "resolved");
+        let report = t.repo.rebase_continue().unwrap();
+        assert_eq!(report.commits, 2);
+        assert_eq!(report.head, Reference::Branch("topic".to_owned()));
+        assert_eq!(t.repo.head(), &report.head);
+        assert!(t.repo.rebase_state().unwrap().is_none());
+
+        let head = transport::read_commit(t.repo.head_hash().unwrap()).unwrap();
+        assert_eq!(head.msg(), "topic");
+        let parent = head.parent().unwrap().unwrap();
+        assert_eq!(parent.msg(), "b");
+        assert_eq!(parent.parent_hash(), Some(upstream.hash()));
+        assert_eq!(fs::read_to_string(t.path().join("a.txt")).unwrap(), "resolved");
+    }
+
+    #[test]
+    fn state_test() {
+        use crate::repo::rebase::{RebaseAction, RebaseStep};
+        use crate::repo::state::RepositoryState;
+
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        assert_eq!(t.repo.state().unwrap(), RepositoryState::Clean);
+        t.repo.create_branch("topic").unwrap();
+        let topic = t.commit_file("a.txt", "topic").hash();
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        let main = t.commit_file("a.txt", "main").hash();
+
+        let merged = t.repo.merge(topic, &MergeOptions::default());
+        assert!(matches!(merged, Err(MergeFailed(_))));
+        assert_eq!(
+            t.repo.state().unwrap(),
+            RepositoryState::Merging {
+                theirs: topic,
+                unresolved: vec![PathBuf::from("a.txt")],
+            }
+        );
+        t.write("a.txt", "resolved");
+        t.commit("merge");
+        assert_eq!(t.repo.state().unwrap(), RepositoryState::Clean);
+
+        t.repo.checkout(Reference::Branch("topic".to_owned()), false).unwrap();
+        let pick = |commit| RebaseStep {
+            action: RebaseAction::Pick,
+            commit,
+        };
+        let rebased = t.repo.rebase(main, vec![pick(topic)]);
+        assert!(matches!(rebased, Err(RebaseStopped(..))));
+        assert_eq!(
+            t.repo.state().unwrap(),
+            RepositoryState::Rebasing {
+                onto: main,
+                stopped: Some(topic),
+                remaining: 0,
+                unresolved: vec![PathBuf::from("a.txt")],
+            }
+        );
+        t.write("notes.txt", "untracked");
+        t.repo.rebase_abort().unwrap();
+        assert_eq!(t.repo.state().unwrap(), RepositoryState::Clean);
+        assert_eq!(fs::read_to_string(t.path().join("notes.txt")).unwrap(), "untracked");
+    }
+
+    #[test]
+    fn autostash_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        assert!(!t.repo.autostash().unwrap());
+
+        t.write("a.txt", "changed");
+        t.write("b.txt", "new");
+        t.add(&["b.txt"]);
+        assert!(t.repo.autostash().unwrap());
+        t.assert_status(&[]);
+        assert!(matches!(t.repo.autostash(), Err(AutostashPending(_))));
+
+        t.commit_file("c.txt", "c");
+        assert_eq!(t.repo.apply_autostash().unwrap(), Some(vec![]));
+        assert_eq!(t.repo.apply_autostash().unwrap(), None);
+        assert_eq!(fs::read_to_string(t.path().join("a.txt")).unwrap(), "changed");
+        t.assert_status(&[("a.txt", FileStatus::Modified), ("b.txt", FileStatus::Added)]);
+    }
+
+    #[test]
+    fn clone_single_branch_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.repo.create_branch("feature").unwrap();
+        t.repo.checkout(Reference::Branch("feature".to_owned()), false).unwrap();
+        t.commit_file("b.txt", "b");
+        t.repo.checkout(Reference::Branch("main".to_owned()), false).unwrap();
+        let cwd = env::current_dir().unwrap();
+
+        let options = CloneOptions {
+            branch: Some("feature".to_owned()),
+            single_branch: true,
+            ..CloneOptions::default()
+        };
+        let out = t.path().with_extension("out");
+        fs::create_dir(&out).unwrap();
+        let cloned = Repository::clone(t.path(), &out, &options).and_then(|_| {
+            let dest = out.join(t.path().file_name().unwrap());
+            assert_eq!(fs::read_to_string(dest.join("b.txt"))?, "b");
+            Repository::open_remote(&dest)
+        });
+        fs::remove_dir_all(&out).unwrap();
+        assert_eq!(cloned.unwrap().head(), &Reference::Branch("feature".to_owned()));
+
+        /* the repository of the thread and its directory are left alone */
+        assert_eq!(env::current_dir().unwrap(), cwd);
+        assert_eq!(transport::current_store(), Some(t.repo.store()));
+    }
+
+    #[test]
+    fn filter_test() {
+        let mut t = TestRepo::new();
+        t.commit_file("a.txt", "a");
+        t.write("secret/key", "key");
+        t.add(&["secret"]);
+        let old = t.commit("add key");
+
+        let options = FilterOptions {
+            remove_paths: vec!["secret/".to_owned()],
+            ..FilterOptions::default()
+        };
+        let report = t.repo.filter(&options).unwrap();
+        assert_eq!(report.commits, 1);
+        assert_eq!(report.refs, ["refs/heads/main"]);
+        assert_eq!(report.untracked, ["secret/key"]);
+
+        let new = transport::read_commit(t.repo.head_hash().unwrap()).unwrap();
+        assert_ne!(new.hash(), old.hash());
+        assert_eq!(new.parent_hash(), old.parent_hash());
+        assert!(new.tree().unwrap().file("secret/key").is_err());
+        t.assert_log(&["add key", "a"]);
     }
 }
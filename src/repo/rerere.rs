@@ -0,0 +1,242 @@
+use crate::error::Result;
+use crate::repo::object::Hash;
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use sha1::Sha1;
+use std::fs;
+use std::path::PathBuf;
+
+/// Config key that turns on recording and reusing conflict resolutions.
+pub const ENABLED_KEY: &str = "rerere.enabled";
+
+/// A part of a file written by a merge.
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    /// Lines outside of conflicts.
+    Context(&'a [u8]),
+    /// The lines of a conflict, from `<<<<<<<` to `>>>>>>>`, and the lines
+    /// of both sides.
+    Conflict {
+        text: &'a [u8],
+        ours: &'a [u8],
+        theirs: &'a [u8],
+    },
+}
+
+/// Splits a file into the conflicts marked by a merge and the lines around
+/// them. Returns None if a conflict is not closed.
+fn parse_conflicts(data: &[u8]) -> Option<Vec<Segment<'_>>> {
+    let mut segments = vec![];
+    let (mut start, mut ours, mut base, mut theirs) = (0, None, None, None);
+    let mut pos = 0;
+    for line in data.split_inclusive(|&b| b == b'\n') {
+        let end = pos + line.len();
+        let marker = |m: &[u8]| line.starts_with(m);
+        if marker(b"<<<<<<<") && ours.is_none() {
+            if pos > start {
+                segments.push(Segment::Context(&data[start..pos]));
+            }
+            (start, ours) = (pos, Some(end));
+        } else if marker(b"|||||||") && ours.is_some() && base.is_none() {
+            base = Some((pos, end));
+        } else if marker(b"=======") && ours.is_some() && theirs.is_none() {
+            let ours_end = base.map_or(pos, |(b, _)| b);
+            theirs = Some((ours_end, end));
+        } else if marker(b">>>>>>>") && theirs.is_some() {
+            let (ours_end, theirs_start) = theirs.unwrap();
+            segments.push(Segment::Conflict {
+                text: &data[start..end],
+                ours: &data[ours.unwrap()..ours_end],
+                theirs: &data[theirs_start..pos],
+            });
+            (start, ours, base, theirs) = (end, None, None, None);
+        }
+        pos = end;
+    }
+    if ours.is_some() {
+        return None;
+    }
+    if start < data.len() {
+        segments.push(Segment::Context(&data[start..]));
+    }
+    Some(segments)
+}
+
+/// Returns the fingerprint of a conflict, which is the same whichever side
+/// is ours.
+fn fingerprint(ours: &[u8], theirs: &[u8]) -> Hash {
+    let (a, b) = if ours <= theirs { (ours, theirs) } else { (theirs, ours) };
+    let mut sha = Sha1::new();
+    sha.update(a);
+    sha.update(b"\0");
+    sha.update(b);
+    Hash::from(sha.digest())
+}
+
+/// Returns the offset of the first line of `data` from `from` on that
+/// starts with `needle`.
+fn find_lines(data: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    let mut pos = from;
+    loop {
+        if data[pos..].starts_with(needle) {
+            return Some(pos);
+        }
+        pos += data[pos..].iter().position(|&b| b == b'\n')? + 1;
+    }
+}
+
+/// Returns the resolutions of the conflicts of `preimage`, a file as a
+/// merge wrote it, in `postimage`, the file once resolved: the text between
+/// the lines around each conflict. Conflicts with no lines between them, or
+/// whose lines around were edited, are left out.
+fn resolutions(preimage: &[u8], postimage: &[u8]) -> Vec<(Hash, Vec<u8>)> {
+    let segments = match parse_conflicts(preimage) {
+        Some(segments) => segments,
+        None => return vec![],
+    };
+    let mut resolutions = vec![];
+    let mut pending = vec![];
+    let mut pos = 0;
+    for segment in &segments {
+        match segment {
+            Segment::Context(context) => {
+                let found = match find_lines(postimage, pos, context) {
+                    Some(found) => found,
+                    None => return resolutions,
+                };
+                if let [conflict] = pending[..] {
+                    resolutions.push((conflict, postimage[pos..found].to_vec()));
+                }
+                pending.clear();
+                pos = found + context.len();
+            }
+            Segment::Conflict { ours, theirs, .. } => pending.push(fingerprint(ours, theirs)),
+        }
+    }
+    if let [conflict] = pending[..] {
+        resolutions.push((conflict, postimage[pos..].to_vec()));
+    }
+    resolutions
+}
+
+impl Repository {
+    fn rerere_enabled(&self) -> Result<bool> {
+        Ok(transport::read_config(self.worktree())?.get_bool(ENABLED_KEY))
+    }
+
+    /// Replaces the conflicts that a merge wrote to files with their
+    /// recorded resolutions, if `rerere.enabled` is set, and remembers the
+    /// files so that the resolutions of the other conflicts are recorded
+    /// when the merge is committed. Returns the files without conflicts
+    /// left.
+    pub(crate) fn rerere(&self, conflicts: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        if !self.rerere_enabled()? {
+            return Ok(vec![]);
+        }
+        let mut files = vec![];
+        let mut resolved = vec![];
+        for path in conflicts {
+            let file = self.worktree().join(path);
+            let data = match fs::read(&file) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            let segments = match parse_conflicts(&data) {
+                Some(s) if s.iter().any(|s| matches!(s, Segment::Conflict { .. })) => s,
+                _ => continue,
+            };
+            files.push((transport::write_blob_data(data.clone())?.hash(), path.clone()));
+
+            let mut merged = vec![];
+            let mut left = 0;
+            for segment in segments {
+                match segment {
+                    Segment::Context(context) => merged.extend_from_slice(context),
+                    Segment::Conflict { text, ours, theirs } => {
+                        let conflict = fingerprint(ours, theirs);
+                        match transport::read_resolution(self.worktree(), conflict)? {
+                            Some(resolution) => merged.extend(resolution),
+                            None => {
+                                merged.extend_from_slice(text);
+                                left += 1;
+                            }
+                        }
+                    }
+                }
+            }
+            if merged != data {
+                fs::write(&file, merged)?;
+            }
+            if left == 0 {
+                resolved.push(path.clone());
+            }
+        }
+        transport::write_merge_rr(self.worktree(), &files)?;
+        Ok(resolved)
+    }
+
+    /// Records how the conflicts of the files remembered by `rerere` are
+    /// resolved in the working tree. Files that still have conflicts are
+    /// skipped. Returns the number of recorded resolutions.
+    pub(crate) fn rerere_record(&self) -> Result<usize> {
+        let mut recorded = 0;
+        for (preimage, path) in transport::read_merge_rr(self.worktree())? {
+            let postimage = match fs::read(self.worktree().join(&path)) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            match parse_conflicts(&postimage) {
+                Some(s) if !s.iter().any(|s| matches!(s, Segment::Conflict { .. })) => (),
+                _ => continue,
+            }
+            let preimage = transport::read_blob(preimage)?;
+            for (conflict, resolution) in resolutions(preimage.content(), &postimage) {
+                transport::write_resolution(self.worktree(), conflict, &resolution)?;
+                recorded += 1;
+            }
+        }
+        Ok(recorded)
+    }
+
+    /// Returns the preimages of the files remembered by `rerere`, which are
+    /// kept until the merge is committed.
+    pub(crate) fn rerere_preimages(&self) -> Result<Vec<Hash>> {
+        let files = transport::read_merge_rr(self.worktree())?;
+        Ok(files.into_iter().map(|(preimage, _)| preimage).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MERGED: &[u8] = b"a\n<<<<<<< ours\nB2\n||||||| original\nb\n=======\nB1\n>>>>>>> theirs\n\
+        c\n<<<<<<< ours\nD2\n=======\nD1\n>>>>>>> theirs\n";
+
+    #[test]
+    fn parse_merged_file() {
+        let segments = parse_conflicts(MERGED).unwrap();
+        assert_eq!(segments.len(), 4);
+        assert_eq!(segments[0], Segment::Context(b"a\n"));
+        match segments[1] {
+            Segment::Conflict { ours, theirs, .. } => {
+                assert_eq!(ours, b"B2\n");
+                assert_eq!(theirs, b"B1\n");
+            }
+            ref s => panic!("{:?}", s),
+        }
+        assert_eq!(segments[2], Segment::Context(b"c\n"));
+        assert!(parse_conflicts(b"a\n<<<<<<< ours\nb\n=======\n").is_none());
+        assert_eq!(parse_conflicts(b"a\n").unwrap(), [Segment::Context(b"a\n")]);
+        assert_eq!(fingerprint(b"x", b"y"), fingerprint(b"y", b"x"));
+    }
+
+    #[test]
+    fn resolutions_between_context() {
+        let resolved = resolutions(MERGED, b"a\nB3\nB4\nc\n");
+        assert_eq!(resolved.len(), 2);
+        assert_eq!(resolved[0], (fingerprint(b"B2\n", b"B1\n"), b"B3\nB4\n".to_vec()));
+        assert_eq!(resolved[1], (fingerprint(b"D2\n", b"D1\n"), vec![]));
+        assert!(resolutions(MERGED, b"x\nB3\n").is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::repo::object::{Commit, Hash};
+use crate::storage::transport;
+use crate::error::{Error, Result};
+use std::collections::{HashSet, VecDeque};
+
+/// Which commits a walk yields depending on their number of parents.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MergeFilter {
+    #[default]
+    All,
+    /// Only merge commits.
+    Merges,
+    /// Only commits with at most one parent.
+    NoMerges,
+}
+
+/// Options of a history walk.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RevWalkOptions {
+    /// Follow only the first parent of merge commits, which is the history
+    /// of the branch they were made on.
+    pub first_parent: bool,
+    pub merges: MergeFilter,
+}
+
+/// An iterator over the commits reachable from a set of commits, newest
+/// first. Each commit is yielded once. A parent that cannot be read is
+/// yielded as a `CommitUnreadable` error right after its child, and the walk
+/// goes on with the other commits.
+#[derive(Debug)]
+pub struct RevWalk {
+    queue: Vec<Commit>,
+    seen: HashSet<Hash>,
+    options: RevWalkOptions,
+    unreadable: VecDeque<Error>,
+}
+
+impl RevWalk {
+    pub fn new(tips: &[Hash], options: RevWalkOptions) -> Result<RevWalk> {
+        let mut walk = RevWalk {
+            queue: vec![],
+            seen: HashSet::new(),
+            options,
+            unreadable: VecDeque::new(),
+        };
+        for &tip in tips {
+            walk.push(tip)?;
+        }
+        Ok(walk)
+    }
+
+    fn push(&mut self, hash: Hash) -> Result<()> {
+        if self.seen.insert(hash) {
+            let commit = transport::read_commit(hash)
+                .map_err(|err| Error::CommitUnreadable(hash, Box::new(err)))?;
+            self.queue.push(commit);
+        }
+        Ok(())
+    }
+
+    /// Removes the newest queued commit and queues its parents.
+    fn next_commit(&mut self) -> Option<Commit> {
+        // the first of equally old commits is taken, to keep the order of tips
+        let newest = self
+            .queue
+            .iter()
+            .enumerate()
+            .rev()
+            .max_by_key(|(_, c)| c.time())
+            .map(|(i, _)| i);
+        let commit = match newest {
+            Some(i) => self.queue.remove(i),
+            None => return None,
+        };
+        let parents = match self.options.first_parent {
+            true => 1,
+            false => usize::MAX,
+        };
+        for &parent in commit.parent_hashes().iter().take(parents) {
+            if let Err(err) = self.push(parent) {
+                self.unreadable.push_back(err);
+            }
+        }
+        Some(commit)
+    }
+
+    fn is_selected(&self, commit: &Commit) -> bool {
+        match self.options.merges {
+            MergeFilter::All => true,
+            MergeFilter::Merges => commit.is_merge(),
+            MergeFilter::NoMerges => !commit.is_merge(),
+        }
+    }
+}
+
+impl Iterator for RevWalk {
+    type Item = Result<Commit>;
+
+    fn next(&mut self) -> Option<Result<Commit>> {
+        loop {
+            if let Some(err) = self.unreadable.pop_front() {
+                return Some(Err(err));
+            }
+            match self.next_commit()? {
+                commit if !self.is_selected(&commit) => continue,
+                commit => return Some(Ok(commit)),
+            }
+        }
+    }
+}
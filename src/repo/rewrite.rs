@@ -0,0 +1,164 @@
+use crate::repo::ignore::IgnorePattern;
+use crate::repo::mailmap::Mailmap;
+use crate::repo::object::{Commit, CommitInfo, Hash, Tree, TreeEntryKind, MAX_TREE_DEPTH};
+use crate::storage::transport;
+use crate::error::{Error, Result};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/// Options for rewriting the history with `Repository::filter`.
+#[derive(Clone, Debug, Default)]
+pub struct FilterOptions {
+    /// Patterns of the paths to remove from every commit, in the syntax of
+    /// the ignore files.
+    pub remove_paths: Vec<String>,
+    /// Canonical identities to record as the authors of the commits.
+    pub mailmap: Option<Mailmap>,
+}
+
+/// What `Repository::filter` changed.
+#[derive(Debug, Default, PartialEq)]
+pub struct FilterReport {
+    /// Number of commits that were rewritten with a new hash.
+    pub commits: usize,
+    /// The references moved to rewritten commits, by their full name.
+    pub refs: Vec<String>,
+    /// The removed paths that were untracked.
+    pub untracked: Vec<String>,
+}
+
+/// Rewrites commits and the trees they reach, keeping the hashes of the
+/// commits and trees that do not change.
+pub struct HistoryRewrite {
+    remove: Vec<IgnorePattern>,
+    mailmap: Mailmap,
+    commits: HashMap<Hash, Hash>,
+    trees: HashMap<(Hash, String), Option<Hash>>,
+}
+
+impl HistoryRewrite {
+    pub fn new(options: &FilterOptions) -> HistoryRewrite {
+        let root = Path::new("");
+        HistoryRewrite {
+            remove: options
+                .remove_paths
+                .iter()
+                .filter_map(|p| IgnorePattern::parse(p, root, root, 0))
+                .collect(),
+            mailmap: options.mailmap.clone().unwrap_or_default(),
+            commits: HashMap::new(),
+            trees: HashMap::new(),
+        }
+    }
+
+    /// Checks if a path relative to the working tree is removed.
+    pub fn is_removed(&self, path: &Path, is_dir: bool) -> bool {
+        self.remove.iter().any(|p| p.matches(path, is_dir))
+    }
+
+    /// Rewrites the commits reachable from the tips, parents first.
+    pub fn rewrite(&mut self, tips: &[Hash]) -> Result<()> {
+        for commit in self.unvisited_commits(tips)? {
+            let tree = match self.rewrite_tree(commit.tree_hash(), "", 0)? {
+                Some(tree) => tree,
+                None => {
+                    let mut empty = Tree::new();
+                    transport::write_tree(&mut empty)?;
+                    empty.hash()
+                }
+            };
+            let parents: Vec<_> = commit.parent_hashes().iter().map(|p| self.commits[p]).collect();
+            let author = self.mailmap.map(commit.author());
+
+            let unchanged = tree == commit.tree_hash()
+                && parents == commit.parent_hashes()
+                && author == commit.author();
+            let hash = if unchanged {
+                commit.hash()
+            } else {
+                let mut new = Commit::new(CommitInfo {
+                    tree,
+                    parents,
+                    author,
+                    time: commit.time(),
+                    msg: commit.msg().to_owned(),
+                });
+                transport::write_commit(&mut new)?;
+                new.hash()
+            };
+            self.commits.insert(commit.hash(), hash);
+        }
+        Ok(())
+    }
+
+    /// Returns the new hash of a rewritten commit.
+    pub fn get(&self, commit: Hash) -> Option<Hash> {
+        self.commits.get(&commit).copied()
+    }
+
+    /// Returns the number of rewritten commits whose hash changed.
+    pub fn changed(&self) -> usize {
+        self.commits.iter().filter(|(old, new)| old != new).count()
+    }
+
+    /// Returns the commits reachable from the tips that were not rewritten
+    /// yet, each after its parents.
+    fn unvisited_commits(&self, tips: &[Hash]) -> Result<Vec<Commit>> {
+        let mut seen: HashSet<Hash> = self.commits.keys().copied().collect();
+        let mut order = vec![];
+        let mut stack: Vec<(Hash, Option<Commit>)> = tips.iter().map(|&h| (h, None)).collect();
+
+        while let Some((hash, visited)) = stack.pop() {
+            if let Some(commit) = visited {
+                order.push(commit);
+                continue;
+            }
+            if !seen.insert(hash) {
+                continue;
+            }
+            let commit = transport::read_commit(hash)?;
+            let parents = commit.parent_hashes().to_vec();
+            stack.push((hash, Some(commit)));
+            stack.extend(parents.into_iter().filter(|p| !seen.contains(p)).map(|p| (p, None)));
+        }
+        Ok(order)
+    }
+
+    /// Rewrites the tree at a directory, given with a trailing `/`.
+    /// Returns None if nothing is left in it.
+    fn rewrite_tree(&mut self, hash: Hash, dir: &str, depth: usize) -> Result<Option<Hash>> {
+        if let Some(&new) = self.trees.get(&(hash, dir.to_owned())) {
+            return Ok(new);
+        }
+        if depth >= MAX_TREE_DEPTH {
+            return Err(Error::TreeTooDeep(dir.into()));
+        }
+        let tree = transport::read_tree(hash)?;
+        let mut new = Tree::new();
+        for e in tree.entries() {
+            let path = format!("{}{}", dir, e.name());
+            match e.kind() {
+                TreeEntryKind::Blob if !self.is_removed(Path::new(&path), false) => {
+                    new.add_file(e.hash(), e.name().to_owned(), e.is_executable())
+                }
+                TreeEntryKind::Tree if !self.is_removed(Path::new(&path), true) => {
+                    if let Some(subtree) = self.rewrite_tree(e.hash(), &(path + "/"), depth + 1)? {
+                        new.add_tree(subtree, e.name().to_owned())
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let new = if new.is_empty() {
+            None
+        } else if new.entries() == tree.entries() {
+            Some(hash)
+        } else {
+            transport::write_tree(&mut new)?;
+            Some(new.hash())
+        };
+        self.trees.insert((hash, dir.to_owned()), new);
+        Ok(new)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::error::{Error::*, Result};
+use crate::repo::object::{Commit, Tree};
+use regex::{Regex, RegexBuilder};
+
+/// Selects the commits of the log by their message and their changes.
+#[derive(Debug, Default)]
+pub struct LogFilter {
+    /// Pattern the message must match.
+    grep: Option<Regex>,
+    /// String whose number of occurrences in some file the commit changes,
+    /// i.e. that it adds or removes.
+    pickaxe: Option<String>,
+}
+
+impl LogFilter {
+    /// Creates a filter for messages matching a regular expression, which
+    /// ignores case if `ignore_case` is set, and for commits adding or
+    /// removing a string.
+    pub fn new(grep: Option<&str>, ignore_case: bool, pickaxe: Option<String>) -> Result<LogFilter> {
+        let grep = grep
+            .map(|pattern| RegexBuilder::new(pattern).case_insensitive(ignore_case).build())
+            .transpose()
+            .map_err(|err| InvalidPattern(err.to_string()))?;
+        Ok(LogFilter { grep, pickaxe })
+    }
+
+    /// Returns whether a commit is selected. Its changes are those from its
+    /// first parent, so a merge only matches `pickaxe` for what it brings
+    /// into the branch.
+    pub fn matches(&self, commit: &Commit) -> Result<bool> {
+        if let Some(grep) = &self.grep {
+            if !grep.is_match(commit.msg()) {
+                return Ok(false);
+            }
+        }
+        match &self.pickaxe {
+            Some(s) => changes_occurrences(commit, s.as_bytes()),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Returns whether a commit changes the number of occurrences of a string in
+/// any file.
+fn changes_occurrences(commit: &Commit, s: &[u8]) -> Result<bool> {
+    let parent = match commit.parent() {
+        Some(parent) => parent?.tree()?,
+        None => Tree::new(),
+    };
+    for change in parent.diff(&commit.tree()?)? {
+        let (old, new) = change.contents()?;
+        if count_occurrences(&old, s) != count_occurrences(&new, s) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Counts the non-overlapping occurrences of a string in data.
+fn count_occurrences(data: &[u8], s: &[u8]) -> usize {
+    if s.is_empty() {
+        return 0;
+    }
+    let (mut count, mut i) = (0, 0);
+    while i + s.len() <= data.len() {
+        if data[i..].starts_with(s) {
+            count += 1;
+            i += s.len();
+        } else {
+            i += 1;
+        }
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    #[test]
+    fn occurrences() {
+        assert_eq!(count_occurrences(b"aaaa", b"aa"), 2);
+        assert_eq!(count_occurrences(b"fn main() {}", b"main"), 1);
+        assert_eq!(count_occurrences(b"abc", b""), 0);
+    }
+
+    #[test]
+    fn filter_commits() {
+        let mut t = TestRepo::new();
+        let add = t.commit_file("a.txt", "let answer = 42;\n");
+        let edit = t.commit_file("a.txt", "let answer = 42;\nprint(answer);\n");
+        let remove = t.commit_file("a.txt", "print(answer);\n");
+
+        let pickaxe = LogFilter::new(None, false, Some("42".to_owned())).unwrap();
+        assert!(pickaxe.matches(&add).unwrap());
+        assert!(!pickaxe.matches(&edit).unwrap());
+        assert!(pickaxe.matches(&remove).unwrap());
+
+        let grep = LogFilter::new(Some("^LET ANSWER"), true, None).unwrap();
+        assert!(grep.matches(&add).unwrap());
+        assert!(!grep.matches(&remove).unwrap());
+        let case = LogFilter::new(Some("^LET ANSWER"), false, None).unwrap();
+        assert!(!case.matches(&add).unwrap());
+        assert!(matches!(LogFilter::new(Some("("), false, None), Err(InvalidPattern(_))));
+    }
+}
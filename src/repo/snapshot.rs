@@ -0,0 +1,173 @@
+use crate::repo::object::Hash;
+use crate::repo::repository::{CheckoutAction, Repository};
+use crate::storage::transport;
+use crate::error::{Error::*, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path};
+use walkdir::WalkDir;
+
+/// Files of the repository directory that are not stored in a snapshot.
+/// Alternates are left out since their objects are stored in the snapshot.
+const EXCLUDED: &[&str] = &["alternates", "statcache"];
+
+/// Entries of the repository directory that a snapshot may restore. Hooks
+/// are left out, as a snapshot must not run commands.
+const RESTORED: &[&str] = &[
+    "AUTOSTASH",
+    "COMMIT_EDITMSG",
+    "HEAD",
+    "MERGE_CONFLICTS",
+    "MERGE_HEAD",
+    "MERGE_RR",
+    "PREV_HEAD",
+    "REBASE_STATE",
+    "bitmaps",
+    "config",
+    "info",
+    "objects",
+    "packed-refs",
+    "refs",
+    "rr-cache",
+    "tracklist",
+];
+
+/// The contents of a repository directory, keyed by their path relative to
+/// `.gnew` (e.g. `objects/<hash>`, `refs/heads/main`).
+#[derive(Debug, Default, PartialEq)]
+pub struct Snapshot {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl Snapshot {
+    pub fn new() -> Snapshot {
+        Snapshot::default()
+    }
+
+    pub fn insert(&mut self, path: String, content: Vec<u8>) {
+        self.files.insert(path, content);
+    }
+
+    /// Returns the files in path order.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.files.iter().map(|(p, c)| (p.as_str(), c.as_slice()))
+    }
+
+    /// Returns the number of objects in the snapshot.
+    pub fn objects(&self) -> usize {
+        self.files
+            .keys()
+            .filter(|p| p.starts_with("objects/"))
+            .count()
+    }
+}
+
+impl Repository {
+    /// Saves the objects, references, tracklist and settings of the
+    /// repository to a snapshot file.
+    /// Returns the number of saved objects.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<usize> {
+        let mut snapshot = Snapshot::new();
+        let objects_dir = self.storage_dir().join("objects");
+
+        for entry in WalkDir::new(self.storage_dir())
+            .into_iter()
+            .filter_entry(|e| e.path() != objects_dir)
+        {
+            let entry = entry?;
+            let name = entry.path().strip_prefix(self.storage_dir()).unwrap();
+            let name = name.to_str().unwrap();
+            if entry.file_type().is_file() && !EXCLUDED.contains(&name) && is_restored(name) {
+                snapshot.insert(name.to_owned(), fs::read(entry.path())?);
+            }
+        }
+        for name in transport::get_all_objects(self.storage_dir())? {
+            let name = name.to_str().unwrap();
+            let hash = name.parse().or(Err(ObjectCorrupted))?;
+            snapshot.insert(format!("objects/{}", name), transport::read_object(hash)?);
+        }
+
+        transport::write_snapshot(path, &snapshot)?;
+        Ok(snapshot.objects())
+    }
+
+    /// Creates a repository in the current directory from a snapshot file
+    /// and writes the files of HEAD to the working tree. Other files in the
+    /// working tree are left alone.
+    pub fn restore_snapshot<P: AsRef<Path>>(path: P) -> Result<Repository> {
+        let worktree = fs::canonicalize(".")?;
+        let storage_dir = transport::storage_dir(&worktree);
+        if storage_dir.exists() {
+            return Err(RepositoryExists);
+        }
+        let snapshot = transport::read_snapshot(path)?;
+        verify_snapshot(&snapshot)?;
+
+        for (name, content) in snapshot.files() {
+            let dest = storage_dir.join(name);
+            fs::create_dir_all(dest.parent().unwrap())?;
+            fs::write(dest, content)?;
+        }
+        fs::create_dir_all(storage_dir.join("objects"))?;
+
+        let mut r = Repository::open_at(&worktree)?;
+        if let Ok(hash) = r.head_hash() {
+            let tree = transport::read_commit(hash)?.tree()?;
+            let mut plan = r.plan_checkout(&tree, true)?;
+            plan.actions
+                .retain(|a| matches!(a, CheckoutAction::Write(..)));
+            r.apply_checkout(plan)?;
+        }
+        Ok(r)
+    }
+}
+
+/// Checks whether a path relative to the repository directory is in an
+/// entry that snapshots restore, without `..` or other special components.
+fn is_restored(name: &str) -> bool {
+    let path = Path::new(name);
+    path.components().all(|c| matches!(c, Component::Normal(_)))
+        && path
+            .components()
+            .next()
+            .is_some_and(|c| RESTORED.iter().any(|r| c.as_os_str() == *r))
+}
+
+/// Checks that the files of a snapshot stay in the entries it restores and
+/// that its objects match their hashes.
+fn verify_snapshot(snapshot: &Snapshot) -> Result<()> {
+    for (name, content) in snapshot.files() {
+        if !is_restored(name) {
+            return Err(SnapshotCorrupted);
+        }
+        if let Some(name) = name.strip_prefix("objects/") {
+            let mut hash = Hash::new();
+            hash.update(content);
+            if hash.to_string() != name {
+                return Err(ObjectCorrupted);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsafe_names_are_rejected() {
+        for name in ["HEAD", "refs/heads/main", "objects", "info/exclude"] {
+            assert!(is_restored(name), "{}", name);
+        }
+        for name in ["../x", "/etc/passwd", "refs/../../x", "hooks/pre-receive", "", "x"] {
+            assert!(!is_restored(name), "{}", name);
+        }
+
+        let mut snapshot = Snapshot::new();
+        snapshot.insert("HEAD".to_owned(), b"ref: refs/heads/main\n".to_vec());
+        assert!(verify_snapshot(&snapshot).is_ok());
+        snapshot.insert("../x".to_owned(), vec![]);
+        assert!(matches!(verify_snapshot(&snapshot), Err(SnapshotCorrupted)));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::error::Result;
+use crate::repo::object::Hash;
+use crate::repo::repository::Repository;
+use crate::storage::transport;
+use std::fs;
+use std::path::PathBuf;
+
+/// What the repository is in the middle of, read from the state files in
+/// the storage directory.
+#[derive(Debug, PartialEq)]
+pub enum RepositoryState {
+    /// No merge or rebase in progress.
+    Clean,
+    /// A merge waiting for its result to be committed.
+    Merging {
+        /// The commit being merged into HEAD.
+        theirs: Hash,
+        /// Files that still have conflict markers.
+        unresolved: Vec<PathBuf>,
+    },
+    /// A rebase stopped at a step with conflicts.
+    Rebasing {
+        /// The commit the steps are replayed on.
+        onto: Hash,
+        /// The commit of the step that stopped, if any.
+        stopped: Option<Hash>,
+        /// Number of steps left after it.
+        remaining: usize,
+        /// Files that still have conflict markers.
+        unresolved: Vec<PathBuf>,
+    },
+}
+
+impl Repository {
+    /// Returns the merge or rebase in progress, if any. A rebase that stopped
+    /// at conflicts is also merging, so it takes precedence.
+    pub fn state(&self) -> Result<RepositoryState> {
+        if let Some(rebase) = self.rebase_state()? {
+            return Ok(RepositoryState::Rebasing {
+                onto: rebase.onto,
+                stopped: rebase.current.map(|step| step.commit),
+                remaining: rebase.todo.len(),
+                unresolved: self.unresolved_conflicts()?,
+            });
+        }
+        if let Some(theirs) = transport::read_merge_head(self.worktree())? {
+            return Ok(RepositoryState::Merging {
+                theirs,
+                unresolved: self.unresolved_conflicts()?,
+            });
+        }
+        Ok(RepositoryState::Clean)
+    }
+
+    /// Returns the conflicted files of the merge in progress that still have
+    /// conflict markers.
+    pub(crate) fn unresolved_conflicts(&self) -> Result<Vec<PathBuf>> {
+        let mut unresolved = vec![];
+        for path in self.conflicts().keys() {
+            let data = match fs::read(self.worktree().join(path)) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            if data.split(|&b| b == b'\n').any(|l| l.starts_with(b"<<<<<<<")) {
+                unresolved.push(PathBuf::from(path));
+            }
+        }
+        Ok(unresolved)
+    }
+}
@@ -0,0 +1,174 @@
+use crate::repo::object::{Hash, TreeEntryKind};
+use crate::repo::repository::Repository;
+use crate::repo::revwalk::{RevWalk, RevWalkOptions};
+use crate::storage::transport;
+use crate::error::Result;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+/// Number of blobs reported in `RepoStats::largest_blobs`.
+const LARGEST_BLOBS: usize = 5;
+
+/// An object in the object store, as checked by `Repository::verify_objects`.
+#[derive(Debug, PartialEq)]
+pub struct ObjectInfo {
+    pub hash: Hash,
+    /// Object type from the header, or `None` if it is unreadable.
+    pub kind: Option<String>,
+    /// Size of the stored object in bytes.
+    pub size: u64,
+    /// Whether the content hashes to the object name.
+    pub valid: bool,
+}
+
+/// Object database and history statistics of a repository.
+#[derive(Debug, Default, PartialEq)]
+pub struct RepoStats {
+    /// Number of objects stored as individual files.
+    pub loose_objects: usize,
+    /// Total size of the loose objects on disk, in bytes.
+    pub loose_size: u64,
+    pub blobs: usize,
+    pub trees: usize,
+    pub commit_objects: usize,
+    /// Number of commits reachable from HEAD or a branch.
+    pub commits: usize,
+    pub branches: usize,
+    /// The largest blobs and their sizes, largest first.
+    pub largest_blobs: Vec<(Hash, u64)>,
+}
+
+/// A blob of the history, as ranked by `Repository::largest_blobs`.
+#[derive(Debug, PartialEq)]
+pub struct HistoryBlob {
+    pub hash: Hash,
+    /// Size of the content in bytes.
+    pub size: u64,
+    /// Path of the blob in the commit that introduced it.
+    pub path: String,
+    /// The oldest commit that contains the blob.
+    pub commit: Hash,
+}
+
+impl Repository {
+    /// Collects statistics about the object store and history.
+    pub fn stats(&self) -> Result<RepoStats> {
+        let objects_dir = self.storage_dir().join("objects");
+        let mut stats = RepoStats {
+            branches: self.branches().len(),
+            ..RepoStats::default()
+        };
+        let mut blobs = vec![];
+
+        for name in transport::get_objects(self.storage_dir())? {
+            let size = fs::metadata(objects_dir.join(&name))?.len();
+            stats.loose_objects += 1;
+            stats.loose_size += size;
+
+            match transport::read_object_kind(self.storage_dir(), &name)?.as_str() {
+                "blob" => {
+                    stats.blobs += 1;
+                    if let Some(hash) = name.to_str().and_then(|n| n.parse().ok()) {
+                        blobs.push((hash, size))
+                    }
+                }
+                "tree" => stats.trees += 1,
+                "commit" => stats.commit_objects += 1,
+                _ => (),
+            }
+        }
+        blobs.sort_by_key(|&(_, size): &(Hash, u64)| Reverse(size));
+        blobs.truncate(LARGEST_BLOBS);
+        stats.largest_blobs = blobs;
+        stats.commits = self.count_commits()?;
+
+        Ok(stats)
+    }
+
+    /// Checks the checksum of every stored object.
+    /// Returns the objects sorted by hash.
+    pub fn verify_objects(&self) -> Result<Vec<ObjectInfo>> {
+        let objects_dir = self.storage_dir().join("objects");
+        let mut objects = vec![];
+
+        for name in transport::get_objects(self.storage_dir())? {
+            let hash = match name.to_str().and_then(|n| n.parse().ok()) {
+                Some(hash) => hash,
+                None => continue,
+            };
+            let content = fs::read(objects_dir.join(&name))?;
+            let mut actual = Hash::new();
+            actual.update(&content);
+
+            objects.push(ObjectInfo {
+                hash,
+                kind: transport::read_object_kind(self.storage_dir(), &name).ok(),
+                size: content.len() as u64,
+                valid: actual == hash,
+            });
+        }
+        objects.sort_unstable_by_key(|o| o.hash.to_string());
+        Ok(objects)
+    }
+
+    /// Returns the `n` largest blobs reachable from HEAD or a reference,
+    /// largest first, with the path and commit that introduced them. Blobs
+    /// left out of a partial clone are not fetched and not ranked.
+    pub fn largest_blobs(&self, n: usize) -> Result<Vec<HistoryBlob>> {
+        let mut tips: Vec<Hash> = self.references().iter().map(|(_, h)| h).collect();
+        tips.extend(self.head_hash().ok());
+        let walk = RevWalk::new(&tips, RevWalkOptions::default())?;
+        let mut commits = walk.collect::<Result<Vec<_>>>()?;
+        // oldest first, so that each blob is attributed to the first commit with it
+        commits.reverse();
+
+        let mut seen_trees = HashSet::new();
+        let mut introduced = HashMap::new();
+        for commit in &commits {
+            let mut trees = vec![(commit.tree_hash(), String::new())];
+            while let Some((hash, dir)) = trees.pop() {
+                if !seen_trees.insert(hash) {
+                    continue;
+                }
+                for e in transport::read_tree(hash)?.entries() {
+                    let path = format!("{}{}", dir, e.name());
+                    match e.kind() {
+                        TreeEntryKind::Tree => trees.push((e.hash(), path + "/")),
+                        TreeEntryKind::Blob => {
+                            introduced.entry(e.hash()).or_insert((path, commit.hash()));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut blobs = vec![];
+        for (hash, (path, commit)) in introduced {
+            if transport::object_exists(hash) {
+                let size = transport::read_blob(hash)?.content().len() as u64;
+                blobs.push(HistoryBlob { hash, size, path, commit });
+            }
+        }
+        blobs.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+        blobs.truncate(n);
+        Ok(blobs)
+    }
+
+    /// Counts the commits reachable from HEAD and all branches.
+    fn count_commits(&self) -> Result<usize> {
+        let mut seen = HashSet::new();
+        let tips = self
+            .head_hash()
+            .into_iter()
+            .chain(self.branches().into_values());
+
+        let mut commits: Vec<_> = tips.collect();
+        while let Some(hash) = commits.pop() {
+            if seen.insert(hash) {
+                commits.extend(transport::read_commit(hash)?.parent_hashes());
+            }
+        }
+        Ok(seen.len())
+    }
+}
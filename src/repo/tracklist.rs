@@ -0,0 +1,163 @@
+use crate::error::Result;
+use crate::repo::repository::{Repository, Tracklist};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path};
+
+/// What `Repository::verify_tracklist` found wrong with the tracklist.
+#[derive(Debug, Default, PartialEq)]
+pub struct TracklistReport {
+    /// Entries that are not in the canonical form, with their canonical path.
+    pub normalized: Vec<(String, String)>,
+    /// Entries dropped as another form of a tracked path, or as a directory
+    /// with tracked files.
+    pub duplicates: Vec<String>,
+    /// Entries dropped since they are not inside the working tree.
+    pub invalid: Vec<String>,
+    /// Entries of files that are neither in the working tree nor in HEAD.
+    pub stale: Vec<String>,
+}
+
+impl TracklistReport {
+    pub fn is_empty(&self) -> bool {
+        self.normalized.is_empty()
+            && self.duplicates.is_empty()
+            && self.invalid.is_empty()
+            && self.stale.is_empty()
+    }
+
+    /// Returns the number of entries that are rewritten or dropped, leaving
+    /// out the stale ones.
+    pub fn fixed(&self) -> usize {
+        let normalized = self.normalized.iter().map(|(entry, _)| entry);
+        let fixed: HashSet<_> = normalized.chain(&self.duplicates).chain(&self.invalid).collect();
+        fixed.len()
+    }
+}
+
+/// Returns the canonical form of a tracklist entry: a path relative to the
+/// working tree with `/` separators, and without empty, `.` or `..`
+/// components. Returns None if the path is absolute or outside the working
+/// tree.
+pub fn canonical_path(path: &str) -> Option<String> {
+    let mut components: Vec<&str> = vec![];
+    if path.starts_with(['/', '\\']) {
+        return None;
+    }
+    for c in path.split(['/', '\\']) {
+        match c {
+            "" | "." => (),
+            ".." => {
+                components.pop()?;
+            }
+            c => components.push(c),
+        }
+    }
+    Some(components.join("/")).filter(|p| !p.is_empty())
+}
+
+/// Returns the tracklist entry of a path relative to the working tree.
+pub fn path_entry(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(c) => c.to_str(),
+            _ => None,
+        })
+        .collect();
+    components.join("/")
+}
+
+/// Rewrites the entries of a tracklist in the canonical form, dropping the
+/// duplicates and the invalid entries. Returns the canonical tracklist and
+/// what was changed.
+pub fn canonical_tracklist(tracklist: &Tracklist) -> (Tracklist, TracklistReport) {
+    let mut report = TracklistReport::default();
+    let mut canonical = Tracklist::new();
+
+    for entry in tracklist {
+        let path = match canonical_path(entry) {
+            Some(path) => path,
+            None => {
+                report.invalid.push(entry.clone());
+                continue;
+            }
+        };
+        if path != *entry {
+            report.normalized.push((entry.clone(), path.clone()));
+        }
+        if !canonical.insert(path) {
+            report.duplicates.push(entry.clone());
+        }
+    }
+
+    // a directory with tracked files is not itself a file
+    let dirs: HashSet<_> = canonical
+        .iter()
+        .flat_map(|p| Path::new(p).ancestors().skip(1))
+        .filter_map(|d| d.to_str())
+        .map(str::to_owned)
+        .collect();
+    canonical.retain(|p| {
+        let is_dir = dirs.contains(p);
+        if is_dir {
+            report.duplicates.push(p.clone());
+        }
+        !is_dir
+    });
+    (canonical, report)
+}
+
+impl Repository {
+    /// Checks that the entries of the tracklist are canonical, unique and
+    /// name files in the working tree or in HEAD. With `repair`, writes the
+    /// canonical tracklist, leaving out stale entries only with `prune`.
+    pub fn verify_tracklist(&mut self, repair: bool, prune: bool) -> Result<TracklistReport> {
+        let (mut tracklist, mut report) = canonical_tracklist(self.tracklist());
+
+        let head_tree = self.head_tree()?;
+        for path in &tracklist {
+            let on_disk = fs::symlink_metadata(self.worktree().join(path)).is_ok();
+            if !on_disk && head_tree.file(Path::new(path)).is_err() {
+                report.stale.push(path.clone());
+            }
+        }
+        if prune {
+            tracklist.retain(|p| !report.stale.contains(p));
+        }
+        if repair && *self.tracklist() != tracklist {
+            self.set_tracklist(tracklist)?;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_paths() {
+        assert_eq!(canonical_path("a/b").as_deref(), Some("a/b"));
+        assert_eq!(canonical_path("./a//b/").as_deref(), Some("a/b"));
+        assert_eq!(canonical_path("a\\b").as_deref(), Some("a/b"));
+        assert_eq!(canonical_path("a/../b").as_deref(), Some("b"));
+        assert_eq!(canonical_path("../a"), None);
+        assert_eq!(canonical_path("/a"), None);
+        assert_eq!(canonical_path("."), None);
+        assert_eq!(path_entry(Path::new("./a/b")), "a/b");
+    }
+
+    #[test]
+    fn canonicalize_tracklist() {
+        let tracklist: Tracklist = ["./b", "dir", "dir/a", "dir\\a", "../x"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (canonical, report) = canonical_tracklist(&tracklist);
+        assert_eq!(canonical.into_iter().collect::<Vec<_>>(), ["b", "dir/a"]);
+        assert_eq!(report.invalid, ["../x"]);
+        assert_eq!(report.duplicates, ["dir\\a", "dir"]);
+        assert_eq!(report.fixed(), 4);
+    }
+}
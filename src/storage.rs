@@ -1,2 +1,3 @@
+pub mod memory;
 pub mod serialize;
 pub mod transport;
@@ -0,0 +1,67 @@
+//! In-memory object store, which replaces the objects directory on wasm32
+//! where there is no file system. Objects are loaded with `insert_object`,
+//! e.g. after downloading them, and read through `transport` as usual.
+
+use crate::repo::object::Hash;
+use crate::error::{Error::*, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    static OBJECTS: RefCell<HashMap<Hash, Vec<u8>>> = RefCell::new(HashMap::new());
+}
+
+/// Stores a serialized object and returns its hash.
+pub fn insert_object(obj: Vec<u8>) -> Hash {
+    let mut hash = Hash::new();
+    hash.update(&obj);
+    OBJECTS.with(|o| o.borrow_mut().insert(hash, obj));
+    hash
+}
+
+/// Stores an object unless an identical copy is already stored, rejecting
+/// a different object with the same hash.
+pub fn write_object(hash: Hash, obj: &[u8]) -> Result<()> {
+    OBJECTS.with(|o| {
+        let mut objects = o.borrow_mut();
+        match objects.get(&hash) {
+            Some(stored) if stored == obj => Ok(()),
+            Some(_) => Err(HashCollision),
+            None => {
+                objects.insert(hash, obj.to_vec());
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Returns the stored content of an object.
+pub fn read_object(hash: Hash) -> Result<Vec<u8>> {
+    OBJECTS.with(|o| o.borrow().get(&hash).cloned().ok_or(ObjectNotFound))
+}
+
+pub fn object_exists(hash: Hash) -> bool {
+    OBJECTS.with(|o| o.borrow().contains_key(&hash))
+}
+
+/// Removes all objects.
+pub fn clear() {
+    OBJECTS.with(|o| o.borrow_mut().clear())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::serialize::deserialize_blob;
+
+    #[test]
+    fn insert_and_read_object() {
+        let hash = insert_object(b"blob\0hello".to_vec());
+        assert!(object_exists(hash));
+        let blob = deserialize_blob(&read_object(hash).unwrap()).unwrap();
+        assert_eq!(blob.content(), b"hello");
+        assert!(write_object(hash, b"blob\0other").is_err());
+        clear();
+        assert!(!object_exists(hash));
+    }
+}
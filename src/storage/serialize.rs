@@ -1,10 +1,28 @@
-use crate::repo::object::{Blob, Commit, CommitInfo, Tree};
+use crate::repo::bitmap::BitmapIndex;
+use crate::repo::cache::{CacheEntry, StatCache};
+use crate::repo::config::Config;
+use crate::repo::object::{Blob, Commit, CommitInfo, Hash, Tree};
+use crate::repo::refs::Refs;
+use crate::repo::repository::{ConflictStages, Conflicts, Tracklist};
+use crate::repo::snapshot::Snapshot;
 use chrono::{TimeZone, Utc};
+use std::error;
+use std::fmt;
+use std::result;
 use std::str::FromStr;
 
 // Length of the string representation of a hash.
 const HASH_LENGTH: usize = 40;
 
+// Header of a tracklist file in the binary format.
+const TRACKLIST_MAGIC: &[u8] = b"GNTL\x01";
+
+// Header of a reachability bitmap file.
+const BITMAP_MAGIC: &[u8] = b"GNBM\x01";
+
+// Header of a snapshot file.
+const SNAPSHOT_MAGIC: &[u8] = b"GNSS\x01";
+
 /// Serializes a blob object and updates its hash.
 pub fn serialize_blob(blob: &mut Blob) -> Vec<u8> {
     // blob format: `blob<NUL><content>`
@@ -20,7 +38,7 @@ pub fn serialize_tree(tree: &mut Tree) -> Vec<u8> {
     let mut entries: Vec<Vec<u8>> = tree
         .entries()
         .iter()
-        .map(|e| format!("{} {}\0{}", e.kind(), e.name(), e.hash()).into_bytes())
+        .map(|e| format!("{} {}\0{}", e.type_name(), e.name(), e.hash()).into_bytes())
         .collect();
 
     // sort by filename
@@ -39,88 +57,420 @@ pub fn serialize_commit(commit: &mut Commit) -> Vec<u8> {
     obj
 }
 
+/// Why an object could not be deserialized.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeserializeError {
+    /// The object does not start with the header of its type.
+    MissingHeader(&'static str),
+    /// A tree entry at a byte offset is truncated or malformed.
+    InvalidEntry(usize),
+    /// A field of a commit is missing or malformed.
+    InvalidField(&'static str),
+    InvalidHash,
+    /// The commit time is not a number of milliseconds in the supported range.
+    InvalidTime,
+    NotUtf8,
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeserializeError::MissingHeader(kind) => write!(f, "missing {} header", kind),
+            DeserializeError::InvalidEntry(offset) => {
+                write!(f, "invalid tree entry at byte {}", offset)
+            }
+            DeserializeError::InvalidField(name) => write!(f, "missing or invalid {}", name),
+            DeserializeError::InvalidHash => write!(f, "invalid hash"),
+            DeserializeError::InvalidTime => write!(f, "invalid time"),
+            DeserializeError::NotUtf8 => write!(f, "text is not UTF-8"),
+        }
+    }
+}
+
+impl error::Error for DeserializeError {}
+
+pub type DeserializeResult<T> = result::Result<T, DeserializeError>;
+
 /// Deserializes a blob object.
-/// Returns None if obj is not a valid blob object.
-pub fn deserialize_blob(obj: &[u8]) -> Option<Blob> {
-    obj.strip_prefix(b"blob\0").map(|content| {
-        let mut blob = Blob::new(content.to_vec());
-        blob.update_hash(obj);
-        blob
-    })
+pub fn deserialize_blob(obj: &[u8]) -> DeserializeResult<Blob> {
+    let content = obj
+        .strip_prefix(b"blob\0")
+        .ok_or(DeserializeError::MissingHeader("blob"))?;
+    let mut blob = Blob::new(content.to_vec());
+    blob.update_hash(obj);
+    Ok(blob)
 }
 
 /// Deserializes a tree object.
-/// Returns None if obj is not a valid tree object.
-pub fn deserialize_tree(obj: &[u8]) -> Option<Tree> {
-    let mut tree = obj
+pub fn deserialize_tree(obj: &[u8]) -> DeserializeResult<Tree> {
+    let entries = obj
         .strip_prefix(b"tree\0")
-        .and_then(deserialize_tree_entries)?;
-
+        .ok_or(DeserializeError::MissingHeader("tree"))?;
+    let mut tree = deserialize_tree_entries(entries, obj.len() - entries.len())?;
     tree.update_hash(obj);
-    Some(tree)
+    Ok(tree)
 }
 
-fn deserialize_tree_entries(obj: &[u8]) -> Option<Tree> {
+/// Parses the entries `<type> <filename><NUL><hash>` of a tree, which start
+/// at `offset` in the object.
+fn deserialize_tree_entries(mut obj: &[u8], mut offset: usize) -> DeserializeResult<Tree> {
     let mut tree = Tree::new();
-    let mut it = obj.split(|&b| b == b'\0');
-    let first = match it.next() {
-        None => return Some(tree),
-        Some(x) => x,
-    };
-    it.try_fold(first, |a, b| {
-        let (kind, name) = (a.get(..4)?, a.get(5..)?);
-        let (hash, next) = (b.get(..HASH_LENGTH)?, b.get(HASH_LENGTH..)?);
-        let name = parse_string(name)?;
-        let hash = parse_from_utf8(hash)?;
+
+    while !obj.is_empty() {
+        let invalid = DeserializeError::InvalidEntry(offset);
+        let nul = obj.iter().position(|&b| b == b'\0').ok_or(invalid.clone())?;
+        let (kind, name) = match obj[..nul].split_first_chunk::<5>() {
+            Some((kind, name)) if kind[4] == b' ' => (&kind[..4], name),
+            _ => return Err(invalid),
+        };
+        let hash = obj
+            .get(nul + 1..nul + 1 + HASH_LENGTH)
+            .ok_or(invalid.clone())?;
+        let name = parse_string(name).ok_or(DeserializeError::NotUtf8)?;
+        let hash = parse_from_utf8(hash).ok_or(DeserializeError::InvalidHash)?;
         match kind {
             b"blob" => tree.add_blob(hash, name),
+            b"exec" => tree.add_file(hash, name, true),
             b"tree" => tree.add_tree(hash, name),
-            _ => return None,
+            _ => return Err(invalid),
         };
-        Some(next)
-    })
-    .and_then(|rest| if rest.is_empty() { Some(tree) } else { None })
+        let len = nul + 1 + HASH_LENGTH;
+        obj = &obj[len..];
+        offset += len;
+    }
+    Ok(tree)
 }
 
 /// Deserializes a commit object.
-/// Returns None if obj is not a valid commit object.
-pub fn deserialize_commit(obj: &[u8]) -> Option<Commit> {
-    let mut commit = obj
+pub fn deserialize_commit(obj: &[u8]) -> DeserializeResult<Commit> {
+    let data = obj
         .strip_prefix(b"commit\0")
-        .and_then(deserialize_commit_data)?;
-
+        .ok_or(DeserializeError::MissingHeader("commit"))?;
+    let mut commit = deserialize_commit_data(data)?;
     commit.update_hash(obj);
-    Some(commit)
+    Ok(commit)
 }
 
-fn deserialize_commit_data(obj: &[u8]) -> Option<Commit> {
+fn deserialize_commit_data(obj: &[u8]) -> DeserializeResult<Commit> {
+    use DeserializeError::*;
+
     let mut it = obj.split(|&b| b == b'\n');
-    let tree = parse_from_utf8(it.next()?.strip_prefix(b"tree ")?)?;
-    let mut next = it.next()?;
-    let parent = match next.strip_prefix(b"parent ") {
-        None => None,
-        Some(b) => {
-            next = it.next()?;
-            Some(parse_from_utf8(b)?)
+    let tree = it
+        .next()
+        .and_then(|line| line.strip_prefix(b"tree "))
+        .ok_or(InvalidField("tree"))?;
+    let tree = parse_from_utf8(tree).ok_or(InvalidHash)?;
+    let mut parents = vec![];
+    let author = loop {
+        let line = it.next().ok_or(InvalidField("author"))?;
+        if let Some(b) = line.strip_prefix(b"parent ") {
+            parents.push(parse_from_utf8(b).ok_or(InvalidHash)?);
+        } else {
+            let author = line.strip_prefix(b"author ").ok_or(InvalidField("author"))?;
+            break parse_string(author).ok_or(NotUtf8)?;
         }
     };
-    let author = parse_string(next.strip_prefix(b"author ")?)?;
-    let time = parse_from_utf8(it.next()?.strip_prefix(b"time ")?)?;
-    let time = Utc.timestamp_millis(time);
-    it.next()?;
-    let msg = parse_string(it.next()?)?;
-    if !it.next()?.is_empty() {
-        return None;
+    let time = it
+        .next()
+        .and_then(|line| line.strip_prefix(b"time "))
+        .ok_or(InvalidField("time"))?;
+    let time = parse_from_utf8(time)
+        .and_then(|t| Utc.timestamp_millis_opt(t).single())
+        .ok_or(InvalidTime)?;
+    if it.next() != Some(b"") {
+        return Err(InvalidField("message"));
     }
-    Some(Commit::new(CommitInfo {
+    // the message may span several lines and ends with a newline
+    let mut lines: Vec<_> = it.collect();
+    if lines.pop() != Some(b"") {
+        return Err(InvalidField("message"));
+    }
+    let msg = parse_string(&lines.join(&b'\n')).ok_or(NotUtf8)?;
+    Ok(Commit::new(CommitInfo {
         tree,
-        parent,
+        parents,
         author,
         time,
         msg,
     }))
 }
 
+/// Serializes a tracklist.
+pub fn serialize_tracklist(tracklist: &Tracklist) -> Vec<u8> {
+    // tracklist format: `GNTL<version><count><entries>`
+    // entry format: `<length><path>`, with sorted paths
+    // counts and lengths are 32-bit big-endian integers
+    let mut obj = TRACKLIST_MAGIC.to_vec();
+    obj.extend((tracklist.len() as u32).to_be_bytes());
+
+    for path in tracklist {
+        obj.extend((path.len() as u32).to_be_bytes());
+        obj.extend(path.as_bytes());
+    }
+    obj
+}
+
+/// Deserializes a tracklist in the binary format, or in the older format
+/// with one path per line.
+/// Returns None if obj is not a valid tracklist.
+pub fn deserialize_tracklist(obj: &[u8]) -> Option<Tracklist> {
+    let mut obj = match obj.strip_prefix(TRACKLIST_MAGIC) {
+        None => {
+            return obj
+                .split(|&b| b == b'\n')
+                .filter(|l| !l.is_empty())
+                .map(parse_string)
+                .collect()
+        }
+        Some(obj) => obj,
+    };
+    let count = parse_u32(&mut obj)?;
+    let mut tracklist = Tracklist::new();
+
+    for _ in 0..count {
+        let len = parse_u32(&mut obj)?;
+        tracklist.insert(parse_string(obj.get(..len)?)?);
+        obj = obj.get(len..)?;
+    }
+    if obj.is_empty() {
+        Some(tracklist)
+    } else {
+        None
+    }
+}
+
+/// Serializes a stat cache.
+pub fn serialize_stat_cache(cache: &StatCache) -> Vec<u8> {
+    // entry format: `<hash> <mtime> <size><TAB><path><LF>`, sorted by path
+    let mut entries: Vec<_> = cache.entries().collect();
+    entries.sort_unstable_by_key(|&(path, _)| path);
+
+    entries
+        .iter()
+        .map(|(path, e)| format!("{} {} {}\t{}\n", e.hash, e.mtime, e.size, path))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Deserializes a stat cache.
+/// Returns None if obj is not a valid stat cache.
+pub fn deserialize_stat_cache(obj: &[u8]) -> Option<StatCache> {
+    let mut cache = StatCache::new();
+
+    for line in parse_string(obj)?.lines() {
+        let (fields, path) = line.split_once('\t')?;
+        let mut fields = fields.split(' ');
+        let entry = CacheEntry {
+            hash: fields.next()?.parse().ok()?,
+            mtime: fields.next()?.parse().ok()?,
+            size: fields.next()?.parse().ok()?,
+        };
+        cache.insert_entry(path.to_owned(), entry);
+    }
+    Some(cache)
+}
+
+/// Serializes the conflicted files of a merge.
+pub fn serialize_conflicts(conflicts: &Conflicts) -> Vec<u8> {
+    // entry format: `<base> <ours> <theirs><TAB><path><LF>`, sorted by path
+    // with `-` for versions where the file does not exist
+    let stage = |h: Option<Hash>| h.map_or_else(|| "-".to_owned(), |h| h.to_string());
+    conflicts
+        .iter()
+        .map(|(path, s)| {
+            let (base, ours, theirs) = (stage(s.base), stage(s.ours), stage(s.theirs));
+            format!("{} {} {}\t{}\n", base, ours, theirs, path)
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Deserializes the conflicted files of a merge.
+/// Returns None if obj is not a valid list of conflicts.
+pub fn deserialize_conflicts(obj: &[u8]) -> Option<Conflicts> {
+    let stage = |s: &str| match s {
+        "-" => Some(None),
+        h => h.parse().ok().map(Some),
+    };
+    let mut conflicts = Conflicts::new();
+
+    for line in parse_string(obj)?.lines() {
+        let (fields, path) = line.split_once('\t')?;
+        let mut fields = fields.split(' ');
+        let stages = ConflictStages {
+            base: stage(fields.next()?)?,
+            ours: stage(fields.next()?)?,
+            theirs: stage(fields.next()?)?,
+        };
+        conflicts.insert(path.to_owned(), stages);
+    }
+    Some(conflicts)
+}
+
+/// Serializes a reachability bitmap index.
+pub fn serialize_bitmaps(index: &BitmapIndex) -> Vec<u8> {
+    // bitmap format: `GNBM<version><count><hashes><count><bitmaps>`
+    // bitmap entry format: `<commit hash><bits>`, one bit per object hash
+    // counts are 32-bit big-endian integers
+    let mut obj = BITMAP_MAGIC.to_vec();
+    obj.extend((index.objects().len() as u32).to_be_bytes());
+    for h in index.objects() {
+        obj.extend(h.to_string().into_bytes());
+    }
+
+    let mut bitmaps: Vec<_> = index.bitmaps().collect();
+    bitmaps.sort_unstable_by_key(|(h, _)| h.to_string());
+    obj.extend((bitmaps.len() as u32).to_be_bytes());
+    for (h, bits) in bitmaps {
+        obj.extend(h.to_string().into_bytes());
+        obj.extend(bits);
+    }
+    obj
+}
+
+/// Deserializes a reachability bitmap index.
+/// Returns None if obj is not a valid bitmap index.
+pub fn deserialize_bitmaps(obj: &[u8]) -> Option<BitmapIndex> {
+    let mut obj = obj.strip_prefix(BITMAP_MAGIC)?;
+    let count = parse_u32(&mut obj)?;
+    let objects = (0..count)
+        .map(|_| parse_hash(&mut obj))
+        .collect::<Option<_>>()?;
+    let mut index = BitmapIndex::new(objects);
+    let len = index.bitmap_len();
+
+    for _ in 0..parse_u32(&mut obj)? {
+        let commit = parse_hash(&mut obj)?;
+        index.insert_bitmap(commit, obj.get(..len)?.to_vec());
+        obj = obj.get(len..)?;
+    }
+    if obj.is_empty() {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+/// Serializes a repository snapshot.
+pub fn serialize_snapshot(snapshot: &Snapshot) -> Vec<u8> {
+    // snapshot format: `GNSS<version><count><entries><checksum>`
+    // entry format: `<path length><path><content length><content>`
+    // lengths are 32-bit and 64-bit big-endian integers, the checksum is
+    // the hash of everything before it
+    let files: Vec<_> = snapshot.files().collect();
+    let mut obj = SNAPSHOT_MAGIC.to_vec();
+    obj.extend((files.len() as u32).to_be_bytes());
+    for (path, content) in files {
+        obj.extend((path.len() as u32).to_be_bytes());
+        obj.extend(path.as_bytes());
+        obj.extend((content.len() as u64).to_be_bytes());
+        obj.extend(content);
+    }
+    let mut checksum = Hash::new();
+    checksum.update(&obj);
+    obj.extend(checksum.to_string().into_bytes());
+    obj
+}
+
+/// Deserializes a repository snapshot.
+/// Returns None if obj is not a valid snapshot or its checksum does not match.
+pub fn deserialize_snapshot(obj: &[u8]) -> Option<Snapshot> {
+    let (content, mut checksum) = obj.split_at(obj.len().checked_sub(HASH_LENGTH)?);
+    let mut actual = Hash::new();
+    actual.update(content);
+    if parse_hash(&mut checksum)? != actual {
+        return None;
+    }
+
+    let mut obj = content.strip_prefix(SNAPSHOT_MAGIC)?;
+    let mut snapshot = Snapshot::new();
+    for _ in 0..parse_u32(&mut obj)? {
+        let len = parse_u32(&mut obj)?;
+        let path = parse_string(obj.get(..len)?)?;
+        obj = obj.get(len..)?;
+        let len = parse_u64(&mut obj)?;
+        snapshot.insert(path, obj.get(..len)?.to_vec());
+        obj = obj.get(len..)?;
+    }
+    if obj.is_empty() {
+        Some(snapshot)
+    } else {
+        None
+    }
+}
+
+/// Serializes a configuration.
+pub fn serialize_config(config: &Config) -> Vec<u8> {
+    // entry format: `<key> = <value><LF>`
+    config
+        .entries()
+        .map(|(k, v)| format!("{} = {}\n", k, v))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Deserializes a configuration. Empty lines and lines starting with `#`
+/// are ignored.
+/// Returns None if obj is not a valid configuration.
+pub fn deserialize_config(obj: &[u8]) -> Option<Config> {
+    let mut config = Config::new();
+
+    for line in parse_string(obj)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once('=')?;
+        config.set(key.trim(), value.trim());
+    }
+    Some(config)
+}
+
+/// Serializes the packed references.
+pub fn serialize_packed_refs(refs: &Refs) -> Vec<u8> {
+    // entry format: `<hash> <name><LF>`
+    refs.iter()
+        .map(|(name, hash)| format!("{} {}\n", hash, name))
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Deserializes packed references. Empty lines and lines starting with `#`
+/// are ignored.
+/// Returns None if obj is not a valid packed references file.
+pub fn deserialize_packed_refs(obj: &[u8]) -> Option<Refs> {
+    let mut refs = Refs::new();
+
+    for line in parse_string(obj)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (hash, name) = line.split_once(' ')?;
+        refs.set(name, parse_from_utf8(hash.as_bytes())?);
+    }
+    Some(refs)
+}
+
+/// Parses a hash and advances past it.
+fn parse_hash(obj: &mut &[u8]) -> Option<Hash> {
+    let hash = parse_from_utf8(obj.get(..HASH_LENGTH)?)?;
+    *obj = obj.get(HASH_LENGTH..)?;
+    Some(hash)
+}
+
+/// Parses a big-endian 32-bit integer and advances past it.
+fn parse_u32(obj: &mut &[u8]) -> Option<usize> {
+    let (n, rest) = (obj.get(..4)?, obj.get(4..)?);
+    *obj = rest;
+    Some(u32::from_be_bytes(n.try_into().ok()?) as usize)
+}
+
+/// Parses a big-endian 64-bit integer and advances past it.
+fn parse_u64(obj: &mut &[u8]) -> Option<usize> {
+    let (n, rest) = (obj.get(..8)?, obj.get(8..)?);
+    *obj = rest;
+    u64::from_be_bytes(n.try_into().ok()?).try_into().ok()
+}
+
 fn parse_from_utf8<T: FromStr>(b: &[u8]) -> Option<T> {
     parse_string(b)?.parse().ok()
 }
@@ -132,7 +482,6 @@ fn parse_string(b: &[u8]) -> Option<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::repo::object::Hash;
 
     #[test]
     fn serde_blob() {
@@ -171,7 +520,7 @@ mod tests {
     fn serde_commit() {
         let mut c1 = Commit::new(CommitInfo {
             tree: Hash::new(),
-            parent: Some(Hash::new()),
+            parents: vec![Hash::new()],
             author: "paul".to_owned(),
             time: Utc.timestamp_millis(1637385703000),
             msg: "write some code".to_owned(),
@@ -184,11 +533,136 @@ mod tests {
         assert_eq!(c1, c2);
     }
 
+    #[test]
+    fn serde_tracklist() {
+        let t1: Tracklist = ["foo.txt", "bar/baz.txt"]
+            .iter()
+            .map(|&p| p.to_owned())
+            .collect();
+
+        let obj = serialize_tracklist(&t1);
+        let t2 = deserialize_tracklist(&obj).unwrap();
+        assert_eq!(t1, t2);
+        assert!(deserialize_tracklist(&obj[..obj.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn deserialize_text_tracklist() {
+        let t1 = deserialize_tracklist(b"foo.txt\nbar/baz.txt").unwrap();
+        let t2: Tracklist = ["bar/baz.txt", "foo.txt"]
+            .iter()
+            .map(|&p| p.to_owned())
+            .collect();
+        assert_eq!(t1, t2);
+    }
+
+    #[test]
+    fn serde_stat_cache() {
+        let mut c1 = StatCache::new();
+        let entry = CacheEntry {
+            hash: Hash::new(),
+            mtime: 1637385703000000000,
+            size: 42,
+        };
+        c1.insert_entry("dir/file name.txt".to_owned(), entry);
+
+        let obj = serialize_stat_cache(&c1);
+        let c2 = deserialize_stat_cache(&obj).unwrap();
+        assert_eq!(c1, c2);
+    }
+
+    #[test]
+    fn serde_conflicts() {
+        let mut c1 = Conflicts::new();
+        let stages = ConflictStages {
+            base: None,
+            ours: Some(Hash::new()),
+            theirs: Some(Hash::new()),
+        };
+        c1.insert("dir/file name.txt".to_owned(), stages);
+
+        let obj = serialize_conflicts(&c1);
+        let c2 = deserialize_conflicts(&obj).unwrap();
+        assert_eq!(c1, c2);
+        assert!(deserialize_conflicts(b"- x -\tfile\n").is_none());
+    }
+
+    #[test]
+    fn serde_config() {
+        let mut c1 = Config::new();
+        c1.set("remote.origin.url", "/tmp/repo");
+        c1.set("init.defaultBranch", "trunk");
+
+        let obj = serialize_config(&c1);
+        let c2 = deserialize_config(&obj).unwrap();
+        assert_eq!(c1, c2);
+        assert!(deserialize_config(b"# comment\n\nkey value\n").is_none());
+    }
+
+    #[test]
+    fn serde_packed_refs() {
+        let mut r1 = Refs::new();
+        r1.set("refs/heads/main", Hash::new());
+        r1.set("refs/tags/v1.0", Hash::new());
+
+        let obj = serialize_packed_refs(&r1);
+        let r2 = deserialize_packed_refs(&obj).unwrap();
+        assert_eq!(r1, r2);
+        assert!(deserialize_packed_refs(b"# packed refs\n\n")
+            .unwrap()
+            .is_empty());
+        assert!(deserialize_packed_refs(b"0123 refs/heads/main\n").is_none());
+    }
+
+    #[test]
+    fn serde_snapshot() {
+        let mut s1 = Snapshot::new();
+        s1.insert("HEAD".to_owned(), b"ref: refs/heads/main\n".to_vec());
+        s1.insert("objects/0123".to_owned(), vec![0, 1, 2, 3]);
+
+        let mut obj = serialize_snapshot(&s1);
+        let s2 = deserialize_snapshot(&obj).unwrap();
+        assert_eq!(s1, s2);
+
+        obj[10] ^= 1;
+        assert!(deserialize_snapshot(&obj).is_none());
+        assert!(deserialize_snapshot(b"GNSS").is_none());
+    }
+
+    #[test]
+    fn serde_bitmaps() {
+        let mut blob = Blob::new(b"foo".to_vec());
+        serialize_blob(&mut blob);
+        let mut i1 = BitmapIndex::new(vec![Hash::new(), blob.hash()]);
+        i1.insert(Hash::new(), &[blob.hash()].into_iter().collect());
+
+        let obj = serialize_bitmaps(&i1);
+        let i2 = deserialize_bitmaps(&obj).unwrap();
+        assert_eq!(i1, i2);
+        let reachable: Vec<_> = i2.reachable(Hash::new()).unwrap().collect();
+        assert_eq!(reachable, vec![blob.hash()]);
+    }
+
+    #[test]
+    fn serde_commit_multiline_message() {
+        let mut c1 = Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![],
+            author: "paul".to_owned(),
+            time: Utc.timestamp_millis(1637385703000),
+            msg: "write some code\n\nand explain it".to_owned(),
+        });
+
+        let obj = serialize_commit(&mut c1);
+        let c2 = deserialize_commit(&obj).unwrap();
+        assert_eq!(c1, c2);
+    }
+
     #[test]
     fn serde_commit_no_parent() {
         let mut c1 = Commit::new(CommitInfo {
             tree: Hash::new(),
-            parent: None,
+            parents: vec![],
             author: "paul".to_owned(),
             time: Utc.timestamp_millis(1637385703000),
             msg: "write some code".to_owned(),
@@ -200,4 +674,149 @@ mod tests {
         let c2 = deserialize_commit(&obj).unwrap();
         assert_eq!(c1, c2);
     }
+
+    #[test]
+    fn serde_commit_merge() {
+        let mut parent = Hash::new();
+        parent.update(b"parent");
+        let mut c1 = Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![Hash::new(), parent],
+            author: "paul".to_owned(),
+            time: Utc.timestamp_millis(1637385703000),
+            msg: "merge some code".to_owned(),
+        });
+
+        let obj = serialize_commit(&mut c1);
+        let c2 = deserialize_commit(&obj).unwrap();
+        assert_eq!(c2.parent_hashes(), [Hash::new(), parent]);
+        assert_eq!(c1, c2);
+    }
+
+    #[test]
+    fn deserialize_malformed_objects() {
+        let mut t1 = Tree::new();
+        t1.add_blob(Hash::new(), "foo.txt".to_owned());
+        let tree = serialize_tree(&mut t1);
+        // truncated objects are rejected without panicking, except the
+        // empty tree and commits cut at the end of a message line
+        for len in 0..tree.len() {
+            assert!(deserialize_tree(&tree[..len]).is_err() || len == 5);
+        }
+
+        let mut c1 = Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![Hash::new()],
+            author: "paul".to_owned(),
+            time: Utc.timestamp_millis(1637385703000),
+            msg: "write some code".to_owned(),
+        });
+        let commit = serialize_commit(&mut c1);
+        for len in 0..commit.len() {
+            assert!(deserialize_commit(&commit[..len]).is_err() || commit[len - 1] == b'\n');
+        }
+
+        let hash = format!("{}\u{e9}{}", "0".repeat(7), "0".repeat(31));
+        let obj = format!("tree\0blob foo\0{}", hash);
+        assert_eq!(deserialize_tree(obj.as_bytes()), Err(DeserializeError::InvalidHash));
+        assert_eq!(
+            deserialize_tree(b"tree\0blobxfoo\0"),
+            Err(DeserializeError::InvalidEntry(5))
+        );
+
+        let obj = format!("commit\0tree {}\nauthor a\ntime 99999999999999999\n\nm\n", Hash::new());
+        assert_eq!(deserialize_commit(obj.as_bytes()), Err(DeserializeError::InvalidTime));
+        assert_eq!(
+            deserialize_commit(b"blob\0"),
+            Err(DeserializeError::MissingHeader("commit"))
+        );
+    }
+}
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // about 250,000 years around 1970, within the range of chrono
+    const MAX_MILLIS: i64 = 8_000_000_000_000_000;
+
+    fn hash() -> impl Strategy<Value = Hash> {
+        any::<Vec<u8>>().prop_map(|data| {
+            let mut hash = Hash::new();
+            hash.update(&data);
+            hash
+        })
+    }
+
+    fn tree() -> impl Strategy<Value = Tree> {
+        // names are any text without NUL, including separators and unicode
+        let entry = (0..3u8, hash());
+        prop::collection::btree_map("[^\0]{1,64}", entry, 0..16).prop_map(|entries| {
+            let mut tree = Tree::new();
+            for (name, (kind, hash)) in entries {
+                match kind {
+                    0 => tree.add_blob(hash, name),
+                    1 => tree.add_file(hash, name, true),
+                    _ => tree.add_tree(hash, name),
+                }
+            }
+            tree
+        })
+    }
+
+    fn commit() -> impl Strategy<Value = Commit> {
+        (
+            hash(),
+            prop::collection::vec(hash(), 0..4),
+            "[^\n]*",
+            -MAX_MILLIS..MAX_MILLIS,
+            ".*(\n.*){0,8}",
+        )
+            .prop_map(|(tree, parents, author, millis, msg)| {
+                Commit::new(CommitInfo {
+                    tree,
+                    parents,
+                    author,
+                    time: Utc.timestamp_millis(millis),
+                    msg,
+                })
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn blob_round_trip(content in any::<Vec<u8>>()) {
+            let mut b1 = Blob::new(content);
+            let obj = serialize_blob(&mut b1);
+            prop_assert_eq!(deserialize_blob(&obj).unwrap(), b1);
+        }
+
+        #[test]
+        fn tree_round_trip(mut t1 in tree()) {
+            let obj = serialize_tree(&mut t1);
+            let t2 = deserialize_tree(&obj).unwrap();
+            prop_assert!(t1.same_entries(&t2));
+            prop_assert_eq!(t1.hash(), t2.hash());
+        }
+
+        #[test]
+        fn commit_round_trip(mut c1 in commit()) {
+            let obj = serialize_commit(&mut c1);
+            prop_assert_eq!(deserialize_commit(&obj).unwrap(), c1);
+        }
+
+        #[test]
+        fn long_message_round_trip(mut c1 in commit(), msg in "(.{0,200}\n){100,200}") {
+            c1 = Commit::new(CommitInfo {
+                tree: c1.tree_hash(),
+                parents: c1.parent_hashes().to_vec(),
+                author: c1.author().to_owned(),
+                time: c1.time(),
+                msg,
+            });
+            let obj = serialize_commit(&mut c1);
+            prop_assert_eq!(deserialize_commit(&obj).unwrap(), c1);
+        }
+    }
 }
@@ -1,21 +1,115 @@
+#[cfg(target_arch = "wasm32")]
+use super::memory;
 use super::serialize::*;
-use crate::repo::object::{Blob, Commit, Hash, Tree};
-use crate::repo::repository::Reference;
-use crate::wd::ui::{Error::*, Result};
-use std::collections::HashMap;
-use std::fs::{self, File};
-use std::io::{BufRead, BufReader, ErrorKind, Write};
+use crate::repo::bitmap::BitmapIndex;
+use crate::repo::cache::StatCache;
+use crate::repo::config::Config;
+use crate::repo::object::{self, Blob, Commit, Hash, Tree};
+use crate::repo::rebase::RebaseState;
+use crate::repo::refs::{self, Refs};
+use crate::repo::repository::{Conflicts, Reference, Tracklist};
+use crate::repo::snapshot::Snapshot;
+use crate::error::{Error::*, Result};
+use sha1::Sha1;
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::path::{Path, PathBuf};
+use std::process;
 use walkdir::WalkDir;
 
+/// Environment variable naming the objects directory of a push that is not
+/// accepted yet, set for the hooks of the receiving repository.
+pub const QUARANTINE_ENV: &str = "GNEW_QUARANTINE_PATH";
+
+/// Environment variable naming the storage directory of the repository,
+/// instead of `.gnew` in its working tree.
+pub const DIR_ENV: &str = "GNEW_DIR";
+
+/// Environment variable naming the working tree of the repository, instead
+/// of the nearest directory above the current one with a `.gnew` directory.
+pub const WORK_TREE_ENV: &str = "GNEW_WORK_TREE";
+
+/// The working tree and storage directory of an opened repository, which
+/// objects are read from and written to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Store {
+    pub worktree: PathBuf,
+    pub storage_dir: PathBuf,
+    /// The objects of a push being received, which are also read.
+    pub quarantine: Option<PathBuf>,
+}
+
+thread_local! {
+    // The store of the repository this thread works with.
+    static STORE: RefCell<Option<Store>> = const { RefCell::new(None) };
+}
+
+/// Restores the store a thread worked with before `enter_store` when dropped.
+pub struct StoreGuard(Option<Store>);
+
+impl Drop for StoreGuard {
+    fn drop(&mut self) {
+        STORE.with(|s| *s.borrow_mut() = self.0.take());
+    }
+}
+
+/// Makes a store the one of the current thread, until the guard is dropped.
+pub fn enter_store(store: &Store) -> StoreGuard {
+    StoreGuard(STORE.with(|s| s.replace(Some(store.clone()))))
+}
+
+/// Makes a store the one of the current thread.
+pub fn set_store(store: &Store) {
+    STORE.with(|s| *s.borrow_mut() = Some(store.clone()));
+}
+
+/// Returns the store of the current thread, if a repository was opened on it.
+pub fn current_store() -> Option<Store> {
+    STORE.with(|s| s.borrow().clone())
+}
+
+/// Returns the storage directory of the repository with a working tree:
+/// the one of the current thread's store, which GNEW_DIR may have moved, or
+/// `.gnew` in the working tree. `.` is the working tree of the current store.
+pub fn storage_dir<P: AsRef<Path>>(worktree: P) -> PathBuf {
+    let worktree = worktree.as_ref();
+    let stored = STORE.with(|s| {
+        s.borrow()
+            .as_ref()
+            .filter(|store| worktree == Path::new(".") || worktree == store.worktree)
+            .map(|store| store.storage_dir.clone())
+    });
+    stored.unwrap_or_else(|| worktree.join(".gnew"))
+}
+
 /// Creates and writes a blob object from the contents of a file.
 pub fn write_blob<P: AsRef<Path>>(path: P) -> Result<Blob> {
-    let mut blob = Blob::new(fs::read(path)?);
+    write_blob_data(fs::read(path)?)
+}
+
+/// Writes a blob object with the given contents to storage.
+pub fn write_blob_data(data: Vec<u8>) -> Result<Blob> {
+    let mut blob = Blob::new(data);
     let obj = serialize_blob(&mut blob);
     write_object(blob.hash(), &obj)?;
     Ok(blob)
 }
 
+/// Writes a blob object with the given contents unless one with the same
+/// hash is stored, which is then not read back. Returns the hash.
+pub fn write_blob_if_absent(data: Vec<u8>) -> Result<Hash> {
+    let hash = object::hash_blob(&data);
+    if !object_exists(hash) {
+        write_blob_data(data)?;
+    }
+    Ok(hash)
+}
+
 /// Updates the hash of a tree object and writes it to storage.
 pub fn write_tree(tree: &mut Tree) -> Result<()> {
     let obj = serialize_tree(tree);
@@ -28,25 +122,50 @@ pub fn write_commit(commit: &mut Commit) -> Result<()> {
     write_object(commit.hash(), &obj)
 }
 
+/// Writes an object unless an identical copy is already stored.
+/// A stored object with different content is either a hash collision, which
+/// is rejected, or a corrupted object, which is repaired with the new data.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(%hash)))]
+#[cfg(not(target_arch = "wasm32"))]
 fn write_object(hash: Hash, obj: &[u8]) -> Result<()> {
     let path = object_path(hash);
-    if !path.exists() {
-        fs::write(path, obj)?;
+    if path.exists() && fs::metadata(&path)?.len() == obj.len() as u64 {
+        let stored = fs::read(&path)?;
+        if stored == obj {
+            return Ok(());
+        }
+        let mut stored_hash = Hash::new();
+        stored_hash.update(&stored);
+        if stored_hash == hash {
+            return Err(HashCollision);
+        }
     }
+    if path.exists() {
+        // the object may be hard linked into another repository
+        fs::remove_file(&path)?;
+    }
+    fs::write(path, obj)?;
     Ok(())
 }
 
+#[cfg(target_arch = "wasm32")]
+fn write_object(hash: Hash, obj: &[u8]) -> Result<()> {
+    memory::write_object(hash, obj)
+}
+
 /// Writes the DIR structure of an empty repo to disk
 pub fn write_empty_repo() -> Result<()> {
-    fs::create_dir_all(".gnew/objects")?;
-    fs::create_dir(".gnew/heads")?;
-    fs::write(".gnew/HEAD", "ref: main\n")?;
-    fs::write(".gnew/tracklist", "")?;
+    let storage = storage_dir(".");
+    fs::create_dir_all(storage.join("objects"))?;
+    fs::create_dir_all(storage.join("refs/heads"))?;
+    fs::create_dir(storage.join("refs/tags"))?;
+    fs::write(storage.join("HEAD"), "ref: refs/heads/main\n")?;
+    write_tracklist(".", &Tracklist::new())?;
     Ok(())
 }
 
 /* generic line filewriter. can be used to write to tracklist and HEAD files */
-pub fn write_lines_gen<P: AsRef<Path>>(path: P, lines: &Vec<String>) -> Result<()> {
+pub fn write_lines_gen<P: AsRef<Path>>(path: P, lines: &[String]) -> Result<()> {
     let content = lines.join("\n");
     fs::write(path, content)?;
     Ok(())
@@ -54,33 +173,174 @@ pub fn write_lines_gen<P: AsRef<Path>>(path: P, lines: &Vec<String>) -> Result<(
 
 /// Reads the blob object with the given hash from storage.
 pub fn read_blob(hash: Hash) -> Result<Blob> {
-    match deserialize_blob(&read_object(hash)?) {
-        Some(blob) if blob.hash() == hash => Ok(blob),
-        _ => Err(ObjectCorrupted),
-    }
+    let obj = read_object(hash)?;
+    check_parsed(hash, &obj, deserialize_blob(&obj), Blob::hash)
 }
 
 /// Reads the tree object with the given hash from storage.
 pub fn read_tree(hash: Hash) -> Result<Tree> {
-    match deserialize_tree(&read_object(hash)?) {
-        Some(tree) if tree.hash() == hash => Ok(tree),
-        _ => Err(ObjectCorrupted),
-    }
+    let obj = read_object(hash)?;
+    check_parsed(hash, &obj, deserialize_tree(&obj), Tree::hash)
 }
 
 /// Reads the commit object with the given hash from storage.
 pub fn read_commit(hash: Hash) -> Result<Commit> {
-    match deserialize_commit(&read_object(hash)?) {
-        Some(commit) if commit.hash() == hash => Ok(commit),
-        _ => Err(ObjectCorrupted),
+    let obj = read_object(hash)?;
+    check_parsed(hash, &obj, deserialize_commit(&obj), Commit::hash)
+}
+
+/// Returns a deserialized object if it has the expected hash. An object
+/// with another hash is corrupted, while one that has the expected hash but
+/// could not be deserialized is malformed, e.g. crafted by a remote.
+fn check_parsed<T>(
+    hash: Hash,
+    obj: &[u8],
+    parsed: DeserializeResult<T>,
+    hash_of: fn(&T) -> Hash,
+) -> Result<T> {
+    match parsed {
+        Ok(o) if hash_of(&o) == hash => Ok(o),
+        Ok(_) => Err(ObjectCorrupted),
+        Err(err) => {
+            let mut actual = Hash::new();
+            actual.update(obj);
+            if actual == hash {
+                Err(ObjectMalformed(hash, err))
+            } else {
+                Err(ObjectCorrupted)
+            }
+        }
     }
 }
 
-fn read_object(hash: Hash) -> Result<Vec<u8>> {
-    fs::read(object_path(hash)).map_err(|err| match err.kind() {
-        ErrorKind::NotFound => ObjectNotFound,
-        _ => err.into(),
-    })
+/// Checks if an object is in storage, including the alternate object stores.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn object_exists(hash: Hash) -> bool {
+    find_object(hash).is_ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn object_exists(hash: Hash) -> bool {
+    memory::object_exists(hash)
+}
+
+/// Reads the stored content of an object without checking it.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(%hash)))]
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_object(hash: Hash) -> Result<Vec<u8>> {
+    Ok(fs::read(find_object(hash)?)?)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_object(hash: Hash) -> Result<Vec<u8>> {
+    memory::read_object(hash)
+}
+
+/// Returns the path of an object, looking in the alternate object stores
+/// and the quarantine of a push being received if it is not in the
+/// repository.
+fn find_object(hash: Hash) -> Result<PathBuf> {
+    let path = object_path(hash);
+    if path.exists() {
+        return Ok(path);
+    }
+    let quarantine = STORE.with(|s| s.borrow().as_ref().and_then(|s| s.quarantine.clone()));
+    match read_alternates(storage_dir("."))?
+        .iter()
+        .chain(&quarantine)
+        .map(|dir| dir.join(hash.to_string()))
+        .find(|p| p.exists())
+    {
+        Some(path) => Ok(path),
+        None => fetch_promised_object(hash),
+    }
+}
+
+/// Copies an object that was left out of a partial clone from the remote
+/// it was cloned from.
+fn fetch_promised_object(hash: Hash) -> Result<PathBuf> {
+    let config = read_config(".")?;
+    let remote = match config.get("remote.origin.url") {
+        Some(url) if config.get_bool("remote.origin.promisor") => PathBuf::from(url),
+        _ => return Err(ObjectNotFound),
+    };
+    let name = PathBuf::from(hash.to_string());
+    let remote_storage = check_repo_exists(remote)?;
+    let alternates = read_alternates(&remote_storage)?;
+    if !object_source(&remote_storage, &alternates, &name).exists() {
+        return Err(ObjectNotFound);
+    }
+    copy_objects(remote_storage.as_path(), &storage_dir("."), &[name])?;
+    Ok(object_path(hash))
+}
+
+/// Writes the content of a blob object to a file, verifying its hash
+/// without reading the whole object into memory.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(%hash)))]
+pub fn checkout_blob<P: AsRef<Path>>(hash: Hash, dest: P) -> Result<()> {
+    let mut src = File::open(find_object(hash)?)?;
+    let mut header = [0u8; 5];
+    src.read_exact(&mut header).or(Err(ObjectCorrupted))?;
+    if &header != b"blob\0" {
+        return Err(ObjectCorrupted);
+    }
+    let mut sha = Sha1::new();
+    sha.update(&header);
+
+    let mut out = File::create(&dest)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match src.read(&mut buf)? {
+            0 => break,
+            n => {
+                sha.update(&buf[..n]);
+                out.write_all(&buf[..n])?;
+            }
+        }
+    }
+    if Hash::from(sha.digest()) != hash {
+        fs::remove_file(dest)?;
+        return Err(ObjectCorrupted);
+    }
+    Ok(())
+}
+
+/// Returns the object directories listed in the alternates file of a repository.
+/// Relative paths are resolved from the repository's object directory.
+pub fn read_alternates<P: AsRef<Path>>(storage_dir: P) -> Result<Vec<PathBuf>> {
+    let path = storage_dir.as_ref().join("alternates");
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let objects_dir = storage_dir.as_ref().join("objects");
+    Ok(read_lines_gen(path)?
+        .iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| objects_dir.join(l.trim()))
+        .collect())
+}
+
+pub fn write_alternates<P: AsRef<Path>>(storage_dir: P, dirs: &[PathBuf]) -> Result<()> {
+    let lines: Vec<_> = dirs
+        .iter()
+        .map(|d| d.to_string_lossy().into_owned())
+        .collect();
+    write_lines_gen(storage_dir.as_ref().join("alternates"), &lines)
+}
+
+/// Returns the type of an object stored in a repository directory,
+/// as found in its header (`blob`, `tree` or `commit`).
+pub fn read_object_kind<P: AsRef<Path>>(storage_dir: P, name: &Path) -> Result<String> {
+    let mut f = File::open(storage_dir.as_ref().join("objects").join(name))?;
+    let mut header = [0u8; 7];
+    let n = f.read(&mut header)?;
+
+    header[..n]
+        .split(|&b| b == b'\0')
+        .next()
+        .filter(|kind| kind.len() < n)
+        .and_then(|kind| String::from_utf8(kind.to_vec()).ok())
+        .ok_or(ObjectCorrupted)
 }
 
 /* generic line filereader. can be used to read from tracklist and HEAD files */
@@ -96,7 +356,7 @@ pub fn read_lines_gen<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
     Ok(out)
 }
 
-pub fn check_existence<P: AsRef<Path>>(files: &Vec<P>) -> Result<()> {
+pub fn check_existence<P: AsRef<Path>>(files: &[P]) -> Result<()> {
     for f in files {
         if !f.as_ref().exists() {
             return Err(FileNotFound);
@@ -105,9 +365,17 @@ pub fn check_existence<P: AsRef<Path>>(files: &Vec<P>) -> Result<()> {
     Ok(())
 }
 
+/// Returns the working tree of the repository containing a directory.
+pub fn find_repo<P: AsRef<Path>>(dir: P) -> Result<PathBuf> {
+    dir.as_ref()
+        .ancestors()
+        .find(|d| d.join(".gnew").is_dir())
+        .map(Path::to_path_buf)
+        .ok_or(NoRepository)
+}
+
 pub fn check_repo_exists<P: AsRef<Path>>(repo: P) -> Result<PathBuf> {
-    let gnew = Path::new(".gnew/");
-    let full_path = repo.as_ref().join(gnew);
+    let full_path = storage_dir(repo);
     if full_path.exists() {
         Ok(full_path)
     } else {
@@ -116,68 +384,382 @@ pub fn check_repo_exists<P: AsRef<Path>>(repo: P) -> Result<PathBuf> {
 }
 
 fn object_path(hash: Hash) -> PathBuf {
-    let mut path = PathBuf::from(".gnew/objects");
+    let mut path = storage_dir(".").join("objects");
     path.push(hash.to_string());
     path
 }
 
-pub fn read_tracklist<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
-    let path = path.as_ref().join(PathBuf::from(".gnew/tracklist"));
-    read_lines_gen(path)
+pub fn read_tracklist<P: AsRef<Path>>(path: P) -> Result<Tracklist> {
+    let path = storage_dir(&path).join("tracklist");
+    deserialize_tracklist(&fs::read(path)?).ok_or(TracklistCorrupted)
+}
+
+/// Writes the tracklist to a temporary file and renames it into place, so
+/// that a crash leaves either the old or the new tracklist. The temporary
+/// file has a name of its own, so concurrent writers do not share it.
+pub fn write_tracklist<P: AsRef<Path>>(path: P, tracklist: &Tracklist) -> Result<()> {
+    let path = storage_dir(&path).join("tracklist");
+    let tmp = path.with_extension(format!("{}-{:x}.tmp", process::id(), random()));
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(&serialize_tracklist(tracklist))?;
+            file.sync_all()
+        });
+    if let Err(err) = written.and_then(|_| fs::rename(&tmp, &path)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Returns a random number, seeded by the standard library's hash maps.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Reads the stat cache, which is empty if it is missing or unreadable.
+pub fn read_stat_cache<P: AsRef<Path>>(path: P) -> StatCache {
+    fs::read(storage_dir(&path).join("statcache"))
+        .ok()
+        .and_then(|obj| deserialize_stat_cache(&obj))
+        .unwrap_or_default()
+}
+
+pub fn write_stat_cache<P: AsRef<Path>>(path: P, cache: &StatCache) -> Result<()> {
+    fs::write(
+        storage_dir(&path).join("statcache"),
+        serialize_stat_cache(cache),
+    )?;
+    Ok(())
+}
+
+/// Reads the conflicted files of the merge in progress, which are empty if
+/// there is none or they are unreadable.
+pub fn read_conflicts<P: AsRef<Path>>(path: P) -> Conflicts {
+    fs::read(storage_dir(&path).join("MERGE_CONFLICTS"))
+        .ok()
+        .and_then(|obj| deserialize_conflicts(&obj))
+        .unwrap_or_default()
+}
+
+/// Writes the conflicted files of a merge, removing the file if there are
+/// none.
+pub fn write_conflicts<P: AsRef<Path>>(path: P, conflicts: &Conflicts) -> Result<()> {
+    let path = storage_dir(&path).join("MERGE_CONFLICTS");
+    if conflicts.is_empty() {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            r => r?,
+        }
+    } else {
+        fs::write(path, serialize_conflicts(conflicts))?;
+    }
+    Ok(())
+}
+
+/// Reads the reachability bitmaps, which are empty if they are missing or unreadable.
+pub fn read_bitmaps() -> BitmapIndex {
+    fs::read(storage_dir(".").join("bitmaps"))
+        .ok()
+        .and_then(|obj| deserialize_bitmaps(&obj))
+        .unwrap_or_default()
 }
 
-pub fn write_tracklist<P: AsRef<Path>>(path: P, lines: &Vec<String>) -> Result<()> {
-    let path = path.as_ref().join(PathBuf::from(".gnew/tracklist"));
-    write_lines_gen(path, lines)
+pub fn write_bitmaps(index: &BitmapIndex) -> Result<()> {
+    fs::write(storage_dir(".").join("bitmaps"), serialize_bitmaps(index))?;
+    Ok(())
+}
+
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<Snapshot> {
+    deserialize_snapshot(&fs::read(path)?).ok_or(SnapshotCorrupted)
+}
+
+pub fn write_snapshot<P: AsRef<Path>>(path: P, snapshot: &Snapshot) -> Result<()> {
+    fs::write(path, serialize_snapshot(snapshot))?;
+    Ok(())
+}
+
+/// Reads the configuration, which is empty if there is no config file.
+pub fn read_config<P: AsRef<Path>>(path: P) -> Result<Config> {
+    match fs::read(storage_dir(&path).join("config")) {
+        Ok(obj) => deserialize_config(&obj).ok_or(ConfigCorrupted),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reads the user configuration from `~/.gnewconfig`, which holds settings
+/// that apply to all repositories.
+pub fn read_user_config() -> Result<Config> {
+    let home = match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home),
+        None => return Ok(Config::new()),
+    };
+    match fs::read(home.join(".gnewconfig")) {
+        Ok(obj) => deserialize_config(&obj).ok_or(ConfigCorrupted),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Config::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn write_config<P: AsRef<Path>>(path: P, config: &Config) -> Result<()> {
+    fs::write(storage_dir(&path).join("config"), serialize_config(config))?;
+    Ok(())
 }
 
 pub fn write_head<P: AsRef<Path>>(path: P, r: &Reference) -> Result<()> {
-    let mut f = File::create(path.as_ref().join(Path::new(".gnew/HEAD")))?;
-    match r {
-        Reference::Hash(h) => writeln!(f, "{}", h),
-        Reference::Branch(b) => writeln!(f, "ref: {}", b),
-    }?;
+    let mut f = File::create(storage_dir(&path).join("HEAD"))?;
+    writeln!(f, "{}", r)?;
     Ok(())
 }
 
 pub fn read_head<P: AsRef<Path>>(path: P) -> Result<Reference> {
-    let head = fs::read_to_string(path.as_ref().join(Path::new(".gnew/HEAD")))?;
-    let head = head.trim();
+    let head = fs::read_to_string(storage_dir(&path).join("HEAD"))?;
+    head.trim().parse()
+}
+
+/// Records the reference that HEAD pointed to before the last checkout.
+pub fn write_prev_head<P: AsRef<Path>>(path: P, r: &Reference) -> Result<()> {
+    fs::write(storage_dir(&path).join("PREV_HEAD"), format!("{}\n", r))?;
+    Ok(())
+}
+
+/// Returns the reference checked out before the current one, if any.
+pub fn read_prev_head<P: AsRef<Path>>(path: P) -> Result<Option<Reference>> {
+    match fs::read_to_string(storage_dir(&path).join("PREV_HEAD")) {
+        Ok(s) => Ok(Some(s.trim().parse()?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records the commit being merged until the merge is committed, or clears
+/// the record.
+pub fn write_merge_head<P: AsRef<Path>>(path: P, commit: Option<Hash>) -> Result<()> {
+    let path = storage_dir(&path).join("MERGE_HEAD");
+    match commit {
+        Some(hash) => fs::write(path, format!("{}\n", hash))?,
+        None => match fs::remove_file(path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            r => r?,
+        },
+    }
+    Ok(())
+}
+
+/// Returns the commit being merged, if a merge is in progress.
+pub fn read_merge_head<P: AsRef<Path>>(path: P) -> Result<Option<Hash>> {
+    match fs::read_to_string(storage_dir(&path).join("MERGE_HEAD")) {
+        Ok(s) => Ok(Some(s.trim().parse().or(Err(ObjectCorrupted))?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records the commit holding the changes saved by `--autostash` until they
+/// are applied again, or clears the record.
+pub fn write_autostash<P: AsRef<Path>>(path: P, commit: Option<Hash>) -> Result<()> {
+    let path = storage_dir(&path).join("AUTOSTASH");
+    match commit {
+        Some(hash) => fs::write(path, format!("{}\n", hash))?,
+        None => match fs::remove_file(path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            r => r?,
+        },
+    }
+    Ok(())
+}
+
+/// Returns the commit of the changes saved by `--autostash`, if any.
+pub fn read_autostash<P: AsRef<Path>>(path: P) -> Result<Option<Hash>> {
+    match fs::read_to_string(storage_dir(&path).join("AUTOSTASH")) {
+        Ok(s) => Ok(Some(s.trim().parse().or(Err(ObjectCorrupted))?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records the files left with conflicts by a merge, as lines
+/// `<preimage>\t<path>`, the preimage being the blob of the file as the
+/// merge wrote it, until the merge is committed or aborted. An empty list
+/// clears the record.
+pub fn write_merge_rr<P: AsRef<Path>>(path: P, files: &[(Hash, PathBuf)]) -> Result<()> {
+    let path = storage_dir(&path).join("MERGE_RR");
+    if files.is_empty() {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        };
+    }
+    let lines: Vec<_> = files
+        .iter()
+        .map(|(preimage, file)| format!("{}\t{}\n", preimage, file.display()))
+        .collect();
+    fs::write(path, lines.concat())?;
+    Ok(())
+}
+
+/// Returns the files left with conflicts by the merge in progress and
+/// their preimages.
+pub fn read_merge_rr<P: AsRef<Path>>(path: P) -> Result<Vec<(Hash, PathBuf)>> {
+    let data = match fs::read_to_string(storage_dir(&path).join("MERGE_RR")) {
+        Ok(data) => data,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    data.lines()
+        .map(|line| {
+            let (preimage, file) = line.split_once('\t').ok_or(ObjectCorrupted)?;
+            Ok((preimage.parse().or(Err(ObjectCorrupted))?, PathBuf::from(file)))
+        })
+        .collect()
+}
 
-    Ok(match head.strip_prefix("ref: ") {
-        Some(b) => Reference::Branch(b.to_owned()),
-        None => Reference::Hash(head.parse().or(Err(ObjectCorrupted))?),
-    })
+/// Returns the recorded resolution of a conflict, by its fingerprint.
+pub fn read_resolution<P: AsRef<Path>>(path: P, conflict: Hash) -> Result<Option<Vec<u8>>> {
+    match fs::read(storage_dir(&path).join("rr-cache").join(conflict.to_string())) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Records the resolution of a conflict, by its fingerprint.
+pub fn write_resolution<P: AsRef<Path>>(path: P, conflict: Hash, data: &[u8]) -> Result<()> {
+    let dir = storage_dir(&path).join("rr-cache");
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(conflict.to_string()), data)?;
+    Ok(())
+}
+
+/// Records the rebase in progress until it completes or is aborted, or
+/// clears the record.
+pub fn write_rebase_state<P: AsRef<Path>>(path: P, state: Option<&RebaseState>) -> Result<()> {
+    let path = storage_dir(&path).join("REBASE_STATE");
+    match state {
+        Some(state) => fs::write(path, state.to_string())?,
+        None => match fs::remove_file(path) {
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            r => r?,
+        },
+    }
+    Ok(())
+}
+
+/// Returns the rebase in progress, if any.
+pub fn read_rebase_state<P: AsRef<Path>>(path: P) -> Result<Option<RebaseState>> {
+    match fs::read_to_string(storage_dir(&path).join("REBASE_STATE")) {
+        Ok(s) => Ok(Some(s.parse()?)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
 }
 
-pub fn write_branch<P: AsRef<Path>>(path: P, name: &str, commit: Hash) -> Result<()> {
-    let mut f = File::create(path.as_ref().join(Path::new(".gnew/heads").join(name)))?;
+/// Returns the file of a loose reference, with a directory for each
+/// component of its name but the last, e.g. `refs/heads/feature/login`.
+fn loose_ref_path(storage_dir: &Path, name: &str) -> PathBuf {
+    name.split('/').fold(storage_dir.to_owned(), |path, c| path.join(c))
+}
+
+/// Writes a loose reference, e.g. `refs/heads/main`.
+pub fn write_ref<P: AsRef<Path>>(path: P, name: &str, commit: Hash) -> Result<()> {
+    let path = loose_ref_path(&storage_dir(&path), name);
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut f = File::create(path)?;
     writeln!(f, "{}", commit)?;
     Ok(())
 }
 
-pub fn read_branches<P: AsRef<Path>>(r_path: P) -> Result<HashMap<String, Hash>> {
-    let mut branches = HashMap::new();
+/// Removes a loose reference if it exists.
+pub fn remove_loose_ref<P: AsRef<Path>>(path: P, name: &str) -> Result<()> {
+    let storage_dir = storage_dir(&path);
+    let mut names = vec![name.to_owned()];
+    if let Some(branch) = name.strip_prefix(refs::HEADS) {
+        names.push(format!("heads/{}", branch));
+    }
+    for name in names {
+        match fs::remove_file(loose_ref_path(&storage_dir, &name)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+            _ => remove_empty_ref_dirs(&storage_dir, &name),
+        }
+    }
+    Ok(())
+}
 
-    for f in WalkDir::new(r_path.as_ref().join(Path::new(".gnew/heads"))) {
+/// Removes the directories of a removed reference that are left empty, up
+/// to its namespace, so that e.g. `refs/heads/feature` can be created after
+/// `refs/heads/feature/login` is removed.
+fn remove_empty_ref_dirs(storage_dir: &Path, name: &str) {
+    let components: Vec<_> = name.split('/').collect();
+    let namespace = if components[0] == "refs" { 2 } else { 1 };
+    for len in (namespace + 1..components.len()).rev() {
+        let dir = loose_ref_path(storage_dir, &components[..len].join("/"));
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
+
+/// Reads all references. Loose references take precedence over packed ones,
+/// and branches in `.gnew/heads` are read as `refs/heads/...`.
+pub fn read_refs<P: AsRef<Path>>(path: P) -> Result<Refs> {
+    let storage_dir = storage_dir(&path);
+    let mut refs = read_packed_refs(&path)?;
+    refs.extend(read_loose_refs(&storage_dir.join("heads"), refs::HEADS)?);
+    refs.extend(read_loose_refs(&storage_dir.join("refs"), "refs/")?);
+    Ok(refs)
+}
+
+/// Reads the loose references in a directory, prefixing their names.
+fn read_loose_refs(dir: &Path, prefix: &str) -> Result<Vec<(String, Hash)>> {
+    let mut refs = vec![];
+    if !dir.exists() {
+        return Ok(refs);
+    }
+    for f in WalkDir::new(dir) {
         let f = f?;
         if !f.file_type().is_file() {
             continue;
         }
         let path = f.path();
-        let name = path
-            .strip_prefix(r_path.as_ref().join(".gnew/heads"))
+        /* join the components with / whatever the separator of the platform */
+        let name: Option<Vec<_>> = path
+            .strip_prefix(dir)
             .unwrap()
-            .to_str()
-            .unwrap();
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect();
+        let name = match name {
+            Some(name) => name.join("/"),
+            None => continue,
+        };
         let hash = fs::read_to_string(path)?
             .trim()
             .parse()
             .or(Err(ObjectCorrupted))?;
 
-        branches.insert(name.to_owned(), hash);
+        refs.push((format!("{}{}", prefix, name), hash));
     }
-    Ok(branches)
+    Ok(refs)
+}
+
+pub fn read_packed_refs<P: AsRef<Path>>(path: P) -> Result<Refs> {
+    match fs::read(storage_dir(&path).join("packed-refs")) {
+        Ok(obj) => deserialize_packed_refs(&obj).ok_or(ObjectCorrupted),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Refs::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn write_packed_refs<P: AsRef<Path>>(path: P, refs: &Refs) -> Result<()> {
+    fs::write(
+        storage_dir(&path).join("packed-refs"),
+        serialize_packed_refs(refs),
+    )?;
+    Ok(())
 }
 
 pub fn get_objects<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
@@ -194,93 +776,287 @@ pub fn get_objects<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
         .collect::<Vec<PathBuf>>())
 }
 
-pub fn copy_objects<P: AsRef<Path>>(from: P, to: P, objects: &Vec<PathBuf>) -> Result<()> {
-    let from_path = from.as_ref().join(Path::new("objects/"));
-    let to_path = to.as_ref().join(Path::new("objects/"));
+/// Returns the objects of a repository, including those in its alternate object stores.
+pub fn get_all_objects<P: AsRef<Path>>(path: P) -> Result<Vec<PathBuf>> {
+    let mut objects = get_objects(&path)?;
+    let mut seen: HashSet<_> = objects.iter().cloned().collect();
+
+    for dir in read_alternates(&path)? {
+        for o in fs::read_dir(&dir)? {
+            let o = PathBuf::from(o?.file_name());
+            if seen.insert(o.clone()) {
+                objects.push(o);
+            }
+        }
+    }
+    Ok(objects)
+}
+
+/// Copies objects from one storage directory to another, checking that
+/// each hashes to its name. Nothing is copied if any object fails.
+pub fn copy_objects<P: AsRef<Path>>(from: P, to: P, objects: &[PathBuf]) -> Result<()> {
+    transfer_objects(from.as_ref(), to.as_ref(), objects, false)
+}
+
+/// Hard links objects from one repository to another, falling back to
+/// copying when a link cannot be created (e.g. across file systems).
+pub fn link_objects<P: AsRef<Path>>(from: P, to: P, objects: &[PathBuf]) -> Result<()> {
+    transfer_objects(from.as_ref(), to.as_ref(), objects, true)
+}
+
+/// Creates a directory in a storage directory to receive the objects of a
+/// push in, laid out like a storage directory. Returns its path.
+pub fn create_quarantine<P: AsRef<Path>>(storage_dir: P) -> Result<PathBuf> {
+    let quarantine = storage_dir
+        .as_ref()
+        .join(format!("incoming-{}", std::process::id()));
+    fs::create_dir_all(quarantine.join("objects"))?;
+    Ok(quarantine)
+}
+
+/// Moves the objects of a quarantine into the object store of a storage
+/// directory, and removes the quarantine.
+pub fn migrate_quarantine<P: AsRef<Path>>(quarantine: P, storage_dir: P) -> Result<()> {
+    let objects_dir = storage_dir.as_ref().join("objects");
+    for entry in fs::read_dir(quarantine.as_ref().join("objects"))? {
+        let entry = entry?;
+        let dest = objects_dir.join(entry.file_name());
+        if !dest.exists() {
+            fs::rename(entry.path(), dest)?;
+        }
+    }
+    remove_quarantine(quarantine)
+}
+
+/// Removes a quarantine with the objects in it.
+pub fn remove_quarantine<P: AsRef<Path>>(quarantine: P) -> Result<()> {
+    Ok(fs::remove_dir_all(quarantine)?)
+}
+
+/// Returns the path of an object in a repository or its alternates.
+fn object_source(storage_dir: &Path, alternates: &[PathBuf], object: &Path) -> PathBuf {
+    let objects_dir = storage_dir.join("objects");
+    std::iter::once(&objects_dir)
+        .chain(alternates)
+        .map(|dir| dir.join(object))
+        .find(|p| p.exists())
+        .unwrap_or_else(|| objects_dir.join(object))
+}
+
+/// Copies or links objects one by one. If any object cannot be copied or
+/// does not hash to its name, the objects added so far are removed again
+/// and the failures are returned: objects that do not match their hashes
+/// as `ObjectsTampered`, along with any others as `ObjectsNotCopied`.
+#[cfg_attr(feature = "tracing", tracing::instrument(skip_all, fields(count = objects.len())))]
+fn transfer_objects(from: &Path, to: &Path, objects: &[PathBuf], link: bool) -> Result<()> {
+    let to_path = to.join(Path::new("objects/"));
+    let alternates = read_alternates(from)?;
+    let mut added = vec![];
+    let mut failed = vec![];
 
     for o in objects {
-        fs::copy(from_path.join(&o), to_path.join(&o))?;
+        let src = object_source(from, &alternates, o);
+        let dest = to_path.join(o);
+        let existed = dest.exists();
+
+        match transfer_object(&src, &dest, o, link) {
+            Ok(()) if !existed => added.push(dest),
+            Ok(()) => (),
+            Err(err) => failed.push((o.clone(), err)),
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
     }
 
+    /* leave the destination as it was */
+    for dest in added {
+        fs::remove_file(dest)?;
+    }
+    match failed.iter().all(|(_, err)| matches!(err, ObjectCorrupted)) {
+        true => Err(ObjectsTampered(failed.into_iter().map(|(o, _)| o).collect())),
+        false => Err(ObjectsNotCopied(failed)),
+    }
+}
+
+/// Streams an object into a temporary file next to its destination while
+/// hashing it, and renames it into place if the hash matches its name, so
+/// that a failure never leaves a partial object behind.
+fn transfer_object(src: &Path, dest: &Path, name: &Path, link: bool) -> Result<()> {
+    let tmp = dest.with_extension("tmp");
+    let linked = link && fs::hard_link(src, &tmp).is_ok();
+
+    let copied = copy_hashed(src, (!linked).then_some(&tmp)).and_then(|hash| {
+        match Some(hash.to_string().as_str()) == name.to_str() {
+            true => Ok(fs::rename(&tmp, dest)?),
+            false => Err(ObjectCorrupted),
+        }
+    });
+    if copied.is_err() && tmp.exists() {
+        fs::remove_file(&tmp)?;
+    }
+    copied
+}
+
+/// Returns the hash of a file, copying it to `dest` along the way if given.
+fn copy_hashed(src: &Path, dest: Option<&PathBuf>) -> Result<Hash> {
+    let mut input = File::open(src)?;
+    let mut output = dest.map(File::create).transpose()?;
+    let mut sha = Sha1::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        match input.read(&mut buf)? {
+            0 => break,
+            n => {
+                sha.update(&buf[..n]);
+                if let Some(out) = &mut output {
+                    out.write_all(&buf[..n])?;
+                }
+            }
+        }
+    }
+    Ok(Hash::from(sha.digest()))
+}
+
+/// Copies a repository directory, leaving out the contents of the object store.
+pub fn copy_repo_without_objects<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dest: Q) -> Result<()> {
+    let src = src.as_ref();
+    let objects_dir = src.join(".gnew/objects");
+
+    for entry in WalkDir::new(src)
+        .into_iter()
+        .filter_entry(|e| e.path().parent() != Some(&objects_dir))
+    {
+        let entry = entry?;
+        let target = dest.as_ref().join(entry.path().strip_prefix(src).unwrap());
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
+    use crate::testutil::TestRepo;
 
     #[test]
     fn make_empty_repo() {
-        let _a1 = write_empty_repo();
+        let _t = TestRepo::new();
+        assert!(write_empty_repo().is_err());
     }
 
-    // for now you have to create .gnew/objects before running these tests
-    // TODO: run them in a test repo
-
     #[test]
     fn read_write_blob() {
-        fs::write("foo.txt", b"test content").unwrap();
-        let b1 = write_blob("foo.txt").unwrap();
+        let t = TestRepo::new();
+        t.write("foo.txt", "test content");
+        let b1 = write_blob(t.path().join("foo.txt")).unwrap();
         let b2 = read_blob(b1.hash()).unwrap();
-        fs::remove_file("foo.txt").unwrap();
         assert_eq!(b1, b2);
     }
+
     #[test]
-    #[should_panic]
-    fn check_blob_behavior_panic() {
-        let _b2 = read_blob(Hash::new()).unwrap();
+    fn write_blob_repairs_corrupted_object() {
+        let t = TestRepo::new();
+        t.write("bar.txt", "repair me");
+        let b1 = write_blob(t.path().join("bar.txt")).unwrap();
+        fs::write(object_path(b1.hash()), b"blob\0corrupted").unwrap();
+        assert!(matches!(read_blob(b1.hash()), Err(ObjectCorrupted)));
+
+        let b2 = write_blob(t.path().join("bar.txt")).unwrap();
+        assert_eq!(b2, read_blob(b1.hash()).unwrap());
     }
+
     #[test]
-    fn check_read_object() {
-        let refe = read_object(Hash::new());
-        let refeunwrap = match refe {
-            Ok(c) => c,
-            Err(_) => {
-                vec![111]
-            }
-        };
-        assert_eq!(refeunwrap, vec![111])
+    fn write_blob_once() {
+        let t = TestRepo::new();
+        t.write("a.txt", "same");
+        let blob = write_blob(t.path().join("a.txt")).unwrap();
+        assert_eq!(object::hash_blob(b"same"), blob.hash());
+
+        let path = object_path(blob.hash());
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(write_blob_if_absent(b"same".to_vec()).unwrap(), blob.hash());
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        let other = write_blob_if_absent(b"other".to_vec()).unwrap();
+        assert_eq!(read_blob(other).unwrap().content(), b"other");
     }
+
     #[test]
-    fn test_read_commit() {
-        let commit = read_commit(Hash::new());
-        let commitur = match commit {
-            Ok(c) => Ok(c),
-            Err(c) => Err(c),
+    fn write_tracklist_replaces_file() {
+        let _t = TestRepo::new();
+        let storage = storage_dir(".");
+        let temporary_files = || {
+            fs::read_dir(&storage)
+                .unwrap()
+                .filter(|e| e.as_ref().unwrap().path().extension() == Some("tmp".as_ref()))
+                .count()
         };
-        assert!(matches!(commitur, Err(ObjectNotFound)))
+        let tracklist = Tracklist::from(["a.txt".to_owned(), "b/c.txt".to_owned()]);
+        write_tracklist(".", &tracklist).unwrap();
+        assert_eq!(read_tracklist(".").unwrap(), tracklist);
+        assert_eq!(temporary_files(), 0);
+
+        fs::remove_file(storage.join("tracklist")).unwrap();
+        fs::create_dir_all(storage.join("tracklist/x")).unwrap();
+        assert!(write_tracklist(".", &Tracklist::new()).is_err());
+        assert_eq!(temporary_files(), 0);
     }
+
+    #[test]
+    fn read_missing_objects() {
+        let _t = TestRepo::new();
+        assert!(matches!(read_object(Hash::new()), Err(ObjectNotFound)));
+        assert!(matches!(read_blob(Hash::new()), Err(ObjectNotFound)));
+        assert!(matches!(read_commit(Hash::new()), Err(ObjectNotFound)));
+    }
+
     #[test]
     fn test_head() {
-        let path = env::current_dir().unwrap_or(PathBuf::new());
-        let head = read_head(path);
-        let heads = match head {
-            Ok(c) => Ok(c),
-            Err(c) => Err(c),
-        };
-        heads.unwrap();
+        let t = TestRepo::new();
+        let head = read_head(t.path()).unwrap();
+        assert_eq!(head, Reference::Branch("main".to_owned()));
     }
+
     #[test]
     fn check_repo_existence_test() {
-        let path = env::current_dir().unwrap_or(PathBuf::new());
-        let buf = check_repo_exists(path);
-        let buff = match buf {
-            Ok(c) => Ok(c),
-            Err(c) => Err(c),
-        };
-        buff.unwrap();
+        let t = TestRepo::new();
+        assert_eq!(check_repo_exists(t.path()).unwrap(), t.path().join(".gnew"));
+        assert!(matches!(check_repo_exists(t.path().join("sub")), Err(NoRepository)));
+    }
+
+    #[test]
+    fn copy_objects_all_or_nothing() {
+        let t = TestRepo::new();
+        t.write("a.txt", "a");
+        t.write("b.txt", "b");
+        let a = PathBuf::from(write_blob(t.path().join("a.txt")).unwrap().hash().to_string());
+        let b = PathBuf::from(write_blob(t.path().join("b.txt")).unwrap().hash().to_string());
+        let (from, to) = (t.path().join(".gnew"), t.path().join("dest"));
+        fs::create_dir_all(to.join("objects")).unwrap();
+        fs::write(from.join("objects").join(&b), b"blob\0tampered").unwrap();
+
+        let copied = copy_objects(&from, &to, &[a.clone(), b.clone()]);
+        assert!(matches!(copied, Err(ObjectsTampered(objects)) if objects == [b.clone()]));
+        let missing = copy_objects(&from, &to, &[a.clone(), PathBuf::from("missing")]);
+        assert!(matches!(missing, Err(ObjectsNotCopied(failed)) if failed.len() == 1));
+        assert_eq!(fs::read_dir(to.join("objects")).unwrap().count(), 0);
+
+        link_objects(&from, &to, std::slice::from_ref(&a)).unwrap();
+        assert_eq!(fs::read(to.join("objects").join(&a)).unwrap(), b"blob\0a");
+        assert_eq!(fs::read_dir(to.join("objects")).unwrap().count(), 1);
     }
+
     #[test]
     fn check_file_existence_test() {
-        let mut path = env::current_dir().unwrap_or(PathBuf::new());
-        path.push("some_file");
-
-        let exists = check_existence(&vec![path]);
-        let filesearch = match exists {
-            Ok(_) => Ok(()),
-            Err(c) => Err(c),
-        };
-        assert!(matches!(filesearch, Err(FileNotFound)))
+        let t = TestRepo::new();
+        t.write("some_file", "");
+        assert!(check_existence(&[t.path().join("some_file")]).is_ok());
+        let exists = check_existence(&[t.path().join("other_file")]);
+        assert!(matches!(exists, Err(FileNotFound)))
     }
 }
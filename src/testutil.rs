@@ -0,0 +1,126 @@
+//! Generators of repositories for benchmarks and tests.
+
+use crate::repo::object::Commit;
+use crate::repo::repository::{AddMode, CommitOptions, FileStatus, Repository};
+use crate::repo::revwalk::RevWalkOptions;
+use crate::error::Result;
+use chrono::{TimeZone, Utc};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Creates a repository in a new temporary directory and commits `n_files`
+/// files spread over directories `n_commits` times, each commit changing a
+/// tenth of the files. The same arguments always give the same history.
+/// The directory is removed when the fixture is dropped.
+pub fn generate_repo(n_commits: usize, n_files: usize) -> Result<TestRepo> {
+    let mut t = TestRepo::create()?;
+    let worktree = t.path().to_owned();
+    let paths: Vec<_> = (0..n_files)
+        .map(|i| PathBuf::from(format!("dir{}/file{}.txt", i % 16, i)))
+        .collect();
+    let changed_per_commit = (n_files / 10).max(1);
+
+    for c in 0..n_commits {
+        let changed = if c == 0 { n_files } else { changed_per_commit };
+        for i in 0..changed {
+            let path = &paths[(c * changed_per_commit + i) % n_files];
+            let file = worktree.join(path);
+            fs::create_dir_all(file.parent().unwrap())?;
+            fs::write(file, format!("{}\nline of commit {}\n", path.display(), c))?;
+        }
+        t.repo.add(&[&worktree], AddMode::All)?;
+        let options = CommitOptions {
+            author: Some("gnew <gnew@example.com>".to_owned()),
+            time: Some(Utc.timestamp(1_600_000_000 + c as i64 * 60, 0)),
+            force: false,
+        };
+        t.repo.commit(format!("commit {}", c), &options)?;
+    }
+    Ok(t)
+}
+
+/// A repository in its own temporary working tree, which is removed when
+/// the fixture is dropped.
+pub struct TestRepo {
+    pub repo: Repository,
+    path: PathBuf,
+    _dir: TempDir,
+}
+
+impl TestRepo {
+    /// Creates an empty repository with the branch `main`.
+    pub fn new() -> TestRepo {
+        TestRepo::create().unwrap()
+    }
+
+    fn create() -> Result<TestRepo> {
+        let dir = tempfile::Builder::new().prefix("gnew-").tempdir()?;
+        let path = fs::canonicalize(dir.path())?;
+        let repo = Repository::init_at(&path, Some("main"))?;
+        Ok(TestRepo {
+            repo,
+            path,
+            _dir: dir,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes a file of the working tree, creating its directories.
+    pub fn write<P: AsRef<Path>>(&self, path: P, content: &str) {
+        let path = self.path.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// Adds files given relative to the working tree.
+    pub fn add<P: AsRef<Path>>(&mut self, paths: &[P]) {
+        let paths: Vec<_> = paths.iter().map(|p| self.path.join(p)).collect();
+        self.repo.add(&paths, AddMode::Paths).unwrap();
+    }
+
+    /// Writes and adds a file, then commits it.
+    pub fn commit_file<P: AsRef<Path>>(&mut self, path: P, content: &str) -> Commit {
+        self.write(&path, content);
+        self.add(&[path]);
+        self.commit(content)
+    }
+
+    pub fn commit(&mut self, msg: &str) -> Commit {
+        self.repo
+            .commit(msg.to_owned(), &CommitOptions::default())
+            .unwrap()
+    }
+
+    /// Asserts that the changed files have the given status, compared to HEAD.
+    pub fn assert_status(&self, expected: &[(&str, FileStatus)]) {
+        let status = self.repo.status(&self.repo.head_tree().unwrap()).unwrap();
+        let mut changed: Vec<_> = status
+            .into_iter()
+            .filter(|(_, s)| *s != FileStatus::Unmodified)
+            .collect();
+        changed.sort_by(|a, b| a.0.cmp(&b.0));
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|(p, s)| (PathBuf::from(p), *s))
+            .collect();
+        assert_eq!(changed, expected);
+    }
+
+    /// Asserts that the messages of the history of HEAD, newest first, are
+    /// the given ones.
+    pub fn assert_log(&self, expected: &[&str]) {
+        let log = self.repo.log(u32::MAX, RevWalkOptions::default()).unwrap();
+        let messages: Vec<_> = log.map(|c| c.unwrap().msg().to_owned()).collect();
+        assert_eq!(messages, expected);
+    }
+}
+
+impl Default for TestRepo {
+    fn default() -> TestRepo {
+        TestRepo::new()
+    }
+}
@@ -1,2 +1,4 @@
 pub mod command;
+pub mod format;
+pub mod profile;
 pub mod ui;
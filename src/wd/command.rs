@@ -1,56 +1,351 @@
-use crate::repo::object::{Hash, Tree};
-use crate::repo::repository::{MergeStrategy, Reference, Repository};
+use crate::repo::mailmap::Mailmap;
+use crate::repo::notes;
+use crate::repo::object::{self, Hash};
+use crate::repo::rebase::{RebaseAction, RebaseReport, RebaseStep};
+use crate::repo::refs;
+use crate::repo::repository::{
+    AddMode, CloneOptions, CommitOptions, MergeOptions, MergeStrategy, Reference, Repository,
+    RmOptions, Stage,
+};
+use crate::repo::revwalk::{MergeFilter, RevWalkOptions};
+use crate::repo::rewrite::FilterOptions;
+use crate::repo::search::LogFilter;
 use crate::storage::transport;
-use crate::wd::ui::{self, Error, Result};
-use std::io::{self, Write};
-use std::path::{Path, PathBuf};
-use structopt::StructOpt;
+use crate::wd::format::{self, RefSort};
+use crate::wd::profile::Profile;
+use crate::error::{Error, Result};
+use crate::wd::ui::{self, DateFormat, DiffAlgorithm, DiffStyle};
+use chrono::{DateTime, Utc};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+use structopt::{clap, StructOpt};
 
 #[derive(Debug, StructOpt)]
 #[structopt(about, author)]
+struct Options {
+    /// Print the time spent reading objects, hashing, diffing and on the
+    /// network to stderr
+    #[structopt(long, global = true)]
+    profile: bool,
+
+    /// Output stable, undecorated results for scripts: status codes with
+    /// root-relative paths for status, and only the hash for commit,
+    /// write-tree, hash-file, merge-base and rev-parse. Other commands reject it
+    #[structopt(long, global = true)]
+    porcelain: bool,
+
+    #[structopt(subcommand)]
+    command: Gnew,
+}
+
+#[derive(Debug, StructOpt)]
 enum Gnew {
     /// Create an empty repository
-    Init,
+    Init {
+        /// Name of the initial branch
+        #[structopt(short = "b", long)]
+        initial_branch: Option<String>,
+    },
     /// Copy an existing repository
-    Clone { repository: PathBuf },
+    Clone {
+        repository: PathBuf,
+
+        /// Share objects with a local repository instead of copying them
+        #[structopt(long)]
+        reference: Option<PathBuf>,
+
+        /// Hard link objects instead of copying them
+        #[structopt(short, long)]
+        local: bool,
+
+        /// Leave out objects, which are fetched when first needed
+        #[structopt(long, possible_values = &["blob:none"])]
+        filter: Option<String>,
+
+        /// Check out this branch instead of the source's HEAD
+        #[structopt(short, long)]
+        branch: Option<String>,
+
+        /// Only copy the history of the checked out branch
+        #[structopt(long)]
+        single_branch: bool,
+    },
     /// Add files to tracking list
     Add {
-        #[structopt(required = true)]
+        #[structopt(required_unless_one = &["update", "all"])]
         paths: Vec<PathBuf>,
+
+        /// Only update tracked files, untracking deleted ones
+        #[structopt(short = "u", long, conflicts_with = "all")]
+        update: bool,
+
+        /// Track all files and untrack deleted ones
+        #[structopt(short = "A", long)]
+        all: bool,
     },
     /// Remove files from tracking list
     Remove {
         #[structopt(required = true)]
         paths: Vec<PathBuf>,
     },
+    /// Show the best common ancestor of two commits
+    MergeBase {
+        /// Output nothing and exit with 0 if the first commit is an ancestor
+        /// of the second, 1 otherwise
+        #[structopt(long)]
+        is_ancestor: bool,
+
+        commit1: String,
+        commit2: String,
+    },
+    /// Remove files from the working tree and the tracking list
+    Rm {
+        #[structopt(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Only remove the files from the tracking list
+        #[structopt(long)]
+        cached: bool,
+
+        /// Allow removing directories
+        #[structopt(short)]
+        r: bool,
+
+        /// Remove files even if they have local modifications
+        #[structopt(short, long)]
+        force: bool,
+    },
+    /// Show the size of the repository
+    Stats {
+        /// Rank the largest blobs of the history with the path and commit that introduced them
+        #[structopt(long)]
+        blobs: bool,
+
+        /// Number of blobs to show
+        #[structopt(short = "n", long, default_value = "10")]
+        count: usize,
+    },
     /// Show the repository status
-    Status,
+    Status {
+        /// Show paths relative to the current directory
+        #[structopt(long, conflicts_with_all = &["absolute", "porcelain"])]
+        relative: bool,
+
+        /// Show paths relative to the root of the working tree
+        #[structopt(long)]
+        absolute: bool,
+
+        /// Fetch a remote, without merging, and show whether the current
+        /// branch is ahead of or behind its branch there
+        #[structopt(long, value_name = "REMOTE")]
+        remote: Option<String>,
+    },
     /// List the heads
-    Heads,
+    Heads {
+        /// Date format: rfc2822, iso, relative or unix
+        #[structopt(long, default_value = "relative")]
+        date: DateFormat,
+
+        /// Read another repository, given by its path or a remote name
+        #[structopt(long, value_name = "PATH")]
+        repo: Option<String>,
+    },
+    /// List the branches
+    Branch {
+        /// Only list the branches containing the commit
+        #[structopt(long, value_name = "COMMIT")]
+        contains: Option<String>,
+
+        /// Only list the branches merged into the commit
+        #[structopt(long, value_name = "COMMIT", conflicts_with = "no-merged")]
+        merged: Option<String>,
+
+        /// Only list the branches not merged into the commit
+        #[structopt(long, value_name = "COMMIT")]
+        no_merged: Option<String>,
+
+        /// List the remote-tracking branches
+        #[structopt(short, long)]
+        remotes: bool,
+
+        /// Delete the branches merged into the commit, except the current
+        /// one and those listed in branch.protected
+        #[structopt(
+            long,
+            value_name = "COMMIT",
+            conflicts_with_all = &["contains", "merged", "no-merged"]
+        )]
+        delete_merged: Option<String>,
+    },
     /// Show changes between commits
     Diff {
+        /// Up to two commits, or `<A>...<B>` for the changes on B since its merge base with A
         #[structopt(max_values = 2)]
         commits: Vec<String>,
+
+        /// Show only the paths of changed files
+        #[structopt(long, conflicts_with = "name-status")]
+        name_only: bool,
+
+        /// Show the paths of changed files with the kind of change (A, D, M, R or T)
+        #[structopt(long)]
+        name_status: bool,
+
+        /// Output nothing and exit with 1 if there are changes, 0 otherwise
+        #[structopt(long, short)]
+        quiet: bool,
+
+        /// The diff algorithm: myers, or patience, also named histogram (default: diff.algorithm
+        /// or myers)
+        #[structopt(long)]
+        diff_algorithm: Option<DiffAlgorithm>,
+
+        /// Color removed and added lines and hunk headers
+        #[structopt(long)]
+        color: bool,
+
+        /// Show the old and new lines side by side, in the width of the terminal
+        #[structopt(long, short = "y")]
+        side_by_side: bool,
     },
     /// Output a file at a commit
-    Cat { commit: String, path: PathBuf },
+    Cat {
+        /// The commit (default: HEAD) and path as `[<REV>] <PATH>` or `<REV>:<PATH>`
+        #[structopt(required = true, max_values = 2)]
+        args: Vec<String>,
+
+        /// Output a version of a file with merge conflicts instead: base, ours or theirs
+        #[structopt(long, possible_values = &["base", "ours", "theirs"])]
+        stage: Option<Stage>,
+
+        /// List the subdirectories of a directory too
+        #[structopt(short, long, conflicts_with = "stage")]
+        recursive: bool,
+
+        /// Read another repository, given by its path or a remote name
+        #[structopt(long, value_name = "PATH")]
+        repo: Option<String>,
+    },
     /// Update the working directory
+    #[structopt(visible_alias = "switch")]
     Checkout(CheckoutOptions),
     /// Commit changes to the repository
-    Commit { message: String },
+    Commit {
+        /// The commit message (default: written in $GNEW_EDITOR or $EDITOR,
+        /// starting from the commit.template file)
+        message: Option<String>,
+
+        /// Show the changes that would be committed without committing
+        #[structopt(long)]
+        dry_run: bool,
+
+        /// Add a Signed-off-by trailer with your identity
+        #[structopt(short, long)]
+        signoff: bool,
+
+        /// Record another author, e.g. "Name <email>"
+        #[structopt(long)]
+        author: Option<String>,
+
+        /// Record another date, as a Unix timestamp, ISO 8601 or RFC 2822 date
+        #[structopt(long, parse(try_from_str = object::parse_time))]
+        date: Option<DateTime<Utc>>,
+
+        /// Commit even if tracked files are missing, leaving them out
+        #[structopt(short, long)]
+        force: bool,
+    },
     /// Show the commit log
     Log {
         #[structopt(default_value = "0")]
         amount: u32,
+
+        /// Format each commit with a format string, e.g. "%h %an %s"
+        #[structopt(long)]
+        format: Option<String>,
+
+        /// Date format: rfc2822, iso, relative or unix
+        #[structopt(long, default_value = "rfc2822")]
+        date: DateFormat,
+
+        /// Show only merge commits
+        #[structopt(long, conflicts_with = "no-merges")]
+        merges: bool,
+
+        /// Leave out merge commits
+        #[structopt(long)]
+        no_merges: bool,
+
+        /// Follow only the first parent of merge commits
+        #[structopt(long)]
+        first_parent: bool,
+
+        /// Show only the commits whose message matches a regular expression
+        #[structopt(long, value_name = "PATTERN")]
+        grep: Option<String>,
+
+        /// Match the --grep pattern regardless of case
+        #[structopt(short = "i", long)]
+        regexp_ignore_case: bool,
+
+        /// Show only the commits that add or remove a string in a file
+        #[structopt(short = "S", value_name = "STRING")]
+        pickaxe: Option<String>,
+
+        /// Read another repository, given by its path or a remote name
+        #[structopt(long, value_name = "PATH")]
+        repo: Option<String>,
+    },
+    /// Show the commits that changed a file or directory, each with its diff
+    Whatchanged {
+        /// The file or directory
+        path: PathBuf,
+
+        /// Show at most this many commits
+        #[structopt(short = "n", long, value_name = "N")]
+        max_count: Option<usize>,
+
+        /// Date format: rfc2822, iso, relative or unix
+        #[structopt(long, default_value = "rfc2822")]
+        date: DateFormat,
+
+        /// The diff algorithm: myers, or patience, also named histogram (default: diff.algorithm
+        /// or myers)
+        #[structopt(long)]
+        diff_algorithm: Option<DiffAlgorithm>,
+
+        /// Color removed and added lines and hunk headers
+        #[structopt(long)]
+        color: bool,
     },
     /// Merge two commits
-    Merge { commit: String },
+    Merge {
+        commit: String,
+
+        /// Merge a commit that has no common ancestor with HEAD
+        #[structopt(long)]
+        allow_unrelated_histories: bool,
+
+        /// Do not commit the result of a three-way merge
+        #[structopt(long)]
+        no_commit: bool,
+    },
     /// Pull changes from another repository
     Pull {
         repository: PathBuf,
 
         #[structopt(short, long)]
         all: bool,
+
+        /// Stash changes to tracked files before pulling and apply them afterwards
+        #[structopt(long)]
+        autostash: bool,
+
+        /// Do not stash changes, even if pull.autostash is set
+        #[structopt(long, conflicts_with = "autostash")]
+        no_autostash: bool,
     },
     /// Push changes to another repository
     Push {
@@ -59,6 +354,78 @@ enum Gnew {
         #[structopt(short, long)]
         all: bool,
     },
+    /// Replay the commits of HEAD on top of another commit
+    Rebase {
+        /// The commit to replay on; commits it contains are left out
+        #[structopt(required_unless_one = &["continue", "abort"])]
+        upstream: Option<String>,
+
+        /// Edit the list of commits to pick, reword, squash or drop
+        #[structopt(short, long)]
+        interactive: bool,
+
+        /// Stash changes to tracked files before rebasing and apply them afterwards
+        #[structopt(long)]
+        autostash: bool,
+
+        /// Do not stash changes, even if rebase.autostash is set
+        #[structopt(long, conflicts_with = "autostash")]
+        no_autostash: bool,
+
+        /// Go on with a rebase stopped at conflicts, once they are resolved
+        #[structopt(name = "continue", long, conflicts_with_all = &["upstream", "abort"])]
+        continue_: bool,
+
+        /// Stop the rebase in progress and restore HEAD
+        #[structopt(long, conflicts_with_all = &["upstream", "interactive"])]
+        abort: bool,
+    },
+
+    /// Restore missing or corrupted objects from another repository
+    Repair {
+        /// The repository to copy objects from
+        #[structopt(long)]
+        from: PathBuf,
+    },
+    /// Save or restore a repository snapshot
+    Snapshot(SnapshotCommand),
+    /// Download objects and branches from a configured remote
+    Fetch {
+        /// The remote, as configured in remote.<name>.url
+        #[structopt(default_value = "origin")]
+        remote: String,
+
+        /// Remove remote-tracking branches deleted on the remote
+        #[structopt(short, long)]
+        prune: bool,
+    },
+    /// Rewrite the history of all branches
+    Filter {
+        /// Remove the paths matching a pattern, in the syntax of .gnewignore
+        #[structopt(long = "remove-path", required_unless = "mailmap", number_of_values = 1)]
+        remove_paths: Vec<String>,
+
+        /// Record the authors as mapped by .mailmap
+        #[structopt(long)]
+        mailmap: bool,
+
+        /// Remove the objects of the old history right away
+        #[structopt(long)]
+        prune: bool,
+    },
+    /// Manage the remote-tracking branches of configured remotes
+    Remote(RemoteCommand),
+    /// Add or show notes attached to commits
+    Notes {
+        /// The notes reference, e.g. ci for refs/notes/ci
+        #[structopt(long = "ref", default_value = "commits")]
+        notes: String,
+
+        #[structopt(subcommand)]
+        cmd: NotesCommand,
+    },
+    /// Run housekeeping tasks on the repository
+    Maintenance(MaintenanceCommand),
 
     // Low-level commands
     //
@@ -69,6 +436,72 @@ enum Gnew {
     },
     /// Write a tree object from the working directory
     WriteTree,
+    /// Write reachability bitmaps for the branch heads
+    WriteBitmaps,
+    /// Move the references into a single file
+    PackRefs,
+    /// Check the objects and list the dangling commits, which no reference leads to
+    Fsck {
+        /// Show the date and message of each dangling commit, newest first
+        #[structopt(long)]
+        lost_found: bool,
+
+        /// Date format: rfc2822, iso, relative or unix
+        #[structopt(long, default_value = "rfc2822")]
+        date: DateFormat,
+
+        /// Create a branch at a dangling commit, without checking it out
+        #[structopt(long, number_of_values = 2, value_names = &["COMMIT", "BRANCH"])]
+        restore: Option<Vec<String>>,
+    },
+    /// Verify the checksums of the stored objects
+    VerifyPack {
+        /// List the objects with their type and size
+        #[structopt(short, long)]
+        verbose: bool,
+    },
+    /// Check the tracklist for entries that are not canonical, duplicated
+    /// or stale
+    VerifyTracklist {
+        /// Rewrite the entries in the canonical form and drop the duplicates
+        #[structopt(long)]
+        repair: bool,
+
+        /// Also drop entries of files neither in the working tree nor in HEAD
+        #[structopt(long)]
+        prune: bool,
+    },
+    /// Count objects and show repository statistics
+    CountObjects {
+        /// Show detailed statistics
+        #[structopt(short, long)]
+        verbose: bool,
+    },
+    /// Show which pattern of the ignore files ignores each path
+    CheckIgnore {
+        /// Show the matching pattern as <FILE>:<LINE>:<PATTERN>, including
+        /// patterns that re-include a path
+        #[structopt(short, long)]
+        verbose: bool,
+
+        #[structopt(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Show the owners of each path, from the GNEWOWNERS files
+    Owners {
+        #[structopt(required_unless = "check")]
+        paths: Vec<PathBuf>,
+
+        /// Check that the authors of the commits from OLD to NEW own the paths
+        /// they changed, e.g. from an update hook, and exit with 1 otherwise
+        #[structopt(
+            long,
+            number_of_values = 2,
+            value_names = &["OLD", "NEW"],
+            conflicts_with = "paths"
+        )]
+        check: Option<Vec<String>>,
+    },
     /// Show the content of an object
     CatObject {
         /// Object type
@@ -78,23 +511,127 @@ enum Gnew {
         /// Object hash
         object: Hash,
     },
+    /// Show the hash of the commit each revision names
+    RevParse {
+        /// Revisions, e.g. HEAD~2, a branch or a hash
+        #[structopt(required = true)]
+        revisions: Vec<String>,
+    },
+    /// List the references with a format string, for scripts
+    ForEachRef {
+        /// Only list the references under these prefixes, e.g. refs/heads
+        patterns: Vec<String>,
+
+        /// Format each reference with the placeholders of log --format,
+        /// %(refname) and %(refname:short)
+        #[structopt(long, default_value = "%H %(refname)")]
+        format: String,
+
+        /// Sort by refname, objectname, authordate or subject; prefix the
+        /// key with - for descending order
+        #[structopt(long, default_value = "refname")]
+        sort: RefSort,
+
+        /// Date format of %ad: rfc2822, iso, relative or unix
+        #[structopt(long, default_value = "rfc2822")]
+        date: DateFormat,
+
+        /// List at most this many references
+        #[structopt(long)]
+        count: Option<usize>,
+    },
+}
+
+impl Gnew {
+    /// Returns whether the command changes its output with `--porcelain`.
+    fn has_porcelain(&self) -> bool {
+        matches!(
+            self,
+            Gnew::Status { .. }
+                | Gnew::Commit { .. }
+                | Gnew::WriteTree
+                | Gnew::HashFile { .. }
+                | Gnew::MergeBase { .. }
+                | Gnew::RevParse { .. }
+        )
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum SnapshotCommand {
+    /// Save the objects, references and tracklist to a file
+    Save { file: PathBuf },
+    /// Create a repository in the current directory from a snapshot file
+    Restore { file: PathBuf },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum RemoteCommand {
+    /// Remove remote-tracking branches deleted on the remote
+    Prune {
+        #[structopt(default_value = "origin")]
+        remote: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum NotesCommand {
+    /// Attach a note to a commit, replacing its note
+    Add {
+        /// The note
+        #[structopt(short, long)]
+        message: String,
+
+        /// Append to the note instead of replacing it
+        #[structopt(long)]
+        append: bool,
+
+        #[structopt(default_value = "HEAD")]
+        revision: String,
+    },
+    /// Show the note of a commit
+    Show {
+        #[structopt(default_value = "HEAD")]
+        revision: String,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum MaintenanceCommand {
+    /// Prune unreachable objects, pack references, write bitmaps and
+    /// refresh the stat cache
+    Run {
+        /// Only run if there are more loose objects than maintenance.auto
+        #[structopt(long)]
+        auto: bool,
+    },
 }
 
 #[derive(Debug, StructOpt)]
 pub struct CheckoutOptions {
-    /// The branch or commit to check out
+    /// The branch, tag or commit to check out, or `-` for the previous one
     branch: String,
 
     /// Create and checkout a new branch
     #[structopt(short = "b")]
     create: bool,
 
+    /// Discard local changes and remove untracked files
     #[structopt(short, long)]
     force: bool,
+
+    /// Show the progress of updating the files, even for a few files or
+    /// when standard error is not a terminal
+    #[structopt(long)]
+    progress: bool,
 }
 
-pub fn init() -> Result<()> {
-    let r = Repository::init()?;
+/// Minimum number of files for a checkout to show its progress.
+const CHECKOUT_PROGRESS_MIN: usize = 100;
+
+pub fn init(initial_branch: Option<&str>) -> Result<()> {
+    let initial_branch = initial_branch.map(refs::normalize_name);
+    let r = Repository::init(initial_branch.as_deref())?;
     println!(
         "Initialized empty Gnew repository in {}",
         r.storage_dir().display()
@@ -102,124 +639,549 @@ pub fn init() -> Result<()> {
     Ok(())
 }
 
-pub fn clone<P: AsRef<Path> + Copy>(rep: P) -> Result<()> {
-    Repository::clone(rep)?;
+pub fn clone<P: AsRef<Path>>(rep: P, options: CloneOptions) -> Result<()> {
+    Repository::clone(rep, ".", &options)?;
     Ok(())
 }
 
-pub fn add<P: AsRef<Path>>(paths: &Vec<P>) -> Result<()> {
+pub fn add<P: AsRef<Path>>(paths: &[P], mode: AddMode) -> Result<()> {
     let mut r = Repository::open()?;
-    r.add(paths)?;
+    let paths: Vec<_> = paths.iter().map(|p| r.resolve_path(p)).collect();
+    r.add(&paths, mode)?;
 
     Ok(())
 }
 
-pub fn remove<P: AsRef<Path>>(paths: &Vec<P>) -> Result<()> {
+pub fn remove<P: AsRef<Path>>(paths: &[P]) -> Result<()> {
     let mut r = Repository::open()?;
-    r.remove(paths)?;
+    let paths: Vec<_> = paths.iter().map(|p| r.resolve_path(p)).collect();
+    r.remove(&paths)?;
 
     Ok(())
 }
 
-pub fn status() -> Result<()> {
-    let r = Repository::open()?;
+pub fn rm<P: AsRef<Path>>(paths: &[P], options: RmOptions) -> Result<()> {
+    let mut r = Repository::open()?;
+    let paths: Vec<_> = paths.iter().map(|p| r.resolve_path(p)).collect();
+    for path in r.rm(&paths, &options)? {
+        println!("rm '{}'", path);
+    }
+    Ok(())
+}
 
-    let tree = match r.head_hash() {
-        Ok(c) => transport::read_commit(c)?.tree()?,
-        Err(_) => Tree::new(),
+pub fn status(
+    relative: bool,
+    absolute: bool,
+    remote: Option<&str>,
+    porcelain: bool,
+) -> Result<()> {
+    let mut r = Repository::open()?;
+    let divergence = match remote {
+        Some(remote) => Some(r.compare_with_remote(remote)?),
+        None => None,
     };
-    ui::print_status(&r.status(&tree)?);
+
+    let tree = r.head_tree()?;
+    if porcelain {
+        if let Some(divergence) = &divergence {
+            ui::print_porcelain_divergence(r.head(), divergence.as_ref());
+        }
+        ui::print_porcelain_status(&r.status(&tree)?);
+        return Ok(());
+    }
+    let config = transport::read_config(r.worktree())?;
+    let relative = relative || !absolute && config.get("status.relativePaths") != Some("false");
+    let base = if relative { r.prefix() } else { Path::new("") };
+    warn_if_empty_branch(&r);
+    if let (Some(remote), Some(divergence)) = (remote, &divergence) {
+        ui::print_divergence(remote, divergence.as_ref());
+    }
+    ui::print_state(&r.state()?, base);
+    ui::print_status(&r.status(&tree)?, base);
 
     Ok(())
 }
 
-pub fn heads() -> Result<()> {
-    let r = Repository::open()?;
-    ui::print_heads(&r);
+/// Warns on stderr if HEAD is a branch without commits.
+fn warn_if_empty_branch(r: &Repository) {
+    if let Err(err @ Error::EmptyBranch(_)) = r.head_hash() {
+        eprintln!("warning: {}", err)
+    }
+}
+
+/// Opens the repository of the current directory, or another repository
+/// given by its path or the name of a remote for read-only queries. Objects
+/// are then read from the other repository.
+fn open_for_query(repo: Option<&str>) -> Result<Repository> {
+    let other = match repo {
+        Some(other) => other,
+        None => return Repository::open(),
+    };
+    let path = std::env::current_dir()?.join(other);
+    let path = match path.is_dir() {
+        true => path,
+        false => Repository::open()?.remote_path(other)?,
+    };
+    transport::check_repo_exists(&path)?;
+    Repository::open_at(path)
+}
+
+pub fn heads(date: DateFormat, repo: Option<&str>) -> Result<()> {
+    let r = open_for_query(repo)?;
+    ui::print_heads(&r, date)
+}
+
+pub fn branch(
+    contains: Option<String>,
+    merged: Option<String>,
+    no_merged: Option<String>,
+    remotes: bool,
+    delete_merged: Option<String>,
+) -> Result<()> {
+    let mut r = Repository::open()?;
+    if let Some(target) = delete_merged {
+        for (branch, hash) in r.delete_merged_branches(&target)? {
+            let abbrev = &hash.to_string()[..format::ABBREV_LENGTH];
+            println!("Deleted branch {} (was {}).", branch, abbrev);
+        }
+        return Ok(());
+    }
+    let contains = contains.map(|c| r.rev_parse(&c)).transpose()?;
+    let merged = merged.map(|c| r.rev_parse(&c)).transpose()?;
+    let no_merged = no_merged.map(|c| r.rev_parse(&c)).transpose()?;
+
+    let (namespace, current) = match r.head() {
+        _ if remotes => (refs::REMOTES, None),
+        Reference::Branch(current) => (refs::HEADS, Some(current.as_str())),
+        Reference::Hash(_) => (refs::HEADS, None),
+    };
+    let mut branches = vec![];
+    for (branch, hash) in r.references().namespace(namespace) {
+        let selected = match contains {
+            Some(c) => r.is_ancestor(c, hash)?,
+            None => true,
+        } && match merged {
+            Some(c) => r.is_ancestor(hash, c)?,
+            None => true,
+        } && match no_merged {
+            Some(c) => !r.is_ancestor(hash, c)?,
+            None => true,
+        };
+        if selected {
+            branches.push(branch);
+        }
+    }
+    ui::print_branches(&branches, current);
     Ok(())
 }
 
-pub fn diff(commits: &[String]) -> Result<()> {
+pub fn diff(
+    commits: &[String],
+    name_only: bool,
+    name_status: bool,
+    quiet: bool,
+    algorithm: Option<DiffAlgorithm>,
+    style: DiffStyle,
+) -> Result<()> {
     let r = Repository::open()?;
 
-    let changes = match commits {
+    // the tree the changes lead to, None for the working tree
+    let (changes, to) = match commits {
         [] => {
-            let tree = match r.head_hash() {
-                Ok(c) => transport::read_commit(c)?.tree()?,
-                Err(_) => return Ok(()),
-            };
-            r.diff_worktree(&tree)
+            if let Err(err @ Error::EmptyBranch(_)) = r.head_hash() {
+                eprintln!("warning: {}", err);
+                return Ok(());
+            }
+            (r.diff_worktree(&r.head_tree()?)?, None)
+        }
+        [range] if range.contains("...") => {
+            let (c1, c2) = range.split_once("...").unwrap();
+            let rev = |c: &str| r.rev_parse(if c.is_empty() { "HEAD" } else { c });
+            let c2 = rev(c2)?;
+            let base = r.merge_base(rev(c1)?, c2)?.ok_or(Error::UnrelatedHistories)?;
+            let t1 = transport::read_commit(base)?.tree()?;
+            let t2 = transport::read_commit(c2)?.tree()?;
+            (t1.diff(&t2)?, Some(t2))
         }
         [c1] => {
             let c1 = r.rev_parse(c1)?;
             let tree = transport::read_commit(c1)?.tree()?;
-            r.diff_worktree(&tree)
+            (r.diff_worktree(&tree)?, None)
         }
         [c1, c2] => {
             let c1 = r.rev_parse(c1)?;
             let c2 = r.rev_parse(c2)?;
             let t1 = transport::read_commit(c1)?.tree()?;
             let t2 = transport::read_commit(c2)?.tree()?;
-            t1.diff(&t2)
+            (t1.diff(&t2)?, Some(t2))
         }
         _ => panic!("too many arguments"),
-    }?;
-    ui::print_diff(&changes)?;
+    };
+    if quiet {
+        if !changes.is_empty() {
+            std::process::exit(1)
+        }
+        return Ok(());
+    }
+    if name_only || name_status {
+        let changes = object::detect_renames(changes)?;
+        let exists = |dir: &Path| match &to {
+            Some(tree) => tree.has_dir(dir),
+            None => r.worktree().join(dir).is_dir(),
+        };
+        let renames = object::detect_dir_renames(&changes, exists);
+        let changes = object::apply_dir_renames(changes, &renames);
+        ui::print_changed_paths(&changes, name_status);
+        return Ok(());
+    }
+    let algorithm = diff_algorithm(&r, algorithm)?;
+    ui::print_diff(&changes, r.attributes()?, algorithm, style)?;
 
     Ok(())
 }
 
-pub fn cat(commit: String, path: &Path) -> Result<()> {
-    let r = Repository::open()?;
-    let c = r.rev_parse(&commit)?;
-    let file = transport::read_commit(c)?.tree()?.file(path)?;
+/// Returns the given diff algorithm, or the one set by `diff.algorithm`.
+fn diff_algorithm(r: &Repository, algorithm: Option<DiffAlgorithm>) -> Result<DiffAlgorithm> {
+    Ok(match algorithm {
+        Some(a) => a,
+        None => match transport::read_config(r.worktree())?.get("diff.algorithm") {
+            Some(a) => a.parse().or(Err(Error::ConfigCorrupted))?,
+            None => DiffAlgorithm::Myers,
+        },
+    })
+}
+
+/// Outputs a file at a revision. Paths in `<REV>:<PATH>` are relative to the
+/// root of the working tree, and other paths to the current directory.
+pub fn cat(
+    args: &[String],
+    stage: Option<Stage>,
+    recursive: bool,
+    repo: Option<&str>,
+) -> Result<()> {
+    let r = open_for_query(repo)?;
+    if let Some(stage) = stage {
+        return cat_stage(&r, args, stage);
+    }
+    let (rev, path) = match args {
+        [rev, path] => (rev.as_str(), r.prefix().join(path)),
+        [arg] => match arg.split_once(':') {
+            Some((rev, path)) => (rev, PathBuf::from(path)),
+            None => ("HEAD", r.prefix().join(arg)),
+        },
+        _ => unreachable!(),
+    };
+    let path = normalize_path(&path);
+
+    let c = r.rev_parse(rev)?;
+    let tree = transport::read_commit(c)?.tree()?;
+    let file = match tree.file(&path) {
+        Err(Error::FileNotFound) => match tree.tree_at(&path) {
+            Err(Error::FileNotFound) => return Err(Error::PathNotFound(rev.to_owned(), path)),
+            dir => return ui::print_tree(&dir?, recursive),
+        },
+        file => file?,
+    };
     io::stdout().write_all(&file.contents()?)?;
     Ok(())
 }
 
+/// Outputs a version of a file with merge conflicts.
+fn cat_stage(r: &Repository, args: &[String], stage: Stage) -> Result<()> {
+    let path = match args {
+        [path] => normalize_path(&r.prefix().join(path)),
+        _ => clap::Error::with_description(
+            "--stage takes a path without a commit",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit(),
+    };
+    let hash = r
+        .conflicts()
+        .get(path.to_str().unwrap())
+        .and_then(|s| s.get(stage))
+        .ok_or_else(|| Error::PathNotFound(stage.to_string(), path))?;
+    io::stdout().write_all(transport::read_blob(hash)?.content())?;
+    Ok(())
+}
+
+pub fn check_ignore(paths: &[PathBuf], verbose: bool) -> Result<()> {
+    let r = Repository::open()?;
+    let ignore = r.ignore_matcher()?;
+    let mut ignored = false;
+
+    for path in paths {
+        let rpath = normalize_path(&r.prefix().join(path));
+        let pattern = ignore.explain(&rpath, r.resolve_path(path).is_dir());
+        if let Some(pattern) = pattern {
+            ignored |= !pattern.is_negated();
+            ui::print_ignore_match(path, pattern, verbose);
+        }
+    }
+    if !ignored {
+        std::process::exit(1)
+    }
+    Ok(())
+}
+
+/// Outputs the owners of paths, or checks the owners of the paths changed
+/// by a branch update, given as hashes with zeros for a new branch.
+pub fn owners(paths: &[PathBuf], check: Option<&[String]>) -> Result<()> {
+    let r = Repository::open()?;
+    if let Some([old, new]) = check {
+        let old = match old.trim_matches('0') {
+            "" => None,
+            _ => Some(r.rev_parse(old)?),
+        };
+        let violations = r.check_owners(old, r.rev_parse(new)?)?;
+        ui::print_owner_violations(&violations);
+        if !violations.is_empty() {
+            std::process::exit(1)
+        }
+        return Ok(());
+    }
+    let owners = r.owners()?;
+    for path in paths {
+        let rpath = normalize_path(&r.prefix().join(path));
+        ui::print_owners(path, owners.get(&rpath));
+    }
+    Ok(())
+}
+
+pub fn merge_base(commit1: &str, commit2: &str, is_ancestor: bool) -> Result<()> {
+    let r = Repository::open()?;
+    let c1 = r.rev_parse(commit1)?;
+    let c2 = r.rev_parse(commit2)?;
+
+    if is_ancestor {
+        std::process::exit(if r.is_ancestor(c1, c2)? { 0 } else { 1 })
+    }
+    match r.merge_base(c1, c2)? {
+        Some(base) => println!("{}", base),
+        None => std::process::exit(1),
+    }
+    Ok(())
+}
+
+pub fn rev_parse(revisions: &[String]) -> Result<()> {
+    let r = Repository::open()?;
+    for revision in revisions {
+        println!("{}", r.rev_parse(revision)?);
+    }
+    Ok(())
+}
+
+pub fn for_each_ref(
+    patterns: &[String],
+    format: &str,
+    sort: RefSort,
+    date: DateFormat,
+    count: Option<usize>,
+) -> Result<()> {
+    let r = Repository::open()?;
+    let mailmap = Mailmap::load(r.worktree())?;
+    let matches = |name: &str| {
+        patterns.is_empty()
+            || patterns.iter().any(|p| {
+                let p = p.trim_end_matches('/');
+                name == p || name.strip_prefix(p).is_some_and(|rest| rest.starts_with('/'))
+            })
+    };
+
+    let mut refs = vec![];
+    for (name, hash) in r.references().iter().filter(|(name, _)| matches(name)) {
+        refs.push((name, transport::read_commit(hash)?));
+    }
+    refs.sort_by(|a, b| sort.compare((a.0, &a.1), (b.0, &b.1)));
+    for (name, commit) in refs.iter().take(count.unwrap_or(usize::MAX)) {
+        println!("{}", format::format_ref(format, name, commit, date, &mailmap));
+    }
+    Ok(())
+}
+
+/// Resolves `.` and `..` components of a relative path.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
 pub fn checkout(o: CheckoutOptions) -> Result<()> {
     let mut r = Repository::open()?;
     if o.create {
-        r.create_branch(&o.branch)?;
-        println!("Switched to new branch '{}'", o.branch);
+        let branch = refs::normalize_name(&o.branch);
+        r.create_branch(&branch)?;
+        println!("Switched to new branch '{}'", branch);
     } else if o.branch != "HEAD" {
-        let new_head = parse_reference(&o.branch);
-        r.checkout(new_head.clone(), o.force)?;
-        println!("Switched to {}", new_head);
+        let new_head = parse_reference(&r, &o.branch)?;
+        let terminal = io::stderr().is_terminal();
+        let progress = |done, total| {
+            if o.progress || (terminal && total >= CHECKOUT_PROGRESS_MIN) {
+                ui::print_progress("Updating files", done, total);
+            }
+        };
+        let summary = r.checkout_with_progress(new_head.clone(), o.force, &progress)?;
+        match new_head {
+            Reference::Branch(b) => println!("Switched to branch '{}'", b),
+            Reference::Hash(h) => println!("Switched to {}", h),
+        }
+        ui::print_checkout_summary(&summary);
     }
     Ok(())
 }
 
-fn parse_reference(s: &str) -> Reference {
-    s.parse()
-        .map_or_else(|_| Reference::Branch(s.to_owned()), Reference::Hash)
+/// Parses a commit hash, the name of an existing branch, or `-` or `@{-1}`
+/// for the previous HEAD. A tag checks out its commit. A missing branch is
+/// reported with the branches and tags of similar names.
+fn parse_reference(r: &Repository, s: &str) -> Result<Reference> {
+    if s == "-" || s == "@{-1}" {
+        return r.previous_head()?.ok_or(Error::NoPreviousHead);
+    }
+    if let Ok(hash) = s.parse() {
+        return Ok(Reference::Hash(hash));
+    }
+    if r.branch(s).is_ok() {
+        return Ok(Reference::Branch(s.to_owned()));
+    }
+    if let Some(hash) = r.references().get(&format!("{}{}", refs::TAGS, s)) {
+        return Ok(Reference::Hash(hash));
+    }
+    if !refs::is_valid_branch_name(s) {
+        return Err(Error::InvalidRefName(s.to_owned()));
+    }
+    let refs = r.references();
+    let names = refs.namespace(refs::HEADS).chain(refs.namespace(refs::TAGS));
+    Err(Error::BranchNotFound(s.to_owned(), refs::similar_names(s, names.map(|(n, _)| n))))
 }
 
-pub fn commit(message: String) -> Result<()> {
+pub fn commit(
+    message: Option<String>,
+    dry_run: bool,
+    signoff: bool,
+    porcelain: bool,
+    options: CommitOptions,
+) -> Result<()> {
     let mut r = Repository::open()?;
-    println!("{}", r.commit(message)?.hash());
+    let summary = r.commit_summary()?;
+    if !dry_run {
+        let message = match message {
+            Some(message) => message,
+            None => r.edit_message()?,
+        };
+        let message = if signoff { r.sign_off(&message)? } else { message };
+        println!("{}", r.commit(message, &options)?.hash());
+    }
+    if dry_run || !porcelain {
+        ui::print_commit_summary(&summary);
+    }
+    if !dry_run {
+        finish_autostash(&mut r)?;
+    }
     Ok(())
 }
 
-pub fn log(amount: u32) -> Result<()> {
-    let r = Repository::open()?;
-    let log = r.log(amount)?;
+pub fn log(
+    amount: u32,
+    format: Option<&str>,
+    date: DateFormat,
+    options: RevWalkOptions,
+    filter: &LogFilter,
+    repo: Option<&str>,
+) -> Result<()> {
+    let r = open_for_query(repo)?;
+    warn_if_empty_branch(&r);
+    let log = r.log(0, options)?;
+    let decorations = r.decorations();
+    let mailmap = Mailmap::load(r.worktree())?;
+    let amount = match amount {
+        0 => usize::MAX,
+        n => n as usize,
+    };
+    let mut shown = 0;
     for l in log {
-        ui::print_commit(l,&r);
+        if shown == amount {
+            break;
+        }
+        let l = l?;
+        if !filter.matches(&l)? {
+            continue;
+        }
+        shown += 1;
+        let d = decorations.get(&l.hash()).map_or(&[][..], Vec::as_slice);
+        match format {
+            Some(format) => println!("{}", format::format_commit(format, &l, d, date, &mailmap)),
+            None => ui::print_commit(l, d, date, &mailmap),
+        }
+    }
+    Ok(())
+}
+
+/// Outputs the commits that changed a path, newest first, each with the
+/// diff of the path against its first parent. Merge commits are left out.
+pub fn whatchanged(
+    path: &Path,
+    max_count: Option<usize>,
+    date: DateFormat,
+    algorithm: Option<DiffAlgorithm>,
+    style: DiffStyle,
+) -> Result<()> {
+    let r = Repository::open()?;
+    warn_if_empty_branch(&r);
+    let paths = [normalize_path(&r.prefix().join(path))];
+    let algorithm = diff_algorithm(&r, algorithm)?;
+    let decorations = r.decorations();
+    let mailmap = Mailmap::load(r.worktree())?;
+    let options = RevWalkOptions {
+        first_parent: false,
+        merges: MergeFilter::NoMerges,
+    };
+    let mut shown = 0;
+    for l in r.log(0, options)? {
+        if max_count == Some(shown) {
+            break;
+        }
+        let l = l?;
+        let parent = match l.parent() {
+            Some(parent) => parent?.tree()?,
+            None => object::Tree::new(),
+        };
+        let changes = parent.diff_paths(&l.tree()?, &paths)?;
+        if changes.is_empty() {
+            continue;
+        }
+        shown += 1;
+        let d = decorations.get(&l.hash()).map_or(&[][..], Vec::as_slice);
+        ui::print_commit(l, d, date, &mailmap);
+        println!();
+        ui::print_diff(&changes, r.attributes()?, algorithm, style)?;
+        println!();
     }
     Ok(())
 }
 
-pub fn merge(commit: String) -> Result<()> {
+pub fn merge(commit: String, mut options: MergeOptions) -> Result<()> {
     let mut r = Repository::open()?;
+    options.message = Some(if r.branch(&commit).is_ok() {
+        format!("Merge branch '{}'", commit)
+    } else {
+        format!("Merge commit '{}'", commit)
+    });
 
-    match r.merge(r.rev_parse(&commit)?) {
+    match r.merge(r.rev_parse(&commit)?, &options) {
+        Ok(MergeStrategy::AlreadyUpToDate) => println!("Already up to date."),
         Ok(MergeStrategy::FastForward) => println!("Fast-forward"),
-        Ok(_) => println!("Merge complete: remember to commit."),
+        Ok(_) if options.no_commit => println!("Merge complete: remember to commit."),
+        Ok(_) => println!("Merge made by the three-way strategy."),
         Err(Error::MergeFailed(conflicts)) => {
-            for path in conflicts {
-                eprintln!("Merge conflict in {}", path.display())
-            }
+            print_conflicts(&r, &conflicts);
             eprintln!("Merge failed: fix conflicts and commit the result.");
             std::process::exit(1)
         }
@@ -228,20 +1190,242 @@ pub fn merge(commit: String) -> Result<()> {
     Ok(())
 }
 
-pub fn pull<P: AsRef<Path>>(path: P, all: bool) -> Result<()> {
+/// Lists the files with conflicts, and those resolved with recorded
+/// resolutions.
+fn print_conflicts(r: &Repository, conflicts: &[PathBuf]) {
+    for path in conflicts {
+        if r.rerere_resolved().contains(path) {
+            eprintln!("Resolved '{}' using previous resolution.", path.display())
+        } else {
+            eprintln!("Merge conflict in {}", path.display())
+        }
+    }
+}
+
+pub fn rebase(upstream: Option<String>, interactive: bool, autostash: Option<bool>) -> Result<()> {
+    let mut r = Repository::open()?;
+    let result = start_rebase(&mut r, &upstream.unwrap(), interactive, autostash);
+    finish_rebase(&mut r, result)
+}
+
+pub fn rebase_continue() -> Result<()> {
+    let mut r = Repository::open()?;
+    let result = r.rebase_continue().map(Some);
+    finish_rebase(&mut r, result)
+}
+
+pub fn rebase_abort() -> Result<()> {
     let mut r = Repository::open()?;
-    r.pull(path, all)?;
+    let result = r.rebase_abort().map(|_| None);
+    finish_rebase(&mut r, result)
+}
+
+/// Starts a rebase onto `upstream`. Returns None if there is nothing to do.
+fn start_rebase(
+    r: &mut Repository,
+    upstream: &str,
+    interactive: bool,
+    autostash: Option<bool>,
+) -> Result<Option<RebaseReport>> {
+    if r.rebase_state()?.is_some() {
+        return Err(Error::RebaseInProgress);
+    }
+    let upstream = r.rev_parse(upstream)?;
+    if !interactive && r.is_ancestor(upstream, r.head_hash()?)? {
+        println!("Current branch is up to date.");
+        return Ok(None);
+    }
+    let commits = r.rebase_commits(upstream)?;
+    let todo = if interactive {
+        r.edit_rebase_todo(&commits)?
+    } else {
+        let pick = |c: &object::Commit| RebaseStep {
+            action: RebaseAction::Pick,
+            commit: c.hash(),
+        };
+        commits.iter().map(pick).collect()
+    };
+    if interactive && todo.is_empty() && !commits.is_empty() {
+        println!("Nothing to do.");
+        return Ok(None);
+    }
+    start_autostash(r, autostash, "rebase.autostash")?;
+    r.rebase(upstream, todo).map(Some)
+}
+
+/// Reports how a rebase command ended, then applies the autostash if the
+/// rebase is over.
+fn finish_rebase(r: &mut Repository, result: Result<Option<RebaseReport>>) -> Result<()> {
+    match result {
+        Ok(Some(report)) => match report.head {
+            Reference::Branch(b) => {
+                println!("Successfully rebased and updated {}.", refs::branch_ref(&b))
+            }
+            Reference::Hash(_) => println!("Successfully rebased and updated detached HEAD."),
+        },
+        Ok(None) => (),
+        Err(Error::RebaseStopped(commit, conflicts)) => {
+            print_conflicts(r, &conflicts);
+            eprintln!("Could not apply {}: fix conflicts and run 'gnew rebase --continue'.", commit);
+            finish_autostash(r)?;
+            std::process::exit(1)
+        }
+        Err(err) => {
+            finish_autostash(r)?;
+            return Err(err);
+        }
+    }
+    finish_autostash(r)
+}
+
+pub fn pull<P: AsRef<Path>>(path: P, all: bool, autostash: Option<bool>) -> Result<()> {
+    let mut r = Repository::open()?;
+    start_autostash(&mut r, autostash, "pull.autostash")?;
+    let report = r.pull(r.resolve_path(path), all);
+    if let Ok(report) = &report {
+        ui::print_pull_report(report);
+    }
+    finish_autostash(&mut r)?;
+    report?;
+    auto_maintenance(&r)
+}
+
+/// Returns whether `--autostash` or `--no-autostash` was given, if any.
+fn autostash_option(autostash: bool, no_autostash: bool) -> Option<bool> {
+    match (autostash, no_autostash) {
+        (false, false) => None,
+        _ => Some(autostash),
+    }
+}
+
+/// Saves the changes to tracked files if asked to, by default if the config
+/// key is set to true.
+fn start_autostash(r: &mut Repository, autostash: Option<bool>, key: &str) -> Result<()> {
+    let autostash = match autostash {
+        Some(autostash) => autostash,
+        None => transport::read_config(r.worktree())?.get_bool(key),
+    };
+    if autostash && r.autostash()? {
+        println!("Created autostash.");
+    }
+    Ok(())
+}
+
+/// Applies the changes saved with `--autostash`, once the merge or rebase
+/// that the command started, if any, is completed.
+fn finish_autostash(r: &mut Repository) -> Result<()> {
+    match r.apply_autostash()? {
+        Some(conflicts) if conflicts.is_empty() => println!("Applied autostash."),
+        Some(conflicts) => {
+            for path in conflicts {
+                eprintln!("Merge conflict in {}", path.display())
+            }
+            eprintln!("Applying autostash resulted in conflicts: fix them before committing.");
+        }
+        None => {
+            if let Some(stash) = transport::read_autostash(r.worktree())? {
+                println!("Autostash {} is applied once the merge or rebase is completed.", stash);
+            }
+        }
+    }
     Ok(())
 }
 
 pub fn push<P: AsRef<Path>>(path: P, all: bool) -> Result<()> {
+    let mut r = Repository::open()?;
+    ui::print_push_report(&r.push(r.resolve_path(path), all)?);
+    Ok(())
+}
+
+pub fn repair<P: AsRef<Path>>(from: P) -> Result<()> {
     let r = Repository::open()?;
-    r.push(path, all)?;
+    let repaired = r.repair(r.resolve_path(from))?;
+    for hash in &repaired {
+        println!("restored {}", hash);
+    }
+    println!("Restored {} objects", repaired.len());
+    Ok(())
+}
+
+pub fn snapshot(cmd: SnapshotCommand) -> Result<()> {
+    match cmd {
+        SnapshotCommand::Save { file } => {
+            let r = Repository::open()?;
+            let n = r.save_snapshot(r.resolve_path(&file))?;
+            println!("Saved {} objects to {}", n, file.display());
+        }
+        SnapshotCommand::Restore { file } => {
+            let r = Repository::restore_snapshot(&file)?;
+            println!("Restored repository in {}", r.storage_dir().display());
+        }
+    }
+    Ok(())
+}
+
+pub fn fetch(remote: &str, prune: bool) -> Result<()> {
+    let mut r = Repository::open()?;
+    for branch in r.fetch(remote, prune)? {
+        println!(" - [pruned] {}/{}", remote, branch);
+    }
+    auto_maintenance(&r)
+}
+
+pub fn filter(remove_paths: Vec<String>, mailmap: bool, prune: bool) -> Result<()> {
+    let mut r = Repository::open()?;
+    let options = FilterOptions {
+        remove_paths,
+        mailmap: if mailmap { Some(Mailmap::load(r.worktree())?) } else { None },
+    };
+    let report = r.filter(&options)?;
+    for path in &report.untracked {
+        println!("untracked '{}'", path);
+    }
+    for name in &report.refs {
+        println!(" - [rewritten] {}", name);
+    }
+    println!("Rewrote {} commits", report.commits);
+    if prune {
+        let (objects, _) = r.prune(SystemTime::now())?;
+        println!("Removed {} unreachable objects", objects);
+    }
+    Ok(())
+}
+
+pub fn remote(cmd: RemoteCommand) -> Result<()> {
+    match cmd {
+        RemoteCommand::Prune { remote } => {
+            let mut r = Repository::open()?;
+            for branch in r.prune_remote(&remote)? {
+                println!(" - [pruned] {}/{}", remote, branch);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn notes(name: &str, cmd: NotesCommand) -> Result<()> {
+    let reference = notes::notes_ref(name)?;
+    let mut r = Repository::open()?;
+    match cmd {
+        NotesCommand::Add {
+            message,
+            append,
+            revision,
+        } => {
+            let commit = r.rev_parse(&revision)?;
+            r.add_note(&reference, commit, &message, append)?;
+        }
+        NotesCommand::Show { revision } => {
+            let commit = r.rev_parse(&revision)?;
+            print!("{}", r.note(&reference, commit)?.ok_or(Error::NoteNotFound(commit))?);
+        }
+    }
     Ok(())
 }
 
 pub fn hash_file<P: AsRef<Path>>(path: P) -> Result<()> {
-    println!("{}", transport::write_blob(path)?.hash());
+    let r = Repository::open()?;
+    println!("{}", transport::write_blob(r.resolve_path(path))?.hash());
     Ok(())
 }
 
@@ -250,7 +1434,77 @@ pub fn write_tree() -> Result<()> {
     Ok(())
 }
 
+pub fn write_bitmaps() -> Result<()> {
+    let n = Repository::open()?.write_bitmaps()?;
+    println!("Wrote bitmaps for {} commits", n);
+    Ok(())
+}
+
+pub fn pack_refs() -> Result<()> {
+    let n = Repository::open()?.pack_refs()?;
+    println!("Packed {} references", n);
+    Ok(())
+}
+
+/// Checks the objects and lists the dangling commits, or creates a branch
+/// at one of them. The dangling commits are only looked for once no object
+/// is corrupted.
+pub fn fsck(lost_found: bool, date: DateFormat, restore: Option<&[String]>) -> Result<()> {
+    let mut r = Repository::open()?;
+    if let Some([commit, branch]) = restore {
+        r.restore_commit(r.rev_parse(commit)?, branch)?;
+        println!("Created branch '{}' at {}", branch, commit);
+        return Ok(());
+    }
+    let objects = r.verify_objects()?;
+    if objects.iter().any(|o| !o.valid) {
+        ui::print_corrupted_objects(&objects);
+        return Err(Error::ObjectCorrupted);
+    }
+    ui::print_dangling_commits(&r.dangling_commits()?, lost_found.then_some(date));
+    Ok(())
+}
+
+pub fn verify_pack(verbose: bool) -> Result<()> {
+    let objects = Repository::open()?.verify_objects()?;
+    ui::print_verified_objects(&objects, verbose);
+    if objects.iter().all(|o| o.valid) {
+        Ok(())
+    } else {
+        Err(Error::ObjectCorrupted)
+    }
+}
+
+pub fn verify_tracklist(repair: bool, prune: bool) -> Result<()> {
+    let mut r = Repository::open()?;
+    let report = r.verify_tracklist(repair || prune, prune)?;
+    ui::print_tracklist_report(&report, repair || prune, prune);
+    if report.is_empty() || repair || prune {
+        Ok(())
+    } else {
+        Err(Error::TracklistCorrupted)
+    }
+}
+
+pub fn stats(blobs: bool, count: usize) -> Result<()> {
+    let r = Repository::open()?;
+    if blobs {
+        ui::print_history_blobs(&r.largest_blobs(count)?);
+    } else {
+        ui::print_stats(&r.stats()?, true);
+    }
+    Ok(())
+}
+
+pub fn count_objects(verbose: bool) -> Result<()> {
+    let r = Repository::open()?;
+    ui::print_stats(&r.stats()?, verbose);
+    Ok(())
+}
+
 pub fn cat_object(type_: &str, object: Hash) -> Result<()> {
+    /* objects are read from the store of the opened repository */
+    let _r = Repository::open()?;
     match type_ {
         "blob" => io::stdout().write_all(transport::read_blob(object)?.content())?,
         "tree" => print!("{}", transport::read_tree(object)?),
@@ -260,29 +1514,330 @@ pub fn cat_object(type_: &str, object: Hash) -> Result<()> {
     Ok(())
 }
 
+/// Prints the spans of the `tracing` feature to stderr, with their
+/// durations, if GNEW_TRACE is set. With `profile`, returns the profile the
+/// spans are collected into.
+#[cfg(feature = "tracing")]
+fn init_tracing(profile: bool) -> Option<Arc<Mutex<Profile>>> {
+    use crate::wd::profile::ProfileLayer;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::fmt::format::FmtSpan;
+    use tracing_subscriber::prelude::*;
+
+    let trace = std::env::var_os("GNEW_TRACE").is_some();
+    if !trace && !profile {
+        return None;
+    }
+    // hashing spans are too many to print
+    let fmt = trace.then(|| {
+        tracing_subscriber::fmt::layer()
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(io::stderr)
+            .with_filter(LevelFilter::DEBUG)
+    });
+    let profile = profile.then(|| Arc::new(Mutex::new(Profile::default())));
+    tracing_subscriber::registry()
+        .with(fmt)
+        .with(profile.clone().map(ProfileLayer))
+        .init();
+    profile
+}
+
+#[cfg(not(feature = "tracing"))]
+fn init_tracing(profile: bool) -> Option<Arc<Mutex<Profile>>> {
+    if profile {
+        eprintln!("warning: --profile needs gnew built with the tracing feature");
+    }
+    None
+}
+
+/// Returns the width of the terminal from $COLUMNS, or 80 columns.
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80)
+}
+
+pub fn maintenance(cmd: MaintenanceCommand) -> Result<()> {
+    match cmd {
+        MaintenanceCommand::Run { auto } => {
+            let r = Repository::open()?;
+            if !auto || r.needs_maintenance()? {
+                ui::print_maintenance_report(&r.maintain()?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs maintenance if the object store has grown past `maintenance.auto`,
+/// after commands that add many objects.
+fn auto_maintenance(r: &Repository) -> Result<()> {
+    if r.needs_maintenance()? {
+        eprintln!("Auto packing the repository for optimum performance.");
+        r.maintain()?;
+    }
+    Ok(())
+}
+
 pub fn main() {
-    let opt = Gnew::from_args();
-    match opt {
-        Gnew::Init => init(),
-        Gnew::Clone { repository } => clone(&repository),
-        Gnew::Add { paths } => add(&paths),
+    let start = Instant::now();
+    let opt = Options::from_args();
+    if opt.porcelain && !opt.command.has_porcelain() {
+        clap::Error::with_description(
+            "--porcelain is only supported by status, commit, write-tree, hash-file, merge-base \
+             and rev-parse",
+            clap::ErrorKind::ArgumentConflict,
+        )
+        .exit();
+    }
+    let profile = init_tracing(opt.profile);
+
+    let result = match opt.command {
+        Gnew::Init { initial_branch } => init(initial_branch.as_deref()),
+        Gnew::Clone {
+            repository,
+            reference,
+            local,
+            filter,
+            branch,
+            single_branch,
+        } => clone(
+            &repository,
+            CloneOptions {
+                reference,
+                local,
+                without_blobs: filter.is_some(),
+                branch,
+                single_branch,
+            },
+        ),
+        Gnew::Add { paths, update, all } => {
+            let mode = if all {
+                AddMode::All
+            } else if update {
+                AddMode::Update
+            } else {
+                AddMode::Paths
+            };
+            add(&paths, mode)
+        }
         Gnew::Remove { paths } => remove(&paths),
-        Gnew::Status => status(),
-        Gnew::Heads => heads(),
-        Gnew::Diff { commits } => diff(&commits),
-        Gnew::Cat { commit, path } => cat(commit, &path),
+        Gnew::Rm {
+            paths,
+            cached,
+            r,
+            force,
+        } => rm(
+            &paths,
+            RmOptions {
+                cached,
+                recursive: r,
+                force,
+            },
+        ),
+        Gnew::Status {
+            relative,
+            absolute,
+            remote,
+        } => status(relative, absolute, remote.as_deref(), opt.porcelain),
+        Gnew::Stats { blobs, count } => stats(blobs, count),
+        Gnew::Heads { date, repo } => heads(date, repo.as_deref()),
+        Gnew::Branch {
+            contains,
+            merged,
+            no_merged,
+            remotes,
+            delete_merged,
+        } => branch(contains, merged, no_merged, remotes, delete_merged),
+        Gnew::Diff {
+            commits,
+            name_only,
+            name_status,
+            quiet,
+            diff_algorithm,
+            color,
+            side_by_side,
+        } => {
+            let style = DiffStyle {
+                color,
+                side_by_side: if side_by_side {
+                    Some(terminal_width())
+                } else {
+                    None
+                },
+            };
+            diff(&commits, name_only, name_status, quiet, diff_algorithm, style)
+        }
+        Gnew::Cat {
+            args,
+            stage,
+            recursive,
+            repo,
+        } => cat(&args, stage, recursive, repo.as_deref()),
         Gnew::Checkout(opt) => checkout(opt),
-        Gnew::Commit { message } => commit(message),
-        Gnew::Log { amount } => log(amount),
-        Gnew::Merge { commit } => merge(commit),
-        Gnew::Pull { repository, all } => pull(repository, all),
+        Gnew::Commit {
+            message,
+            dry_run,
+            signoff,
+            author,
+            date,
+            force,
+        } => commit(
+            message,
+            dry_run,
+            signoff,
+            opt.porcelain,
+            CommitOptions {
+                author,
+                time: date,
+                force,
+            },
+        ),
+        Gnew::Log {
+            amount,
+            format,
+            date,
+            merges,
+            no_merges,
+            first_parent,
+            grep,
+            regexp_ignore_case,
+            pickaxe,
+            repo,
+        } => LogFilter::new(grep.as_deref(), regexp_ignore_case, pickaxe).and_then(|filter| {
+            log(
+                amount,
+                format.as_deref(),
+                date,
+                RevWalkOptions {
+                    first_parent,
+                    merges: if merges {
+                        MergeFilter::Merges
+                    } else if no_merges {
+                        MergeFilter::NoMerges
+                    } else {
+                        MergeFilter::All
+                    },
+                },
+                &filter,
+                repo.as_deref(),
+            )
+        }),
+        Gnew::Whatchanged {
+            path,
+            max_count,
+            date,
+            diff_algorithm,
+            color,
+        } => {
+            let style = DiffStyle {
+                color,
+                side_by_side: None,
+            };
+            whatchanged(&path, max_count, date, diff_algorithm, style)
+        }
+        Gnew::Merge {
+            commit,
+            allow_unrelated_histories,
+            no_commit,
+        } => merge(
+            commit,
+            MergeOptions {
+                allow_unrelated_histories,
+                no_commit,
+                message: None,
+            },
+        ),
+        Gnew::Pull {
+            repository,
+            all,
+            autostash,
+            no_autostash,
+        } => pull(repository, all, autostash_option(autostash, no_autostash)),
         Gnew::Push { repository, all } => push(repository, all),
+        Gnew::Rebase {
+            continue_: true, ..
+        } => rebase_continue(),
+        Gnew::Rebase { abort: true, .. } => rebase_abort(),
+        Gnew::Rebase {
+            upstream,
+            interactive,
+            autostash,
+            no_autostash,
+            ..
+        } => rebase(upstream, interactive, autostash_option(autostash, no_autostash)),
+        Gnew::Repair { from } => repair(from),
+        Gnew::Snapshot(cmd) => snapshot(cmd),
+        Gnew::Fetch { remote, prune } => fetch(&remote, prune),
+        Gnew::Filter {
+            remove_paths,
+            mailmap,
+            prune,
+        } => filter(remove_paths, mailmap, prune),
+        Gnew::Remote(cmd) => remote(cmd),
+        Gnew::Notes { notes: name, cmd } => notes(&name, cmd),
+        Gnew::Maintenance(cmd) => maintenance(cmd),
         Gnew::HashFile { path } => hash_file(path),
         Gnew::WriteTree => write_tree(),
+        Gnew::WriteBitmaps => write_bitmaps(),
+        Gnew::PackRefs => pack_refs(),
+        Gnew::Fsck {
+            lost_found,
+            date,
+            restore,
+        } => fsck(lost_found, date, restore.as_deref()),
+        Gnew::VerifyPack { verbose } => verify_pack(verbose),
+        Gnew::VerifyTracklist { repair, prune } => verify_tracklist(repair, prune),
+        Gnew::CountObjects { verbose } => count_objects(verbose),
         Gnew::CatObject { type_, object } => cat_object(&type_, object),
+        Gnew::CheckIgnore { verbose, paths } => check_ignore(&paths, verbose),
+        Gnew::Owners { paths, check } => owners(&paths, check.as_deref()),
+        Gnew::MergeBase {
+            is_ancestor,
+            commit1,
+            commit2,
+        } => merge_base(&commit1, &commit2, is_ancestor),
+        Gnew::RevParse { revisions } => rev_parse(&revisions),
+        Gnew::ForEachRef {
+            patterns,
+            format,
+            sort,
+            date,
+            count,
+        } => for_each_ref(&patterns, &format, sort, date, count),
+    };
+    if let Some(profile) = profile {
+        ui::print_profile(&profile.lock().unwrap(), start.elapsed());
     }
-    .unwrap_or_else(|err| {
+    result.unwrap_or_else(|err| {
         eprintln!("fatal: {}", err);
+        match err {
+            Error::ObjectMissing | Error::ObjectCorrupted => {
+                eprintln!("hint: restore objects with 'gnew repair --from <repository>'")
+            }
+            Error::CommitUnreadable(..) => {
+                eprintln!("hint: the history from this commit on is not shown; check the objects with 'gnew fsck' and restore them with 'gnew repair --from <repository>'")
+            }
+            Error::LocalModifications(_) => {
+                eprintln!("hint: use --cached to keep the files or --force to remove them")
+            }
+            Error::BranchNotFound(_, similar) if !similar.is_empty() => {
+                let names: Vec<_> = similar.iter().map(|n| format!("'{}'", n)).collect();
+                eprintln!("hint: did you mean {}?", names.join(" or "))
+            }
+            Error::InvalidRefName(_) => {
+                eprintln!("hint: names are made of ASCII letters, digits and -_./, do not start with - or contain .. or empty or dot-first components, and are not HEAD, a hash, or ending in .lock")
+            }
+            Error::UnrelatedHistories => {
+                eprintln!("hint: the commits have no common ancestor; use --allow-unrelated-histories to merge them anyway")
+            }
+            Error::FilesMissing(_) => {
+                eprintln!("hint: restore the files, untrack them with 'gnew rm --cached', or use --force to commit without them")
+            }
+            _ => (),
+        }
         std::process::exit(1)
     })
 }
@@ -0,0 +1,303 @@
+use crate::repo::mailmap::Mailmap;
+use crate::repo::object::Commit;
+use crate::repo::refs;
+use crate::wd::ui::{self, DateFormat};
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::result;
+use std::str;
+
+/// Length of abbreviated hashes.
+pub const ABBREV_LENGTH: usize = 7;
+
+/// Formats a commit with a format string. Placeholders:
+///
+/// - `%H`: hash, `%h`: abbreviated hash
+/// - `%an`: author, `%aN`: author mapped by `.mailmap`
+/// - `%ad`: date in the given format, `%ai`: date (ISO 8601),
+///   `%ar`: relative date, `%at`: Unix timestamp,
+///   `%ad{<format>}`: date in a strftime format, e.g. `%ad{%Y-%m-%d}`
+/// - `%s`: subject (first line of the message), `%b`: body
+/// - `%d`: decorations, e.g. ` (main, feature)`, `%D`: without the parentheses
+/// - `%n`: newline, `%%`: `%`
+///
+/// Unknown placeholders are output as is.
+pub fn format_commit(
+    format: &str,
+    commit: &Commit,
+    decorations: &[String],
+    date: DateFormat,
+    mailmap: &Mailmap,
+) -> String {
+    format_with(format, |s| expand(s, commit, decorations, date, mailmap))
+}
+
+/// Formats a reference to a commit with a format string. Takes the
+/// placeholders of `format_commit`, and:
+///
+/// - `%(refname)`: full name, e.g. `refs/heads/main`
+/// - `%(refname:short)`: name without its namespace, e.g. `main`
+pub fn format_ref(
+    format: &str,
+    name: &str,
+    commit: &Commit,
+    date: DateFormat,
+    mailmap: &Mailmap,
+) -> String {
+    format_with(format, |s| {
+        if s.starts_with("%(refname)") {
+            (Some(name.to_owned()), "%(refname)".len())
+        } else if s.starts_with("%(refname:short)") {
+            (Some(refs::short_name(name).to_owned()), "%(refname:short)".len())
+        } else {
+            expand(s, commit, &[], date, mailmap)
+        }
+    })
+}
+
+/// Replaces each placeholder of a format string by its expansion.
+fn format_with<F>(format: &str, expand: F) -> String
+where
+    F: Fn(&str) -> (Option<String>, usize),
+{
+    let mut out = String::new();
+    let mut rest = format;
+
+    while let Some(i) = rest.find('%') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        let (expansion, len) = expand(rest);
+        match expansion {
+            Some(s) => out.push_str(&s),
+            None => out.push_str(&rest[..len]),
+        }
+        rest = &rest[len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The order of references listed by `for-each-ref`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RefSort {
+    key: RefSortKey,
+    descending: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RefSortKey {
+    RefName,
+    ObjectName,
+    AuthorDate,
+    Subject,
+}
+
+impl RefSort {
+    /// Compares two references to commits by the sort key.
+    pub fn compare(&self, a: (&str, &Commit), b: (&str, &Commit)) -> Ordering {
+        let ordering = match self.key {
+            RefSortKey::RefName => a.0.cmp(b.0),
+            RefSortKey::ObjectName => a.1.hash().to_string().cmp(&b.1.hash().to_string()),
+            RefSortKey::AuthorDate => a.1.time().cmp(&b.1.time()),
+            RefSortKey::Subject => split_message(a.1.msg()).0.cmp(split_message(b.1.msg()).0),
+        };
+        if self.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    }
+}
+
+impl str::FromStr for RefSort {
+    type Err = String;
+
+    /// Parses a sort key, prefixed with `-` for descending order.
+    fn from_str(s: &str) -> result::Result<RefSort, String> {
+        let (descending, key) = match s.strip_prefix('-') {
+            Some(key) => (true, key),
+            None => (false, s),
+        };
+        let key = match key {
+            "refname" => RefSortKey::RefName,
+            "objectname" => RefSortKey::ObjectName,
+            "authordate" | "date" => RefSortKey::AuthorDate,
+            "subject" => RefSortKey::Subject,
+            _ => return Err(format!("unknown sort key '{}'", key)),
+        };
+        Ok(RefSort { key, descending })
+    }
+}
+
+/// Placeholders in the order they are matched.
+const PLACEHOLDERS: &[&str] = &[
+    "%%", "%n", "%H", "%h", "%an", "%aN", "%ai", "%ar", "%at", "%ad", "%s", "%b", "%d", "%D",
+];
+
+/// Expands the placeholder at the start of s.
+/// Returns the expansion, if any, and the length of the placeholder.
+fn expand(
+    s: &str,
+    commit: &Commit,
+    decorations: &[String],
+    date: DateFormat,
+    mailmap: &Mailmap,
+) -> (Option<String>, usize) {
+    let time = commit.time();
+
+    if let Some(spec) = s.strip_prefix("%ad{") {
+        if let Some(end) = spec.find('}') {
+            let mut date = String::new();
+            let ok = write!(date, "{}", time.format(&spec[..end])).is_ok();
+            return (Some(date).filter(|_| ok), end + 5);
+        }
+    }
+    let placeholder = match PLACEHOLDERS.iter().find(|&p| s.starts_with(p)) {
+        Some(p) => *p,
+        None => return (None, 1),
+    };
+    let (subject, body) = split_message(commit.msg());
+
+    let expansion = match placeholder {
+        "%%" => "%".to_owned(),
+        "%n" => "\n".to_owned(),
+        "%H" => commit.hash().to_string(),
+        "%h" => commit.hash().to_string()[..ABBREV_LENGTH].to_owned(),
+        "%an" => commit.author().to_owned(),
+        "%aN" => mailmap.map(commit.author()),
+        "%ai" => ui::format_time(time, DateFormat::Iso),
+        "%ar" => ui::format_time(time, DateFormat::Relative),
+        "%at" => ui::format_time(time, DateFormat::Unix),
+        "%ad" => ui::format_time(time, date),
+        "%s" => subject.to_owned(),
+        "%b" => body.to_owned(),
+        "%d" if decorations.is_empty() => String::new(),
+        "%d" => format!(" ({})", decorations.join(", ")),
+        "%D" => decorations.join(", "),
+        _ => unreachable!(),
+    };
+    (Some(expansion), placeholder.len())
+}
+
+/// Splits a commit message into its subject and body.
+fn split_message(msg: &str) -> (&str, &str) {
+    match msg.split_once('\n') {
+        Some((subject, body)) => (subject, body.trim_start_matches('\n')),
+        None => (msg, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::object::{CommitInfo, Hash};
+    use chrono::{TimeZone, Utc};
+
+    fn commit() -> Commit {
+        Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![],
+            author: "G7".to_owned(),
+            time: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            msg: "subject\n\nbody".to_owned(),
+        })
+    }
+
+    #[test]
+    fn format_fields() {
+        let c = commit();
+        let none = Mailmap::default();
+        let hash = c.hash().to_string();
+        let decorations = vec!["main".to_owned(), "feature".to_owned()];
+
+        assert_eq!(
+            format_commit("%h %an %s", &c, &[], DateFormat::Rfc2822, &none),
+            format!("{} G7 subject", &hash[..7])
+        );
+        assert_eq!(
+            format_commit("%H%n%b", &c, &[], DateFormat::Rfc2822, &none),
+            format!("{}\nbody", hash)
+        );
+        assert_eq!(
+            format_commit("%at", &c, &[], DateFormat::Rfc2822, &none),
+            "1600000000"
+        );
+        assert_eq!(
+            format_commit("%ad{%Y-%m-%d}", &c, &[], DateFormat::Rfc2822, &none),
+            "2020-09-13"
+        );
+        assert_eq!(
+            format_commit("%s%d", &c, &decorations, DateFormat::Rfc2822, &none),
+            "subject (main, feature)"
+        );
+        assert_eq!(format_commit("[%D]", &c, &[], DateFormat::Rfc2822, &none), "[]");
+    }
+
+    #[test]
+    fn format_mailmap_author() {
+        let c = Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![],
+            author: "g7 <old@example.com>".to_owned(),
+            time: Utc.timestamp_opt(1_600_000_000, 0).unwrap(),
+            msg: "subject".to_owned(),
+        });
+        let mailmap = Mailmap::parse("G7 <g7@example.com> <old@example.com>");
+        assert_eq!(
+            format_commit("%an, %aN", &c, &[], DateFormat::Rfc2822, &mailmap),
+            "g7 <old@example.com>, G7 <g7@example.com>"
+        );
+    }
+
+    #[test]
+    fn format_refs() {
+        let c = commit();
+        let none = Mailmap::default();
+        assert_eq!(
+            format_ref("%h %(refname) %s", "refs/heads/main", &c, DateFormat::Unix, &none),
+            format!("{} refs/heads/main subject", &c.hash().to_string()[..7])
+        );
+        assert_eq!(
+            format_ref("%(refname:short)|%at", "refs/tags/v1", &c, DateFormat::Unix, &none),
+            "v1|1600000000"
+        );
+        assert_eq!(
+            format_ref("%%(refname) %(other)", "refs/heads/a", &c, DateFormat::Unix, &none),
+            "%(refname) %(other)"
+        );
+    }
+
+    #[test]
+    fn sort_refs() {
+        let old = commit();
+        let new = Commit::new(CommitInfo {
+            tree: Hash::new(),
+            parents: vec![],
+            author: "G7".to_owned(),
+            time: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+            msg: "another".to_owned(),
+        });
+        let (a, b) = (("refs/heads/a", &new), ("refs/heads/b", &old));
+        let sort = |s: &str| s.parse::<RefSort>().unwrap();
+        assert_eq!(sort("refname").compare(a, b), Ordering::Less);
+        assert_eq!(sort("-refname").compare(a, b), Ordering::Greater);
+        assert_eq!(sort("authordate").compare(a, b), Ordering::Greater);
+        assert_eq!(sort("subject").compare(a, b), Ordering::Less);
+        assert!("size".parse::<RefSort>().is_err());
+    }
+
+    #[test]
+    fn format_literals() {
+        let c = commit();
+        let none = Mailmap::default();
+        assert_eq!(
+            format_commit("100%% %x %", &c, &[], DateFormat::Rfc2822, &none),
+            "100% %x %"
+        );
+        assert_eq!(
+            format_commit("%ad{unterminated", &c, &[], DateFormat::Rfc2822, &none),
+            format!("{}{{unterminated", c.time().to_rfc2822())
+        );
+    }
+}
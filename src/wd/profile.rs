@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+/// What a span of the `tracing` feature spends its time on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Category {
+    ObjectReads,
+    Hashing,
+    Diffing,
+    Network,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::ObjectReads,
+        Category::Hashing,
+        Category::Diffing,
+        Category::Network,
+    ];
+
+    /// Returns the category of a span by its name, or None for spans whose
+    /// time is left to the spans around them.
+    pub fn of_span(name: &str) -> Option<Category> {
+        match name {
+            "read_object" | "checkout_blob" => Some(Category::ObjectReads),
+            "hash" => Some(Category::Hashing),
+            "diff" | "diff_paths" | "diff_worktree" | "status" | "diff_lines" => Some(Category::Diffing),
+            "clone" | "fetch" | "pull" | "push" | "transfer_objects" => Some(Category::Network),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::ObjectReads => "object reads",
+            Category::Hashing => "hashing",
+            Category::Diffing => "diffing",
+            Category::Network => "network",
+        }
+    }
+}
+
+/// The time spent in each category, over all threads.
+#[derive(Debug, Default)]
+pub struct Profile {
+    times: [Duration; 4],
+    calls: [usize; 4],
+}
+
+impl Profile {
+    /// Returns the time spent in a category and the number of its spans.
+    pub fn get(&self, category: Category) -> (Duration, usize) {
+        (self.times[category as usize], self.calls[category as usize])
+    }
+}
+
+/// The spans a thread is in, innermost last. Time is counted once, for the
+/// innermost span, so that reading objects during a pull is not also
+/// network time.
+#[derive(Debug, Default)]
+pub struct SpanStack(Vec<(Category, Instant)>);
+
+impl SpanStack {
+    pub fn enter(&mut self, profile: &mut Profile, category: Category, now: Instant) {
+        if let Some((outer, start)) = self.0.last() {
+            profile.times[*outer as usize] += now - *start;
+        }
+        self.0.push((category, now));
+    }
+
+    pub fn exit(&mut self, profile: &mut Profile, now: Instant) {
+        if let Some((category, start)) = self.0.pop() {
+            profile.times[category as usize] += now - start;
+            profile.calls[category as usize] += 1;
+        }
+        if let Some((_, start)) = self.0.last_mut() {
+            *start = now;
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+thread_local! {
+    /// The spans of the profile that the current thread is in.
+    static SPANS: std::cell::RefCell<SpanStack> = std::cell::RefCell::default();
+}
+
+/// Collects the spans of the `tracing` feature into a shared profile, with
+/// the nesting of spans followed separately for each thread.
+#[cfg(feature = "tracing")]
+pub struct ProfileLayer(pub std::sync::Arc<std::sync::Mutex<Profile>>);
+
+#[cfg(feature = "tracing")]
+impl<S> tracing_subscriber::Layer<S> for ProfileLayer
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if let Some(category) = ctx.metadata(id).and_then(|m| Category::of_span(m.name())) {
+            let mut profile = self.0.lock().unwrap();
+            SPANS.with(|s| s.borrow_mut().enter(&mut profile, category, Instant::now()));
+        }
+    }
+
+    fn on_exit(&self, id: &tracing::span::Id, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        if ctx.metadata(id).and_then(|m| Category::of_span(m.name())).is_some() {
+            let mut profile = self.0.lock().unwrap();
+            SPANS.with(|s| s.borrow_mut().exit(&mut profile, Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn innermost_span_gets_the_time() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut profile = Profile::default();
+        let mut spans = SpanStack::default();
+        spans.enter(&mut profile, Category::Network, at(0));
+        spans.enter(&mut profile, Category::ObjectReads, at(10));
+        spans.enter(&mut profile, Category::Hashing, at(12));
+        spans.exit(&mut profile, at(15));
+        spans.exit(&mut profile, at(20));
+        spans.enter(&mut profile, Category::ObjectReads, at(25));
+        spans.exit(&mut profile, at(30));
+        spans.exit(&mut profile, at(40));
+
+        let ms = Duration::from_millis;
+        assert_eq!(profile.get(Category::Network), (ms(25), 1));
+        assert_eq!(profile.get(Category::ObjectReads), (ms(12), 2));
+        assert_eq!(profile.get(Category::Hashing), (ms(3), 1));
+        assert_eq!(profile.get(Category::Diffing), (ms(0), 0));
+        assert_eq!(Category::of_span("read_object"), Some(Category::ObjectReads));
+        assert_eq!(Category::of_span("checkout"), None);
+    }
+    #[test]
+    fn threads_nest_spans_separately() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut profile = Profile::default();
+        let (mut main, mut worker) = (SpanStack::default(), SpanStack::default());
+        main.enter(&mut profile, Category::Network, at(0));
+        worker.enter(&mut profile, Category::ObjectReads, at(5));
+        worker.exit(&mut profile, at(10));
+        main.exit(&mut profile, at(20));
+
+        let ms = Duration::from_millis;
+        assert_eq!(profile.get(Category::Network), (ms(20), 1));
+        assert_eq!(profile.get(Category::ObjectReads), (ms(5), 1));
+    }
+}
@@ -1,136 +1,824 @@
-use self::Error::*;
-use crate::repo::object::{Change,Commit};
-use crate::repo::repository::{FileStatus, Reference, Repository, Status};
-use similar::TextDiff;
-use std::error;
-use std::fmt;
+use crate::repo::attributes::Attributes;
+use crate::repo::ignore::IgnorePattern;
+use crate::repo::object::{Change, Commit, Hash, Tree, TreeEntryKind};
+use crate::repo::mailmap::Mailmap;
+use crate::repo::maintenance::MaintenanceReport;
+use crate::repo::owners::OwnerViolation;
+use crate::repo::refs;
+use crate::repo::repository::{
+    CheckoutSummary, CommitSummary, Divergence, FileStatus, MergeStrategy, PullReport, PushReport,
+    RefUpdate, Reference, Repository, Status,
+};
+use crate::repo::state::RepositoryState;
+use crate::repo::stats::{HistoryBlob, ObjectInfo, RepoStats};
+use crate::repo::tracklist::TracklistReport;
+use crate::storage::transport;
+use crate::wd::format::ABBREV_LENGTH;
+use crate::wd::profile::{Category, Profile};
+use chrono::{DateTime, Utc};
+use similar::udiff::UnifiedDiffHunk;
+use similar::{Algorithm, ChangeTag, DiffOp, DiffTag, TextDiff};
 use std::io;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::result;
+use std::time::Duration;
+use std::str;
 
-pub type Result<T> = result::Result<T, Error>;
-
-#[derive(Debug)]
-pub enum Error {
-    BranchExists,
-    CheckoutFailed,
-    DirtyWorktree,
-    FileNotFound,
-    IoError(io::Error),
-    MergeFailed(Vec<PathBuf>),
-    NoRepository,
-    NothingToMerge,
-    ObjectCorrupted,
-    ObjectMissing,
-    ObjectNotFound,
-    PushFailed,
-    ReferenceNotFound,
-    RevisionNotFound,
-    RepositoryExists,
-}
-
-impl error::Error for Error {}
-
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            BranchExists => write!(f, "branch already exists"),
-            CheckoutFailed => write!(f, "commit or remove changes first"),
-            DirtyWorktree => write!(f, "dirty work tree"),
-            FileNotFound => write!(f, "file not found"),
-            IoError(error) => write!(f, "IO error: {}", error),
-            MergeFailed(_) => write!(f, "merge failed"),
-            NoRepository => write!(f, "no repository at file path"),
-            NothingToMerge => write!(f, "nothing to merge"),
-            ObjectCorrupted => write!(f, "corrupted object"),
-            ObjectMissing => write!(f, "missing object"),
-            ObjectNotFound => write!(f, "object not found"),
-            PushFailed => write!(f, "local and remote repositories differ, pull first"),
-            ReferenceNotFound => write!(f, "reference not found"),
-            RevisionNotFound => write!(f, "revision not found"),
-            RepositoryExists => write!(
-                f,
-                "local repository by the same name already exists, delete it first"
-            ),
+// the errors are shared by all modules, and still reachable from here
+pub use crate::error::{Error, Result};
+
+/// How dates are shown.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DateFormat {
+    /// RFC 2822, e.g. `Tue, 1 Jul 2003 10:52:37 +0000`.
+    Rfc2822,
+    /// ISO 8601, e.g. `2003-07-01 10:52:37 +0000`.
+    Iso,
+    /// Time since the date, e.g. `3 days ago`.
+    Relative,
+    /// Seconds since the Unix epoch.
+    Unix,
+}
+
+impl str::FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<DateFormat, String> {
+        match s {
+            "rfc" | "rfc2822" | "default" => Ok(DateFormat::Rfc2822),
+            "iso" => Ok(DateFormat::Iso),
+            "relative" => Ok(DateFormat::Relative),
+            "unix" => Ok(DateFormat::Unix),
+            _ => Err(format!("unknown date format '{}'", s)),
         }
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Error {
-        IoError(err)
-    }
+/// The algorithm used to compute line diffs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiffAlgorithm {
+    Myers,
+    /// Matches lines that occur once on both sides first, which keeps moved
+    /// blocks of code together. Also read from `histogram`, git's
+    /// refinement of it, so that git settings keep working.
+    Patience,
 }
 
-impl From<walkdir::Error> for Error {
-    fn from(err: walkdir::Error) -> Error {
-        IoError(err.into())
+impl str::FromStr for DiffAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> result::Result<DiffAlgorithm, String> {
+        match s {
+            "myers" | "default" => Ok(DiffAlgorithm::Myers),
+            "patience" | "histogram" => Ok(DiffAlgorithm::Patience),
+            _ => Err(format!("unknown diff algorithm '{}'", s)),
+        }
     }
 }
-pub fn print_commit(l:Commit, r:&Repository){
-    println!("\x1b[96mcommit {}\x1b[0m", l.hash());
-    // if !r.head_hash().is_err(){
-    //     println!("HEAD: {} ", r.head_hash().unwrap());
-    // }else{
-    //     println!("HEAD: detached");
-    // }
 
-    for i in (*(*r).branches()).keys(){
-        if (*(*r).branches())[i]==l.hash() {
-            println!("Branch: {} ", i);
+impl From<DiffAlgorithm> for Algorithm {
+    fn from(algorithm: DiffAlgorithm) -> Algorithm {
+        match algorithm {
+            DiffAlgorithm::Myers => Algorithm::Myers,
+            DiffAlgorithm::Patience => Algorithm::Patience,
         }
     }
-    println!("Author: {}", l.author());
-    println!("Time: {}", l.time().to_rfc2822());
+}
+
+/// Formats a time for display.
+pub fn format_time(time: DateTime<Utc>, format: DateFormat) -> String {
+    match format {
+        DateFormat::Rfc2822 => time.to_rfc2822(),
+        DateFormat::Iso => time.format("%Y-%m-%d %H:%M:%S %z").to_string(),
+        DateFormat::Relative => format_relative_time(time, Utc::now()),
+        DateFormat::Unix => time.timestamp().to_string(),
+    }
+}
+
+/// Formats the time between a time and now, e.g. `3 days ago`.
+fn format_relative_time(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - time).num_seconds();
+    if secs < 0 {
+        return "in the future".to_owned();
+    }
+    let units = [
+        (60, 1, "second"),
+        (60 * 60, 60, "minute"),
+        (60 * 60 * 24, 60 * 60, "hour"),
+        (60 * 60 * 24 * 7, 60 * 60 * 24, "day"),
+        (60 * 60 * 24 * 30, 60 * 60 * 24 * 7, "week"),
+        (60 * 60 * 24 * 365, 60 * 60 * 24 * 30, "month"),
+        (i64::MAX, 60 * 60 * 24 * 365, "year"),
+    ];
+    let (_, size, unit) = units.iter().find(|(limit, _, _)| secs < *limit).unwrap();
+    match secs / size {
+        1 => format!("1 {} ago", unit),
+        n => format!("{} {}s ago", n, unit),
+    }
+}
+
+/// Outputs a commit with the names of the references pointing to it and
+/// its author mapped by the mailmap.
+pub fn print_commit(l:Commit, decorations: &[String], date: DateFormat, mailmap: &Mailmap){
+    if decorations.is_empty() {
+        println!("\x1b[96mcommit {}\x1b[0m", l.hash());
+    } else {
+        println!("\x1b[96mcommit {}\x1b[0m ({})", l.hash(), decorations.join(", "));
+    }
+    println!("Author: {}", mailmap.map(l.author()));
+    println!("Time: {}", format_time(l.time(), date));
     println!("Summary:\n{}", l.msg());
 }
 
-pub fn print_status(status: &Status) {
+/// Outputs the status of changed files, with paths relative to base, and
+/// a count of each kind of change. Both are relative to the working tree.
+pub fn print_status(status: &Status, base: &Path) {
     for (path, fstatus) in status {
         match fstatus {
             FileStatus::Unmodified => (),
-            _ => println!("{} {}", fstatus.code(), path.display()),
+            _ => println!("{} {}", fstatus.code(), relative_path(path, base).display()),
+        }
+    }
+    println!("{}", format_status_summary(status));
+}
+
+/// Outputs the merge or rebase in progress, with the files left to resolve
+/// and how to go on, followed by a blank line. Outputs nothing otherwise.
+/// Prints how the current branch compares to its remote-tracking branch on
+/// a remote, and whether it needs a push or a pull.
+pub fn print_divergence(remote: &str, divergence: Option<&Divergence>) {
+    let commits = |n: usize| format!("{} {}", n, if n == 1 { "commit" } else { "commits" });
+    let divergence = match divergence {
+        Some(divergence) => divergence,
+        None => return println!("The current branch is not on remote '{}'.", remote),
+    };
+    let tracking = &divergence.tracking;
+    match (divergence.ahead, divergence.behind) {
+        (0, 0) => println!("Your branch is up to date with '{}'.", tracking),
+        (ahead, 0) => println!(
+            "Your branch is ahead of '{}' by {}: push to publish them.",
+            tracking,
+            commits(ahead)
+        ),
+        (0, behind) => println!(
+            "Your branch is behind '{}' by {}: pull to fast-forward.",
+            tracking,
+            commits(behind)
+        ),
+        (ahead, behind) => println!(
+            "Your branch and '{}' have diverged, with {} and {} different: pull to merge them.",
+            tracking,
+            commits(ahead),
+            commits(behind)
+        ),
+    }
+}
+
+/// Prints the branch line of `status --porcelain --remote`, e.g.
+/// `## main...origin/main [ahead 1, behind 2]`.
+pub fn print_porcelain_divergence(head: &Reference, divergence: Option<&Divergence>) {
+    let branch = match head {
+        Reference::Branch(branch) => branch.as_str(),
+        Reference::Hash(_) => "HEAD (no branch)",
+    };
+    let divergence = match divergence {
+        Some(divergence) => divergence,
+        None => return println!("## {}", branch),
+    };
+    let mut counts = vec![];
+    if divergence.ahead > 0 {
+        counts.push(format!("ahead {}", divergence.ahead));
+    }
+    if divergence.behind > 0 {
+        counts.push(format!("behind {}", divergence.behind));
+    }
+    match counts.is_empty() {
+        true => println!("## {}...{}", branch, divergence.tracking),
+        false => println!("## {}...{} [{}]", branch, divergence.tracking, counts.join(", ")),
+    }
+}
+
+pub fn print_state(state: &RepositoryState, base: &Path) {
+    let short = |hash: &Hash| hash.to_string()[..ABBREV_LENGTH].to_owned();
+    let (unresolved, next) = match state {
+        RepositoryState::Clean => return,
+        RepositoryState::Merging { theirs, unresolved } => {
+            println!("You are in the middle of merging {}.", short(theirs));
+            let next = match unresolved.is_empty() {
+                true => "All conflicts fixed: commit the result to conclude the merge.",
+                false => "Fix conflicts and commit the result.",
+            };
+            (unresolved, next)
+        }
+        RepositoryState::Rebasing {
+            onto,
+            stopped,
+            remaining,
+            unresolved,
+        } => {
+            let stopped = match stopped {
+                Some(commit) => format!(", stopped at {}", short(commit)),
+                None => String::new(),
+            };
+            println!(
+                "You are in the middle of rebasing onto {}{} with {} {} left.",
+                short(onto),
+                stopped,
+                remaining,
+                if *remaining == 1 { "commit" } else { "commits" }
+            );
+            let next = match unresolved.is_empty() {
+                true => "All conflicts fixed: run 'gnew rebase --continue'.",
+                false => "Fix conflicts and run 'gnew rebase --continue'.",
+            };
+            (unresolved, next)
+        }
+    };
+    if !unresolved.is_empty() {
+        println!("Unresolved conflicts:");
+        for path in unresolved {
+            println!("  {}", relative_path(path, base).display());
         }
     }
+    println!("{}", next);
+    if let RepositoryState::Rebasing { .. } = state {
+        println!("Use 'gnew rebase --abort' to restore the branch.");
+    }
+    println!();
 }
 
-pub fn print_heads(r: &Repository) {
-    let mut branches: Vec<_> = r.branches().keys().collect();
-    branches.sort();
+/// Formats the number of files with each kind of change, e.g.
+/// `2 modified, 1 untracked`, or a message for a clean working tree.
+pub fn format_status_summary(status: &Status) -> String {
+    let kinds = [
+        (FileStatus::Added, "added"),
+        (FileStatus::Modified, "modified"),
+        (FileStatus::ModeChanged, "mode changed"),
+        (FileStatus::Deleted, "deleted"),
+        (FileStatus::Missing, "missing"),
+        (FileStatus::Untracked, "untracked"),
+    ];
+    let counts: Vec<_> = kinds
+        .iter()
+        .map(|(kind, name)| (status.values().filter(|s| *s == kind).count(), name))
+        .filter(|(n, _)| *n > 0)
+        .map(|(n, name)| format!("{} {}", n, name))
+        .collect();
+    if counts.is_empty() {
+        return "nothing to commit, working tree clean".to_owned();
+    }
+    counts.join(", ")
+}
 
-    for branch in branches {
+/// Outputs a path matched by an ignore pattern. Paths re-included by a
+/// negated pattern are only shown with the pattern.
+/// Outputs a path and its owners, separated by spaces, or `(unowned)`.
+pub fn print_owners(path: &Path, owners: &[String]) {
+    if owners.is_empty() {
+        println!("{}: (unowned)", path.display())
+    } else {
+        println!("{}: {}", path.display(), owners.join(" "))
+    }
+}
+
+pub fn print_owner_violations(violations: &[OwnerViolation]) {
+    for v in violations {
+        eprintln!("{}: {} does not own {}", v.commit, v.author, v.path.display());
+    }
+}
+
+pub fn print_ignore_match(path: &Path, pattern: &IgnorePattern, verbose: bool) {
+    if verbose {
+        println!(
+            "{}:{}:{}\t{}",
+            pattern.source.display(),
+            pattern.line,
+            pattern.text,
+            path.display()
+        )
+    } else if !pattern.is_negated() {
+        println!("{}", path.display())
+    }
+}
+
+/// Outputs the status in the stable format of `status --porcelain`:
+/// one `<code> <path>` line per changed file in path order, with paths
+/// relative to the root of the working tree.
+pub fn print_porcelain_status(status: &Status) {
+    print!("{}", format_porcelain_status(status))
+}
+
+/// Formats a status with a line `<code> <path>` per changed file.
+pub fn format_porcelain_status(status: &Status) -> String {
+    let mut status: Vec<_> = status.iter().collect();
+    status.sort_by_key(|(path, _)| *path);
+
+    let mut out = String::new();
+    for (path, fstatus) in status {
+        if *fstatus != FileStatus::Unmodified {
+            out += &format!("{} {}\n", fstatus.porcelain_code(), path.display());
+        }
+    }
+    out
+}
+
+/// Returns a path relative to a base directory.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path = path.components().peekable();
+    let mut base = base.components().peekable();
+    while path.peek().is_some() && path.peek() == base.peek() {
+        path.next();
+        base.next();
+    }
+    base.map(|_| Component::ParentDir).chain(path).collect()
+}
+
+/// Outputs the files recorded by a commit and a count of the changes.
+pub fn print_commit_summary(summary: &CommitSummary) {
+    let groups = [
+        (FileStatus::Added, &summary.added),
+        (FileStatus::Modified, &summary.modified),
+        (FileStatus::Deleted, &summary.deleted),
+    ];
+    for (fstatus, paths) in groups {
+        for path in paths {
+            println!("{} {}", fstatus.code(), path.display());
+        }
+    }
+    println!(
+        "{} added, {} modified, {} deleted",
+        summary.added.len(),
+        summary.modified.len(),
+        summary.deleted.len()
+    );
+}
+
+/// Outputs how many files a checkout updated, created and deleted, if any.
+pub fn print_checkout_summary(summary: &CheckoutSummary) {
+    if !summary.is_empty() {
+        println!(
+            "{} updated, {} created, {} deleted",
+            summary.updated.len(),
+            summary.created.len(),
+            summary.deleted.len()
+        );
+    }
+}
+
+/// Shows the progress of an operation on standard error as a line that is
+/// rewritten whenever the percentage changes, and ends once all is done.
+pub fn print_progress(title: &str, done: usize, total: usize) {
+    let percent = |n: usize| n * 100 / total.max(1);
+    if done > 0 && done < total && percent(done) == percent(done - 1) {
+        return;
+    }
+    eprint!("\r{}: {:3}% ({}/{})", title, percent(done), done, total);
+    if done >= total {
+        eprintln!(", done.");
+    }
+}
+
+/// Outputs the branches with the date of their last commit.
+/// Lists the entries of a tree as `<type> <hash>\t<name>`, like `cat-object
+/// tree`. With `recursive`, the entries of each subtree follow it, named by
+/// their path in the tree.
+pub fn print_tree(tree: &Tree, recursive: bool) -> Result<()> {
+    print_tree_entries(tree, "", recursive, &mut vec![tree.hash()])
+}
+
+fn print_tree_entries(
+    tree: &Tree,
+    prefix: &str,
+    recursive: bool,
+    trees: &mut Vec<Hash>,
+) -> Result<()> {
+    for entry in tree.entries() {
+        let path = format!("{}{}", prefix, entry.name());
+        println!("{} {}\t{}", entry.type_name(), entry.hash(), path);
+        if !recursive || entry.kind() != TreeEntryKind::Tree {
+            continue;
+        }
+        if trees.contains(&entry.hash()) {
+            return Err(Error::TreeCycle(entry.hash()));
+        }
+        trees.push(entry.hash());
+        let subtree = transport::read_tree(entry.hash())?;
+        print_tree_entries(&subtree, &format!("{}/", path), true, trees)?;
+        trees.pop();
+    }
+    Ok(())
+}
+
+pub fn print_heads(r: &Repository, date: DateFormat) -> Result<()> {
+    for (branch, hash) in r.references().namespace(refs::HEADS) {
         let current = match r.head() {
             Reference::Branch(b) if b == branch => "*",
             _ => " ",
         };
-        println!("{} {}", current, branch)
+        let time = transport::read_commit(hash)?.time();
+        println!("{} {} ({})", current, branch, format_time(time, date))
     }
+    Ok(())
 }
 
-/// Outputs the changes as a unified diff.
-pub fn print_diff(changes: &[Change]) -> Result<()> {
-    changes.iter().try_for_each(print_file_diff)
+pub fn print_branches(branches: &[&str], current: Option<&str>) {
+    for &branch in branches {
+        let marker = if Some(branch) == current { "*" } else { " " };
+        println!("{} {}", marker, branch)
+    }
+}
+
+/// Prints the result of a push for each branch.
+pub fn print_ref_updates(updates: &[RefUpdate]) {
+    for update in updates {
+        let (flag, summary) = match update.old {
+            None => ('*', "[new branch]".to_owned()),
+            Some(old) if old == update.new => ('=', "[up to date]".to_owned()),
+            Some(old) => {
+                let (old, new) = (old.to_string(), update.new.to_string());
+                (' ', format!("{}..{}", &old[..ABBREV_LENGTH], &new[..ABBREV_LENGTH]))
+            }
+        };
+        println!(" {} {:<17} {}", flag, summary, update.name)
+    }
 }
 
-fn print_file_diff(change: &Change) -> Result<()> {
+/// Outputs the number of objects a pull received, the branches it moved and
+/// how the current branch was updated.
+pub fn print_pull_report(report: &PullReport) {
+    if report.strategy == MergeStrategy::AlreadyUpToDate {
+        println!("Already up to date.");
+        return;
+    }
+    println!("Received {} objects", report.objects);
+    print_ref_updates(&report.updates);
+    match report.strategy {
+        MergeStrategy::FastForward => println!("Fast-forward"),
+        _ => println!("Merge made by the three-way strategy."),
+    }
+}
+
+/// Outputs the number of objects a push sent and the remote branches,
+/// and its warnings on standard error.
+pub fn print_push_report(report: &PushReport) {
+    println!("Sent {} objects", report.objects);
+    print_ref_updates(&report.updates);
+    if let Some(notes) = &report.notes {
+        println!("Recorded the push in {}", notes);
+    }
+    for warning in &report.warnings {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+/// Outputs blobs as `<hash> <size> <commit> <path>`, with abbreviated hashes.
+pub fn print_history_blobs(blobs: &[HistoryBlob]) {
+    for b in blobs {
+        let (hash, commit) = (b.hash.to_string(), b.commit.to_string());
+        println!("{} {:>10} {} {}", &hash[..ABBREV_LENGTH], b.size, &commit[..ABBREV_LENGTH], b.path);
+    }
+}
+
+/// Outputs the dangling commits, one `dangling commit <HASH>` line each, or
+/// with their dates and subjects if a date format is given.
+pub fn print_dangling_commits(commits: &[Commit], date: Option<DateFormat>) {
+    for c in commits {
+        match date {
+            Some(date) => println!(
+                "{} {} {}",
+                c.hash(),
+                format_time(c.time(), date),
+                c.msg().lines().next().unwrap_or("")
+            ),
+            None => println!("dangling commit {}", c.hash()),
+        }
+    }
+}
+
+pub fn print_maintenance_report(report: &MaintenanceReport) {
+    println!("gc: removed {} unreachable objects", report.pruned_objects);
+    if report.pruned_quarantines > 0 {
+        println!("gc: removed {} abandoned quarantines", report.pruned_quarantines);
+    }
+    println!("pack-refs: packed {} references", report.packed_refs);
+    println!("bitmaps: wrote bitmaps for {} commits", report.bitmaps);
+    println!("status-cache: refreshed");
+}
+
+pub fn print_stats(stats: &RepoStats, verbose: bool) {
+    if !verbose {
+        println!(
+            "{} objects, {} kilobytes",
+            stats.loose_objects,
+            stats.loose_size / 1024
+        );
+        return;
+    }
+    println!("count: {}", stats.loose_objects);
+    println!("size: {}", stats.loose_size / 1024);
+    println!("blobs: {}", stats.blobs);
+    println!("trees: {}", stats.trees);
+    println!("commits: {}", stats.commit_objects);
+    println!("reachable-commits: {}", stats.commits);
+    println!("branches: {}", stats.branches);
+
+    if !stats.largest_blobs.is_empty() {
+        println!("largest-blobs:");
+        for (hash, size) in &stats.largest_blobs {
+            println!("  {} {}", hash, size);
+        }
+    }
+}
+
+/// Outputs to stderr the time spent in each category of a profile, and the
+/// rest of the total time.
+pub fn print_profile(profile: &Profile, total: Duration) {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let mut rest = total;
+    eprintln!("profile:");
+    for category in Category::ALL {
+        let (time, calls) = profile.get(category);
+        rest = rest.saturating_sub(time);
+        eprintln!("  {:<14}{:>10.3} ms{:>8} calls", category.name(), ms(time), calls);
+    }
+    eprintln!("  {:<14}{:>10.3} ms", "other", ms(rest));
+    eprintln!("  {:<14}{:>10.3} ms", "total", ms(total));
+}
+
+/// Outputs the result of verifying the object store. Corrupted objects are
+/// always listed.
+pub fn print_verified_objects(objects: &[ObjectInfo], verbose: bool) {
+    let mut corrupted = 0;
+    for o in objects {
+        let kind = o.kind.as_deref().unwrap_or("unknown");
+        if !o.valid {
+            corrupted += 1;
+            println!("{} {} {} bad checksum", o.hash, kind, o.size);
+        } else if verbose {
+            println!("{} {} {}", o.hash, kind, o.size);
+        }
+    }
+    println!("{} objects, {} corrupted", objects.len(), corrupted);
+}
+
+/// Outputs the objects that failed verification.
+pub fn print_corrupted_objects(objects: &[ObjectInfo]) {
+    for o in objects.iter().filter(|o| !o.valid) {
+        let kind = o.kind.as_deref().unwrap_or("unknown");
+        println!("{} {} {} bad checksum", o.hash, kind, o.size);
+    }
+}
+
+pub fn print_tracklist_report(report: &TracklistReport, repaired: bool, pruned: bool) {
+    for (entry, path) in &report.normalized {
+        println!("not canonical: '{}' -> '{}'", entry, path);
+    }
+    for entry in &report.duplicates {
+        println!("duplicate: '{}'", entry);
+    }
+    for entry in &report.invalid {
+        println!("outside the working tree: '{}'", entry);
+    }
+    for entry in &report.stale {
+        println!("stale: '{}'", entry);
+    }
+    let fixed = report.fixed();
+    match (repaired, pruned) {
+        (false, _) if report.is_empty() => println!("tracklist is ok"),
+        (false, _) => println!("{} problems found, fix them with --repair", fixed + report.stale.len()),
+        (true, false) => println!("{} entries fixed, {} stale", fixed, report.stale.len()),
+        (true, true) => println!("{} entries fixed, {} pruned", fixed, report.stale.len()),
+    }
+}
+
+/// How `print_diff` shows the changed lines.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DiffStyle {
+    /// Show removed lines in red, added lines in green and hunk headers in
+    /// cyan.
+    pub color: bool,
+    /// Show the old and new lines in two columns that fit in this width.
+    pub side_by_side: Option<usize>,
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Outputs the changes as a unified diff. Binary files are only reported as
+/// changed.
+pub fn print_diff(
+    changes: &[Change],
+    attributes: &Attributes,
+    algorithm: DiffAlgorithm,
+    style: DiffStyle,
+) -> Result<()> {
+    changes
+        .iter()
+        .try_for_each(|c| print_file_diff(c, attributes, algorithm, style))
+}
+
+fn print_file_diff(
+    change: &Change,
+    attributes: &Attributes,
+    algorithm: DiffAlgorithm,
+    style: DiffStyle,
+) -> Result<()> {
     let (old, new) = change.contents()?;
     let (a, b) = diff_header(change);
+    let (bold, reset) = if style.color { (BOLD, RESET) } else { ("", "") };
+
+    if let Some((old_mode, new_mode)) = change.mode_change() {
+        println!("{}old mode {}{}", bold, file_mode(old_mode), reset);
+        println!("{}new mode {}{}", bold, file_mode(new_mode), reset);
+        if old == new {
+            println!("{}--- {}{}", bold, a.display(), reset);
+            println!("{}+++ {}{}", bold, b.display(), reset);
+            return Ok(());
+        }
+    }
+    if attributes.is_binary_diff(change.path(), &old, &new) {
+        println!("Binary files {} and {} differ", a.display(), b.display());
+        return Ok(());
+    }
+
+    let diff = {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("diff_lines").entered();
+        TextDiff::configure()
+            .algorithm(algorithm.into())
+            .diff_lines(&old, &new)
+    };
+    let (a, b) = (a.to_string_lossy(), b.to_string_lossy());
+    let mut unified = diff.unified_diff();
+
+    if !style.color && style.side_by_side.is_none() {
+        return Ok(unified.header(&a, &b).to_writer(io::stdout())?);
+    }
+    if unified.iter_hunks().next().is_none() {
+        return Ok(());
+    }
+    println!("{}--- {}{}", bold, a, reset);
+    println!("{}+++ {}{}", bold, b, reset);
+
+    for hunk in unified.iter_hunks() {
+        match style.color {
+            true => println!("{}{}{}", CYAN, hunk.header(), RESET),
+            false => println!("{}", hunk.header()),
+        }
+        match style.side_by_side {
+            Some(width) => print_side_by_side(&diff, hunk.ops(), width, style.color),
+            None => print_colored_hunk(&hunk),
+        }
+    }
+    Ok(())
+}
 
-    Ok(TextDiff::from_lines(&old, &new)
-        .unified_diff()
-        .header(&a.to_string_lossy(), &b.to_string_lossy())
-        .to_writer(io::stdout())?)
+/// Outputs the lines of a hunk with `-` and `+` lines colored.
+fn print_colored_hunk<'a>(hunk: &UnifiedDiffHunk<'_, 'a, 'a, '_, [u8]>) {
+    for change in hunk.iter_changes() {
+        let (sign, color, reset) = match change.tag() {
+            ChangeTag::Equal => (' ', "", ""),
+            ChangeTag::Delete => ('-', RED, RESET),
+            ChangeTag::Insert => ('+', GREEN, RESET),
+        };
+        let line = String::from_utf8_lossy(change.value());
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        println!("{}{}{}{}", color, sign, line, reset);
+        if change.missing_newline() {
+            println!("\\ No newline at end of file");
+        }
+    }
+}
+
+/// Outputs the lines of a hunk in two columns, old on the left and new on
+/// the right, with a marker between them: `<` removed, `>` added and `|`
+/// changed.
+fn print_side_by_side(diff: &TextDiff<[u8]>, ops: &[DiffOp], width: usize, color: bool) {
+    let column = width.saturating_sub(3) / 2;
+    let (old, new) = (diff.old_slices(), diff.new_slices());
+    let cell = |line: Option<&[u8]>, color_code: &str| {
+        let text: String = String::from_utf8_lossy(line.unwrap_or_default())
+            .trim_end_matches(['\n', '\r'])
+            .replace('\t', "    ")
+            .chars()
+            .take(column)
+            .collect();
+        let padded = format!("{:<1$}", text, column);
+        match (color, line) {
+            (true, Some(_)) if !color_code.is_empty() => {
+                format!("{}{}{}", color_code, padded, RESET)
+            }
+            _ => padded,
+        }
+    };
+
+    for op in ops {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let rows = old_range.len().max(new_range.len());
+        for i in 0..rows {
+            let l = old.get(old_range.start + i).filter(|_| i < old_range.len());
+            let r = new.get(new_range.start + i).filter(|_| i < new_range.len());
+            let marker = match (tag, l, r) {
+                (DiffTag::Equal, ..) => ' ',
+                (_, Some(_), Some(_)) => '|',
+                (_, Some(_), None) => '<',
+                _ => '>',
+            };
+            let right = cell(r.copied(), GREEN);
+            println!("{} {} {}", cell(l.copied(), RED), marker, right.trim_end());
+        }
+    }
+}
+
+/// Returns the mode of a regular file as shown in diff headers.
+fn file_mode(executable: bool) -> &'static str {
+    match executable {
+        true => "100755",
+        false => "100644",
+    }
 }
 
 fn diff_header(change: &Change) -> (PathBuf, PathBuf) {
     let (a, b) = match change {
         Change::Add(_) => (None, Some("b")),
         Change::Remove(_) => (Some("a"), None),
-        Change::Modify(..) => (Some("a"), Some("b")),
+        Change::Modify(..) | Change::Rename(..) => (Some("a"), Some("b")),
+        Change::TypeChange(old, new) => (
+            Some("a").filter(|_| !old.is_dir()),
+            Some("b").filter(|_| !new.is_dir()),
+        ),
     };
-    let header_path = |f| match f {
+    let header_path = |f, path| match f {
         None => PathBuf::from("/dev/null"),
-        Some(p) => Path::new(p).join(change.path()),
+        Some(p) => Path::new(p).join(path),
+    };
+    let old_path = match change {
+        Change::Rename(old, _) => old.path(),
+        _ => change.path(),
     };
-    (header_path(a), header_path(b))
+    (header_path(a, old_path), header_path(b, change.path()))
+}
+
+/// Outputs the paths of the changed files in path order, with the kind of
+/// change if `status` is set. Renames are shown with both paths.
+pub fn print_changed_paths(changes: &[Change], status: bool) {
+    let mut changes: Vec<_> = changes.iter().collect();
+    changes.sort_by_key(|c| c.path());
+
+    for change in changes {
+        match change {
+            Change::Rename(old, new) if status => {
+                println!("R\t{}\t{}", old.path().display(), new.path().display())
+            }
+            _ if status => println!("{}\t{}", change.status(), change.path().display()),
+            _ => println!("{}", change.path().display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::object::parse_time;
+    use chrono::TimeZone;
+    use chrono::Duration;
+
+    #[test]
+    fn relative_time() {
+        let now = Utc::now();
+        let ago = |d| format_relative_time(now - d, now);
+
+        assert_eq!(ago(Duration::seconds(1)), "1 second ago");
+        assert_eq!(ago(Duration::minutes(5)), "5 minutes ago");
+        assert_eq!(ago(Duration::hours(23)), "23 hours ago");
+        assert_eq!(ago(Duration::days(3)), "3 days ago");
+        assert_eq!(ago(Duration::days(14)), "2 weeks ago");
+        assert_eq!(ago(Duration::days(60)), "2 months ago");
+        assert_eq!(ago(Duration::days(800)), "2 years ago");
+        assert_eq!(ago(Duration::seconds(-10)), "in the future");
+    }
+
+    #[test]
+    fn parse_formatted_times() {
+        let time = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        for format in [DateFormat::Rfc2822, DateFormat::Iso, DateFormat::Unix] {
+            assert_eq!(parse_time(&format_time(time, format)).ok(), Some(time));
+        }
+    }
+
+    #[test]
+    fn status_summary() {
+        let mut status = Status::new();
+        status.insert(PathBuf::from("a"), FileStatus::Unmodified);
+        assert_eq!(format_status_summary(&status), "nothing to commit, working tree clean");
+
+        status.insert(PathBuf::from("b"), FileStatus::Modified);
+        status.insert(PathBuf::from("c"), FileStatus::Untracked);
+        status.insert(PathBuf::from("d"), FileStatus::Untracked);
+        status.insert(PathBuf::from("e"), FileStatus::Missing);
+        assert_eq!(format_status_summary(&status), "1 modified, 1 missing, 2 untracked");
+    }
 }
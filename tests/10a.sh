@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	echo "user.name = nick" >>.gnew/config &&
+	echo "user.email = old@laptop" >>.gnew/config &&
+	test_commit first a &&
+	cat >.mailmap <<-\EOF
+	# canonical identities
+	Jane Doe <jane@example.com> <old@laptop>
+	EOF
+'
+
+test_expect_success 'log shows the authors mapped by .mailmap' '
+	gnew log >out &&
+	grep "^Author: Jane Doe <jane@example.com>$" out
+'
+
+test_expect_success 'log --format %aN maps the author, %an does not' '
+	gnew log --format "%an|%aN" >out &&
+	echo "nick <old@laptop>|Jane Doe <jane@example.com>" >expected &&
+	diff expected out
+'
+
+test_expect_success 'filter --mailmap records the mapped authors' '
+	gnew filter --mailmap >out &&
+	grep "Rewrote 1 commits" out &&
+	rm .mailmap &&
+	gnew log --format "%an" >out &&
+	echo "Jane Doe <jane@example.com>" >expected &&
+	diff expected out
+'
+
+test_expect_success 'filter needs --remove-path or --mailmap' '
+	! gnew filter 2>out &&
+	grep "required" out
+'
@@ -0,0 +1,43 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	test_commit first a &&
+	gnew checkout -b feature &&
+	gnew checkout -b fix &&
+	gnew checkout main
+'
+
+test_expect_success 'checkout of a misspelled branch suggests similar names' '
+	! gnew checkout featrue 2>err &&
+	grep "fatal: reference not found: .featrue." err &&
+	grep "hint: did you mean .feature.?" err
+'
+
+test_expect_success 'checkout suggests names one edit away from short names' '
+	! gnew checkout fi 2>err &&
+	grep "hint: did you mean .fix.?" err &&
+	! gnew checkout man 2>err &&
+	grep "hint: did you mean .main.?" err
+'
+
+test_expect_success 'checkout of an unrelated name gives no hint' '
+	! gnew checkout unrelated 2>err &&
+	! grep hint err
+'
+
+test_expect_success 'checkout rejects invalid branch names' '
+	! gnew checkout "bad name" 2>err &&
+	grep "invalid reference name" err
+'
+
+test_expect_success 'suggested tags can be checked out' '
+	mkdir -p .gnew/refs/tags &&
+	main=$(gnew rev-parse main) &&
+	echo $main >.gnew/refs/tags/release-1 &&
+	! gnew checkout release-2 2>err &&
+	grep "hint: did you mean .release-1.?" err &&
+	gnew checkout release-1 >out &&
+	grep "Switched to $main" out
+'
@@ -0,0 +1,59 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpect\n" >.gnew/info/exclude &&
+	test_commit first script &&
+	test_commit other file &&
+	gnew checkout -b plain &&
+	gnew checkout main
+'
+
+test_expect_success 'status shows a file whose executable bit changed' '
+	chmod +x script &&
+	gnew status >out &&
+	grep "^T script$" out &&
+	grep "1 mode changed" out &&
+	gnew status --porcelain >out &&
+	grep "^T script$" out
+'
+
+test_expect_success 'diff shows the old and new modes' '
+	cat >expect <<-\EOF &&
+	old mode 100644
+	new mode 100755
+	--- a/script
+	+++ b/script
+	EOF
+	gnew diff >out &&
+	diff expect out
+'
+
+test_expect_success 'commit records the executable bit' '
+	gnew commit "make executable" >out &&
+	grep "^M script$" out &&
+	gnew status >out &&
+	grep "nothing to commit" out
+'
+
+test_expect_success 'diff shows mode and content changes together' '
+	echo changed >script &&
+	chmod -x script &&
+	gnew diff >out &&
+	grep "^old mode 100755$" out &&
+	grep "^new mode 100644$" out &&
+	grep "^+changed$" out
+'
+
+test_expect_success 'checkout restores the executable bit' '
+	gnew checkout -f main &&
+	test -x script &&
+	gnew checkout plain &&
+	! test -x script &&
+	gnew checkout main &&
+	test -x script &&
+	gnew status >out &&
+	grep "nothing to commit" out
+'
@@ -0,0 +1,35 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir -p .gnew/info &&
+	printf "out\n" >.gnew/info/exclude &&
+	test_commit first a &&
+	test_commit second b &&
+	gnew checkout -b other &&
+	echo changed >a &&
+	mkdir dir &&
+	echo lost >dir/c &&
+	gnew add a dir &&
+	gnew commit other &&
+	gnew checkout main
+'
+
+test_expect_success 'failed checkout leaves the working tree as it was' '
+	echo lost >lost &&
+	rm lost .gnew/objects/$(gnew hash-file lost) &&
+	! gnew checkout other 2>out &&
+	grep "^first$" a &&
+	test ! -e dir &&
+	test ! -e .gnew/checkout-journal &&
+	gnew status >out &&
+	grep "nothing to commit" out
+'
+
+test_expect_success 'checkout refuses to run over an interrupted checkout' '
+	mkdir .gnew/checkout-journal &&
+	! gnew checkout other 2>out &&
+	grep "interrupted" out &&
+	rmdir .gnew/checkout-journal
+'
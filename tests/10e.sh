@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir -p .gnew/info &&
+	printf "out\n" >.gnew/info/exclude &&
+	test_commit first a &&
+	gnew checkout -b other &&
+	test_commit second b &&
+	gnew checkout main
+'
+
+test_expect_success 'checkout keeps untracked files' '
+	echo notes >notes &&
+	gnew checkout other &&
+	test -f notes &&
+	gnew checkout main &&
+	grep "^notes$" notes
+'
+
+test_expect_success 'checkout fails if it would overwrite an untracked file' '
+	echo mine >b &&
+	! gnew checkout other 2>out &&
+	grep "fatal: untracked files would be overwritten: b" out &&
+	grep "^mine$" b &&
+	gnew status >out &&
+	grep "^? b$" out
+'
+
+test_expect_success 'checkout replaces an untracked file with the same contents' '
+	echo second >b &&
+	gnew checkout other &&
+	grep "^second$" b &&
+	gnew checkout main &&
+	test ! -e b
+'
+
+test_expect_success 'forced checkout removes untracked files' '
+	echo mine >b &&
+	gnew checkout -f other &&
+	grep "^second$" b &&
+	test ! -e notes
+'
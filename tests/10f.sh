@@ -0,0 +1,43 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'checkout - fails without a previous checkout' '
+	test_commit first a &&
+	! gnew checkout - 2>err &&
+	grep "fatal: no previous branch or commit was checked out" err
+'
+
+test_expect_success 'checkout - returns to the previous branch' '
+	gnew checkout -b other &&
+	test_commit second a &&
+	gnew checkout main &&
+	gnew checkout - >out &&
+	grep "Switched to branch .other." out &&
+	grep "^second$" a &&
+	gnew checkout - >out &&
+	grep "Switched to branch .main." out
+'
+
+test_expect_success 'switch - is the same as checkout -' '
+	gnew switch - >out &&
+	grep "Switched to branch .other." out &&
+	gnew switch - &&
+	grep "^first$" a
+'
+
+test_expect_success '@{-1} names the previous HEAD in revisions' '
+	gnew cat @{-1} a >out &&
+	grep "^second$" out &&
+	gnew cat @{-1}~1 a >out &&
+	grep "^first$" out
+'
+
+test_expect_success 'a fast-forward merge keeps the previous HEAD' '
+	gnew merge other >out &&
+	grep "Fast-forward" out &&
+	gnew checkout - >out &&
+	grep "Switched to branch .other." out &&
+	gnew checkout - >out &&
+	grep "Switched to branch .main." out
+'
@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit init r.txt &&
+	cp -R . ../local
+'
+
+test_expect_success 'pull reports the objects and the fast-forwarded branch' '
+	test_commit second r.txt &&
+	cd ../local &&
+	gnew pull ../remote >../out &&
+	grep "^Received 3 objects$" ../out &&
+	grep "^   [0-9a-f]*\.\.[0-9a-f]* *main$" ../out &&
+	grep "^Fast-forward$" ../out
+'
+
+test_expect_success 'pull reports a three-way merge' '
+	test_commit local l.txt &&
+	(cd ../remote && test_commit third r.txt) &&
+	gnew pull ../remote >../out &&
+	grep "^Received 3 objects$" ../out &&
+	grep "main$" ../out &&
+	grep "^Merge made by the three-way strategy.$" ../out
+'
+
+test_expect_success 'push reports the objects sent and the remote branch' '
+	gnew push ../remote >../out &&
+	grep "^Sent [1-9][0-9]* objects$" ../out &&
+	grep "^   [0-9a-f]*\.\.[0-9a-f]* *main$" ../out &&
+	gnew push ../remote >../out &&
+	grep "^Sent 0 objects$" ../out &&
+	grep "^ = \[up to date\] *main$" ../out
+'
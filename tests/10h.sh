@@ -0,0 +1,71 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'rebase replays the commits of the branch on upstream' '
+	test_commit base a &&
+	gnew checkout -b topic &&
+	test_commit one b &&
+	test_commit two c &&
+	gnew checkout main &&
+	test_commit upstream d &&
+	gnew checkout topic &&
+	gnew rebase main >out &&
+	grep "Successfully rebased and updated refs/heads/topic." out &&
+	gnew log 4 --format=%s >out &&
+	printf "two\none\nupstream\nbase\n" >expect &&
+	diff expect out &&
+	grep "^upstream$" d
+'
+
+test_expect_success 'rebase does nothing when the branch is up to date' '
+	gnew rebase main >out &&
+	grep "Current branch is up to date." out
+'
+
+test_expect_success 'rebase -i squashes and drops commits from the todo list' '
+	printf "#!/bin/sh\nsed -i -e \"/ two\$/s/^pick/fixup/\" -e \"/ extra\$/d\" \"\$1\"\n" >.gnew/editor &&
+	chmod +x .gnew/editor &&
+	test_commit extra e &&
+	GNEW_EDITOR=.gnew/editor gnew rebase -i main &&
+	gnew log 2 --format=%s >out &&
+	printf "one\nupstream\n" >expect &&
+	diff expect out &&
+	! test -e e &&
+	grep "^two$" c
+'
+
+test_expect_success 'rebase stops at conflicts until --continue' '
+	test_commit ours a &&
+	gnew checkout main &&
+	test_commit theirs a &&
+	gnew checkout topic &&
+	! gnew rebase main 2>err &&
+	grep "Merge conflict in a" err &&
+	! gnew rebase --continue 2>err &&
+	grep "Could not apply" err &&
+	echo resolved >a &&
+	gnew add a &&
+	gnew rebase --continue >out &&
+	grep "Successfully rebased" out &&
+	gnew log 2 --format=%s >out &&
+	printf "ours\none\n" >expect &&
+	diff expect out &&
+	grep "^resolved$" a
+'
+
+test_expect_success 'rebase --abort restores the branch' '
+	test_commit again a &&
+	gnew checkout main &&
+	test_commit conflicting a &&
+	gnew checkout topic &&
+	! gnew rebase main &&
+	! gnew rebase main 2>err &&
+	grep "a rebase is in progress" err &&
+	gnew rebase --abort &&
+	grep "^again$" a &&
+	gnew log 1 --format=%s >out &&
+	grep "^again$" out &&
+	! gnew rebase --continue 2>err &&
+	grep "no rebase in progress" err
+'
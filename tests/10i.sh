@@ -0,0 +1,87 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit init r.txt &&
+	test_commit notes n.txt &&
+	cp -R . ../local
+'
+
+test_expect_success 'pull fails with local changes' '
+	test_commit second r.txt &&
+	cd ../local &&
+	echo mine >n.txt &&
+	! gnew pull ../remote 2>../err &&
+	grep "commit or remove changes first" ../err
+'
+
+test_expect_success 'pull --autostash applies local changes after pulling' '
+	gnew pull --autostash ../remote >../out &&
+	grep "^Created autostash.$" ../out &&
+	grep "^Fast-forward$" ../out &&
+	grep "^Applied autostash.$" ../out &&
+	grep "^second$" r.txt &&
+	grep "^mine$" n.txt &&
+	! test -e .gnew/AUTOSTASH
+'
+
+test_expect_success 'pull.autostash stashes by default unless --no-autostash' '
+	echo "pull.autostash = true" >>.gnew/config &&
+	(cd ../remote && test_commit third r.txt) &&
+	! gnew pull --no-autostash ../remote &&
+	gnew pull ../remote >../out &&
+	grep "^Applied autostash.$" ../out &&
+	grep "^third$" r.txt &&
+	grep "^mine$" n.txt
+'
+
+test_expect_success 'autostash conflicts are left in the working tree' '
+	(cd ../remote && test_commit theirs n.txt) &&
+	gnew pull ../remote >../out 2>../err &&
+	grep "Merge conflict in n.txt" ../err &&
+	grep "^<<<<<<<" n.txt &&
+	! test -e .gnew/AUTOSTASH
+'
+
+test_expect_success 'rebase --autostash applies local changes after rebasing' '
+	cd .. &&
+	mkdir rebase &&
+	cd rebase &&
+	gnew init >/dev/null &&
+	test_commit base a &&
+	test_commit notes n &&
+	gnew checkout -b topic &&
+	test_commit topic b &&
+	gnew checkout main &&
+	test_commit upstream c &&
+	gnew checkout topic &&
+	echo changed >n &&
+	! gnew rebase main 2>../err &&
+	grep "dirty work tree" ../err &&
+	gnew rebase --autostash main >../out &&
+	grep "^Successfully rebased and updated refs/heads/topic.$" ../out &&
+	grep "^Applied autostash.$" ../out &&
+	grep "^changed$" n &&
+	grep "^upstream$" c
+'
+
+test_expect_success 'rebase keeps the autostash until the rebase completes' '
+	echo "rebase.autostash = true" >>.gnew/config &&
+	test_commit ours a &&
+	gnew checkout --force main &&
+	test_commit theirs a &&
+	gnew checkout --force topic &&
+	echo again >n &&
+	! gnew rebase main >../out 2>../err &&
+	grep "Merge conflict in a" ../err &&
+	grep "is applied once the merge or rebase is completed" ../out &&
+	echo resolved >a &&
+	gnew add a &&
+	gnew rebase --continue >../out &&
+	grep "^Applied autostash.$" ../out &&
+	grep "^again$" n
+'
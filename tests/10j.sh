@@ -0,0 +1,40 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'verify-tracklist accepts a canonical tracklist' '
+	mkdir dir &&
+	test_commit one dir/a &&
+	test_commit two b &&
+	gnew verify-tracklist >../out &&
+	grep "^tracklist is ok$" ../out
+'
+
+test_expect_success 'verify-tracklist reports entries that drifted' '
+	printf "./b\ndir\ndir\\\\a\ndir/a\n../outside\ngone\n" >.gnew/tracklist &&
+	! gnew verify-tracklist >../out &&
+	grep "^not canonical: .\./b. -> .b.$" ../out &&
+	grep "^not canonical: .dir.a. -> .dir/a.$" ../out &&
+	grep "^duplicate: .dir.$" ../out &&
+	grep "^outside the working tree: .\.\./outside.$" ../out &&
+	grep "^stale: .gone.$" ../out
+'
+
+test_expect_success 'verify-tracklist --repair keeps stale entries unless --prune' '
+	gnew verify-tracklist --repair >../out &&
+	grep "^4 entries fixed, 1 stale$" ../out &&
+	! gnew verify-tracklist >../out &&
+	grep "^stale: .gone.$" ../out &&
+	gnew verify-tracklist --prune >../out &&
+	grep "^0 entries fixed, 1 pruned$" ../out &&
+	gnew verify-tracklist >../out &&
+	grep "^tracklist is ok$" ../out
+'
+
+test_expect_success 'add repairs the tracklist' '
+	printf "./b\ndir/a\n" >.gnew/tracklist &&
+	echo c >c &&
+	gnew add c &&
+	gnew verify-tracklist >../out &&
+	grep "^tracklist is ok$" ../out
+'
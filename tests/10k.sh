@@ -0,0 +1,55 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir old &&
+	printf "1\n2\n3\n" >old/a &&
+	echo b >old/b &&
+	echo c >old/c &&
+	gnew add old &&
+	gnew commit base
+'
+
+test_expect_success 'diff --name-status pairs modified files of a moved directory' '
+	gnew checkout -b moved &&
+	mv old new &&
+	printf "1\n2\n3\n4\n" >new/a &&
+	gnew add -u &&
+	gnew add new &&
+	gnew commit "move old to new" &&
+	gnew diff main moved --name-status >../out &&
+	grep "^R	old/a	new/a$" ../out &&
+	grep "^R	old/b	new/b$" ../out &&
+	! grep "^[AD]" ../out
+'
+
+test_expect_success 'merge moves changes under a moved directory to its new path' '
+	gnew checkout main &&
+	gnew checkout -b changed &&
+	printf "0\n1\n2\n3\n" >old/a &&
+	echo d >old/d &&
+	gnew add old/d &&
+	gnew commit "change old" &&
+	gnew merge moved &&
+	! test -e old &&
+	printf "0\n1\n2\n3\n4\n" >expect &&
+	diff expect new/a &&
+	grep "^d$" new/d &&
+	gnew status --porcelain >../out &&
+	! grep -v "^?" ../out
+'
+
+test_expect_success 'merge moves the other side'"'"'s changes into a directory moved on this side' '
+	gnew checkout main &&
+	gnew checkout -b edit &&
+	echo b2 >old/b &&
+	echo e >old/e &&
+	gnew add old/e &&
+	gnew commit "edit old" &&
+	gnew checkout moved &&
+	gnew merge edit &&
+	grep "^b2$" new/b &&
+	grep "^e$" new/e &&
+	! test -e old
+'
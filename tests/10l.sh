@@ -0,0 +1,49 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'status shows nothing in progress' '
+	test_commit base a &&
+	gnew status >../out &&
+	! grep "in the middle of" ../out
+'
+
+test_expect_success 'status shows a merge with unresolved conflicts' '
+	gnew checkout -b topic &&
+	test_commit topic a &&
+	gnew checkout main &&
+	test_commit main a &&
+	! gnew merge topic &&
+	gnew status >../out &&
+	grep "^You are in the middle of merging [0-9a-f]\{7\}.$" ../out &&
+	grep "^Unresolved conflicts:$" ../out &&
+	grep "^  a$" ../out &&
+	grep "^Fix conflicts and commit the result.$" ../out &&
+	gnew status --porcelain >../out &&
+	! grep "in the middle of" ../out
+'
+
+test_expect_success 'status shows when the conflicts of a merge are fixed' '
+	echo resolved >a &&
+	gnew status >../out &&
+	! grep "^Unresolved conflicts:$" ../out &&
+	grep "^All conflicts fixed: commit the result to conclude the merge.$" ../out &&
+	gnew commit merged &&
+	gnew status >../out &&
+	! grep "in the middle of" ../out
+'
+
+test_expect_success 'status shows a rebase stopped at conflicts' '
+	gnew checkout topic &&
+	test_commit more a &&
+	test_commit extra b &&
+	! gnew rebase main &&
+	gnew status >../out &&
+	grep "^You are in the middle of rebasing onto [0-9a-f]\{7\}, stopped at [0-9a-f]\{7\} with 1 commit left.$" ../out &&
+	grep "^  a$" ../out &&
+	grep "^Fix conflicts and run .gnew rebase --continue.\.$" ../out &&
+	grep "^Use .gnew rebase --abort. to restore the branch.$" ../out &&
+	gnew rebase --abort &&
+	gnew status >../out &&
+	! grep "in the middle of" ../out
+'
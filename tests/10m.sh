@@ -0,0 +1,37 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'log lists the history before a corrupted commit' '
+	test_commit one a &&
+	test_commit two a &&
+	test_commit three a &&
+	test_commit four a &&
+	two=$(gnew log 3 --format=%H | tail -n 1) &&
+	chmod u+w .gnew/objects/$two && echo garbage >>.gnew/objects/$two &&
+	! gnew log --format=%s >../out 2>../err &&
+	printf "four\nthree\n" >expect &&
+	diff expect ../out &&
+	grep "^fatal: cannot read commit $two: corrupted object$" ../err &&
+	grep "^hint: .*gnew fsck" ../err
+'
+
+test_expect_success 'fsck reports the corrupted commit' '
+	! gnew fsck >../out &&
+	grep "^$two commit [0-9]* bad checksum$" ../out
+'
+
+test_expect_success 'log stops before the amount when a commit is corrupted' '
+	gnew log 1 --format=%s >../out &&
+	grep "^four$" ../out &&
+	! gnew log 3 --format=%s >../out
+'
+
+test_expect_success 'log reports a corrupted HEAD commit' '
+	gnew checkout -b broken &&
+	test_commit five a &&
+	head=$(gnew log 1 --format=%H) &&
+	chmod u+w .gnew/objects/$head && echo garbage >>.gnew/objects/$head &&
+	! gnew log 2>../err &&
+	grep "^fatal: cannot read commit $head" ../err
+'
@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit --porcelain prints only the hash' '
+	echo one >a &&
+	gnew add a &&
+	gnew commit --porcelain one >../out &&
+	test $(wc -l <../out) = 1 &&
+	grep "^[0-9a-f]\{40\}$" ../out &&
+	test "$(cat ../out)" = "$(gnew log 1 --format=%H)"
+'
+
+test_expect_success 'rev-parse prints the hash of each revision' '
+	echo two >a &&
+	one=$(gnew rev-parse HEAD) &&
+	two=$(gnew --porcelain commit two) &&
+	gnew rev-parse HEAD HEAD~1 main >../out &&
+	printf "%s\n%s\n%s\n" $two $one $two >expect &&
+	diff expect ../out &&
+	! gnew rev-parse nope 2>../err &&
+	grep "revision not found" ../err
+'
+
+test_expect_success 'plumbing output composes in scripts' '
+	gnew checkout -b topic &&
+	test_commit three b &&
+	base=$(gnew merge-base --porcelain "$(gnew rev-parse topic)" main) &&
+	test "$base" = "$(gnew rev-parse main)" &&
+	tree=$(gnew write-tree --porcelain) &&
+	gnew cat-object tree $tree >../out &&
+	grep "b$" ../out &&
+	test "$(gnew hash-file --porcelain b)" = "$(gnew hash-file b)"
+'
+
+test_expect_success 'status --porcelain still conflicts with --relative' '
+	! gnew status --porcelain --relative
+'
+
+test_expect_success 'commands without porcelain output reject --porcelain' '
+	! gnew log --porcelain 2>../err &&
+	grep "only supported by status" ../err &&
+	! gnew --porcelain diff
+'
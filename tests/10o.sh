@@ -0,0 +1,47 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'GNEW_AUTHOR_* set the author and date of commits' '
+	echo one >a &&
+	gnew add a &&
+	GNEW_AUTHOR_NAME=Ada GNEW_AUTHOR_EMAIL=ada@example.com \
+		GNEW_AUTHOR_DATE="2020-09-13 12:26:40 +0000" gnew commit one &&
+	gnew log 1 --format="%an %at" >../out &&
+	echo "Ada <ada@example.com> 1600000000" >expect &&
+	diff expect ../out
+'
+
+test_expect_success 'commit --date takes precedence over GNEW_AUTHOR_DATE' '
+	echo two >a &&
+	GNEW_AUTHOR_DATE=1600000000 gnew commit --date 1700000000 two &&
+	test "$(gnew log 1 --format=%at)" = 1700000000 &&
+	echo three >a &&
+	! GNEW_AUTHOR_DATE=yesterday gnew commit three 2>../err &&
+	grep "invalid date" ../err
+'
+
+test_expect_success 'GNEW_DIR keeps the storage outside the working tree' '
+	mkdir ../env-tree &&
+	(
+		cd ../env-tree &&
+		GNEW_DIR=../env-store gnew init &&
+		test -d ../env-store/objects &&
+		! test -e .gnew &&
+		echo hello >f &&
+		GNEW_DIR=../env-store gnew add f &&
+		GNEW_DIR=../env-store gnew commit hello &&
+		GNEW_DIR=../env-store gnew status --porcelain >../out &&
+		! test -s ../out
+	)
+'
+
+test_expect_success 'GNEW_WORK_TREE runs commands from anywhere' '
+	echo changed >../env-tree/f &&
+	GNEW_DIR=../env-store GNEW_WORK_TREE=../env-tree gnew status --porcelain >../out &&
+	grep "^M *f$" ../out &&
+	GNEW_WORK_TREE=. gnew log 1 --format=%s >../out &&
+	grep two ../out
+'
+
+rm -rf ../env-tree ../env-store
@@ -0,0 +1,42 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'for-each-ref lists every reference by name' '
+	echo one >a &&
+	gnew add a &&
+	GNEW_AUTHOR_DATE=1600000000 gnew commit one &&
+	gnew checkout -b topic &&
+	echo two >b &&
+	gnew add b &&
+	GNEW_AUTHOR_DATE=1700000000 gnew commit two &&
+	gnew for-each-ref >../out &&
+	printf "%s refs/heads/main\n%s refs/heads/topic\n" \
+		$(gnew rev-parse main) $(gnew rev-parse topic) >expect &&
+	diff expect ../out
+'
+
+test_expect_success 'for-each-ref formats the date and subject' '
+	gnew for-each-ref --format="%(refname:short) %at %s" refs/heads >../out &&
+	printf "main 1600000000 one\ntopic 1700000000 two\n" >expect &&
+	diff expect ../out
+'
+
+test_expect_success 'for-each-ref sorts and limits the references' '
+	gnew for-each-ref --sort=-authordate --count 1 --format="%(refname)" >../out &&
+	echo refs/heads/topic >expect &&
+	diff expect ../out &&
+	gnew for-each-ref --sort=subject --format="%s" >../out &&
+	printf "one\ntwo\n" >expect &&
+	diff expect ../out &&
+	! gnew for-each-ref --sort=size 2>../err &&
+	grep "unknown sort key" ../err
+'
+
+test_expect_success 'for-each-ref matches whole name components' '
+	gnew for-each-ref refs/heads/to refs/tags >../out &&
+	! test -s ../out &&
+	gnew for-each-ref refs/heads/topic/ --format="%(refname:short)" >../out &&
+	echo topic >expect &&
+	diff expect ../out
+'
@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'branches can have nested names' '
+	test_commit one a &&
+	gnew checkout -b feature/login &&
+	test_commit two b &&
+	test -f .gnew/refs/heads/feature/login &&
+	gnew branch >../out &&
+	grep "feature/login" ../out &&
+	gnew checkout main &&
+	gnew checkout feature/login &&
+	gnew log 1 --format=%s >../out &&
+	grep two ../out
+'
+
+test_expect_success 'a branch cannot be the directory of another' '
+	! gnew checkout -b feature 2>../err &&
+	grep "branch .feature/login. exists" ../err &&
+	! gnew checkout -b feature/login/x 2>../err &&
+	grep "branch .feature/login. exists" ../err
+'
+
+test_expect_success 'deleting a nested branch frees its directory' '
+	gnew checkout main &&
+	gnew checkout -b feature/old &&
+	gnew checkout main &&
+	gnew branch --delete-merged main >../out &&
+	grep "Deleted branch feature/old" ../out &&
+	test -d .gnew/refs/heads/feature &&
+	gnew merge feature/login &&
+	gnew branch --delete-merged main >../out &&
+	grep "Deleted branch feature/login" ../out &&
+	! test -d .gnew/refs/heads/feature &&
+	gnew checkout -b feature &&
+	test -f .gnew/refs/heads/feature
+'
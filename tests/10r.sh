@@ -0,0 +1,32 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'checkout -b rejects names read as other revisions' '
+	test_commit one a &&
+	! gnew checkout -b HEAD 2>../err &&
+	grep "invalid reference name: .HEAD." ../err &&
+	grep "^hint:" ../err &&
+	! gnew checkout -b $(gnew rev-parse HEAD) &&
+	! gnew checkout -b topic.lock &&
+	! gnew checkout -b ../topic &&
+	! gnew checkout -b "a b" &&
+	gnew branch >../out &&
+	! grep -v main ../out
+'
+
+test_expect_success 'checkout -b normalizes slashes' '
+	gnew checkout -b /feature//login/ >../out &&
+	grep "new branch .feature/login.$" ../out &&
+	test -f .gnew/refs/heads/feature/login
+'
+
+test_expect_success 'init rejects an invalid initial branch' '
+	mkdir ../init-invalid &&
+	(
+		cd ../init-invalid &&
+		! gnew init -b "bad..name" 2>../err &&
+		! test -e .gnew
+	) &&
+	rm -rf ../init-invalid
+'
@@ -0,0 +1,34 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'a refused push leaves no objects in the remote' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit init file.txt &&
+	cp -R . ../local &&
+	cd ../local &&
+	echo one >one.txt &&
+	echo two >two.txt &&
+	gnew add one.txt two.txt &&
+	blob=$(gnew hash-file two.txt) &&
+	gnew commit two &&
+	chmod u+w .gnew/objects/$blob &&
+	printf "blob\0tampered\n" >.gnew/objects/$blob &&
+	ls ../remote/.gnew/objects >../before &&
+	! gnew push ../remote 2>../err &&
+	grep "received objects do not match their hashes: $blob" ../err &&
+	ls ../remote/.gnew/objects >../after &&
+	diff ../before ../after &&
+	! ls ../remote/.gnew | grep incoming
+'
+
+test_expect_success 'a push of missing objects reports each of them' '
+	printf "blob\0two\n" >.gnew/objects/$blob &&
+	rm .gnew/objects/$(gnew hash-file one.txt) &&
+	! gnew push ../remote 2>../err &&
+	grep "could not copy objects: $(gnew hash-file one.txt) (" ../err &&
+	ls ../remote/.gnew/objects >../after &&
+	diff ../before ../after
+'
@@ -0,0 +1,52 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a clone of a remote' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit one a &&
+	origin=$(pwd) &&
+	mkdir ../local &&
+	cd ../local &&
+	gnew clone "$origin" &&
+	cd remote &&
+	gnew status --remote origin >../out &&
+	grep "up to date with .origin/main." ../out
+'
+
+test_expect_success 'status --remote counts the commits to push' '
+	test_commit two b &&
+	test_commit three c &&
+	gnew status --remote origin >../out &&
+	grep "ahead of .origin/main. by 2 commits: push" ../out
+'
+
+test_expect_success 'status --remote fetches the commits to pull' '
+	clone=$(pwd) &&
+	cd "$origin" &&
+	test_commit four d &&
+	cd "$clone" &&
+	gnew status --remote origin >../out &&
+	grep "diverged, with 2 commits and 1 commit different" ../out &&
+	test "$(gnew rev-parse origin/main)" = "$(cd "$origin" && gnew rev-parse HEAD)" &&
+	test "$(gnew log 1 --format=%s)" = three
+'
+
+test_expect_success 'status --porcelain --remote prints a branch line first' '
+	gnew --porcelain status --remote origin >../out &&
+	head -n 1 ../out >../first &&
+	echo "## main...origin/main [ahead 2, behind 1]" >../expect &&
+	diff ../expect ../first &&
+	gnew checkout -b local-only &&
+	gnew --porcelain status --remote origin >../out &&
+	grep "^## local-only$" ../out &&
+	gnew status --remote origin >../out &&
+	grep "not on remote .origin." ../out
+'
+
+test_expect_success 'status --remote needs a configured remote' '
+	! gnew status --remote nope 2>../err &&
+	grep "nope" ../err
+'
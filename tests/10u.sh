@@ -0,0 +1,42 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a history' '
+	echo "let answer = 42;" >a.txt &&
+	gnew add a.txt &&
+	gnew commit "Add the answer" &&
+	echo "print(answer);" >>a.txt &&
+	gnew commit "Print it" &&
+	echo "print(answer);" >a.txt &&
+	gnew commit "fix: drop the constant" &&
+	echo "done" >b.txt &&
+	gnew add b.txt &&
+	gnew commit "Fix the build"
+'
+
+test_expect_success 'log --grep filters by message' '
+	gnew log --grep "^Fix" --format=%s >../out &&
+	echo "Fix the build" >expect &&
+	diff expect ../out &&
+	gnew log --grep "^fix" -i --format=%s >../out &&
+	printf "Fix the build\nfix: drop the constant\n" >expect &&
+	diff expect ../out
+'
+
+test_expect_success 'log -S finds the commits adding or removing a string' '
+	gnew log -S 42 --format=%s >../out &&
+	printf "fix: drop the constant\nAdd the answer\n" >expect &&
+	diff expect ../out &&
+	gnew log -S 42 1 --format=%s >../out &&
+	echo "fix: drop the constant" >expect &&
+	diff expect ../out &&
+	gnew log -S "print(" --grep Print --format=%s >../out &&
+	echo "Print it" >expect &&
+	diff expect ../out
+'
+
+test_expect_success 'log --grep rejects an invalid pattern' '
+	! gnew log --grep "(" 2>../err &&
+	grep "invalid pattern" ../err
+'
@@ -0,0 +1,32 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'cat lists a directory' '
+	mkdir -p src/util &&
+	echo main >src/main.rs &&
+	echo lib >src/util/lib.rs &&
+	gnew add src &&
+	gnew commit sources &&
+	gnew cat src >../out &&
+	grep "^blob $(gnew hash-file src/main.rs)	main.rs$" ../out &&
+	grep "^tree [0-9a-f]\{40\}	util$" ../out &&
+	test $(wc -l <../out) = 2
+'
+
+test_expect_success 'cat --recursive walks the subtree' '
+	gnew cat -r HEAD:src >../out &&
+	grep "^tree [0-9a-f]*	util$" ../out &&
+	grep "^blob $(gnew hash-file src/util/lib.rs)	util/lib.rs$" ../out &&
+	test $(wc -l <../out) = 3 &&
+	gnew cat HEAD: >../out &&
+	grep "	src$" ../out &&
+	cd src &&
+	gnew cat util >../../out &&
+	grep "	lib.rs$" ../../out
+'
+
+test_expect_success 'cat of a missing path still fails' '
+	! gnew cat HEAD:nope 2>../err &&
+	grep "file not found" ../err
+'
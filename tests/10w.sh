@@ -0,0 +1,74 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a remote' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit one a &&
+	origin=$(pwd) &&
+	mkdir ../local &&
+	cd ../local &&
+	gnew clone "$origin" &&
+	cd remote
+'
+
+test_expect_success 'notes add and show' '
+	gnew notes add -m "reviewed" &&
+	gnew notes show >../out &&
+	echo reviewed >../expect &&
+	diff ../expect ../out &&
+	gnew notes add -m "approved" --append HEAD &&
+	gnew notes show HEAD >../out &&
+	printf "reviewed\n\napproved\n" >../expect &&
+	diff ../expect ../out
+'
+
+test_expect_success 'notes show fails without a note' '
+	! gnew notes --ref ci show 2>../err &&
+	grep "no note found" ../err
+'
+
+test_expect_success 'push records nothing without push.notes' '
+	test_commit two b &&
+	gnew push "$origin" >../out &&
+	! grep "Recorded" ../out &&
+	! gnew notes --ref ci show
+'
+
+test_expect_success 'push records a note with push.notes' '
+	echo "push.notes = ci" >>.gnew/config &&
+	old=$(gnew rev-parse HEAD) &&
+	test_commit three c &&
+	new=$(gnew rev-parse HEAD) &&
+	gnew push "$origin" >../out &&
+	grep "Recorded the push in refs/notes/ci" ../out &&
+	gnew notes --ref ci show >../out &&
+	grep "^Pushed-To: $origin$" ../out &&
+	grep "^Ref: refs/heads/main$" ../out &&
+	grep "^Old: $old$" ../out &&
+	grep "^New: $new$" ../out &&
+	grep "^Remote-State: $new$" ../out &&
+	grep "^Date: [0-9-]*T[0-9:]*Z$" ../out &&
+	gnew for-each-ref refs/notes >../out &&
+	grep "refs/notes/ci" ../out
+'
+
+test_expect_success 'push checks push.notes before pushing' '
+	sed -i "s/^push.notes = .*/push.notes = a..b/" .gnew/config &&
+	test_commit four d &&
+	! gnew push "$origin" &&
+	(cd "$origin" && gnew log) >../out &&
+	! grep four ../out
+'
+
+test_expect_success 'push warns when the note cannot be recorded' '
+	sed -i "s/^push.notes = .*/push.notes = audit/" .gnew/config &&
+	mkdir -p .gnew/refs/notes/audit/x &&
+	gnew push "$origin" >../out 2>../err &&
+	grep "warning: could not record the push in refs/notes/audit" ../err &&
+	! grep "Recorded" ../out &&
+	(cd "$origin" && gnew log) >../out &&
+	grep four ../out
+'
@@ -0,0 +1,46 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a conflict' '
+	printf "a\nb\nc\nd\n" >f &&
+	gnew add f >/dev/null &&
+	gnew commit base >/dev/null &&
+	gnew checkout -b side >/dev/null &&
+	printf "a\nB1\nc\nD1\n" >f &&
+	gnew commit side >/dev/null &&
+	gnew checkout main >/dev/null &&
+	printf "a\nB2\nc\nD2\n" >f &&
+	gnew commit main >/dev/null &&
+	gnew checkout -b again >/dev/null &&
+	gnew checkout -b disabled >/dev/null &&
+	gnew checkout main >/dev/null
+'
+
+test_expect_success 'a resolved merge records the resolutions' '
+	echo "rerere.enabled = true" >>.gnew/config &&
+	! gnew merge side 2>../err &&
+	grep "Merge conflict in f" ../err &&
+	printf "a\nB1 and B2\nc\nD2\n" >f &&
+	gnew commit "resolve" >/dev/null &&
+	test "$(ls .gnew/rr-cache | wc -l)" = 2 &&
+	test ! -e .gnew/MERGE_RR
+'
+
+test_expect_success 'the same conflicts are resolved again' '
+	gnew checkout again >/dev/null &&
+	! gnew merge side 2>../err &&
+	grep "Resolved .f. using previous resolution." ../err &&
+	printf "a\nB1 and B2\nc\nD2\n" >../expect &&
+	diff ../expect f &&
+	gnew commit "resolve again" >/dev/null &&
+	test "$(gnew log 1 --merges --format=%s)" = "resolve again"
+'
+
+test_expect_success 'resolutions are not reused unless rerere.enabled is set' '
+	sed -i "/rerere/d" .gnew/config &&
+	gnew checkout disabled >/dev/null &&
+	! gnew merge side 2>../err &&
+	grep "Merge conflict in f" ../err &&
+	grep "<<<<<<<" f
+'
@@ -0,0 +1,30 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup two branches' '
+	test_commit one a &&
+	test_commit two b &&
+	gnew checkout -b other >/dev/null &&
+	test_commit changed a &&
+	test_commit three c
+'
+
+test_expect_success 'checkout summarizes the changed files' '
+	gnew checkout main >../out 2>../err &&
+	grep "Switched to branch .main." ../out &&
+	grep "^1 updated, 0 created, 1 deleted$" ../out &&
+	! grep "Updating files" ../err &&
+	gnew checkout other >../out &&
+	grep "^1 updated, 1 created, 0 deleted$" ../out
+'
+
+test_expect_success 'checkout --progress shows the progress' '
+	gnew checkout --progress main 2>../err &&
+	grep "Updating files: 100% (2/2), done." ../err
+'
+
+test_expect_success 'checkout without changes prints no summary' '
+	gnew checkout main >../out &&
+	! grep "updated" ../out
+'
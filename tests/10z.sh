@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup history of a directory' '
+	mkdir dir &&
+	test_commit one dir/a &&
+	test_commit other b &&
+	test_commit two dir/a &&
+	test_commit three b
+'
+
+test_expect_success 'whatchanged shows the commits that changed a path' '
+	gnew whatchanged dir >../out &&
+	grep "^+two$" ../out &&
+	grep "^+one$" ../out &&
+	! grep "other" ../out &&
+	! grep "b/b$" ../out &&
+	test $(grep -c "commit [0-9a-f]" ../out) = 2
+'
+
+test_expect_success 'whatchanged takes paths relative to the current directory' '
+	(cd dir && gnew whatchanged a -n 1) >../out &&
+	grep "^+two$" ../out &&
+	! grep "^+one$" ../out
+'
+
+test_expect_success 'whatchanged of an unchanged path shows nothing' '
+	gnew whatchanged missing >../out &&
+	! grep . ../out
+'
@@ -0,0 +1,34 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a rebased branch' '
+	test_commit one a &&
+	gnew checkout -b topic >/dev/null &&
+	test_commit "old topic" b &&
+	gnew checkout main >/dev/null &&
+	test_commit two c &&
+	gnew checkout topic >/dev/null &&
+	gnew rebase main >/dev/null
+'
+
+test_expect_success 'fsck lists the commit left behind by the rebase' '
+	gnew fsck >../out &&
+	test $(wc -l <../out) = 1 &&
+	grep "^dangling commit [0-9a-f]*$" ../out
+'
+
+test_expect_success 'fsck --lost-found shows the message of dangling commits' '
+	gnew fsck --lost-found --date unix >../out &&
+	grep "^[0-9a-f]* [0-9]* old topic$" ../out
+'
+
+test_expect_success 'fsck --restore creates a branch at a dangling commit' '
+	commit=$(cut -d" " -f1 ../out) &&
+	gnew fsck --restore $commit old >../out &&
+	grep "Created branch .old." ../out &&
+	test "$(gnew rev-parse old)" = $commit &&
+	gnew fsck >../out &&
+	! grep . ../out &&
+	! gnew fsck --restore $commit old
+'
@@ -0,0 +1,49 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup owners files' '
+	mkdir -p .gnew/info docs lib &&
+	printf "out\nremote\n" >.gnew/info/exclude &&
+	printf "* G7\ndocs/ writer \"Ed <ed@example.com>\"\n*.lock\n" >GNEWOWNERS &&
+	printf "*.c maintainer\n" >lib/GNEWOWNERS &&
+	gnew add GNEWOWNERS lib/GNEWOWNERS >/dev/null &&
+	gnew commit owners >/dev/null
+'
+
+test_expect_success 'owners shows the owners of each path' '
+	gnew owners a docs/guide/b Cargo.lock lib/x.c >out &&
+	grep "^a: G7$" out &&
+	grep "^docs/guide/b: writer Ed <ed@example.com>$" out &&
+	grep "^Cargo.lock: (unowned)$" out &&
+	grep "^lib/x.c: maintainer$" out &&
+	(cd lib && gnew owners x.c) >out &&
+	grep "^x.c: maintainer$" out
+'
+
+test_expect_success 'setup a remote checking owners on update' '
+	mkdir remote && (
+		cd remote &&
+		gnew init >/dev/null &&
+		mkdir -p .gnew/hooks &&
+		printf "#!/bin/sh\nexec gnew owners --check \$2 \$3\n" >.gnew/hooks/update &&
+		chmod +x .gnew/hooks/update
+	) &&
+	gnew push remote
+'
+
+test_expect_success 'push is rejected for paths the author does not own' '
+	test_commit guide docs/guide &&
+	! gnew push remote 2>out &&
+	grep "G7 does not own docs/guide" out &&
+	grep "update hook failed" out
+'
+
+test_expect_success 'changes by an owner are not reported' '
+	echo changed >docs/guide &&
+	gnew add docs/guide >/dev/null &&
+	GNEW_AUTHOR_NAME=Ed GNEW_AUTHOR_EMAIL=ed@example.com gnew commit "fix guide" >/dev/null &&
+	! gnew push remote 2>out &&
+	grep "G7 does not own docs/guide" out &&
+	test $(grep -c "does not own" out) = 1
+'
@@ -0,0 +1,33 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a subdirectory' '
+	mkdir -p sub/deep &&
+	test_commit one sub/a &&
+	gnew checkout -b other >/dev/null &&
+	gnew checkout main >/dev/null
+'
+
+test_expect_success 'add, diff and commit work from a subdirectory' '
+	(
+		cd sub/deep &&
+		echo two >../a &&
+		echo new >b &&
+		gnew add b ../a &&
+		gnew diff HEAD >../../out &&
+		gnew commit two >/dev/null
+	) &&
+	grep "^+two$" out &&
+	grep "^+new$" out &&
+	gnew status >out &&
+	! grep "sub/" out
+'
+
+test_expect_success 'merge writes files from a subdirectory' '
+	gnew checkout other >/dev/null &&
+	test_commit three c &&
+	(cd sub && gnew merge main >/dev/null) &&
+	test "$(cat sub/a)" = two &&
+	test "$(cat sub/deep/b)" = new
+'
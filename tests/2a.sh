@@ -24,8 +24,8 @@ test_expect_success 'commit correctly creates objects' '
 '
 
 test_expect_success 'commit correctly sets the branch head' '
-	test_commit initial file >gout &&
-	cat .gnew/heads/main > cout &&
+	test_commit initial file | head -n 1 >gout &&
+	cat .gnew/refs/heads/main > cout &&
 	diff gout cout
 '
 
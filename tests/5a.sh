@@ -2,9 +2,9 @@
 
 . ./test-lib.sh
 
-test_expect_success 'merge fails if nothing to merge' '
+test_expect_success 'merge reports when already up to date' '
 	test_commit init foo &&
 	gnew checkout -b branch1 &&
-	! gnew merge main 2>out &&
-	grep "fatal: nothing to merge" out
+	gnew merge main >out &&
+	grep "Already up to date." out
 '
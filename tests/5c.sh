@@ -11,7 +11,6 @@ test_expect_success 'three-way merge' '
 	printf "init\nchange on main\n" >foo &&
 	gnew commit foo &&
 	gnew merge branch1 &&
-	gnew commit "merge branch1" &&
 	printf "change on branch1\ninit\nchange on main\n" >expect &&
 	gnew cat HEAD foo >out &&
 	diff expect out
@@ -0,0 +1,18 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'count-objects counts loose objects' '
+	test_commit one foo &&
+	gnew count-objects >out &&
+	grep "^3 objects" out
+'
+
+test_expect_success 'count-objects -v shows history statistics' '
+	test_commit two foo &&
+	gnew checkout -b branch1 &&
+	gnew count-objects -v >out &&
+	grep "^count: 6" out &&
+	grep "^reachable-commits: 2" out &&
+	grep "^branches: 2" out
+'
@@ -0,0 +1,19 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'repair restores missing objects from another repository' '
+	test_commit one foo &&
+	mkdir backup && cp -R .gnew backup &&
+	rm .gnew/objects/$(gnew hash-file foo) &&
+	! gnew cat HEAD foo &&
+	gnew repair --from backup >out &&
+	grep "Restored 1 objects" out &&
+	gnew cat HEAD foo
+'
+
+test_expect_success 'repair fails if the object cannot be found' '
+	rm .gnew/objects/$(gnew hash-file foo) &&
+	mkdir empty && (cd empty && gnew init) &&
+	! gnew repair --from empty
+'
@@ -0,0 +1,32 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'clone --reference shares objects with the reference' '
+	mkdir remote && cd remote &&
+	gnew init &&
+	test_commit one foo &&
+	mkdir ../local && cd ../local &&
+	gnew clone --reference ../remote ../remote &&
+	cd remote &&
+	test -f .gnew/alternates &&
+	test -z "$(ls .gnew/objects)" &&
+	gnew cat HEAD foo | grep one
+'
+
+test_expect_success 'commits in a clone with alternates can be pushed' '
+	test_commit two bar &&
+	mkdir ../../other && (cd ../../other && gnew init) &&
+	gnew push ../../other &&
+	cd ../../other &&
+	gnew cat HEAD foo
+'
+
+test_expect_success 'clone --local hard links objects' '
+	mkdir ../linked && cd ../linked &&
+	gnew clone --local ../other &&
+	obj=$(ls other/.gnew/objects | head -n 1) &&
+	test other/.gnew/objects/$obj -ef ../other/.gnew/objects/$obj &&
+	cd other &&
+	gnew cat HEAD bar
+'
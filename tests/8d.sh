@@ -0,0 +1,29 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit caches the hashes of unchanged files' '
+	echo foo >foo &&
+	touch -d "2020-01-01" foo &&
+	gnew add foo &&
+	gnew commit one &&
+	grep "foo$" .gnew/statcache
+'
+
+test_expect_success 'status notices changes to cached files' '
+	echo bar >foo &&
+	gnew status >out &&
+	grep "M foo" out
+'
+
+test_expect_success 'diff uses the cache for unchanged files' '
+	echo foo >foo &&
+	touch -d "2020-01-02" foo &&
+	gnew diff >out &&
+	test ! -s out &&
+	grep "foo$" .gnew/statcache &&
+	echo bazz >foo &&
+	touch -d "2020-01-02" foo &&
+	gnew diff >out &&
+	grep "+bazz" out
+'
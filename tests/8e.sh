@@ -0,0 +1,18 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'checkout restores many files in nested directories' '
+	mkdir -p a/b c &&
+	for i in $(seq 100); do echo $i >a/b/$i; echo $i >c/$i; done &&
+	gnew add a c &&
+	gnew commit many &&
+	gnew checkout -b other &&
+	gnew remove a c &&
+	rm -r a c &&
+	test_commit empty foo &&
+	gnew checkout main &&
+	test "$(cat a/b/42)" = 42 &&
+	test "$(ls c | wc -l)" = 100 &&
+	test ! -f foo
+'
@@ -0,0 +1,23 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'partial clone leaves out blobs' '
+	mkdir remote && cd remote &&
+	gnew init &&
+	test_commit one foo &&
+	test_commit two foo &&
+	mkdir ../local && cd ../local &&
+	gnew clone --filter blob:none ../remote &&
+	cd remote &&
+	test "$(ls .gnew/objects | wc -l)" = 4 &&
+	grep "remote.origin.promisor = true" .gnew/config
+'
+
+test_expect_success 'partial clone fetches blobs when needed' '
+	gnew log >../out &&
+	first=$(grep -o "[0-9a-f]\{40\}" ../out | tail -n 1) &&
+	gnew cat $first foo >../out &&
+	grep one ../out &&
+	test "$(ls .gnew/objects | wc -l)" = 5
+'
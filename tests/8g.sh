@@ -0,0 +1,24 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'write-bitmaps writes bitmaps for the branch heads' '
+	mkdir local && cd local &&
+	gnew init &&
+	test_commit one foo &&
+	gnew checkout -b branch1 &&
+	test_commit two bar &&
+	gnew write-bitmaps >../out &&
+	grep "for 2 commits" ../out &&
+	test -f .gnew/bitmaps
+'
+
+test_expect_success 'push sends only the objects the remote lacks' '
+	mkdir ../remote && cp -R .gnew ../remote &&
+	rm ../remote/.gnew/bitmaps &&
+	test_commit three bar &&
+	gnew push ../remote &&
+	test "$(ls ../remote/.gnew/objects | wc -l)" = 9 &&
+	cd ../remote &&
+	grep three bar
+'
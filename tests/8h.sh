@@ -0,0 +1,23 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'verify-pack accepts intact objects' '
+	test_commit one foo &&
+	gnew verify-pack >out &&
+	grep "^3 objects, 0 corrupted" out
+'
+
+test_expect_success 'verify-pack -v lists objects' '
+	gnew verify-pack -v >out &&
+	grep "^$(gnew hash-file foo) blob" out &&
+	test $(grep -c "^[0-9a-f]\{40\} " out) = 3
+'
+
+test_expect_success 'verify-pack reports corrupted objects' '
+	obj=.gnew/objects/$(gnew hash-file foo) &&
+	chmod u+w $obj && echo garbage >>$obj &&
+	! gnew verify-pack >out &&
+	grep "bad checksum" out &&
+	grep "^3 objects, 1 corrupted" out
+'
@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'branches are stored under refs/heads' '
+	test_commit one foo &&
+	gnew checkout -b branch1 &&
+	test -f .gnew/refs/heads/main &&
+	test -f .gnew/refs/heads/branch1 &&
+	grep "^ref: refs/heads/branch1$" .gnew/HEAD
+'
+
+test_expect_success 'pack-refs moves references into packed-refs' '
+	gnew pack-refs >out &&
+	grep "Packed 2 references" out &&
+	! test -f .gnew/refs/heads/main &&
+	grep " refs/heads/main$" .gnew/packed-refs &&
+	gnew heads >out &&
+	grep "\* branch1" out &&
+	grep "  main" out
+'
+
+test_expect_success 'loose references override packed ones' '
+	test_commit two foo &&
+	test -f .gnew/refs/heads/branch1 &&
+	gnew log 1 | grep two
+'
+
+test_expect_success 'branches in the old heads directory are read' '
+	mkdir old && cd old && gnew init &&
+	test_commit one foo &&
+	mkdir .gnew/heads && mv .gnew/refs/heads/main .gnew/heads/main &&
+	echo "ref: main" >.gnew/HEAD &&
+	gnew log 1 | grep one &&
+	gnew pack-refs &&
+	! test -f .gnew/heads/main &&
+	gnew log 1 | grep one
+'
@@ -0,0 +1,43 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'init --initial-branch sets the initial branch' '
+	mkdir trunk && (
+		cd trunk &&
+		gnew init --initial-branch trunk &&
+		grep "^ref: refs/heads/trunk$" .gnew/HEAD &&
+		test_commit one foo &&
+		gnew heads | grep "\* trunk"
+	)
+'
+
+test_expect_success 'init uses init.defaultBranch from the user configuration' '
+	mkdir home && echo "init.defaultBranch = develop" >home/.gnewconfig &&
+	mkdir develop && (
+		cd develop &&
+		HOME=../home gnew init &&
+		grep "^ref: refs/heads/develop$" .gnew/HEAD
+	)
+'
+
+test_expect_success 'init --initial-branch overrides init.defaultBranch' '
+	mkdir other && (
+		cd other &&
+		HOME=../home gnew init -b other &&
+		grep "^ref: refs/heads/other$" .gnew/HEAD
+	)
+'
+
+test_expect_success 'clone checks out the default branch of the source' '
+	(cd trunk && gnew checkout -b feature && test_commit two foo && gnew checkout trunk) &&
+	mkdir clones && (
+		cd clones &&
+		gnew clone ../trunk &&
+		cd trunk &&
+		grep "^ref: refs/heads/trunk$" .gnew/HEAD &&
+		gnew heads >../out &&
+		grep "\* trunk" ../out &&
+		grep "  feature" ../out
+	)
+'
@@ -0,0 +1,40 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'snapshot save writes the repository to a file' '
+	mkdir repo && cd repo && gnew init &&
+	test_commit one foo &&
+	gnew checkout -b branch1 &&
+	test_commit two foo &&
+	gnew snapshot save ../snap >../out &&
+	grep "Saved 6 objects" ../out
+'
+
+test_expect_success 'snapshot restore recreates the repository' '
+	mkdir ../restored && (
+		cd ../restored &&
+		gnew snapshot restore ../snap &&
+		gnew heads >../out &&
+		grep "\* branch1" ../out &&
+		grep "  main" ../out &&
+		test "$(cat foo)" = two &&
+		gnew log 1 | grep two &&
+		gnew checkout main &&
+		test "$(cat foo)" = one
+	)
+'
+
+test_expect_success 'snapshot restore refuses to overwrite a repository' '
+	! gnew snapshot restore ../snap
+'
+
+test_expect_success 'snapshot restore rejects a corrupted snapshot' '
+	sed "s/two/owt/" ../snap >../bad &&
+	mkdir ../corrupted && (
+		cd ../corrupted &&
+		! gnew snapshot restore ../bad 2>../err &&
+		grep "corrupted snapshot" ../err &&
+		! test -d .gnew
+	)
+'
@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit prints a summary of the recorded changes' '
+	echo one >foo && echo one >bar &&
+	gnew add foo bar &&
+	gnew commit one >out &&
+	grep "^A bar$" out &&
+	grep "^A foo$" out &&
+	grep "^2 added, 0 modified, 0 deleted$" out
+'
+
+test_expect_success 'commit summary lists removed files as deleted' '
+	echo two >foo &&
+	gnew remove bar &&
+	gnew status | grep "^R bar$" &&
+	gnew commit two >out &&
+	grep "^M foo$" out &&
+	grep "^R bar$" out &&
+	grep "^0 added, 1 modified, 1 deleted$" out
+'
+
+test_expect_success 'commit --dry-run shows the changes without committing' '
+	head=$(cat .gnew/refs/heads/main) &&
+	echo three >foo &&
+	gnew commit --dry-run three >out &&
+	grep "^M foo$" out &&
+	grep "^0 added, 1 modified, 0 deleted$" out &&
+	test "$(cat .gnew/refs/heads/main)" = "$head"
+'
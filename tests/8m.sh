@@ -0,0 +1,50 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'rm removes a file from disk and the tracklist' '
+	test_commit one foo &&
+	gnew rm foo >out &&
+	grep "rm '\''foo'\''" out &&
+	! test -f foo &&
+	gnew status | grep "^R foo$"
+'
+
+test_expect_success 'rm --cached keeps the file on disk' '
+	test_commit two bar &&
+	gnew rm --cached bar &&
+	test -f bar &&
+	gnew status | grep "^R bar$"
+'
+
+test_expect_success 'rm refuses to remove a directory without -r' '
+	mkdir dir && test_commit three dir/baz &&
+	! gnew rm dir 2>err &&
+	grep "without -r" err &&
+	gnew rm -r dir &&
+	! test -d dir
+'
+
+test_expect_success 'rm refuses to remove locally modified files' '
+	test_commit four qux &&
+	echo changed >qux &&
+	! gnew rm qux 2>err &&
+	grep "local modifications: qux" err &&
+	test -f qux &&
+	gnew rm -f qux &&
+	! test -f qux
+'
+
+test_expect_success 'rm refuses to remove added files' '
+	echo new >new && gnew add new &&
+	! gnew rm new &&
+	gnew rm --cached new &&
+	test -f new
+'
+
+test_expect_success 'rm untracks files that are already missing' '
+	test_commit five gone &&
+	rm gone &&
+	gnew rm gone &&
+	gnew status | grep "^R gone$"
+'
@@ -0,0 +1,35 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'add requires paths unless -u or -A is given' '
+	! gnew add
+'
+
+test_expect_success 'add -u untracks deleted files only' '
+	test_commit one foo &&
+	test_commit two bar &&
+	echo new >new &&
+	rm foo &&
+	gnew add -u &&
+	gnew status >out &&
+	grep "^R foo$" out &&
+	grep "^? new$" out
+'
+
+test_expect_success 'add -A tracks all files' '
+	mkdir dir && echo baz >dir/baz &&
+	gnew add -A &&
+	gnew status >out &&
+	grep "^A new$" out &&
+	grep "^A dir/baz$" out &&
+	grep "^A out$" out
+'
+
+test_expect_success 'add -A is limited to the given paths' '
+	echo qux >dir/qux && echo other >other &&
+	gnew add -A dir &&
+	gnew status >out &&
+	grep "^A dir/qux$" out &&
+	grep "^? other$" out
+'
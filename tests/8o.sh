@@ -0,0 +1,40 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commands find the repository from a subdirectory' '
+	mkdir -p dir/sub &&
+	echo foo >dir/sub/foo &&
+	cd dir &&
+	gnew add sub/foo &&
+	gnew commit one &&
+	gnew log 1 | grep one
+'
+
+test_expect_success 'status shows paths relative to the current directory' '
+	echo bar >../bar && echo baz >sub/baz &&
+	gnew status >../out &&
+	grep "^? ../bar$" ../out &&
+	grep "^? sub/baz$" ../out
+'
+
+test_expect_success 'status --absolute shows paths from the working tree root' '
+	gnew status --absolute >../out &&
+	grep "^? bar$" ../out &&
+	grep "^? dir/sub/baz$" ../out
+'
+
+test_expect_success 'status.relativePaths can be turned off' '
+	echo "status.relativePaths = false" >../.gnew/config &&
+	gnew status >../out &&
+	grep "^? dir/sub/baz$" ../out &&
+	gnew status --relative >../out &&
+	grep "^? sub/baz$" ../out
+'
+
+test_expect_success 'rm resolves paths from the current directory' '
+	cd sub &&
+	gnew rm --cached foo &&
+	gnew status --relative >../../out &&
+	grep "^R foo$" ../../out
+'
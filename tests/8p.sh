@@ -0,0 +1,37 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'cat defaults to HEAD' '
+	mkdir dir &&
+	test_commit one dir/foo &&
+	test_commit two dir/foo &&
+	test_commit three dir/foo &&
+	test "$(gnew cat dir/foo)" = three
+'
+
+test_expect_success 'cat accepts rev:path' '
+	test "$(gnew cat HEAD:dir/foo)" = three &&
+	test "$(gnew cat main:dir/foo)" = three
+'
+
+test_expect_success 'cat resolves ancestors with ~ and ^' '
+	test "$(gnew cat HEAD~1:dir/foo)" = two &&
+	test "$(gnew cat HEAD~2 dir/foo)" = one &&
+	test "$(gnew cat HEAD^^ dir/foo)" = one &&
+	test "$(gnew cat main~1^ dir/foo)" = one &&
+	! gnew cat HEAD~3 dir/foo
+'
+
+test_expect_success 'cat resolves paths from the current directory' '
+	cd dir &&
+	test "$(gnew cat foo)" = three &&
+	test "$(gnew cat HEAD~1 ../dir/foo)" = two &&
+	test "$(gnew cat HEAD:dir/foo)" = three &&
+	cd ..
+'
+
+test_expect_success 'cat names the revision and path of a missing file' '
+	! gnew cat HEAD~1:bar 2>err &&
+	grep "file not found: '\''bar'\'' in '\''HEAD~1'\''" err
+'
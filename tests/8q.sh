@@ -0,0 +1,25 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'log --format formats each commit' '
+	test_commit one foo &&
+	test_commit two foo &&
+	gnew log --format="%s by %an" >out &&
+	printf "two by G7\none by G7\n" >expect &&
+	diff expect out
+'
+
+test_expect_success 'log --format shows hashes and decorations' '
+	gnew checkout -b branch1 &&
+	gnew log 1 --format="%H %h%d" >out &&
+	hash=$(cat .gnew/refs/heads/main) &&
+	short=$(echo $hash | cut -c1-7) &&
+	echo "$hash $short (HEAD -> branch1, main)" >expect &&
+	diff expect out
+'
+
+test_expect_success 'log --format formats dates' '
+	gnew log 1 --format="%ad{%Y}|%at" >out &&
+	grep "^20[0-9][0-9]|[0-9]*$" out
+'
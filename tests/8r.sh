@@ -0,0 +1,28 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'log decorates commits with HEAD and branches' '
+	test_commit one foo &&
+	gnew checkout -b branch1 &&
+	test_commit two foo &&
+	gnew log >out &&
+	grep "(HEAD -> branch1)$" out &&
+	grep "(main)$" out
+'
+
+test_expect_success 'log decorates tags and remote branches' '
+	main=$(cat .gnew/refs/heads/main) &&
+	mkdir -p .gnew/refs/remotes/origin &&
+	echo $main >.gnew/refs/remotes/origin/main &&
+	echo $main >.gnew/refs/tags/v1.0 &&
+	gnew log --format="%D" >out &&
+	grep "^main, origin/main, tag: v1.0$" out
+'
+
+test_expect_success 'log decorates a detached HEAD' '
+	rm out &&
+	gnew checkout $(cat .gnew/refs/heads/main) &&
+	gnew log --format="%D" >out &&
+	grep "^HEAD, main, origin/main, tag: v1.0$" out
+'
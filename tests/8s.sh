@@ -0,0 +1,33 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'heads shows relative dates' '
+	test_commit one foo &&
+	gnew heads >out &&
+	grep "^\* main ([0-9]* seconds\? ago)$" out
+'
+
+test_expect_success 'heads --date switches the date format' '
+	gnew heads --date=unix >out &&
+	grep "^\* main ([0-9]*)$" out &&
+	gnew heads --date=iso >out &&
+	grep "^\* main ([0-9-]* [0-9:]* +0000)$" out
+'
+
+test_expect_success 'log --date switches the date format' '
+	gnew log --date=relative >out &&
+	grep "^Time: [0-9]* seconds\? ago$" out &&
+	gnew log --date=unix --format="%ad" >out &&
+	grep "^[0-9]*$" out
+'
+
+test_expect_success 'log --format %ar shows relative dates' '
+	gnew log --format="%ar" >out &&
+	grep "ago$" out
+'
+
+test_expect_success 'unknown date formats are rejected' '
+	! gnew log --date=never 2>out &&
+	grep "unknown date format" out
+'
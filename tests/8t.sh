@@ -0,0 +1,43 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'diff --name-only lists changed paths' '
+	test_commit one foo &&
+	test_commit one bar &&
+	test_commit two foo &&
+	gnew diff HEAD~2 HEAD --name-only >out &&
+	printf "bar\nfoo\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'diff --name-status shows the kind of change' '
+	gnew rm bar &&
+	echo baz >baz &&
+	gnew add baz &&
+	gnew commit "remove bar" &&
+	gnew diff HEAD~1 HEAD --name-status >out &&
+	printf "D\tbar\nA\tbaz\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'diff --name-status detects renames' '
+	mv foo moved &&
+	gnew add -u &&
+	gnew add moved &&
+	gnew commit "move foo" &&
+	gnew diff HEAD~1 HEAD --name-status >out &&
+	printf "R\tfoo\tmoved\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'diff --name-status compares with the working tree' '
+	echo changed >baz &&
+	gnew diff --name-status >out &&
+	printf "M\tbaz\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'diff --name-only and --name-status conflict' '
+	! gnew diff --name-only --name-status
+'
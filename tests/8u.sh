@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'diff --quiet exits with 0 on a clean tree' '
+	test_commit one foo &&
+	gnew diff --quiet >out &&
+	test ! -s out
+'
+
+test_expect_success 'diff --quiet exits with 1 if there are changes' '
+	echo two >foo &&
+	! gnew diff --quiet >out &&
+	test ! -s out
+'
+
+test_expect_success 'diff --quiet compares commits' '
+	gnew add foo &&
+	gnew commit two &&
+	! gnew diff --quiet HEAD~1 HEAD &&
+	gnew diff --quiet HEAD HEAD
+'
+
+test_expect_success 'status --porcelain outputs stable codes in path order' '
+	mkdir dir &&
+	echo bar >dir/bar &&
+	echo new >new &&
+	gnew add dir/bar new &&
+	gnew rm --cached foo &&
+	echo changed >new &&
+	gnew status --porcelain >out &&
+	grep "^D foo$" out &&
+	grep "^A dir/bar$" out &&
+	grep "^? out$" out &&
+	cut -c3- out >paths &&
+	LC_ALL=C sort paths | diff - paths
+'
+
+test_expect_success 'status --porcelain uses root-relative paths' '
+	cd dir &&
+	gnew status --porcelain >../out2 &&
+	cd .. &&
+	grep "^A dir/bar$" out2
+'
@@ -0,0 +1,72 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit rejects subjects over commit.maxSubjectLength' '
+	echo "commit.maxSubjectLength = 10" >>.gnew/config &&
+	echo one >foo &&
+	gnew add foo &&
+	! gnew commit "a subject that is too long" 2>err &&
+	grep "subject is 26 characters long, the limit is 10" err &&
+	gnew commit "short"
+'
+
+test_expect_success 'commit.requireBody requires a body' '
+	echo "commit.requireBody = true" >>.gnew/config &&
+	echo two >foo &&
+	! gnew commit "subject" 2>err &&
+	grep "missing body" err &&
+	gnew commit "subject
+
+body"
+'
+
+test_expect_success 'commit-msg hook can reject a commit' '
+	mkdir -p .gnew/hooks &&
+	printf "#!/bin/sh\ngrep -q ticket \"\$1\"\n" >.gnew/hooks/commit-msg &&
+	chmod +x .gnew/hooks/commit-msg &&
+	echo three >foo &&
+	! gnew commit "subject
+
+body" 2>err &&
+	grep "commit-msg hook failed" err &&
+	gnew commit "subject
+
+ticket 1"
+'
+
+test_expect_success 'commit-msg hook can edit the message' '
+	printf "#!/bin/sh\necho \"Reviewed: yes\" >>\"\$1\"\n" >.gnew/hooks/commit-msg &&
+	echo four >foo &&
+	gnew commit "edited
+
+body" &&
+	gnew log 1 --format=%b >out &&
+	grep "Reviewed: yes" out
+'
+
+test_expect_success 'commit fails if the commit-msg hook is not executable' '
+	chmod -x .gnew/hooks/commit-msg &&
+	echo four-and-a-half >foo &&
+	! gnew commit "not run" 2>err &&
+	grep "commit-msg hook is not executable" err
+'
+
+test_expect_success 'commit without a message uses commit.template' '
+	rm -r .gnew/hooks &&
+	printf "# comment\ntemplated\n\nbody\n" >.gnew/template &&
+	echo "commit.template = .gnew/template" >>.gnew/config &&
+	echo five >foo &&
+	gnew commit &&
+	gnew log 1 --format=%s >out &&
+	grep "^templated$" out
+'
+
+test_expect_success 'commit without a message runs the editor' '
+	printf "#!/bin/sh\nsed -i s/templated/edited/ \"\$1\"\n" >.gnew/editor &&
+	chmod +x .gnew/editor &&
+	echo six >foo &&
+	GNEW_EDITOR=.gnew/editor gnew commit &&
+	gnew log 1 --format=%s >out &&
+	grep "^edited$" out
+'
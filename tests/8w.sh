@@ -0,0 +1,25 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit -s adds a Signed-off-by trailer' '
+	echo one >foo &&
+	gnew add foo &&
+	gnew commit -s "one" &&
+	gnew log 1 --format=%b >out &&
+	grep "^Signed-off-by: G7$" out
+'
+
+test_expect_success 'commit -s uses user.name and user.email' '
+	echo "user.name = Gee Seven" >>.gnew/config &&
+	echo "user.email = g7@example.com" >>.gnew/config &&
+	echo two >foo &&
+	gnew commit --signoff "two
+
+Fixes: #1" &&
+	gnew log 1 --format=%b >out &&
+	printf "Fixes: #1\nSigned-off-by: Gee Seven <g7@example.com>\n" >expected &&
+	diff expected out &&
+	gnew log 1 --format=%an >out &&
+	grep "^Gee Seven <g7@example.com>$" out
+'
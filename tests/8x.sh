@@ -0,0 +1,30 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit --author records another author' '
+	echo one >foo &&
+	gnew add foo &&
+	gnew commit --author "Bot <bot@example.com>" "one" &&
+	gnew log 1 --format=%an >out &&
+	grep "^Bot <bot@example.com>$" out
+'
+
+test_expect_success 'commit --date records another date' '
+	echo two >foo &&
+	gnew commit --date 1600000000 "two" &&
+	gnew log 1 --format=%at >out &&
+	grep "^1600000000$" out &&
+	echo three >foo &&
+	gnew commit --date "2020-09-13 14:26:40 +0200" "three" &&
+	gnew log 1 --format=%at >out &&
+	grep "^1600000000$" out
+'
+
+test_expect_success 'commit rejects invalid authors and dates' '
+	echo four >foo &&
+	! gnew commit --author "" "four" 2>err &&
+	grep "invalid author" err &&
+	! gnew commit --date yesterday "four" 2>err &&
+	grep "invalid date" err
+'
@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'pull rejects objects that do not match their hashes' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit init file.txt &&
+	cp -R . ../local &&
+	echo foo >foo.txt &&
+	gnew add foo.txt &&
+	blob=$(gnew hash-file foo.txt) &&
+	gnew commit foo &&
+	chmod u+w .gnew/objects/$blob &&
+	printf "blob\0tampered\n" >.gnew/objects/$blob &&
+	cd ../local &&
+	before=$(cat .gnew/refs/heads/main) &&
+	! gnew pull ../remote 2>../err &&
+	grep "received objects do not match their hashes: $blob" ../err &&
+	test ! -e .gnew/objects/$blob &&
+	test "$(cat .gnew/refs/heads/main)" = "$before"
+'
+
+test_expect_success 'pull accepts intact objects' '
+	cd ../remote &&
+	printf "blob\0foo\n" >.gnew/objects/$blob &&
+	cd ../local &&
+	gnew pull ../remote &&
+	gnew log | grep foo
+'
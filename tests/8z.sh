@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+write_object () {
+	hash=$(sha1sum obj | cut -d " " -f 1) &&
+	cp obj .gnew/objects/$hash &&
+	rm obj
+}
+
+test_expect_success 'checkout refuses trees with paths escaping the worktree' '
+	mkdir repo &&
+	cd repo &&
+	gnew init >/dev/null &&
+	test_commit one foo &&
+	blob=$(gnew hash-file foo) &&
+	printf "tree\0blob ..\0%s" $blob >obj && write_object && tree=$hash &&
+	printf "tree\0tree evil\0%s" $tree >obj && write_object && tree=$hash &&
+	printf "commit\0tree %s\nauthor G7\ntime 0\n\nevil\n" $tree >obj && write_object &&
+	! gnew checkout $hash 2>../err &&
+	grep "refusing unsafe path in tree: '"'"'..'"'"'" ../err &&
+	test "$(cat foo)" = one
+'
+
+test_expect_success 'checkout refuses trees naming .gnew' '
+	printf "tree\0blob .gnew\0%s" $blob >obj && write_object && tree=$hash &&
+	printf "commit\0tree %s\nauthor G7\ntime 0\n\nevil\n" $tree >obj && write_object &&
+	! gnew checkout $hash 2>../err &&
+	grep "unsafe path" ../err &&
+	test -d .gnew
+'
@@ -0,0 +1,27 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'commit refuses when tracked files are missing' '
+	test_commit one foo &&
+	test_commit two bar &&
+	rm foo &&
+	echo three >bar &&
+	! gnew commit three 2>err &&
+	grep "tracked files are missing: foo" err &&
+	grep "hint:" err &&
+	gnew log --format=%s >out &&
+	test "$(head -n 1 out)" = two
+'
+
+test_expect_success 'commit --force leaves missing files out' '
+	gnew commit --force three &&
+	gnew cat HEAD bar &&
+	! gnew cat HEAD foo
+'
+
+test_expect_success 'commit succeeds once missing files are untracked' '
+	gnew rm --cached foo &&
+	echo four >bar &&
+	gnew commit four
+'
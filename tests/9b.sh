@@ -0,0 +1,34 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'status and add -A skip nested repositories' '
+	test_commit one foo &&
+	mkdir nested &&
+	cd nested &&
+	gnew init >/dev/null &&
+	test_commit inner inner.txt &&
+	cd .. &&
+	gnew status --porcelain >out &&
+	! grep nested out &&
+	gnew add -A &&
+	! grep nested .gnew/tracklist
+'
+
+test_expect_success 'copied .gnew directories are not committed' '
+	mkdir copy &&
+	cp -R .gnew copy/.gnew &&
+	echo bar >bar &&
+	gnew add -A &&
+	gnew commit two &&
+	! gnew cat HEAD copy/.gnew/HEAD &&
+	gnew status --porcelain >out &&
+	! grep copy out
+'
+
+test_expect_success 'add refuses files inside nested repositories' '
+	! gnew add nested/inner.txt 2>err &&
+	grep "'"'"'nested/inner.txt'"'"' is inside a nested repository" err &&
+	! gnew add copy/.gnew/HEAD &&
+	! grep nested .gnew/tracklist
+'
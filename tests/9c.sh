@@ -0,0 +1,41 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'status and add -A leave out ignored files' '
+	printf "*.log\n!keep.log\nbuild/\nout\nexpected\n" >.gnewignore &&
+	mkdir build sub &&
+	echo a >a.log &&
+	echo k >keep.log &&
+	echo b >build/out &&
+	echo s >sub/s.log &&
+	gnew status --porcelain >out &&
+	! grep "a.log" out &&
+	! grep "build" out &&
+	! grep "sub/s.log" out &&
+	grep "keep.log" out &&
+	gnew add -A &&
+	! grep "a.log" .gnew/tracklist &&
+	grep "keep.log" .gnew/tracklist
+'
+
+test_expect_success 'check-ignore lists ignored paths' '
+	gnew check-ignore a.log keep.log build/out main.rs >out &&
+	printf "a.log\nbuild/out\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'check-ignore -v shows the pattern' '
+	gnew check-ignore -v a.log keep.log build/out >out &&
+	printf ".gnewignore:1:*.log\ta.log\n.gnewignore:2:!keep.log\tkeep.log\n.gnewignore:3:build/\tbuild/out\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'check-ignore uses nested ignore files and exits with 1 if nothing is ignored' '
+	echo "!s.log" >sub/.gnewignore &&
+	cd sub &&
+	! gnew check-ignore s.log &&
+	gnew check-ignore -v ../a.log >../out &&
+	cd .. &&
+	grep "^.gnewignore:1:\*.log	../a.log$" out
+'
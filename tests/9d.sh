@@ -0,0 +1,56 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'diff reports binary files' '
+	printf "out\nexpected\n" >.gnewignore &&
+	printf "a\0b\n" >data &&
+	echo text >notes &&
+	gnew add .gnewignore data notes &&
+	gnew commit "add files" &&
+	printf "a\0c\n" >data &&
+	echo "more text" >notes &&
+	gnew diff >out &&
+	grep "^Binary files a/data and b/data differ$" out &&
+	grep "^+more text$" out
+'
+
+test_expect_success 'diff attribute forces binary or text diffs' '
+	printf "notes -diff\ndata diff\n" >.gnewattributes &&
+	gnew diff >out &&
+	grep "^Binary files a/notes and b/notes differ$" out &&
+	! grep "^Binary files a/data" out
+'
+
+test_expect_success 'text files are stored with LF and checked out with eol' '
+	printf "*.txt eol=crlf\n" >.gnewattributes &&
+	printf "one\r\ntwo\r\n" >crlf.txt &&
+	gnew add .gnewattributes crlf.txt data notes &&
+	gnew commit "add crlf" &&
+	gnew cat HEAD crlf.txt >out &&
+	printf "one\ntwo\n" >expected &&
+	diff expected out &&
+	gnew status --porcelain >out &&
+	! grep crlf.txt out &&
+	gnew checkout $(gnew log 2 --format=%H | tail -1) &&
+	test ! -e crlf.txt &&
+	gnew checkout main &&
+	printf "one\r\ntwo\r\n" >expected &&
+	cmp expected crlf.txt
+'
+
+test_expect_success 'merge=ours keeps our version' '
+	printf "*.lock merge=ours\n" >.gnewattributes &&
+	echo base >deps.lock &&
+	gnew add .gnewattributes deps.lock &&
+	gnew commit "add lock" &&
+	gnew checkout -b other &&
+	echo theirs >deps.lock &&
+	gnew commit deps.lock &&
+	gnew checkout main &&
+	echo ours >deps.lock &&
+	gnew commit deps.lock &&
+	gnew merge other &&
+	echo ours >expected &&
+	diff expected deps.lock
+'
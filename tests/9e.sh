@@ -0,0 +1,43 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup moved function' '
+	printf "out\n" >.gnewignore &&
+	printf "fn a() {\n    one\n}\n\nfn b() {\n    two\n}\n" >f &&
+	gnew add .gnewignore f &&
+	gnew commit "add f" &&
+	printf "fn b() {\n    two\n}\n\nfn a() {\n    one\n}\n" >f
+'
+
+test_expect_success 'myers is the default algorithm' '
+	gnew diff >out &&
+	test "$(tail -1 out)" = " }"
+'
+
+test_expect_success 'diff --diff-algorithm=patience keeps functions together' '
+	gnew diff --diff-algorithm=patience >out &&
+	test "$(tail -1 out)" = "-}"
+'
+
+test_expect_success 'diff.algorithm sets the default' '
+	echo "diff.algorithm = patience" >>.gnew/config &&
+	gnew diff >out &&
+	test "$(tail -1 out)" = "-}" &&
+	gnew diff --diff-algorithm=myers >out &&
+	test "$(tail -1 out)" = " }"
+'
+
+test_expect_success 'histogram is computed with patience' '
+	gnew diff --diff-algorithm=histogram >out &&
+	test "$(tail -1 out)" = "-}" &&
+	echo "diff.algorithm = histogram" >>.gnew/config &&
+	gnew diff >out &&
+	test "$(tail -1 out)" = "-}"
+'
+
+test_expect_success 'unknown diff algorithms are rejected' '
+	! gnew diff --diff-algorithm=foo &&
+	echo "diff.algorithm = foo" >>.gnew/config &&
+	! gnew diff
+'
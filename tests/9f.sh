@@ -0,0 +1,61 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup file and directory branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n*.log\n" >.gnew/info/exclude &&
+	test_commit file p &&
+	gnew checkout -b dir &&
+	gnew rm --cached p &&
+	rm p &&
+	mkdir p &&
+	echo x >p/x &&
+	gnew add p/x &&
+	gnew commit "p is a directory"
+'
+
+test_expect_success 'diff reports type changes' '
+	gnew diff main dir --name-status >out &&
+	printf "T\tp\nA\tp/x\n" >expected &&
+	diff expected out &&
+	gnew diff main dir >out &&
+	grep "^+++ /dev/null$" out
+'
+
+test_expect_success 'checkout replaces a directory with a file and back' '
+	gnew checkout main &&
+	test -f p &&
+	gnew checkout dir &&
+	test -f p/x &&
+	gnew status --porcelain >out &&
+	test ! -s out
+'
+
+test_expect_success 'checkout fails without changes if an ignored file is in the way' '
+	echo log >p/x.log &&
+	! gnew checkout main 2>out &&
+	grep "fatal:" out &&
+	test -f p/x &&
+	grep "p/x" .gnew/tracklist &&
+	rm p/x.log
+'
+
+test_expect_success 'merge replaces a file with a directory' '
+	gnew checkout main &&
+	gnew checkout -b other &&
+	test_commit other q &&
+	gnew merge dir &&
+	test -f p/x &&
+	gnew status --porcelain >out &&
+	test ! -s out
+'
+
+test_expect_success 'merge reports a file changed where theirs has a directory as a conflict' '
+	gnew checkout main &&
+	gnew checkout -b changed &&
+	echo changed >p &&
+	gnew commit p &&
+	! gnew merge dir 2>out &&
+	grep "p/x" out
+'
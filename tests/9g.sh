@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup diverged branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b feature &&
+	test_commit feature f &&
+	gnew checkout main &&
+	test_commit main m
+'
+
+test_expect_success 'diff A...B shows only the changes on B' '
+	gnew diff main...feature --name-status >out &&
+	printf "A\tf\n" >expected &&
+	diff expected out &&
+	gnew diff main feature --name-status >out &&
+	grep "D	m" out
+'
+
+test_expect_success 'diff A... compares with HEAD' '
+	gnew diff feature... --name-status >out &&
+	printf "A\tm\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'diff A...B rejects unknown revisions' '
+	! gnew diff main...nope
+'
@@ -0,0 +1,34 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup diverged branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b feature &&
+	test_commit feature f &&
+	gnew checkout main &&
+	test_commit main m
+'
+
+test_expect_success 'merge-base shows the common ancestor' '
+	gnew merge-base main feature >out &&
+	gnew log 3 --format=%H | tail -1 >expected &&
+	diff expected out &&
+	gnew merge-base feature main >out &&
+	diff expected out
+'
+
+test_expect_success 'merge-base --is-ancestor' '
+	gnew merge-base --is-ancestor main~1 feature >out &&
+	test ! -s out &&
+	gnew merge-base --is-ancestor HEAD HEAD &&
+	! gnew merge-base --is-ancestor feature main &&
+	! gnew merge-base --is-ancestor main feature
+'
+
+test_expect_success 'merge-base fails on unknown revisions' '
+	! gnew merge-base main nope 2>out &&
+	grep "fatal:" out
+'
@@ -0,0 +1,42 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'pull reports when already up to date' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	test_commit init r.txt &&
+	cp -R . ../local &&
+	cd ../local &&
+	gnew pull ../remote >../out &&
+	grep "Already up to date." ../out &&
+	test_commit local l.txt &&
+	gnew pull ../remote >../out &&
+	grep "Already up to date." ../out
+'
+
+test_expect_success 'merge refuses unrelated histories' '
+	mkdir ../unrelated &&
+	cd ../unrelated &&
+	gnew init >/dev/null &&
+	test_commit unrelated u.txt &&
+	cd ../local &&
+	! gnew pull ../unrelated 2>../err &&
+	grep "fatal: the histories are unrelated" ../err &&
+	grep "hint:.*--allow-unrelated-histories" ../err &&
+	! gnew merge $(cd ../unrelated && gnew log 1 --format=%H) 2>../err &&
+	grep "fatal: the histories are unrelated" ../err &&
+	test ! -e u.txt
+'
+
+test_expect_success 'merge-base exits with 1 for unrelated histories' '
+	! gnew merge-base main $(cd ../unrelated && gnew log 1 --format=%H) >../out &&
+	test ! -s ../out
+'
+
+test_expect_success 'merge --allow-unrelated-histories merges them' '
+	gnew merge --allow-unrelated-histories $(cd ../unrelated && gnew log 1 --format=%H) &&
+	test -f u.txt &&
+	test -f l.txt
+'
@@ -0,0 +1,65 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup diverged branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b feature &&
+	test_commit feature f &&
+	gnew checkout main &&
+	test_commit main m
+'
+
+test_expect_success 'merge commits a clean three-way merge with both parents' '
+	gnew merge feature >out &&
+	grep "Merge made by the three-way strategy." out &&
+	gnew log 1 --format=%s >out &&
+	echo "Merge branch '"'"'feature'"'"'" >expected &&
+	diff expected out &&
+	gnew cat-object commit $(gnew log 1 --format=%H) >out &&
+	test $(grep -c "^parent " out) = 2 &&
+	grep "^parent $(gnew merge-base HEAD feature)$" out &&
+	gnew status --porcelain >out &&
+	test ! -s out
+'
+
+test_expect_success 'merging the branch again uses the merged commit as base' '
+	gnew checkout feature &&
+	tip=$(gnew log 1 --format=%H) &&
+	echo "more" >>f &&
+	gnew commit f &&
+	gnew checkout main &&
+	echo "more on main" >>m &&
+	gnew commit m &&
+	test "$(gnew merge-base main feature)" = "$tip" &&
+	gnew merge feature &&
+	grep "more" f
+'
+
+test_expect_success 'merge --no-commit leaves the merge for the next commit' '
+	gnew checkout -b topic &&
+	test_commit topic t &&
+	gnew checkout main &&
+	test_commit main2 m2 &&
+	gnew merge --no-commit topic >out &&
+	grep "remember to commit" out &&
+	test -f .gnew/MERGE_HEAD &&
+	gnew commit "merge topic" &&
+	test ! -e .gnew/MERGE_HEAD &&
+	gnew cat-object commit $(gnew log 1 --format=%H) >out &&
+	test $(grep -c "^parent " out) = 2
+'
+
+test_expect_success 'merge commits push the objects of both parents' '
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	cd .. &&
+	gnew push remote &&
+	cd remote &&
+	gnew checkout main &&
+	test -f t &&
+	test -f f
+'
@@ -0,0 +1,49 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup conflicting branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n" >.gnew/info/exclude &&
+	test_commit base foo &&
+	gnew checkout -b branch1 &&
+	echo theirs >foo &&
+	echo new >bar &&
+	gnew add bar &&
+	gnew commit "theirs" &&
+	gnew checkout main &&
+	echo ours >foo &&
+	echo other >bar &&
+	gnew add bar &&
+	gnew commit "ours" &&
+	! gnew merge branch1 2>out &&
+	grep "Merge conflict in foo" out
+'
+
+test_expect_success 'cat --stage outputs each version of a conflicted file' '
+	echo base >expected &&
+	gnew cat --stage base foo >out &&
+	diff expected out &&
+	echo ours >expected &&
+	gnew cat --stage ours foo >out &&
+	diff expected out &&
+	echo theirs >expected &&
+	gnew cat --stage theirs foo >out &&
+	diff expected out
+'
+
+test_expect_success 'cat --stage fails for missing versions and files' '
+	gnew cat --stage theirs bar >out &&
+	! gnew cat --stage base bar 2>out &&
+	grep "fatal: file not found: '"'"'bar'"'"' in '"'"'base'"'"'" out &&
+	! gnew cat --stage ours nothere &&
+	! gnew cat --stage ours HEAD foo
+'
+
+test_expect_success 'committing the merge forgets the stages' '
+	echo resolved >foo &&
+	echo resolved >bar &&
+	gnew commit "merge branch1" &&
+	! gnew cat --stage ours foo 2>out &&
+	test ! -e .gnew/MERGE_CONFLICTS
+'
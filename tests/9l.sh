@@ -0,0 +1,39 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup merged branch' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b feature &&
+	test_commit feature f &&
+	gnew checkout main &&
+	test_commit main m &&
+	gnew merge feature
+'
+
+test_expect_success 'log shows the commits of all parents' '
+	gnew log --format=%s >out &&
+	printf "Merge branch '"'"'feature'"'"'\nmain\nfeature\nbase\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'log --first-parent follows the mainline' '
+	gnew log --first-parent --format=%s >out &&
+	printf "Merge branch '"'"'feature'"'"'\nmain\nbase\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'log --merges and --no-merges' '
+	gnew log --merges --format=%s >out &&
+	printf "Merge branch '"'"'feature'"'"'\n" >expected &&
+	diff expected out &&
+	gnew log --no-merges --format=%s >out &&
+	printf "main\nfeature\nbase\n" >expected &&
+	diff expected out &&
+	gnew log 2 --no-merges --format=%s >out &&
+	printf "main\nfeature\n" >expected &&
+	diff expected out &&
+	! gnew log --merges --no-merges
+'
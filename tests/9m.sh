@@ -0,0 +1,81 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b merged &&
+	gnew checkout -b stale &&
+	test_commit stale s &&
+	gnew checkout main &&
+	test_commit main m
+'
+
+test_expect_success 'branch lists all branches' '
+	gnew branch >out &&
+	printf "* main\n  merged\n  stale\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch --merged lists branches reachable from a commit' '
+	gnew branch --merged main >out &&
+	printf "* main\n  merged\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch --no-merged lists the other branches' '
+	gnew branch --no-merged main >out &&
+	printf "  stale\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch --contains lists branches containing a commit' '
+	gnew branch --contains main~1 >out &&
+	printf "* main\n  merged\n  stale\n" >expected &&
+	diff expected out &&
+	gnew branch --contains stale >out &&
+	printf "  stale\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch filters can be combined' '
+	gnew branch --contains merged --no-merged main >out &&
+	printf "  stale\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch fails on unknown revisions' '
+	! gnew branch --merged nope 2>out &&
+	grep "fatal:" out
+'
+
+test_expect_success 'branch --delete-merged deletes merged branches' '
+	gnew checkout -b kept &&
+	gnew checkout -b packed &&
+	gnew checkout main &&
+	gnew pack-refs &&
+	echo "branch.protected = kept other" >>.gnew/config &&
+	gnew branch --delete-merged main >out &&
+	grep "^Deleted branch merged (was " out &&
+	grep "^Deleted branch packed (was " out &&
+	test $(wc -l <out) -eq 2 &&
+	gnew branch >out &&
+	printf "  kept\n* main\n  stale\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'branch --delete-merged keeps the current branch' '
+	gnew checkout stale &&
+	gnew branch --delete-merged main >out &&
+	test ! -s out &&
+	gnew checkout -b done &&
+	gnew checkout main &&
+	gnew branch --delete-merged stale >out &&
+	grep "^Deleted branch done (was " out &&
+	test $(wc -l <out) -eq 1 &&
+	gnew branch >out &&
+	printf "  kept\n* main\n  stale\n" >expected &&
+	diff expected out
+'
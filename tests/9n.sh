@@ -0,0 +1,80 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup branches and a remote' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b release/1.0 &&
+	test_commit release r &&
+	gnew checkout -b experiment &&
+	test_commit experiment e &&
+	gnew checkout main &&
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	cd .. &&
+	echo "remote.origin.url = remote" >>.gnew/config
+'
+
+test_expect_success 'push --all only pushes the branches matching the push refspecs' '
+	echo "remote.origin.push = main release/*:stable/*" >>.gnew/config &&
+	gnew push --all remote &&
+	cd remote &&
+	gnew branch >../out &&
+	cd .. &&
+	printf "* main\n  stable/1.0\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'pull --all maps the branches with the fetch refspecs' '
+	echo "remote.origin.fetch = stable/*:upstream/*" >>.gnew/config &&
+	gnew pull --all remote &&
+	gnew branch >out &&
+	printf "  experiment\n* main\n  release/1.0\n  upstream/1.0\n" >expected &&
+	diff expected out &&
+	gnew merge-base --is-ancestor upstream/1.0 release/1.0 &&
+	gnew merge-base --is-ancestor release/1.0 upstream/1.0
+'
+
+test_expect_success 'sync without refspecs uses all branches' '
+	echo "remote.origin.url = elsewhere" >>.gnew/config &&
+	gnew push --all remote &&
+	cd remote &&
+	gnew branch >../out &&
+	cd .. &&
+	printf "  experiment\n* main\n  release/1.0\n  stable/1.0\n  upstream/1.0\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'invalid refspecs are rejected' '
+	echo "remote.origin.url = remote" >>.gnew/config &&
+	echo "remote.origin.push = release/*:stable" >>.gnew/config &&
+	! gnew push --all remote 2>out &&
+	grep "invalid refspec" out
+'
+
+test_expect_success 'push --all with a renaming refspec leaves the other branches of the remote alone' '
+	echo mirror >>.gnew/info/exclude &&
+	mkdir mirror &&
+	(cd mirror && gnew init >/dev/null && test_commit mirrored m) &&
+	echo "remote.mirror.url = mirror" >>.gnew/config &&
+	echo "remote.mirror.push = main:upstream" >>.gnew/config &&
+	gnew push --all mirror &&
+	cd mirror &&
+	gnew branch >../out &&
+	test -f m &&
+	! test -f a &&
+	cd .. &&
+	printf "* main\n  upstream\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'push --all refreshes the remote working tree when its branch moves' '
+	(cd mirror && gnew checkout upstream >/dev/null) &&
+	test_commit pushed p &&
+	gnew push --all mirror &&
+	test -f mirror/p &&
+	test "$(cd mirror && gnew rev-parse HEAD)" = "$(gnew rev-parse main)"
+'
@@ -0,0 +1,53 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup branches and a remote' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\nbefore\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	gnew checkout -b dev &&
+	gnew checkout main &&
+	mkdir remote &&
+	cd remote &&
+	gnew init >/dev/null &&
+	cd ..
+'
+
+test_expect_success 'push --all reports each branch' '
+	gnew push --all remote >out &&
+	grep "^ \* \[new branch\] *dev$" out &&
+	grep "^ \* \[new branch\] *main$" out &&
+	test_commit second b &&
+	gnew push --all remote >out &&
+	grep "^ = \[up to date\] *dev$" out &&
+	grep "^   [0-9a-f]\{7\}\.\.[0-9a-f]\{7\} *main$" out
+'
+
+test_expect_success 'push --all rejects the whole push if a branch diverged' '
+	cd remote &&
+	gnew checkout dev &&
+	test_commit remote r &&
+	gnew checkout main &&
+	cat .gnew/refs/heads/* >../before &&
+	cd .. &&
+	test_commit third c &&
+	! gnew push --all remote 2>out &&
+	grep "pull first: dev$" out &&
+	cat remote/.gnew/refs/heads/* >out &&
+	diff before out
+'
+
+test_expect_success 'push --all restores the branches if an update fails' '
+	echo "remote2" >>.gnew/info/exclude &&
+	gnew checkout -b zzz &&
+	gnew checkout main &&
+	mkdir remote2 &&
+	cd remote2 &&
+	gnew init >/dev/null &&
+	mkdir -p .gnew/refs/heads/zzz/blocked &&
+	cd .. &&
+	! gnew push --all remote2 &&
+	test ! -e remote2/.gnew/refs/heads/dev &&
+	test ! -e remote2/.gnew/refs/heads/main
+'
@@ -0,0 +1,66 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a source with two branches' '
+	mkdir source && (
+		cd source &&
+		gnew init >/dev/null &&
+		test_commit base a &&
+		gnew checkout -b feature &&
+		test_commit feature f &&
+		gnew checkout main &&
+		test_commit main m
+	) &&
+	mkdir clones
+'
+
+test_expect_success 'clone --branch checks out the branch' '
+	(
+		cd clones &&
+		gnew clone --branch feature ../source &&
+		cd source &&
+		grep "^ref: refs/heads/feature$" .gnew/HEAD &&
+		test -f f && test ! -f m &&
+		gnew status >../out &&
+		! grep "modified\|deleted\|untracked" ../out &&
+		gnew branch >../out &&
+		printf "* feature\n  main\n" >../expected &&
+		diff ../expected ../out
+	)
+'
+
+test_expect_success 'clone --branch fails on an unknown branch' '
+	rm -rf clones/source &&
+	(cd clones && ! gnew clone --branch nope ../source 2>out && grep "fatal:" out) &&
+	test ! -e clones/source
+'
+
+test_expect_success 'clone --single-branch copies only the history of the branch' '
+	(
+		cd clones &&
+		gnew clone --branch feature --single-branch ../source &&
+		cd source &&
+		gnew branch >../out &&
+		printf "* feature\n" >../expected &&
+		diff ../expected ../out &&
+		gnew log --format=%s >../out &&
+		printf "feature\nbase\n" >../expected &&
+		diff ../expected ../out &&
+		grep "^remote.origin.fetch = feature$" .gnew/config
+	) &&
+	test $(ls clones/source/.gnew/objects | wc -l) -lt $(ls source/.gnew/objects | wc -l)
+'
+
+test_expect_success 'clone --single-branch defaults to the source HEAD' '
+	rm -rf clones/source &&
+	(
+		cd clones &&
+		gnew clone --single-branch ../source &&
+		cd source &&
+		gnew branch >../out &&
+		printf "* main\n" >../expected &&
+		diff ../expected ../out &&
+		test -f m
+	)
+'
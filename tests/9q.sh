@@ -0,0 +1,65 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a remote with two branches' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	mkdir remote && (
+		cd remote &&
+		gnew init >/dev/null &&
+		test_commit remote r &&
+		gnew checkout -b feature &&
+		test_commit feature f &&
+		gnew checkout main
+	) &&
+	echo "remote.origin.url = remote" >>.gnew/config
+'
+
+test_expect_success 'fetch creates remote-tracking branches' '
+	gnew fetch >out &&
+	test ! -s out &&
+	gnew branch -r >out &&
+	printf "  origin/feature\n  origin/main\n" >expected &&
+	diff expected out &&
+	gnew branch >out &&
+	printf "* main\n" >expected &&
+	diff expected out &&
+	gnew merge-base --is-ancestor origin/main origin/feature
+'
+
+test_expect_success 'fetch without --prune keeps deleted branches' '
+	(cd remote && rm .gnew/refs/heads/feature) &&
+	gnew fetch origin &&
+	gnew branch -r >out &&
+	printf "  origin/feature\n  origin/main\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'fetch --prune removes deleted branches' '
+	gnew fetch --prune origin >out &&
+	echo " - [pruned] origin/feature" >expected &&
+	diff expected out &&
+	gnew branch -r >out &&
+	printf "  origin/main\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'remote prune removes deleted branches without fetching' '
+	(cd remote && gnew checkout -b other && gnew checkout main) &&
+	gnew fetch &&
+	(cd remote && rm .gnew/refs/heads/other && test_commit more m) &&
+	gnew remote prune origin >out &&
+	echo " - [pruned] origin/other" >expected &&
+	diff expected out &&
+	! gnew merge-base --is-ancestor $(cd remote && gnew log 1 --format=%H) origin/main &&
+	gnew branch -r >out &&
+	printf "  origin/main\n" >expected &&
+	diff expected out
+'
+
+test_expect_success 'fetch fails on an unknown remote' '
+	! gnew fetch nope 2>out &&
+	grep "no such remote: .nope." out
+'
@@ -0,0 +1,63 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a remote with receive hooks' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	mkdir remote && (
+		cd remote &&
+		gnew init >/dev/null &&
+		mkdir -p .gnew/hooks &&
+		printf "#!/bin/sh\ncat >>.gnew/received\n! grep -q refs/heads/frozen .gnew/received\n" >.gnew/hooks/pre-receive &&
+		printf "#!/bin/sh\necho \"\$@\" >>.gnew/updated\ngnew cat-object commit \$3 | grep -q ticket\n" >.gnew/hooks/update &&
+		chmod +x .gnew/hooks/pre-receive .gnew/hooks/update
+	) &&
+	echo "remote.origin.url = remote" >>.gnew/config
+'
+
+test_expect_success 'update hook can reject a push by commit message' '
+	! gnew push remote 2>out &&
+	grep "update hook failed" out &&
+	test ! -e remote/.gnew/refs/heads/main &&
+	new=$(gnew log 1 --format=%H) &&
+	echo "0000000000000000000000000000000000000000 $new refs/heads/main" >expected &&
+	diff expected remote/.gnew/received &&
+	echo "refs/heads/main 0000000000000000000000000000000000000000 $new" >expected &&
+	diff expected remote/.gnew/updated
+'
+
+test_expect_success 'receive hooks are given the old and new heads' '
+	rm remote/.gnew/received remote/.gnew/updated &&
+	test_commit "fix for ticket 1" b &&
+	gnew push remote &&
+	old=$(gnew log 1 --format=%H) &&
+	test_commit "fix for ticket 2" c &&
+	rm remote/.gnew/received remote/.gnew/updated &&
+	gnew push remote &&
+	new=$(gnew log 1 --format=%H) &&
+	echo "$old $new refs/heads/main" >expected &&
+	diff expected remote/.gnew/received &&
+	echo "refs/heads/main $old $new" >expected &&
+	diff expected remote/.gnew/updated
+'
+
+test_expect_success 'pre-receive hook can reject a push' '
+	gnew checkout -b frozen &&
+	gnew checkout main &&
+	! gnew push --all remote 2>out &&
+	grep "pre-receive hook failed" out &&
+	test ! -e remote/.gnew/refs/heads/frozen
+'
+
+test_expect_success 'push rejects updates that are not fast-forwards' '
+	rm remote/.gnew/hooks/pre-receive &&
+	gnew checkout -b old &&
+	gnew checkout main &&
+	test_commit "ticket 3" d &&
+	gnew push remote &&
+	echo "remote.origin.push = old:main" >>.gnew/config &&
+	! gnew push --all remote 2>out &&
+	grep "pull first: main" out
+'
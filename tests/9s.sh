@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a remote' '
+	mkdir -p .gnew/info &&
+	printf "out\nexpected\nremote\n" >.gnew/info/exclude &&
+	test_commit base a &&
+	mkdir remote && (
+		cd remote &&
+		gnew init >/dev/null &&
+		mkdir -p .gnew/hooks
+	) &&
+	gnew push remote
+'
+
+test_expect_success 'hooks read pushed objects from the quarantine' '
+	printf "#!/bin/sh\ntest ! -e .gnew/objects/\$3 &&\ntest -e \"\$GNEW_QUARANTINE_PATH/\$3\" &&\ngnew cat-object commit \$3 >.gnew/seen\n" >remote/.gnew/hooks/update &&
+	chmod +x remote/.gnew/hooks/update &&
+	test_commit second b &&
+	gnew push remote &&
+	grep "^tree " remote/.gnew/seen &&
+	test -e remote/.gnew/objects/$(gnew log 1 --format=%H) &&
+	test -z "$(ls -d remote/.gnew/incoming-* 2>/dev/null)"
+'
+
+test_expect_success 'a refused push leaves no objects behind' '
+	ls remote/.gnew/objects >expected &&
+	printf "#!/bin/sh\nexit 1\n" >remote/.gnew/hooks/pre-receive &&
+	chmod +x remote/.gnew/hooks/pre-receive &&
+	test_commit third c &&
+	! gnew push remote 2>out &&
+	grep "pre-receive hook failed" out &&
+	ls remote/.gnew/objects >out &&
+	diff expected out &&
+	test -z "$(ls -d remote/.gnew/incoming-* 2>/dev/null)"
+'
+
+test_expect_success 'a receive hook that cannot be run refuses the push' '
+	printf "#!/bin/sh\nexit 0\n" >remote/.gnew/hooks/pre-receive &&
+	chmod -x remote/.gnew/hooks/pre-receive &&
+	! gnew push remote 2>out &&
+	grep "pre-receive hook is not executable" out
+'
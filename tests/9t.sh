@@ -0,0 +1,31 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	printf "one\ntwo\nthree\n" >f &&
+	gnew add f &&
+	gnew commit first &&
+	printf "one\n2\nthree\nfour\n" >f
+'
+
+test_expect_success 'diff --color colors changed lines and hunk headers' '
+	gnew diff --color >out &&
+	grep "$(printf "\033\\[31m-two")" out &&
+	grep "$(printf "\033\\[32m+2")" out &&
+	grep "$(printf "\033\\[36m@@ -1,3 +1,4 @@")" out &&
+	grep "^ one$" out
+'
+
+test_expect_success 'diff without --color is plain' '
+	gnew diff >out &&
+	! grep "$(printf "\033")" out
+'
+
+test_expect_success 'diff --side-by-side shows two columns' '
+	COLUMNS=23 gnew diff --side-by-side >out &&
+	grep "^one  *one$" out &&
+	grep "^two  *| 2$" out &&
+	grep "^  *> four$" out &&
+	test "$(awk "{ print length }" out | sort -n | tail -n 1)" -le 23
+'
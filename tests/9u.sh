@@ -0,0 +1,26 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'status reports a clean working tree' '
+	mkdir -p .gnew/info &&
+	echo out >.gnew/info/exclude &&
+	test_commit first a &&
+	gnew status >out &&
+	grep "^nothing to commit, working tree clean$" out
+'
+
+test_expect_success 'status counts each kind of change' '
+	echo changed >a &&
+	test_commit second b &&
+	echo changed >b &&
+	touch c d &&
+	gnew status >out &&
+	grep "^1 modified, 2 untracked$" out &&
+	! grep "nothing to commit" out
+'
+
+test_expect_success 'status --porcelain has no summary' '
+	gnew status --porcelain >out &&
+	! grep "untracked" out
+'
@@ -0,0 +1,38 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup a remote with commits' '
+	mkdir -p .gnew/info &&
+	printf "out\nremote\n" >.gnew/info/exclude &&
+	mkdir remote && (
+		cd remote &&
+		gnew init >/dev/null &&
+		echo x >x &&
+		gnew add x &&
+		gnew commit first
+	)
+'
+
+test_expect_success 'status lists untracked files on an empty branch' '
+	echo a >a &&
+	gnew status >out 2>&1 &&
+	grep "^? a$" out &&
+	grep "^warning: branch .main. has no commits yet$" out
+'
+
+test_expect_success 'log and diff report an empty history' '
+	gnew log >out 2>&1 &&
+	grep "has no commits yet" out &&
+	gnew diff >out 2>&1 &&
+	grep "has no commits yet" out
+'
+
+test_expect_success 'pull, push and merge fail on an empty branch' '
+	! gnew pull remote 2>out &&
+	grep "^fatal: branch .main. has no commits yet$" out &&
+	! gnew push remote 2>out &&
+	grep "^fatal: branch .main. has no commits yet$" out &&
+	! gnew merge HEAD 2>out &&
+	grep "^fatal: branch .main. has no commits yet$" out
+'
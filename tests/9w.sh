@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup another repository' '
+	mkdir -p .gnew/info &&
+	printf "out\nother\n" >.gnew/info/exclude &&
+	test_commit local l &&
+	mkdir other && (
+		cd other &&
+		gnew init >/dev/null &&
+		mkdir dir &&
+		echo content >dir/f &&
+		gnew add dir &&
+		gnew commit "other commit"
+	)
+'
+
+test_expect_success 'log --repo shows the history of another repository' '
+	gnew log --repo other --format "%s" >out &&
+	echo "other commit" >expected &&
+	diff expected out
+'
+
+test_expect_success 'cat --repo reads paths from the root of the other repository' '
+	mkdir -p sub && (
+		cd sub &&
+		gnew cat --repo ../other dir/f >../out
+	) &&
+	echo content >expected &&
+	diff expected out
+'
+
+test_expect_success 'heads --repo accepts a remote name' '
+	echo "remote.origin.url = other" >>.gnew/config &&
+	gnew heads --repo origin >out &&
+	grep "^\* main " out
+'
+
+test_expect_success 'queries fail for a path that is not a repository' '
+	mkdir -p plain &&
+	! gnew log --repo plain 2>out &&
+	grep "fatal:" out
+'
@@ -0,0 +1,44 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	test_commit first f &&
+	echo orphan >orphan &&
+	gnew hash-file orphan >hash &&
+	echo recent >recent &&
+	gnew hash-file recent >recent-hash
+'
+
+test_expect_success 'maintenance run prunes old unreachable objects' '
+	touch -d "30 days ago" .gnew/objects/$(cat hash) &&
+	gnew maintenance run >out &&
+	grep "gc: removed 1 unreachable objects" out &&
+	! test -f .gnew/objects/$(cat hash) &&
+	test -f .gnew/objects/$(cat recent-hash)
+'
+
+test_expect_success 'maintenance run keeps old reachable objects' '
+	touch -d "30 days ago" .gnew/objects/* &&
+	gnew maintenance run >out &&
+	grep "gc: removed 1 unreachable objects" out &&
+	! test -f .gnew/objects/$(cat recent-hash) &&
+	gnew cat f &&
+	gnew log >/dev/null
+'
+
+test_expect_success 'maintenance run packs references' '
+	grep "pack-refs: packed 1 references" out &&
+	test -f .gnew/packed-refs
+'
+
+test_expect_success 'maintenance run --auto does nothing below the threshold' '
+	gnew maintenance run --auto >out &&
+	! test -s out
+'
+
+test_expect_success 'maintenance run --auto runs above maintenance.auto' '
+	echo "maintenance.auto = 1" >>.gnew/config &&
+	gnew maintenance run --auto >out &&
+	grep "status-cache: refreshed" out
+'
@@ -0,0 +1,33 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	mkdir dir &&
+	seq 1 1000 >dir/big &&
+	gnew add dir &&
+	gnew commit "add big" &&
+	gnew log --format "%h" >big-commit &&
+	echo small >small &&
+	gnew add small &&
+	gnew commit "add small" &&
+	gnew rm dir/big &&
+	gnew commit "remove big"
+'
+
+test_expect_success 'stats --blobs ranks deleted blobs by size' '
+	gnew stats --blobs >out &&
+	head -n 1 out >first &&
+	grep " 3893 $(cat big-commit) dir/big$" first &&
+	grep " small$" out
+'
+
+test_expect_success 'stats --blobs -n limits the number of blobs' '
+	gnew stats --blobs -n 1 >out &&
+	test $(wc -l <out) = 1
+'
+
+test_expect_success 'stats shows the size of the repository' '
+	gnew stats >out &&
+	grep "^reachable-commits: 3$" out
+'
@@ -0,0 +1,55 @@
+#!/bin/sh
+
+. ./test-lib.sh
+
+test_expect_success 'setup' '
+	test_commit first a &&
+	echo password >secret.env &&
+	mkdir logs &&
+	echo log >logs/big.log &&
+	gnew add secret.env logs &&
+	gnew commit "add secret and logs" &&
+	gnew cat secret.env >/dev/null &&
+	test_commit second b &&
+	gnew log --format "%h" >old
+'
+
+test_expect_success 'filter refuses a dirty working tree' '
+	cp a a.orig &&
+	echo changed >a &&
+	! gnew filter --remove-path "*.env" &&
+	mv a.orig a
+'
+
+test_expect_success 'filter --remove-path rewrites the history without the paths' '
+	gnew filter --remove-path "*.env" --remove-path logs/ >out &&
+	grep "Rewrote 2 commits" out &&
+	grep "untracked .secret.env." out &&
+	grep "refs/heads/main" out &&
+	gnew log --format "%s" >log &&
+	printf "second\nadd secret and logs\nfirst\n" >expected &&
+	diff expected log &&
+	! gnew cat secret.env &&
+	! gnew cat logs/big.log &&
+	gnew cat b
+'
+
+test_expect_success 'filter keeps the removed files on disk, untracked' '
+	test -f secret.env &&
+	gnew status --porcelain >out &&
+	grep "^? secret.env$" out
+'
+
+test_expect_success 'filter keeps unchanged commits' '
+	gnew log --format "%h" >new &&
+	test "$(tail -n 1 old)" = "$(tail -n 1 new)" &&
+	test "$(head -n 1 old)" != "$(head -n 1 new)"
+'
+
+test_expect_success 'filter --prune removes the old objects' '
+	gnew filter --remove-path "*.env" --prune >out &&
+	grep "Rewrote 0 commits" out &&
+	grep "Removed [1-9][0-9]* unreachable objects" out &&
+	gnew filter --remove-path "*.env" --prune >out &&
+	grep "Removed 0 unreachable objects" out
+'